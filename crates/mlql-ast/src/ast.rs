@@ -1,54 +1,170 @@
 //! AST types for MLQL
 //!
 //! Minimal AST representation closely following the Pest grammar.
+//!
+//! Every node carries the byte [`Span`] of the source text it was parsed
+//! from, so later stages can point diagnostics back at the query. Node types
+//! with several shapes are split into a struct holding the span and a
+//! `*Kind` enum holding the shape.
 
 use serde::{Deserialize, Serialize};
 
+/// Byte range `start..end` into the parsed source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    /// Pest spans of rules ending in an optional element include the
+    /// whitespace skipped while looking for it, which is trimmed here
+    fn from(span: pest::Span<'_>) -> Self {
+        let text = span.as_str().trim_end();
+        Span::new(span.start(), span.start() + text.len())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub pragma: Option<Pragma>,
     pub lets: Vec<LetStatement>,
     pub pipeline: Pipeline,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pragma {
     pub options: Vec<(String, Value)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LetStatement {
     pub name: String,
     pub pipeline: Pipeline,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pipeline {
     pub source: Source,
     pub operators: Vec<Operator>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Source {
+pub struct Source {
+    pub kind: SourceKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SourceKind {
     Table { name: String, alias: Option<String> },
     Graph { graph_name: String, alias: String },
     SubQuery { pipeline: Box<Pipeline>, alias: Option<String> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Operator {
+pub struct Operator {
+    pub kind: OperatorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OperatorKind {
     Select { items: Vec<SelectItem> },
     Filter { expr: Expr },
     Join { source: Source, on: Expr, join_type: Option<JoinType> },
     GroupBy { keys: Vec<ColumnRef>, aggs: Vec<(String, FuncCall)> },
+    Window { windows: Vec<(String, WindowDef)> },
     Sort { keys: Vec<SortKey> },
     Take { limit: i64 },
     Distinct,
-    // ... more operators as needed
+    Union { all: bool, source: Source },
+    Except { source: Source },
+    Intersect { source: Source },
+    Map { mappings: Vec<(String, Expr)> },
+    Expand { expr: Expr, alias: Option<String> },
+    Resample { interval: String, method: String, on: ColumnRef },
+    Agg { group_key: GroupKey, aggs: Vec<(String, FuncCall)> },
+    Knn { query: Expr, k: i64, on: Option<ColumnRef>, index: Option<String>, metric: Option<String> },
+    Rank { by: Expr },
+    Neighbors { start: Expr, depth: i64, edge: Option<String> },
+    TopK { k: i64, by: Expr },
+    Sample { fraction: f64, seed: Option<i64> },
+    Assert { condition: Expr, message: Option<String> },
+    Explain { mode: ExplainMode },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowDef {
+    pub func: String,
+    pub args: Vec<Expr>,
+    pub partition: Option<Vec<ColumnRef>>,
+    pub order: Option<Vec<SortKey>>,
+    pub frame: Option<FrameSpec>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum SelectItem {
+pub struct FrameSpec {
+    pub mode: FrameMode,
+    pub start: FrameBound,
+    pub end: FrameBound,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FrameMode {
+    Rows,
+    Range,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    UnboundedFollowing,
+    CurrentRow,
+    Preceding(i64),
+    Following(i64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupKey {
+    pub kind: GroupKeyKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GroupKeyKind {
+    Tumbling { expr: Expr, interval: String },
+    Hopping { expr: Expr, size: String, slide: String },
+    Session { expr: Expr, gap: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExplainMode {
+    Logical,
+    Physical,
+    Cost,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectItem {
+    pub kind: SelectItemKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SelectItemKind {
     Wildcard,
     Expr(Expr),
     Aliased { expr: Expr, alias: String },
@@ -58,6 +174,7 @@ pub enum SelectItem {
 pub struct SortKey {
     pub expr: Expr,
     pub desc: bool,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,25 +189,41 @@ pub enum JoinType {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExprKind {
     Literal(Value),
     Column(ColumnRef),
     BinaryOp { op: BinOp, left: Box<Expr>, right: Box<Expr> },
     UnaryOp { op: UnOp, expr: Box<Expr> },
     FuncCall(FuncCall),
-    // ... more expression types
+    FieldAccess { expr: Box<Expr>, field: String },
+    Index { expr: Box<Expr>, index: Box<Expr> },
+    Array(Vec<Expr>),
+    Object(Vec<(String, Expr)>),
+    Vector(Vec<f64>),
+    /// `expr in [start, end]` (inclusive) or `expr in [start, end)`
+    InRange { expr: Box<Expr>, start: Box<Expr>, end: Box<Expr>, inclusive: bool },
+    /// `expr in (a, b, ...)`
+    InSet { expr: Box<Expr>, set: Vec<Expr> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FuncCall {
     pub name: String,
     pub args: Vec<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColumnRef {
     pub table: Option<String>,
     pub column: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,4 +247,10 @@ pub enum Value {
     Int(i64),
     Float(f64),
     String(String),
+    Bytes(Vec<u8>),
+    Date(String),
+    Time(String),
+    Timestamp(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
 }
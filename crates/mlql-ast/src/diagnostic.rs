@@ -0,0 +1,154 @@
+//! Source diagnostics for MLQL text
+//!
+//! A [`Diagnostic`] pins a message to a [`Span`] of the query and renders it
+//! with the offending line and a caret underline:
+//!
+//! ```text
+//! error: unexpected `selec`
+//!  --> 1:14
+//!   |
+//! 1 | from users | selec [name]
+//!   |              ^^^^^
+//!   = expected one of `agg`, `assert`, `distinct`, ...
+//! ```
+//!
+//! Errors raised after parsing (lowering, Substrait translation, SQL
+//! generation) only know the [`OpLocation`] of the failing operator; a
+//! [`SourceMap`] built from the AST maps it back to a span.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use mlql_ir::OpLocation;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{Program, Span};
+
+/// An error message attached to a location in MLQL source text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    /// 1-based line of `span.start`
+    pub line: usize,
+    /// 1-based column (in characters) of `span.start`
+    pub column: usize,
+    /// 1-based column one past the underlined text, clipped to the end of the line
+    pub end_column: usize,
+    /// Full text of the line containing `span.start`
+    pub source_line: String,
+    /// What would have been accepted here, e.g. keywords or column names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expected: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic for `span` of `source`
+    pub fn new(source: &str, span: Span, message: impl Into<String>) -> Self {
+        let start = floor_char_boundary(source, span.start.min(source.len()));
+        let end = floor_char_boundary(source, span.end.clamp(start, source.len()));
+
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let source_line = source[line_start..line_end].trim_end_matches('\r');
+
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let end_column = column + source[start..end.min(line_end)].chars().count();
+
+        Diagnostic {
+            message: message.into(),
+            span: Span::new(start, end),
+            line,
+            column,
+            end_column,
+            source_line: source_line.to_string(),
+            expected: Vec::new(),
+        }
+    }
+
+    /// Attach the list of alternatives that would have been accepted
+    pub fn with_expected(mut self, expected: Vec<String>) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Render the diagnostic as a multi-line, caret-annotated message
+    pub fn render(&self) -> String {
+        let number = self.line.to_string();
+        let pad = " ".repeat(number.len());
+
+        // Keep tabs in the indentation so the caret lines up with the source
+        let indent: String = self.source_line.chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(self.end_column.saturating_sub(self.column).max(1));
+
+        let mut out = format!(
+            "error: {}\n{pad}--> {}:{}\n{pad} |\n{number} | {}\n{pad} | {indent}{carets}",
+            self.message, self.line, self.column, self.source_line,
+        );
+        match self.expected.as_slice() {
+            [] => {}
+            [only] => out.push_str(&format!("\n{pad} = expected {}", only)),
+            many => out.push_str(&format!("\n{pad} = expected one of {}", many.join(", "))),
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Spans of every operator in a parsed program, keyed by IR location
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    program: Span,
+    spans: HashMap<OpLocation, Span>,
+}
+
+impl SourceMap {
+    /// Span of the operator (or source) at `location`
+    pub fn span(&self, location: &OpLocation) -> Option<Span> {
+        self.spans.get(location).copied()
+    }
+
+    /// Create a diagnostic for `location`, falling back to the whole program
+    /// when the location is unknown
+    pub fn diagnostic(&self, source: &str, location: &OpLocation, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(source, self.span(location).unwrap_or(self.program), message)
+    }
+}
+
+impl Program {
+    /// Map the IR location of every source and operator back to its span.
+    ///
+    /// AST operators lower 1:1 to IR operators, so indices are shared.
+    pub fn source_map(&self) -> SourceMap {
+        let mut spans = HashMap::new();
+        let pipelines = self.lets.iter()
+            .enumerate()
+            .map(|(i, l)| (Some(i), &l.pipeline))
+            .chain(std::iter::once((None, &self.pipeline)));
+
+        for (binding, pipeline) in pipelines {
+            spans.insert(OpLocation { binding, op: None }, pipeline.source.span);
+            for (i, op) in pipeline.operators.iter().enumerate() {
+                spans.insert(OpLocation { binding, op: Some(i) }, op.span);
+            }
+        }
+
+        SourceMap { program: self.span, spans }
+    }
+}
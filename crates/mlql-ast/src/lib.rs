@@ -1,11 +1,15 @@
 //! MLQL AST - parser and AST types
 
 pub mod ast;
+mod diagnostic;
 mod parser;
+mod printer;
 mod to_ir;
 
 pub use ast::*;
+pub use diagnostic::{Diagnostic, SourceMap};
 pub use parser::{parse, ParseError};
+pub use printer::print;
 
 #[cfg(test)]
 mod tests {
@@ -1,10 +1,16 @@
 //! Pest-based parser for MLQL
 
+use std::collections::HashSet;
+use std::sync::Once;
+
+use pest::error::{ErrorVariant, InputLocation};
+use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
 use thiserror::Error;
 
 use crate::ast::*;
+use crate::diagnostic::Diagnostic;
 
 #[derive(Parser)]
 #[grammar = "mlql.pest"]
@@ -12,18 +18,37 @@ pub struct MlqlParser;
 
 #[derive(Debug, Error)]
 pub enum ParseError {
-    #[error("Syntax error: {0}")]
-    Syntax(String),
+    #[error("{0}")]
+    Syntax(Diagnostic),
+}
+
+impl ParseError {
+    /// Structured form of the error, with location and expected alternatives
+    pub fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            ParseError::Syntax(diagnostic) => diagnostic,
+        }
+    }
+}
 
-    #[error("Pest error: {0}")]
-    Pest(#[from] pest::error::Error<Rule>),
+/// Error pointing at the text matched by `pair`
+fn error(pair: &Pair<Rule>, message: impl Into<String>) -> ParseError {
+    let span = pair.as_span();
+    ParseError::Syntax(Diagnostic::new(span.get_input(), span.into(), message))
 }
 
+static ERROR_DETAIL: Once = Once::new();
+
 /// Parse MLQL source text into AST
 pub fn parse(source: &str) -> Result<Program, ParseError> {
+    // Pest only records the literal tokens it tried (keywords, punctuation)
+    // when error detail is enabled; they feed the "expected one of" hint
+    ERROR_DETAIL.call_once(|| pest::set_error_detail(true));
 
-    let mut pairs = MlqlParser::parse(Rule::program, source)?;
-    let program_pair = pairs.next().ok_or_else(|| ParseError::Syntax("Empty input".to_string()))?;
+    let mut pairs = MlqlParser::parse(Rule::program, source)
+        .map_err(|e| syntax_error(source, e))?;
+    let program_pair = pairs.next().unwrap();
+    let span = program_pair.as_span().into();
 
     let mut pragma = None;
     let mut lets = Vec::new();
@@ -48,23 +73,200 @@ pub fn parse(source: &str) -> Result<Program, ParseError> {
     Ok(Program {
         pragma,
         lets,
-        pipeline: pipeline.ok_or_else(|| ParseError::Syntax("Missing pipeline".to_string()))?,
+        // The grammar requires a pipeline
+        pipeline: pipeline.unwrap(),
+        span,
     })
 }
 
-fn parse_pragma(_pair: pest::iterators::Pair<Rule>) -> Result<Pragma, ParseError> {
-    // TODO: Implement pragma parsing
-    Ok(Pragma { options: vec![] })
+/// Convert a pest error into a diagnostic pointing at the offending token
+fn syntax_error(source: &str, err: pest::error::Error<Rule>) -> ParseError {
+    let pos = match err.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((start, _)) => start,
+    };
+
+    let diagnostic = match &err.variant {
+        ErrorVariant::CustomError { message } => {
+            Diagnostic::new(source, Span::new(pos, pos), message.clone())
+        }
+        ErrorVariant::ParsingError { .. } => {
+            let (message, end) = match unexpected_token(&source[pos..]) {
+                Some(token) => (format!("Unexpected `{}`", token), pos + token.len()),
+                None => ("Unexpected end of input".to_string(), pos),
+            };
+            Diagnostic::new(source, Span::new(pos, end), message)
+                .with_expected(expected_alternatives(&err))
+        }
+    };
+    ParseError::Syntax(diagnostic)
+}
+
+/// The word or symbol at the start of `rest`
+fn unexpected_token(rest: &str) -> Option<&str> {
+    let first = rest.chars().next()?;
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let len = if is_word(first) {
+        rest.find(|c: char| !is_word(c)).unwrap_or(rest.len())
+    } else {
+        first.len_utf8()
+    };
+    Some(&rest[..len])
+}
+
+/// Tokens pest tries that are never worth suggesting (whitespace, comments,
+/// and the `_` that can start an identifier)
+const IGNORED_TOKENS: &[&str] = &[" ", "\t", "\r", "\n", "//", "/*", "_", "BUILTIN_RULE"];
+
+/// Whether a displayed pest token is a character range such as `0..9`
+fn is_char_range(token: &str) -> bool {
+    let chars: Vec<char> = token.chars().collect();
+    chars.len() == 4 && chars[1] == '.' && chars[2] == '.'
 }
 
-fn parse_let_stmt(pair: pest::iterators::Pair<Rule>) -> Result<LetStatement, ParseError> {
+/// Describe what the parser would have accepted where it failed.
+///
+/// Where a whole expression, identifier or number is allowed it is named as
+/// such; otherwise the literal keywords and punctuation pest tried are listed.
+fn expected_alternatives(err: &pest::error::Error<Rule>) -> Vec<String> {
+    let positives = match &err.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives.as_slice(),
+        ErrorVariant::CustomError { .. } => &[],
+    };
+
+    let mut expected: Vec<String> = Vec::new();
+    let mut push = |item: String| {
+        if !expected.contains(&item) {
+            expected.push(item);
+        }
+    };
+
+    let described: Vec<&str> = positives.iter().filter_map(|rule| describe_rule(*rule)).collect();
+    if described.is_empty() {
+        if let Some(attempts) = err.parse_attempts() {
+            for token in attempts.expected_tokens().iter().map(|t| t.to_string()) {
+                if !IGNORED_TOKENS.contains(&token.as_str()) && !is_char_range(&token) {
+                    push(format!("`{}`", token));
+                }
+            }
+        }
+    } else {
+        described.into_iter().for_each(|name| push(name.to_string()));
+    }
+
+    if positives.contains(&Rule::EOI) {
+        push("end of input".to_string());
+    }
+    expected
+}
+
+fn describe_rule(rule: Rule) -> Option<&'static str> {
+    match rule {
+        Rule::expr | Rule::or_expr | Rule::and_expr | Rule::not_expr | Rule::cmp_expr
+        | Rule::add_expr | Rule::mul_expr | Rule::unary_expr | Rule::postfix | Rule::primary
+        | Rule::sort_key => Some("expression"),
+        Rule::ident | Rule::col_ref | Rule::col_list | Rule::alias => Some("identifier"),
+        Rule::from_kw => Some("`from`"),
+        Rule::int => Some("integer"),
+        Rule::decimal => Some("decimal"),
+        Rule::string => Some("string"),
+        Rule::interval => Some("interval such as `5m`"),
+        Rule::obj => Some("`{`"),
+        _ => None,
+    }
+}
+
+/// Record a name from a `{ name: ... }` list, rejecting repeats.
+///
+/// The IR keeps these lists in maps, so a repeated name would otherwise
+/// silently replace the earlier entry.
+fn unique_name(seen: &mut HashSet<String>, pair: &Pair<Rule>) -> Result<String, ParseError> {
+    let name = match pair.as_rule() {
+        Rule::string => unquote(pair.as_str()),
+        _ => pair.as_str().to_string(),
+    };
+    if !seen.insert(name.clone()) {
+        return Err(error(pair, format!("Duplicate name `{}`", name)));
+    }
+    Ok(name)
+}
+
+fn parse_pragma(pair: Pair<Rule>) -> Result<Pragma, ParseError> {
+    let span = pair.as_span().into();
+    let obj = pair.into_inner().next().unwrap();
+    Ok(Pragma { options: parse_obj_pairs(obj)?, span })
+}
+
+fn parse_obj_pairs(pair: Pair<Rule>) -> Result<Vec<(String, Value)>, ParseError> {
+    let mut seen = HashSet::new();
+    pair.into_inner()
+        .map(|obj_pair| {
+            let mut inner = obj_pair.into_inner();
+            let key = unique_name(&mut seen, &inner.next().unwrap())?;
+            Ok((key, parse_value(inner.next().unwrap())?))
+        })
+        .collect()
+}
+
+/// Parse a constant value (pragma options and their nested objects/arrays).
+///
+/// Bare identifiers are read as strings, so `execution_mode: substrait`
+/// is equivalent to `execution_mode: "substrait"`.
+fn parse_value(pair: Pair<Rule>) -> Result<Value, ParseError> {
+    match pair.as_rule() {
+        Rule::obj => Ok(Value::Object(parse_obj_pairs(pair)?)),
+        Rule::array => Ok(Value::Array(
+            pair.into_inner().map(parse_value).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Rule::literal => parse_literal_value(pair),
+        Rule::col_ref => {
+            let col = parse_column_ref(pair.clone());
+            match col.table {
+                None => Ok(Value::String(col.column)),
+                Some(table) => Err(error(&pair, format!(
+                    "Expected a constant value, got column {}.{}", table, col.column
+                ))),
+            }
+        }
+        Rule::unary_expr => {
+            let mut inner = pair.clone().into_inner();
+            let first = inner.next().unwrap();
+            if first.as_rule() != Rule::unary_op {
+                return parse_value(first);
+            }
+            let negate = first.as_str() == "-";
+            match parse_value(inner.next().unwrap())? {
+                Value::Int(i) if negate => Ok(Value::Int(-i)),
+                Value::Float(f) if negate => Ok(Value::Float(-f)),
+                v @ (Value::Int(_) | Value::Float(_)) => Ok(v),
+                _ => Err(error(&pair, "Sign applied to a non-numeric value")),
+            }
+        }
+        // Expression layers that wrap a single constant
+        Rule::obj_val | Rule::expr | Rule::or_expr | Rule::and_expr | Rule::not_expr
+        | Rule::cmp_expr | Rule::add_expr | Rule::mul_expr | Rule::postfix | Rule::primary => {
+            let mut inner = pair.clone().into_inner();
+            let first = inner.next()
+                .ok_or_else(|| error(&pair, "Empty value"))?;
+            if inner.next().is_some() {
+                return Err(error(&pair, format!("Expected a constant value, got: {}", pair.as_str().trim_end())));
+            }
+            parse_value(first)
+        }
+        _ => Err(error(&pair, format!("Expected a constant value, got: {}", pair.as_str().trim_end()))),
+    }
+}
+
+fn parse_let_stmt(pair: Pair<Rule>) -> Result<LetStatement, ParseError> {
+    let span = pair.as_span().into();
     let mut inner = pair.into_inner();
     let name = inner.next().unwrap().as_str().to_string();
     let pipeline = parse_pipeline(inner.next().unwrap())?;
-    Ok(LetStatement { name, pipeline })
+    Ok(LetStatement { name, pipeline, span })
 }
 
-fn parse_pipeline(pair: pest::iterators::Pair<Rule>) -> Result<Pipeline, ParseError> {
+fn parse_pipeline(pair: Pair<Rule>) -> Result<Pipeline, ParseError> {
+    let span = pair.as_span().into();
     let mut inner = pair.into_inner();
     let source = parse_source(inner.next().unwrap())?;
 
@@ -75,105 +277,420 @@ fn parse_pipeline(pair: pest::iterators::Pair<Rule>) -> Result<Pipeline, ParseEr
         }
     }
 
-    Ok(Pipeline { source, operators })
+    Ok(Pipeline { source, operators, span })
 }
 
-fn parse_source(pair: pest::iterators::Pair<Rule>) -> Result<Source, ParseError> {
-    let mut inner = pair.into_inner();
+/// Parse a `source` or an `op_source`, whose `from` is optional
+fn parse_source(pair: Pair<Rule>) -> Result<Source, ParseError> {
+    let span = pair.as_span().into();
+    let mut inner = pair.into_inner().skip_while(|p| p.as_rule() == Rule::from_kw);
     let source_body = inner.next().unwrap();
     let alias = inner.next().map(|p| p.as_str().to_string());
 
-    let source_inner = source_body.into_inner().next().unwrap();
-    match source_inner.as_rule() {
-        Rule::ident => {
-            Ok(Source::Table {
+    let mut body = source_body.into_inner();
+    let source_inner = body.next().unwrap();
+    let kind = match source_inner.as_rule() {
+        Rule::ident => match body.next() {
+            // graph(<name>) <alias>
+            Some(graph_alias) => {
+                if alias.is_some() {
+                    return Err(error(&graph_alias, "A graph source takes a single alias"));
+                }
+                SourceKind::Graph {
+                    graph_name: source_inner.as_str().to_string(),
+                    alias: graph_alias.as_str().to_string(),
+                }
+            }
+            None => SourceKind::Table {
                 name: source_inner.as_str().to_string(),
                 alias,
-            })
-        }
-        Rule::pipeline => {
-            Ok(Source::SubQuery {
-                pipeline: Box::new(parse_pipeline(source_inner)?),
-                alias,
-            })
-        }
-        _ => Err(ParseError::Syntax("Invalid source".to_string())),
-    }
+            },
+        },
+        Rule::pipeline => SourceKind::SubQuery {
+            pipeline: Box::new(parse_pipeline(source_inner)?),
+            alias,
+        },
+        _ => return Err(error(&source_inner, "Invalid source")),
+    };
+    Ok(Source { kind, span })
 }
 
-fn parse_operator(pair: pest::iterators::Pair<Rule>) -> Result<Operator, ParseError> {
-    match pair.as_rule() {
+fn parse_operator(pair: Pair<Rule>) -> Result<Operator, ParseError> {
+    let span = pair.as_span().into();
+    let kind = match pair.as_rule() {
         Rule::select_op => {
             let select_list = pair.into_inner().next().unwrap();
             let items: Result<Vec<_>, _> = select_list.into_inner()
                 .map(parse_select_item)
                 .collect();
-            Ok(Operator::Select { items: items? })
+            OperatorKind::Select { items: items? }
         }
         Rule::filter_op => {
             let expr = parse_expr(pair.into_inner().next().unwrap())?;
-            Ok(Operator::Filter { expr })
+            OperatorKind::Filter { expr }
         }
-        Rule::sort_op => {
-            let keys: Result<Vec<_>, _> = pair.into_inner()
-                .map(parse_sort_key)
+        Rule::join_op => {
+            let mut inner = pair.into_inner();
+            let source = parse_source(inner.next().unwrap())?;
+            let on = parse_expr(inner.next().unwrap())?;
+            let join_type = match inner.next() {
+                Some(tail) => Some(parse_join_type(tail.into_inner().next().unwrap())?),
+                None => None,
+            };
+            OperatorKind::Join { source, on, join_type }
+        }
+        Rule::group_op => {
+            let mut inner = pair.into_inner().peekable();
+            let keys = match inner.peek().map(|p| p.as_rule()) {
+                Some(Rule::col_list) => parse_col_list(inner.next().unwrap()),
+                _ => Vec::new(),
+            };
+            let aggs = parse_agg_list(inner.next().unwrap())?;
+            OperatorKind::GroupBy { keys, aggs }
+        }
+        Rule::window_op => {
+            let win_list = pair.into_inner().next().unwrap();
+            let mut seen = HashSet::new();
+            let windows: Result<Vec<_>, _> = win_list.into_inner()
+                .map(|win_def| parse_win_def(win_def, &mut seen))
                 .collect();
-            Ok(Operator::Sort { keys: keys? })
+            OperatorKind::Window { windows: windows? }
+        }
+        Rule::sort_op => {
+            let keys = parse_sort_keys(pair.into_inner().next().unwrap())?;
+            OperatorKind::Sort { keys }
         }
         Rule::take_op => {
-            let limit = pair.into_inner().next().unwrap()
-                .as_str().parse().unwrap();
-            Ok(Operator::Take { limit })
+            let limit = parse_int(pair.into_inner().next().unwrap())?;
+            OperatorKind::Take { limit }
+        }
+        Rule::distinct_op => OperatorKind::Distinct,
+        Rule::union_op => {
+            let mut inner = pair.into_inner().peekable();
+            let all = inner.next_if(|p| p.as_rule() == Rule::union_all).is_some();
+            let source = parse_source(inner.next().unwrap())?;
+            OperatorKind::Union { all, source }
         }
-        Rule::distinct_op => {
-            Ok(Operator::Distinct)
+        Rule::setdiff_op => OperatorKind::Except {
+            source: parse_source(pair.into_inner().next().unwrap())?,
+        },
+        Rule::intersect_op => OperatorKind::Intersect {
+            source: parse_source(pair.into_inner().next().unwrap())?,
+        },
+        Rule::map_op => {
+            let map_list = pair.into_inner().next().unwrap();
+            let mut seen = HashSet::new();
+            let mappings: Result<Vec<_>, ParseError> = map_list.into_inner()
+                .map(|item| {
+                    let mut inner = item.into_inner();
+                    let name = unique_name(&mut seen, &inner.next().unwrap())?;
+                    let expr = parse_expr(inner.next().unwrap())?;
+                    Ok((name, expr))
+                })
+                .collect();
+            OperatorKind::Map { mappings: mappings? }
+        }
+        Rule::expand_op => {
+            let mut inner = pair.into_inner();
+            let expr = parse_expr(inner.next().unwrap())?;
+            let alias = inner.next().map(|p| p.as_str().to_string());
+            OperatorKind::Expand { expr, alias }
+        }
+        Rule::resample_op => {
+            let mut inner = pair.into_inner();
+            let interval = inner.next().unwrap().as_str().to_string();
+            let method = inner.next().unwrap().as_str().to_string();
+            let on = parse_column_ref(inner.next().unwrap());
+            OperatorKind::Resample { interval, method, on }
+        }
+        Rule::agg_op => {
+            let mut inner = pair.into_inner();
+            let group_key = parse_group_key(inner.next().unwrap())?;
+            let aggs = parse_agg_list(inner.next().unwrap())?;
+            OperatorKind::Agg { group_key, aggs }
+        }
+        Rule::knn_op => {
+            let mut inner = pair.into_inner();
+            let query = parse_expr(inner.next().unwrap())?;
+            let k = parse_int(inner.next().unwrap())?;
+
+            let mut on = None;
+            let mut index = None;
+            let mut metric = None;
+            for option in inner {
+                let value = option.clone().into_inner().next().unwrap();
+                match option.as_rule() {
+                    Rule::knn_on => on = Some(parse_column_ref(value)),
+                    Rule::knn_index => index = Some(value.as_str().to_string()),
+                    Rule::knn_metric => metric = Some(value.as_str().to_string()),
+                    _ => {}
+                }
+            }
+
+            OperatorKind::Knn { query, k, on, index, metric }
+        }
+        Rule::rank_op => {
+            let by = parse_expr(pair.into_inner().next().unwrap())?;
+            OperatorKind::Rank { by }
+        }
+        Rule::neighbors_op => {
+            let mut inner = pair.into_inner();
+            let start = parse_expr(inner.next().unwrap())?;
+            let depth = parse_int(inner.next().unwrap())?;
+            let edge = inner.next().map(|p| p.as_str().to_string());
+            OperatorKind::Neighbors { start, depth, edge }
+        }
+        Rule::topk_op => {
+            let mut inner = pair.into_inner();
+            let k = parse_int(inner.next().unwrap())?;
+            let by = parse_expr(inner.next().unwrap())?;
+            OperatorKind::TopK { k, by }
         }
-        _ => Err(ParseError::Syntax(format!("Unknown operator: {:?}", pair.as_rule()))),
+        Rule::sample_op => {
+            let mut inner = pair.into_inner();
+            let fraction_pair = inner.next().unwrap();
+            let fraction = fraction_pair.as_str().parse()
+                .map_err(|_| error(&fraction_pair, format!("Invalid sample fraction: {}", fraction_pair.as_str())))?;
+            let seed = match inner.next() {
+                Some(seed_pair) => Some(parse_int(seed_pair)?),
+                None => None,
+            };
+            OperatorKind::Sample { fraction, seed }
+        }
+        Rule::assert_op => {
+            let mut inner = pair.into_inner();
+            let condition = parse_expr(inner.next().unwrap())?;
+            let message = inner.next().map(|p| unquote(p.as_str()));
+            OperatorKind::Assert { condition, message }
+        }
+        Rule::explain_op => {
+            let mode_pair = pair.into_inner().next().unwrap();
+            let mode = match mode_pair.as_str() {
+                "logical" => ExplainMode::Logical,
+                "physical" => ExplainMode::Physical,
+                "cost" => ExplainMode::Cost,
+                other => return Err(error(&mode_pair, format!("Unknown explain mode: {}", other))),
+            };
+            OperatorKind::Explain { mode }
+        }
+        _ => return Err(error(&pair, format!("Unknown operator: {:?}", pair.as_rule()))),
+    };
+    Ok(Operator { kind, span })
+}
+
+fn parse_join_type(pair: Pair<Rule>) -> Result<JoinType, ParseError> {
+    match pair.as_str() {
+        "inner" => Ok(JoinType::Inner),
+        "left" => Ok(JoinType::Left),
+        "right" => Ok(JoinType::Right),
+        "full" => Ok(JoinType::Full),
+        "semi" => Ok(JoinType::Semi),
+        "anti" => Ok(JoinType::Anti),
+        "cross" => Ok(JoinType::Cross),
+        other => Err(error(&pair, format!("Unknown join type: {}", other))),
     }
 }
 
-fn parse_select_item(pair: pest::iterators::Pair<Rule>) -> Result<SelectItem, ParseError> {
+fn parse_col_list(pair: Pair<Rule>) -> Vec<ColumnRef> {
+    pair.into_inner().map(parse_column_ref).collect()
+}
+
+fn parse_agg_list(pair: Pair<Rule>) -> Result<Vec<(String, FuncCall)>, ParseError> {
+    let mut seen = HashSet::new();
+    pair.into_inner()
+        .map(|item| {
+            let mut inner = item.into_inner();
+            let name = unique_name(&mut seen, &inner.next().unwrap())?;
+            // agg_call wraps a single func_call
+            let call = inner.next().unwrap().into_inner().next().unwrap();
+            Ok((name, parse_func(call)?))
+        })
+        .collect()
+}
+
+fn parse_win_def(pair: Pair<Rule>, seen: &mut HashSet<String>) -> Result<(String, WindowDef), ParseError> {
+    let mut inner = pair.into_inner();
+    let name = unique_name(seen, &inner.next().unwrap())?;
+
+    // win_func: ident ( arg_list? ) over win_spec
+    let win_func = inner.next().unwrap();
+    let span = win_func.as_span().into();
+    let mut func_inner = win_func.into_inner();
+    let func = func_inner.next().unwrap().as_str().to_string();
+
+    let mut args = Vec::new();
+    let mut partition = None;
+    let mut order = None;
+    let mut frame = None;
+
+    for part in func_inner {
+        match part.as_rule() {
+            Rule::arg_list => {
+                args = part.into_inner()
+                    .map(parse_expr)
+                    .collect::<Result<Vec<_>, _>>()?;
+            }
+            Rule::win_spec => {
+                for spec in part.into_inner() {
+                    match spec.as_rule() {
+                        Rule::col_list => partition = Some(parse_col_list(spec)),
+                        Rule::sort_keys => order = Some(parse_sort_keys(spec)?),
+                        Rule::frame_spec => frame = Some(parse_frame_spec(spec)?),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok((name, WindowDef { func, args, partition, order, frame, span }))
+}
+
+fn parse_frame_spec(pair: Pair<Rule>) -> Result<FrameSpec, ParseError> {
+    let span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    let mode = match inner.next().unwrap().as_str() {
+        "rows" => FrameMode::Rows,
+        _ => FrameMode::Range,
+    };
+
+    let mut edges = inner.next().unwrap().into_inner();
+    let start = parse_frame_edge(edges.next().unwrap())?;
+    let end = parse_frame_edge(edges.next().unwrap())?;
+
+    Ok(FrameSpec { mode, start, end, span })
+}
+
+fn parse_frame_edge(pair: Pair<Rule>) -> Result<FrameBound, ParseError> {
+    let text = pair.as_str();
+    match text {
+        "unbounded_preceding" => Ok(FrameBound::UnboundedPreceding),
+        "unbounded_following" => Ok(FrameBound::UnboundedFollowing),
+        "current_row" => Ok(FrameBound::CurrentRow),
+        _ => {
+            let offset = parse_int(pair.into_inner().next().unwrap())?;
+            if text.ends_with("preceding") {
+                Ok(FrameBound::Preceding(offset))
+            } else {
+                Ok(FrameBound::Following(offset))
+            }
+        }
+    }
+}
+
+fn parse_group_key(pair: Pair<Rule>) -> Result<GroupKey, ParseError> {
+    let span = pair.as_span().into();
+    let key = pair.into_inner().next().unwrap();
+    let rule = key.as_rule();
+    let mut inner = key.clone().into_inner();
+    let expr = parse_expr(inner.next().unwrap())?;
+    let first = inner.next().unwrap().as_str().to_string();
+
+    let kind = match rule {
+        Rule::tumbling_key => GroupKeyKind::Tumbling { expr, interval: first },
+        Rule::hopping_key => {
+            let slide = inner.next().unwrap().as_str().to_string();
+            GroupKeyKind::Hopping { expr, size: first, slide }
+        }
+        Rule::session_key => GroupKeyKind::Session { expr, gap: first },
+        _ => return Err(error(&key, format!("Invalid group key: {:?}", rule))),
+    };
+    Ok(GroupKey { kind, span })
+}
+
+fn parse_int(pair: Pair<Rule>) -> Result<i64, ParseError> {
+    pair.as_str().parse()
+        .map_err(|_| error(&pair, format!("Invalid integer: {}", pair.as_str())))
+}
+
+/// Strip the surrounding quotes from a string literal and resolve its escapes
+fn unquote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s[1..s.len()-1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(escaped @ ('"' | '\\')) => out.push(escaped),
+            // Unknown escapes (e.g. in regex patterns) are kept verbatim
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn parse_select_item(pair: Pair<Rule>) -> Result<SelectItem, ParseError> {
+    let span = pair.as_span().into();
     let mut inner = pair.into_inner();
     let first = inner.next().unwrap();
 
-    match first.as_rule() {
-        Rule::wildcard => Ok(SelectItem::Wildcard),
+    let kind = match first.as_rule() {
+        Rule::wildcard => SelectItemKind::Wildcard,
         Rule::expr => {
             let expr = parse_expr(first)?;
             // Check if there's an "as alias" following
             if let Some(alias_pair) = inner.next() {
-                Ok(SelectItem::Aliased {
+                SelectItemKind::Aliased {
                     expr,
                     alias: alias_pair.as_str().to_string(),
-                })
+                }
             } else {
-                Ok(SelectItem::Expr(expr))
+                SelectItemKind::Expr(expr)
             }
         }
-        _ => Err(ParseError::Syntax("Invalid select item".to_string())),
-    }
+        _ => return Err(error(&first, "Invalid select item")),
+    };
+    Ok(SelectItem { kind, span })
 }
 
-fn parse_sort_key(pair: pest::iterators::Pair<Rule>) -> Result<SortKey, ParseError> {
-    let text = pair.as_str();
-    let desc = text.starts_with('-');
+fn parse_sort_keys(pair: Pair<Rule>) -> Result<Vec<SortKey>, ParseError> {
+    pair.into_inner().map(parse_sort_key).collect()
+}
+
+fn parse_sort_key(pair: Pair<Rule>) -> Result<SortKey, ParseError> {
+    let span = pair.as_span().into();
+    let desc = pair.as_str().starts_with('-');
     let expr_pair = pair.into_inner().next().unwrap();
 
     Ok(SortKey {
         expr: parse_expr(expr_pair)?,
         desc,
+        span,
     })
 }
 
-fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
+/// Combine two operands into a binary expression spanning both
+fn binary(op: BinOp, left: Expr, right: Expr) -> Expr {
+    let span = Span::new(left.span.start, right.span.end);
+    Expr {
+        kind: ExprKind::BinaryOp {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        span,
+    }
+}
+
+fn parse_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
     match pair.as_rule() {
         // Expression hierarchy - unwrap single-element rules
         Rule::expr => {
-            let mut inner = pair.into_inner();
+            let mut inner = pair.clone().into_inner();
             if let Some(first) = inner.next() {
                 parse_expr(first)
             } else {
-                Err(ParseError::Syntax("Empty expression".to_string()))
+                Err(error(&pair, "Empty expression"))
             }
         }
         // Binary operators: OR, AND, comparison
@@ -182,6 +699,11 @@ fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
             let mut left = parse_expr(inner.next().unwrap())?;
 
             while let Some(op_pair) = inner.next() {
+                if op_pair.as_rule() == Rule::in_op {
+                    left = parse_membership(left, op_pair.into_inner().next().unwrap())?;
+                    continue;
+                }
+
                 let op = match op_pair.as_rule() {
                     Rule::or_op => BinOp::Or,
                     Rule::and_op => BinOp::And,
@@ -195,37 +717,37 @@ fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
                             ">=" => BinOp::Ge,
                             "like" => BinOp::Like,
                             "ilike" => BinOp::ILike,
-                            _ => return Err(ParseError::Syntax(format!("Unknown comparison operator: {}", op_pair.as_str()))),
+                            _ => return Err(error(&op_pair, format!("Unknown comparison operator: {}", op_pair.as_str()))),
                         }
                     }
-                    _ => return Err(ParseError::Syntax(format!("Expected operator, got: {:?}", op_pair.as_rule()))),
+                    _ => return Err(error(&op_pair, format!("Expected operator, got: {:?}", op_pair.as_rule()))),
                 };
 
-                let right_pair = inner.next().ok_or_else(|| ParseError::Syntax("Missing right operand".to_string()))?;
+                let right_pair = inner.next().ok_or_else(|| error(&op_pair, "Missing right operand"))?;
                 let right = parse_expr(right_pair)?;
 
-                left = Expr::BinaryOp {
-                    op,
-                    left: Box::new(left),
-                    right: Box::new(right),
-                };
+                left = binary(op, left, right);
             }
 
             Ok(left)
         }
         // Unary NOT operator
         Rule::not_expr => {
+            let span = pair.as_span().into();
             let mut inner = pair.into_inner();
             let first = inner.next().unwrap();
 
             // Check if it's a NOT operator or just the expression
             if first.as_rule() == Rule::not_op {
                 // It's a NOT, next should be the expression
-                let expr_pair = inner.next().ok_or_else(|| ParseError::Syntax("Missing expression after NOT".to_string()))?;
+                let expr_pair = inner.next().ok_or_else(|| error(&first, "Missing expression after NOT"))?;
                 let expr = parse_expr(expr_pair)?;
-                Ok(Expr::UnaryOp {
-                    op: UnOp::Not,
-                    expr: Box::new(expr),
+                Ok(Expr {
+                    kind: ExprKind::UnaryOp {
+                        op: UnOp::Not,
+                        expr: Box::new(expr),
+                    },
+                    span,
                 })
             } else {
                 // No NOT, just parse the expression
@@ -248,72 +770,201 @@ fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
                             "*" => BinOp::Mul,
                             "/" => BinOp::Div,
                             "%" => BinOp::Mod,
-                            _ => return Err(ParseError::Syntax(format!("Unknown operator: {}", op_pair.as_str()))),
+                            _ => return Err(error(&op_pair, format!("Unknown operator: {}", op_pair.as_str()))),
                         }
                     }
-                    _ => return Err(ParseError::Syntax(format!("Expected operator, got: {:?}", op_pair.as_rule()))),
+                    _ => return Err(error(&op_pair, format!("Expected operator, got: {:?}", op_pair.as_rule()))),
                 };
 
                 // Next must be the right operand
-                let right_pair = inner.next().ok_or_else(|| ParseError::Syntax("Missing right operand".to_string()))?;
+                let right_pair = inner.next().ok_or_else(|| error(&op_pair, "Missing right operand"))?;
                 let right = parse_expr(right_pair)?;
 
-                left = Expr::BinaryOp {
-                    op,
-                    left: Box::new(left),
-                    right: Box::new(right),
-                };
+                left = binary(op, left, right);
             }
 
             Ok(left)
         }
-        Rule::unary_expr | Rule::postfix => {
+        Rule::unary_expr => {
+            let span = pair.as_span().into();
             let mut inner = pair.into_inner();
-            if let Some(first) = inner.next() {
-                parse_expr(first)
-            } else {
-                Err(ParseError::Syntax("Empty expression".to_string()))
+            let first = inner.next().unwrap();
+            if first.as_rule() != Rule::unary_op {
+                return parse_expr(first);
             }
+
+            let operand_pair = inner.next().unwrap();
+            let parenthesized = operand_pair.as_str().starts_with('(');
+            let operand = parse_expr(operand_pair)?;
+            if first.as_str() == "+" {
+                return Ok(operand);
+            }
+
+            // `-5` is a negative literal; `-(5)` stays a negation
+            let kind = match operand.kind {
+                ExprKind::Literal(Value::Int(i)) if !parenthesized => ExprKind::Literal(Value::Int(-i)),
+                ExprKind::Literal(Value::Float(f)) if !parenthesized => ExprKind::Literal(Value::Float(-f)),
+                kind => ExprKind::UnaryOp {
+                    op: UnOp::Neg,
+                    expr: Box::new(Expr { kind, span: operand.span }),
+                },
+            };
+            Ok(Expr { kind, span })
         }
-        Rule::primary => {
+        Rule::postfix => parse_postfix(pair),
+        Rule::primary | Rule::obj_val => {
             let inner = pair.into_inner().next().unwrap();
             match inner.as_rule() {
                 Rule::literal => parse_literal(inner),
                 Rule::col_ref => parse_col_ref(inner),
                 Rule::func_call => parse_func_call(inner),
                 Rule::expr => parse_expr(inner),
-                _ => Err(ParseError::Syntax(format!("Invalid primary: {:?}", inner.as_rule()))),
+                Rule::obj => parse_object(inner),
+                Rule::array => parse_array(inner),
+                Rule::vector_lit => parse_vector(inner),
+                _ => Err(error(&inner, format!("Invalid primary: {:?}", inner.as_rule()))),
             }
         }
         Rule::literal => parse_literal(pair),
         Rule::col_ref => parse_col_ref(pair),
         Rule::func_call => parse_func_call(pair),
-        _ => Err(ParseError::Syntax(format!("Cannot parse expr: {:?}", pair.as_rule()))),
+        Rule::obj => parse_object(pair),
+        Rule::array => parse_array(pair),
+        _ => Err(error(&pair, format!("Cannot parse expr: {:?}", pair.as_rule()))),
+    }
+}
+
+/// A primary followed by field accesses, indexing and calls
+fn parse_postfix(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let mut inner = pair.into_inner();
+    let primary = inner.next().unwrap();
+    let parenthesized = primary.as_str().starts_with('(');
+    let mut expr = parse_expr(primary)?;
+
+    for tail in inner {
+        let span = Span::new(expr.span.start, Span::from(tail.as_span()).end);
+        let kind = match tail.as_str().chars().next() {
+            Some('.') => ExprKind::FieldAccess {
+                field: tail.into_inner().next().unwrap().as_str().to_string(),
+                expr: Box::new(expr),
+            },
+            Some('[') => ExprKind::Index {
+                index: Box::new(parse_expr(tail.into_inner().next().unwrap())?),
+                expr: Box::new(expr),
+            },
+            _ => {
+                // Only a bare name can be called: `lower(name)`
+                let name = match expr.kind {
+                    ExprKind::Column(ColumnRef { table: None, column, .. }) if !parenthesized => column,
+                    _ => return Err(error(&tail, "Only named functions can be called")),
+                };
+                let args = match tail.into_inner().next() {
+                    Some(arg_list) => arg_list.into_inner()
+                        .map(parse_expr)
+                        .collect::<Result<Vec<_>, _>>()?,
+                    None => Vec::new(),
+                };
+                ExprKind::FuncCall(FuncCall { name, args, span })
+            }
+        };
+        expr = Expr { kind, span };
     }
+
+    Ok(expr)
 }
 
-fn parse_literal(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
+/// `left in [start, end]`, `left in [start, end)` or `left in (a, b, ...)`
+fn parse_membership(left: Expr, pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = Span::new(left.span.start, Span::from(pair.as_span()).end);
+    let text = pair.as_str().trim_end();
+    let is_range = text.starts_with('[');
+    let inclusive = text.ends_with(']');
+    let mut items = pair.into_inner()
+        .map(parse_expr)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+
+    let kind = if is_range {
+        ExprKind::InRange {
+            expr: Box::new(left),
+            start: Box::new(items.next().unwrap()),
+            end: Box::new(items.next().unwrap()),
+            inclusive,
+        }
+    } else {
+        ExprKind::InSet { expr: Box::new(left), set: items.collect() }
+    };
+    Ok(Expr { kind, span })
+}
+
+fn parse_object(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = pair.as_span().into();
+    let mut seen = HashSet::new();
+    let fields = pair.into_inner()
+        .map(|obj_pair| {
+            let mut inner = obj_pair.into_inner();
+            let key = unique_name(&mut seen, &inner.next().unwrap())?;
+            Ok((key, parse_expr(inner.next().unwrap())?))
+        })
+        .collect::<Result<Vec<_>, ParseError>>()?;
+    Ok(Expr { kind: ExprKind::Object(fields), span })
+}
+
+fn parse_array(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = pair.as_span().into();
+    let elements = pair.into_inner()
+        .map(parse_expr)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Expr { kind: ExprKind::Array(elements), span })
+}
+
+fn parse_literal(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = pair.as_span().into();
+    Ok(Expr { kind: ExprKind::Literal(parse_literal_value(pair)?), span })
+}
+
+fn parse_literal_value(pair: Pair<Rule>) -> Result<Value, ParseError> {
     let inner = pair.into_inner().next().unwrap();
     let value = match inner.as_rule() {
-        Rule::int => Value::Int(inner.as_str().parse().unwrap()),
-        Rule::decimal => Value::Float(inner.as_str().parse().unwrap()),
+        Rule::int => Value::Int(inner.as_str().parse()
+            .map_err(|_| error(&inner, format!("Integer out of range: {}", inner.as_str())))?),
+        Rule::decimal | Rule::exp => Value::Float(inner.as_str().parse().unwrap()),
         Rule::string => {
             let s = inner.as_str();
-            Value::String(s[1..s.len()-1].to_string()) // Remove quotes
+            Value::String(unquote(s))
         }
+        Rule::bytes_lit => {
+            let hex = &inner.as_str()[2..];
+            if hex.len() & 1 == 1 {
+                return Err(error(&inner, "Bytes literal needs an even number of hex digits"));
+            }
+            Value::Bytes((0..hex.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect())
+        }
+        Rule::date_lit => Value::Date(inner.as_str().to_string()),
+        Rule::time_lit => Value::Time(inner.as_str().to_string()),
+        Rule::ts_lit => Value::Timestamp(inner.as_str().to_string()),
         _ => {
             match inner.as_str() {
                 "true" => Value::Bool(true),
                 "false" => Value::Bool(false),
                 "null" => Value::Null,
-                _ => return Err(ParseError::Syntax("Invalid literal".to_string())),
+                _ => return Err(error(&inner, "Invalid literal")),
             }
         }
     };
-    Ok(Expr::Literal(value))
+    Ok(value)
+}
+
+fn parse_col_ref(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let col = parse_column_ref(pair);
+    let span = col.span;
+    Ok(Expr { kind: ExprKind::Column(col), span })
 }
 
-fn parse_col_ref(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
+fn parse_column_ref(pair: Pair<Rule>) -> ColumnRef {
+    let span = pair.as_span().into();
     let parts: Vec<_> = pair.into_inner().collect();
 
     let (table, column) = if parts.len() == 2 {
@@ -322,10 +973,26 @@ fn parse_col_ref(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError>
         (None, parts[0].as_str().to_string())
     };
 
-    Ok(Expr::Column(ColumnRef { table, column }))
+    ColumnRef { table, column, span }
 }
 
-fn parse_func_call(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError> {
+fn parse_vector(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = pair.as_span().into();
+    let values = pair.into_inner()
+        .map(|p| p.as_str().parse()
+            .map_err(|_| error(&p, format!("Invalid vector component: {}", p.as_str()))))
+        .collect::<Result<Vec<f64>, _>>()?;
+    Ok(Expr { kind: ExprKind::Vector(values), span })
+}
+
+fn parse_func_call(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let func = parse_func(pair)?;
+    let span = func.span;
+    Ok(Expr { kind: ExprKind::FuncCall(func), span })
+}
+
+fn parse_func(pair: Pair<Rule>) -> Result<FuncCall, ParseError> {
+    let span = pair.as_span().into();
     let mut inner = pair.into_inner();
     let name = inner.next().unwrap().as_str().to_string();
 
@@ -337,7 +1004,7 @@ fn parse_func_call(pair: pest::iterators::Pair<Rule>) -> Result<Expr, ParseError
         vec![]
     };
 
-    Ok(Expr::FuncCall(FuncCall { name, args }))
+    Ok(FuncCall { name, args, span })
 }
 
 #[cfg(test)]
@@ -350,6 +1017,240 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    fn parse_ops(source: &str) -> Vec<mlql_ir::Operator> {
+        parse(source)
+            .unwrap_or_else(|e| panic!("Failed to parse {:?}: {}", source, e))
+            .to_ir()
+            .pipeline
+            .ops
+    }
+
+    #[test]
+    fn test_parse_sort_keys() {
+        let ops = parse_ops("from users | sort -age, name");
+        match &ops[0] {
+            mlql_ir::Operator::Sort { keys } => {
+                assert_eq!(keys.len(), 2);
+                assert!(keys[0].desc);
+                assert!(!keys[1].desc);
+            }
+            other => panic!("Expected Sort, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_join() {
+        let ops = parse_ops("from users u | join from orders o on u.id == o.user_id type: left");
+        match &ops[0] {
+            mlql_ir::Operator::Join { source, join_type, .. } => {
+                match source {
+                    mlql_ir::Source::Table { name, alias } => {
+                        assert_eq!(name, "orders");
+                        assert_eq!(alias.as_deref(), Some("o"));
+                    }
+                    other => panic!("Expected Table source, got {:?}", other),
+                }
+                assert!(matches!(join_type, Some(mlql_ir::JoinType::Left)));
+            }
+            other => panic!("Expected Join, got {:?}", other),
+        }
+
+        // Without an alias, `on` must not be taken as the alias
+        let ops = parse_ops("from users | join from orders on id == user_id");
+        assert!(matches!(&ops[0], mlql_ir::Operator::Join { join_type: None, .. }));
+
+        // Join sources may leave out `from`, but not a table whose name starts with it
+        let program = parse(
+            "from (from orders | group by user_id { total: sum(amount) }) totals \
+             | join (from users | filter age > 18) adults on totals.user_id == adults.id \
+             | join fromage on adults.id == fromage.user_id"
+        ).unwrap().to_ir();
+        assert!(matches!(&program.pipeline.source, mlql_ir::Source::SubPipeline { alias: Some(alias), .. } if alias == "totals"));
+        match &program.pipeline.ops[0] {
+            mlql_ir::Operator::Join { source: mlql_ir::Source::SubPipeline { pipeline, alias }, .. } => {
+                assert_eq!(alias.as_deref(), Some("adults"));
+                assert_eq!(pipeline.ops.len(), 1);
+            }
+            other => panic!("Expected Join of a sub-pipeline, got {:?}", other),
+        }
+        assert!(matches!(&program.pipeline.ops[1], mlql_ir::Operator::Join { source: mlql_ir::Source::Table { name, alias: None }, .. } if name == "fromage"));
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        let ops = parse_ops("from sales | group by region, s.product { revenue: sum(price * qty), n: count() }");
+        match &ops[0] {
+            mlql_ir::Operator::GroupBy { keys, aggs } => {
+                assert_eq!(keys.len(), 2);
+                assert_eq!(keys[1].table.as_deref(), Some("s"));
+                assert_eq!(aggs["revenue"].func, "sum");
+                assert_eq!(aggs["revenue"].args.len(), 1);
+                assert!(aggs["n"].args.is_empty());
+            }
+            other => panic!("Expected GroupBy, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_window() {
+        let ops = parse_ops(
+            "from trades | window { rn: row_number() over part: symbol order: -ts, \
+             total: sum(qty) over order: ts frame: rows [unbounded_preceding, 2 following] }"
+        );
+        match &ops[0] {
+            mlql_ir::Operator::Window { windows } => {
+                let rn = &windows["rn"];
+                assert_eq!(rn.func, "row_number");
+                assert_eq!(rn.partition.as_ref().unwrap().len(), 1);
+                assert!(rn.order.as_ref().unwrap()[0].desc);
+                assert!(rn.frame.is_none());
+
+                let frame = windows["total"].frame.as_ref().unwrap();
+                assert!(matches!(frame.mode, mlql_ir::FrameMode::Rows));
+                assert!(matches!(frame.start, mlql_ir::FrameBound::UnboundedPreceding));
+                assert!(matches!(frame.end, mlql_ir::FrameBound::Following(2)));
+            }
+            other => panic!("Expected Window, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_set_and_shape_operators() {
+        let ops = parse_ops(
+            "from events | union all archived | union (from staged | filter ok) s \
+             | except from deleted d | intersect from visible \
+             | map { doubled: x * 2 } | expand tags as tag | distinct"
+        );
+        assert!(matches!(&ops[0], mlql_ir::Operator::Union { all: true, source: mlql_ir::Source::Table { name, alias: None } } if name == "archived"));
+        match &ops[1] {
+            mlql_ir::Operator::Union { all: false, source: mlql_ir::Source::SubPipeline { pipeline, alias } } => {
+                assert_eq!(alias.as_deref(), Some("s"));
+                assert_eq!(pipeline.ops.len(), 1);
+            }
+            other => panic!("Expected Union of a sub-pipeline, got {:?}", other),
+        }
+        assert!(matches!(&ops[2], mlql_ir::Operator::Except { source: mlql_ir::Source::Table { alias: Some(alias), .. } } if alias == "d"));
+        assert!(matches!(&ops[3], mlql_ir::Operator::Intersect { source: mlql_ir::Source::Table { name, .. } } if name == "visible"));
+        match &ops[4] {
+            mlql_ir::Operator::Map { mappings } => assert!(mappings.contains_key("doubled")),
+            other => panic!("Expected Map, got {:?}", other),
+        }
+        match &ops[5] {
+            mlql_ir::Operator::Expand { alias, .. } => assert_eq!(alias.as_deref(), Some("tag")),
+            other => panic!("Expected Expand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_time_series_operators() {
+        let ops = parse_ops(
+            "from metrics | resample 5m method: ffill on: ts \
+             | agg by hopping(ts, 10m, 5m) { avg_v: avg(v) } \
+             | agg by session(ts, 30s) { n: count() } \
+             | agg by tumbling(ts, 1h) { total: sum(v) }"
+        );
+        match &ops[0] {
+            mlql_ir::Operator::Resample { interval, method, on, .. } => {
+                assert_eq!(interval, "5m");
+                assert_eq!(method, "ffill");
+                assert_eq!(on.column, "ts");
+            }
+            other => panic!("Expected Resample, got {:?}", other),
+        }
+        match &ops[1] {
+            mlql_ir::Operator::Agg { group_key: mlql_ir::GroupKey::Hopping { size, slide, .. }, aggs } => {
+                assert_eq!(size, "10m");
+                assert_eq!(slide, "5m");
+                assert!(aggs.contains_key("avg_v"));
+            }
+            other => panic!("Expected hopping Agg, got {:?}", other),
+        }
+        assert!(matches!(&ops[2], mlql_ir::Operator::Agg { group_key: mlql_ir::GroupKey::Session { gap, .. }, .. } if gap == "30s"));
+        assert!(matches!(&ops[3], mlql_ir::Operator::Agg { group_key: mlql_ir::GroupKey::Tumbling { interval, .. }, .. } if interval == "1h"));
+    }
+
+    #[test]
+    fn test_parse_search_and_graph_operators() {
+        let ops = parse_ops(
+            "from docs | knn q: <0.1, 0.2, 0.3> k: 10 metric: cosine \
+             | knn q: embedding k: 5 index: docs_hnsw \
+             | knn q: <1, 0> k: 2 on: d.embedding metric: ip \
+             | rank by score | topk 3 by score \
+             | neighbors start: 42 depth: 3 edge: follows"
+        );
+        match &ops[0] {
+            mlql_ir::Operator::Knn { query, k, on, index, metric } => {
+                assert!(matches!(query, mlql_ir::Expr::Vector { values } if values.len() == 3));
+                assert_eq!(*k, 10);
+                assert!(on.is_none());
+                assert!(index.is_none());
+                assert_eq!(metric.as_deref(), Some("cosine"));
+            }
+            other => panic!("Expected Knn, got {:?}", other),
+        }
+        match &ops[1] {
+            mlql_ir::Operator::Knn { index, metric, .. } => {
+                assert_eq!(index.as_deref(), Some("docs_hnsw"));
+                assert!(metric.is_none());
+            }
+            other => panic!("Expected Knn, got {:?}", other),
+        }
+        match &ops[2] {
+            mlql_ir::Operator::Knn { on: Some(on), metric, .. } => {
+                assert_eq!((on.table.as_deref(), on.column.as_str()), (Some("d"), "embedding"));
+                assert_eq!(metric.as_deref(), Some("ip"));
+            }
+            other => panic!("Expected Knn on a column, got {:?}", other),
+        }
+        assert!(matches!(ops[3], mlql_ir::Operator::Rank { .. }));
+        assert!(matches!(ops[4], mlql_ir::Operator::TopK { k: 3, .. }));
+        assert!(matches!(&ops[5], mlql_ir::Operator::Neighbors { depth: 3, edge: Some(edge), .. } if edge == "follows"));
+    }
+
+    #[test]
+    fn test_parse_control_operators() {
+        let ops = parse_ops(
+            "from users | sample 0.25 seed: 7 | assert age >= 0 \"ages must be positive\" | explain cost"
+        );
+        assert!(matches!(ops[0], mlql_ir::Operator::Sample { seed: Some(7), .. }));
+        match &ops[1] {
+            mlql_ir::Operator::Assert { message, .. } => {
+                assert_eq!(message.as_deref(), Some("ages must be positive"));
+            }
+            other => panic!("Expected Assert, got {:?}", other),
+        }
+        assert!(matches!(ops[2], mlql_ir::Operator::Explain { mode: mlql_ir::ExplainMode::Cost }));
+    }
+
+    #[test]
+    fn test_parse_postfix_and_membership_expressions() {
+        use mlql_ir::{Expr, Value};
+
+        let ops = parse_ops(
+            r#"from events | map { a: lower(name), b: payload.user.id, c: tags[0], d: -5, e: -(5),
+               f: x in [1, 10), g: kind in ("a", "b\"c"), h: 0x0aff, i: 2024-01-15T10:00:00Z }"#
+        );
+        let mappings = match &ops[0] {
+            mlql_ir::Operator::Map { mappings } => mappings,
+            other => panic!("Expected Map, got {:?}", other),
+        };
+
+        assert!(matches!(&mappings["a"], Expr::FuncCall { func, args } if func == "lower" && args.len() == 1));
+        assert!(matches!(&mappings["b"], Expr::FieldAccess { expr, field }
+            if field == "id" && matches!(&**expr, Expr::Column { col } if col.table.as_deref() == Some("payload"))));
+        assert!(matches!(&mappings["c"], Expr::Index { .. }));
+        assert!(matches!(&mappings["d"], Expr::Literal { value: Value::Int(-5) }));
+        assert!(matches!(&mappings["e"], Expr::UnaryOp { .. }));
+        assert!(matches!(&mappings["f"], Expr::InRange { inclusive: false, .. }));
+        assert!(matches!(&mappings["g"], Expr::InSet { set, .. }
+            if matches!(&set[1], Expr::Literal { value: Value::String(s) } if s == "b\"c")));
+        assert!(matches!(&mappings["h"], Expr::Literal { value: Value::Bytes(b) } if b == &[0x0a, 0xff]));
+        assert!(matches!(&mappings["i"], Expr::Literal { value: Value::Timestamp(_) }));
+
+        assert!(parse("from t | filter (lower)(name)").is_err());
+        assert!(parse("from t | filter x == 0xabc").is_err());
+    }
+
     #[test]
     fn test_parse_binary_expr() {
         // Debug what Pest generates for binary operators
@@ -376,4 +1277,123 @@ mod tests {
             print_pairs(&inner, depth + 1);
         }
     }
+
+    #[test]
+    fn test_parse_pragma() {
+        let program = parse(
+            r#"pragma { timeout: 30000, "cache": true, budget: {rows_out: 1000},
+                        execution_mode: sql, tags: ["a", "b"], offset: -5 }
+               from users"#
+        ).unwrap();
+        let options = program.pragma.as_ref().unwrap().options.clone();
+        assert_eq!(options.len(), 6);
+        assert!(matches!(&options[3], (k, Value::String(v)) if k == "execution_mode" && v == "sql"));
+        assert!(matches!(&options[4], (_, Value::Array(items)) if items.len() == 2));
+        assert!(matches!(options[5], (_, Value::Int(-5))));
+
+        let pragma = program.to_ir().pragma.unwrap();
+        assert_eq!(pragma.timeout_ms(), Some(30000));
+        assert_eq!(pragma.rows_out(), Some(1000));
+        assert_eq!(pragma.execution_mode(), Some("sql"));
+        // Nothing caches results, so `cache` is ignored like any other unknown key
+        assert_eq!(pragma.unknown_keys(), vec!["cache", "offset", "tags"]);
+    }
+
+    #[test]
+    fn test_parse_pragma_rejects_expressions() {
+        assert!(parse("pragma { timeout: 1000 * 30 } from users").is_err());
+        assert!(parse("pragma { timeout: t.limit } from users").is_err());
+    }
+
+    #[test]
+    fn test_parse_let_statements() {
+        let program = parse(
+            "let active = from users | filter is_active == true;
+             let recent = from active a | sort -created_at
+             from recent | join from orders on recent.id == orders.user_id"
+        ).unwrap();
+        assert_eq!(program.lets.len(), 2);
+        assert_eq!(program.lets[0].name, "active");
+        assert!(matches!(
+            &program.lets[1].pipeline.source.kind,
+            SourceKind::Table { name, alias: Some(a) } if name == "active" && a == "a"
+        ));
+        assert!(matches!(&program.pipeline.source.kind, SourceKind::Table { name, alias: None } if name == "recent"));
+    }
+
+    fn diagnostic(source: &str) -> Diagnostic {
+        parse(source).expect_err("should fail to parse").diagnostic().clone()
+    }
+
+    #[test]
+    fn test_spans_cover_source_text() {
+        let source = "from users | filter age > 18 | select [name, age * 2 as double_age]";
+        let program = parse(source).unwrap();
+        let text = |span: Span| &source[span.start..span.end];
+
+        assert_eq!(text(program.pipeline.source.span), "from users");
+        assert_eq!(text(program.pipeline.operators[0].span), "filter age > 18");
+        match &program.pipeline.operators[1].kind {
+            OperatorKind::Select { items } => {
+                assert_eq!(text(items[1].span), "age * 2 as double_age");
+                match &items[1].kind {
+                    SelectItemKind::Aliased { expr, .. } => assert_eq!(text(expr.span), "age * 2"),
+                    other => panic!("Expected Aliased, got {:?}", other),
+                }
+            }
+            other => panic!("Expected Select, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_syntax_error_diagnostic() {
+        let diag = diagnostic("from users | selec [name]");
+        assert_eq!((diag.line, diag.column, diag.end_column), (1, 14, 19));
+        assert_eq!(diag.message, "Unexpected `selec`");
+        assert!(diag.expected.contains(&"`select`".to_string()));
+        assert_eq!(
+            diag.render().lines().take(5).collect::<Vec<_>>(),
+            vec![
+                "error: Unexpected `selec`",
+                " --> 1:14",
+                "  |",
+                "1 | from users | selec [name]",
+                "  |              ^^^^^",
+            ]
+        );
+
+        let diag = diagnostic("from users\n  | filter\n  | take 5");
+        assert_eq!((diag.line, diag.column), (3, 3));
+        assert_eq!(diag.expected, vec!["expression"]);
+
+        let diag = diagnostic("from users | take");
+        assert_eq!(diag.message, "Unexpected end of input");
+        assert_eq!(diag.expected, vec!["integer"]);
+    }
+
+    #[test]
+    fn test_duplicate_names_are_rejected() {
+        let source = "from sales | group by region { n: count(), n: sum(qty) }";
+        let diag = diagnostic(source);
+        assert_eq!(diag.message, "Duplicate name `n`");
+        assert_eq!(&source[diag.span.start..diag.span.end], "n");
+        assert_eq!(diag.column, 44);
+
+        assert!(parse("from t | map { a: 1, a: 2 }").is_err());
+        assert!(parse("pragma { timeout: 1, timeout: 2 } from t").is_err());
+    }
+
+    #[test]
+    fn test_source_map_locates_operators() {
+        let source = "let a = from users | take 5\nfrom a | filter x > 1";
+        let program = parse(source).unwrap();
+        let map = program.source_map();
+
+        let location = mlql_ir::OpLocation { binding: Some(0), op: Some(0) };
+        let span = map.span(&location).unwrap();
+        assert_eq!(&source[span.start..span.end], "take 5");
+
+        let diag = map.diagnostic(source, &mlql_ir::OpLocation { binding: None, op: Some(0) }, "Unknown column `x`");
+        assert_eq!((diag.line, diag.column), (2, 10));
+    }
 }
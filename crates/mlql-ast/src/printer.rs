@@ -0,0 +1,724 @@
+//! Render IR back to MLQL source
+//!
+//! [`print`] emits canonical MLQL text for an [`ir::Program`]: the pragma on
+//! its own line, one `let` per line, and the main pipeline with one operator
+//! per line. Map keys are sorted and parentheses are only added where the
+//! grammar needs them, so printing is idempotent and parsing the output
+//! lowers back to the same IR.
+//!
+//! A few IR values have no MLQL spelling and do not round-trip: non-finite
+//! floats, `i64::MIN`, empty byte strings, names that are not identifiers,
+//! and literal arrays/objects (which re-parse as `Expr::Array` /
+//! `Expr::Object`).
+
+use std::collections::HashMap;
+
+use mlql_ir::{self as ir};
+
+/// Print an IR program as canonical MLQL source
+pub fn print(program: &ir::Program) -> String {
+    let mut out = String::new();
+
+    if let Some(pragma) = &program.pragma {
+        out.push_str(&format!("pragma {}\n", object(&pragma.options, value)));
+    }
+    for binding in &program.lets {
+        out.push_str(&format!("let {} = {};\n", binding.name, pipeline(&binding.pipeline, true)));
+    }
+    out.push_str(&pipeline(&program.pipeline, true));
+    out
+}
+
+/// Top-level pipelines put each operator on its own line; nested ones stay inline
+fn pipeline(pipeline: &ir::Pipeline, multiline: bool) -> String {
+    let separator = if multiline { "\n  | " } else { " | " };
+    let mut out = source(&pipeline.source);
+    for op in &pipeline.ops {
+        out.push_str(separator);
+        out.push_str(&operator(op));
+    }
+    out
+}
+
+fn source(source: &ir::Source) -> String {
+    let (body, alias) = match source {
+        ir::Source::Table { name, alias } => (name.clone(), alias.as_deref()),
+        ir::Source::Graph { graph_name, alias } => (format!("graph({})", graph_name), Some(alias.as_str())),
+        ir::Source::SubPipeline { pipeline: inner, alias } => {
+            (format!("({})", pipeline(inner, false)), alias.as_deref())
+        }
+    };
+    match alias {
+        Some(alias) => format!("from {} {}", body, alias),
+        None => format!("from {}", body),
+    }
+}
+
+fn operator(op: &ir::Operator) -> String {
+    match op {
+        ir::Operator::Select { projections } => {
+            let items: Vec<String> = projections.iter()
+                .map(|p| match p {
+                    ir::Projection::Expr(ir::Expr::Column { col }) if col.table.is_none() && col.column == "*" => {
+                        "*".to_string()
+                    }
+                    ir::Projection::Expr(e) => expr(e),
+                    ir::Projection::Aliased { expr: e, alias } => format!("{} as {}", expr(e), alias),
+                })
+                .collect();
+            format!("select [{}]", items.join(", "))
+        }
+        ir::Operator::Filter { condition } => format!("filter {}", expr(condition)),
+        ir::Operator::Join { source: s, on, join_type } => {
+            let mut out = format!("join {} on {}", source(s), expr(on));
+            if let Some(join_type) = join_type {
+                out.push_str(&format!(" type: {}", join_type_name(join_type)));
+            }
+            out
+        }
+        ir::Operator::GroupBy { keys, aggs } => {
+            let keys = columns(keys);
+            let space = if keys.is_empty() { "" } else { " " };
+            format!("group by {}{}{{ {} }}", keys, space, agg_list(aggs))
+        }
+        ir::Operator::Window { windows } => {
+            let defs: Vec<String> = sorted(windows).into_iter()
+                .map(|(name, def)| format!("{}: {}", name, window_def(def)))
+                .collect();
+            format!("window {{ {} }}", defs.join(", "))
+        }
+        ir::Operator::Sort { keys } => format!("sort {}", sort_keys(keys)),
+        ir::Operator::Take { limit } => format!("take {}", limit),
+        ir::Operator::Distinct => "distinct".to_string(),
+        ir::Operator::Union { all: true, source: s } => format!("union all {}", source(s)),
+        ir::Operator::Union { all: false, source: s } => format!("union {}", source(s)),
+        ir::Operator::Except { source: s } => format!("except {}", source(s)),
+        ir::Operator::Intersect { source: s } => format!("intersect {}", source(s)),
+        ir::Operator::Map { mappings } => {
+            let items: Vec<String> = sorted(mappings).into_iter()
+                .map(|(name, e)| format!("{}: {}", name, expr(e)))
+                .collect();
+            format!("map {{ {} }}", items.join(", "))
+        }
+        ir::Operator::Expand { expr: e, alias } => match alias {
+            Some(alias) => format!("expand {} as {}", expr(e), alias),
+            None => format!("expand {}", expr(e)),
+        },
+        // The grammar cannot name fill columns, so printed programs infer them
+        ir::Operator::Resample { interval, method, on, .. } => {
+            format!("resample {} method: {} on: {}", interval, method, column(on))
+        }
+        ir::Operator::Agg { group_key, aggs } => {
+            let key = match group_key {
+                ir::GroupKey::Tumbling { expr: e, interval } => format!("tumbling({}, {})", expr(e), interval),
+                ir::GroupKey::Hopping { expr: e, size, slide } => format!("hopping({}, {}, {})", expr(e), size, slide),
+                ir::GroupKey::Session { expr: e, gap } => format!("session({}, {})", expr(e), gap),
+            };
+            format!("agg by {} {{ {} }}", key, agg_list(aggs))
+        }
+        ir::Operator::Knn { query, k, on, index, metric } => {
+            let mut out = format!("knn q: {} k: {}", expr(query), k);
+            if let Some(on) = on {
+                out.push_str(&format!(" on: {}", column(on)));
+            }
+            if let Some(index) = index {
+                out.push_str(&format!(" index: {}", index));
+            }
+            if let Some(metric) = metric {
+                out.push_str(&format!(" metric: {}", metric));
+            }
+            out
+        }
+        ir::Operator::Rank { by } => format!("rank by {}", expr(by)),
+        ir::Operator::Neighbors { start, depth, edge } => {
+            let mut out = format!("neighbors start: {} depth: {}", expr(start), depth);
+            if let Some(edge) = edge {
+                out.push_str(&format!(" edge: {}", edge));
+            }
+            out
+        }
+        ir::Operator::TopK { k, by } => format!("topk {} by {}", k, expr(by)),
+        ir::Operator::Sample { fraction, seed } => match seed {
+            Some(seed) => format!("sample {} seed: {}", float(*fraction), seed),
+            None => format!("sample {}", float(*fraction)),
+        },
+        ir::Operator::Assert { condition, message } => match message {
+            Some(message) => format!("assert {} {}", expr(condition), quote(message)),
+            None => format!("assert {}", expr(condition)),
+        },
+        ir::Operator::Explain { mode } => {
+            let mode = match mode {
+                ir::ExplainMode::Logical => "logical",
+                ir::ExplainMode::Physical => "physical",
+                ir::ExplainMode::Cost => "cost",
+            };
+            format!("explain {}", mode)
+        }
+    }
+}
+
+fn join_type_name(join_type: &ir::JoinType) -> &'static str {
+    match join_type {
+        ir::JoinType::Inner => "inner",
+        ir::JoinType::Left => "left",
+        ir::JoinType::Right => "right",
+        ir::JoinType::Full => "full",
+        ir::JoinType::Semi => "semi",
+        ir::JoinType::Anti => "anti",
+        ir::JoinType::Cross => "cross",
+    }
+}
+
+fn agg_list(aggs: &HashMap<String, ir::AggCall>) -> String {
+    let items: Vec<String> = sorted(aggs).into_iter()
+        .map(|(name, agg)| format!("{}: {}({})", name, agg.func, expr_list(&agg.args)))
+        .collect();
+    items.join(", ")
+}
+
+fn window_def(def: &ir::WindowDef) -> String {
+    let mut out = format!("{}({}) over", def.func, expr_list(&def.args));
+    if let Some(partition) = &def.partition {
+        out.push_str(&format!(" part: {}", columns(partition)));
+    }
+    if let Some(order) = &def.order {
+        out.push_str(&format!(" order: {}", sort_keys(order)));
+    }
+    if let Some(frame) = &def.frame {
+        let mode = match frame.mode {
+            ir::FrameMode::Rows => "rows",
+            ir::FrameMode::Range => "range",
+        };
+        out.push_str(&format!(" frame: {} [{}, {}]", mode, frame_bound(&frame.start), frame_bound(&frame.end)));
+    }
+    out
+}
+
+fn frame_bound(bound: &ir::FrameBound) -> String {
+    match bound {
+        ir::FrameBound::UnboundedPreceding => "unbounded_preceding".to_string(),
+        ir::FrameBound::UnboundedFollowing => "unbounded_following".to_string(),
+        ir::FrameBound::CurrentRow => "current_row".to_string(),
+        ir::FrameBound::Preceding(n) => format!("{} preceding", n),
+        ir::FrameBound::Following(n) => format!("{} following", n),
+    }
+}
+
+fn sort_keys(keys: &[ir::SortKey]) -> String {
+    let keys: Vec<String> = keys.iter()
+        .map(|key| {
+            let text = expr(&key.expr);
+            if key.desc {
+                format!("-{}", text)
+            } else if text.starts_with('-') {
+                // A leading `-` would read as descending
+                format!("({})", text)
+            } else {
+                text
+            }
+        })
+        .collect();
+    keys.join(", ")
+}
+
+fn columns(cols: &[ir::ColumnRef]) -> String {
+    cols.iter().map(column).collect::<Vec<_>>().join(", ")
+}
+
+fn column(col: &ir::ColumnRef) -> String {
+    match &col.table {
+        Some(table) => format!("{}.{}", table, col.column),
+        None => col.column.clone(),
+    }
+}
+
+fn expr_list(exprs: &[ir::Expr]) -> String {
+    exprs.iter().map(expr).collect::<Vec<_>>().join(", ")
+}
+
+fn expr(e: &ir::Expr) -> String {
+    expr_at(e, 0)
+}
+
+/// Print `e`, parenthesized if it binds looser than `min`
+fn expr_at(e: &ir::Expr, min: u8) -> String {
+    let text = match e {
+        ir::Expr::Literal { value: v } => value(v),
+        ir::Expr::Column { col } => column(col),
+        ir::Expr::BinaryOp { op, left, right } => {
+            let prec = binop_precedence(op);
+            format!("{} {} {}", expr_at(left, prec), binop_symbol(op), expr_at(right, prec + 1))
+        }
+        ir::Expr::UnaryOp { op: ir::UnOp::Not, expr: inner } => format!("!{}", expr_at(inner, 4)),
+        ir::Expr::UnaryOp { op: ir::UnOp::Neg, expr: inner } => match **inner {
+            // `-5` would parse as a negative literal
+            ir::Expr::Literal { .. } => format!("-({})", expr(inner)),
+            _ => format!("-{}", expr_at(inner, 8)),
+        },
+        ir::Expr::FuncCall { func, args } => format!("{}({})", func, expr_list(args)),
+        ir::Expr::FieldAccess { expr: base, field } => format!("{}.{}", postfix_base(base, true), field),
+        ir::Expr::Index { expr: base, index } => format!("{}[{}]", postfix_base(base, false), expr(index)),
+        ir::Expr::Array { elements } => format!("[{}]", expr_list(elements)),
+        ir::Expr::Object { fields } => object(fields, expr),
+        ir::Expr::Vector { values } => {
+            format!("<{}>", values.iter().map(|v| float(*v)).collect::<Vec<_>>().join(", "))
+        }
+        ir::Expr::InRange { expr: inner, start, end, inclusive } => format!(
+            "{} in [{}, {}{}",
+            expr_at(inner, 4), expr(start), expr(end), if *inclusive { "]" } else { ")" },
+        ),
+        ir::Expr::InSet { expr: inner, set } => format!("{} in ({})", expr_at(inner, 4), expr_list(set)),
+    };
+
+    if precedence(e) < min {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+/// The base of `.field` or `[index]`
+fn postfix_base(base: &ir::Expr, field_access: bool) -> String {
+    let needs_parens = match base {
+        ir::Expr::Literal { .. } | ir::Expr::Vector { .. } => true,
+        // `a.b` would read as column `b` of table `a`
+        ir::Expr::Column { col } => field_access && col.table.is_none(),
+        _ => precedence(base) < 8,
+    };
+    if needs_parens {
+        format!("({})", expr(base))
+    } else {
+        expr(base)
+    }
+}
+
+/// Binding strength, mirroring the expression rules of the grammar
+fn precedence(e: &ir::Expr) -> u8 {
+    match e {
+        ir::Expr::BinaryOp { op, .. } => binop_precedence(op),
+        ir::Expr::UnaryOp { op: ir::UnOp::Not, .. } => 3,
+        ir::Expr::InRange { .. } | ir::Expr::InSet { .. } => 4,
+        ir::Expr::UnaryOp { op: ir::UnOp::Neg, .. } => 7,
+        ir::Expr::FuncCall { .. } | ir::Expr::FieldAccess { .. } | ir::Expr::Index { .. } => 8,
+        _ => 9,
+    }
+}
+
+fn binop_precedence(op: &ir::BinOp) -> u8 {
+    match op {
+        ir::BinOp::Or => 1,
+        ir::BinOp::And => 2,
+        ir::BinOp::Eq | ir::BinOp::Ne | ir::BinOp::Lt | ir::BinOp::Le | ir::BinOp::Gt | ir::BinOp::Ge
+        | ir::BinOp::Like | ir::BinOp::ILike => 4,
+        ir::BinOp::Add | ir::BinOp::Sub => 5,
+        ir::BinOp::Mul | ir::BinOp::Div | ir::BinOp::Mod => 6,
+    }
+}
+
+fn binop_symbol(op: &ir::BinOp) -> &'static str {
+    match op {
+        ir::BinOp::Add => "+",
+        ir::BinOp::Sub => "-",
+        ir::BinOp::Mul => "*",
+        ir::BinOp::Div => "/",
+        ir::BinOp::Mod => "%",
+        ir::BinOp::Eq => "==",
+        ir::BinOp::Ne => "!=",
+        ir::BinOp::Lt => "<",
+        ir::BinOp::Le => "<=",
+        ir::BinOp::Gt => ">",
+        ir::BinOp::Ge => ">=",
+        ir::BinOp::And => "&&",
+        ir::BinOp::Or => "||",
+        ir::BinOp::Like => "like",
+        ir::BinOp::ILike => "ilike",
+    }
+}
+
+fn value(v: &ir::Value) -> String {
+    match v {
+        ir::Value::Null => "null".to_string(),
+        ir::Value::Bool(b) => b.to_string(),
+        ir::Value::Int(i) => i.to_string(),
+        ir::Value::Float(f) => float(*f),
+        ir::Value::String(s) => quote(s),
+        ir::Value::Bytes(bytes) => {
+            format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+        }
+        ir::Value::Date(s) | ir::Value::Time(s) | ir::Value::Timestamp(s) => s.clone(),
+        ir::Value::Array(values) => {
+            format!("[{}]", values.iter().map(value).collect::<Vec<_>>().join(", "))
+        }
+        ir::Value::Object(fields) => object(fields, value),
+    }
+}
+
+/// Shortest representation that parses back to the same `f64`, always with a
+/// `.` or exponent so it stays a float
+fn float(f: f64) -> String {
+    format!("{:?}", f)
+}
+
+fn object<V>(fields: &HashMap<String, V>, print_value: impl Fn(&V) -> String) -> String {
+    if fields.is_empty() {
+        return "{}".to_string();
+    }
+    let items: Vec<String> = sorted(fields).into_iter()
+        .map(|(key, v)| {
+            let key = if is_ident(key) { key.clone() } else { quote(key) };
+            format!("{}: {}", key, print_value(v))
+        })
+        .collect();
+    format!("{{ {} }}", items.join(", "))
+}
+
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn reprint(source: &str) -> String {
+        print(&parse(source).unwrap().to_ir())
+    }
+
+    #[test]
+    fn test_print_canonical_layout() {
+        let source = r#"pragma {timeout: 1000, "budget": {rows_out: 10}}
+            let adults = from users | filter age>=18 && !(name like "a%");
+            from adults a | join from orders o on a.id==o.user_id type: left
+            | group by a.id {n: count(), total: sum(o.amount)}
+            | sort -total, (-n) | take 10"#;
+
+        assert_eq!(reprint(source), "\
+pragma { budget: { rows_out: 10 }, timeout: 1000 }
+let adults = from users
+  | filter age >= 18 && !name like \"a%\";
+from adults a
+  | join from orders o on a.id == o.user_id type: left
+  | group by a.id { n: count(), total: sum(o.amount) }
+  | sort -total, (-n)
+  | take 10");
+    }
+
+    #[test]
+    fn test_print_adds_only_needed_parens() {
+        assert_eq!(reprint("from t | filter (a + b) * c - (d - e) > -(5)"), "from t\n  | filter (a + b) * c - (d - e) > -(5)");
+        assert_eq!(reprint("from t | filter ((a || b)) && -x.y < -5"), "from t\n  | filter (a || b) && -x.y < -5");
+        assert_eq!(reprint("from t | map {f: (a).b, g: t.a.b[0], h: lower(name).x}"),
+                   "from t\n  | map { f: (a).b, g: t.a.b[0], h: lower(name).x }");
+        assert_eq!(reprint("from t | filter x in [1, 2) || y in (\"a\", \"b\\\"\")"),
+                   "from t\n  | filter x in [1, 2) || y in (\"a\", \"b\\\"\")");
+    }
+
+    #[test]
+    fn test_print_parse_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let program = gen_program(&mut rng);
+            let text = print(&program);
+            let reparsed = parse(&text)
+                .unwrap_or_else(|e| panic!("printed program does not parse:\n{}\n{}", text, e))
+                .to_ir();
+            assert_eq!(reparsed.fingerprint(), program.fingerprint(), "round trip changed:\n{}", text);
+            assert_eq!(print(&reparsed), text, "printing is not idempotent");
+        }
+    }
+
+    // Random canonical programs
+
+    /// Deterministic xorshift generator so failures are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn chance(&mut self) -> bool {
+            self.next() & 1 == 0
+        }
+
+        fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())].clone()
+        }
+
+        fn name(&mut self) -> String {
+            self.pick(&["id", "name", "age", "score", "user_id", "total", "_tmp", "x1", "café"]).to_string()
+        }
+
+        fn int(&mut self) -> i64 {
+            match self.below(3) {
+                0 => self.below(100) as i64,
+                1 => -(self.below(100) as i64),
+                _ => (self.next() as i64).max(i64::MIN + 1),
+            }
+        }
+
+        fn float(&mut self) -> f64 {
+            match self.below(3) {
+                0 => self.pick(&[0.5, -2.25, 1e-7, 3.0, 1e20, -0.0, 0.1]),
+                1 => self.below(1000) as f64 / 8.0,
+                _ => (self.next() as i64) as f64 / 1e6,
+            }
+        }
+    }
+
+    fn gen_program(rng: &mut Rng) -> ir::Program {
+        let pragma = rng.chance().then(|| {
+            let options = (0..rng.below(3) + 1)
+                .map(|_| {
+                    let (key, v) = rng.pick(&[
+                        ("timeout", ir::Value::Int(1000)),
+                        ("cache", ir::Value::Bool(false)),
+                        ("budget", ir::Value::Object(HashMap::from([
+                            ("rows_out".to_string(), ir::Value::Int(5)),
+                            ("max bytes".to_string(), ir::Value::Int(1 << 20)),
+                        ]))),
+                        ("tags", ir::Value::Array(vec![ir::Value::String("a".to_string()), ir::Value::Null])),
+                        ("two words", ir::Value::Float(0.25)),
+                    ]);
+                    (key.to_string(), v)
+                })
+                .collect();
+            ir::Pragma { options }
+        });
+        let lets = (0..rng.below(3))
+            .map(|_| ir::LetBinding { name: rng.name(), pipeline: gen_pipeline(rng, 1) })
+            .collect();
+        ir::Program { pragma, lets, pipeline: gen_pipeline(rng, 1) }
+    }
+
+    fn gen_pipeline(rng: &mut Rng, depth: usize) -> ir::Pipeline {
+        ir::Pipeline {
+            source: gen_source(rng, depth),
+            ops: (0..rng.below(5)).map(|_| gen_operator(rng, depth)).collect(),
+        }
+    }
+
+    fn gen_source(rng: &mut Rng, depth: usize) -> ir::Source {
+        let alias = rng.chance().then(|| rng.pick(&["u", "o", "t2"]).to_string());
+        match rng.below(if depth > 0 { 3 } else { 2 }) {
+            0 => ir::Source::Table { name: rng.name(), alias },
+            1 => ir::Source::Graph { graph_name: rng.name(), alias: "g".to_string() },
+            _ => ir::Source::SubPipeline { pipeline: Box::new(gen_pipeline(rng, depth - 1)), alias },
+        }
+    }
+
+    fn gen_operator(rng: &mut Rng, depth: usize) -> ir::Operator {
+        match rng.below(22) {
+            0 => ir::Operator::Select {
+                projections: (0..rng.below(3) + 1)
+                    .map(|_| match rng.below(3) {
+                        0 => ir::Projection::Expr(ir::Expr::Column { col: ir::ColumnRef { table: None, column: "*".to_string() } }),
+                        1 => ir::Projection::Expr(gen_expr(rng, 2)),
+                        _ => ir::Projection::Aliased { expr: gen_expr(rng, 2), alias: rng.name() },
+                    })
+                    .collect(),
+            },
+            1 => ir::Operator::Filter { condition: gen_expr(rng, 3) },
+            2 => ir::Operator::Join {
+                source: gen_source(rng, depth),
+                on: gen_expr(rng, 2),
+                join_type: rng.chance().then(|| {
+                    rng.pick(&[ir::JoinType::Inner, ir::JoinType::Left, ir::JoinType::Anti, ir::JoinType::Cross])
+                }),
+            },
+            3 => ir::Operator::GroupBy {
+                keys: (0..rng.below(3)).map(|_| gen_column(rng)).collect(),
+                aggs: gen_map(rng, gen_agg),
+            },
+            4 => ir::Operator::Window {
+                windows: gen_map(rng, |rng| ir::WindowDef {
+                    func: rng.pick(&["row_number", "lag", "sum"]).to_string(),
+                    args: (0..rng.below(3)).map(|_| gen_expr(rng, 1)).collect(),
+                    partition: rng.chance().then(|| (0..rng.below(2) + 1).map(|_| gen_column(rng)).collect()),
+                    order: rng.chance().then(|| gen_sort_keys(rng)),
+                    frame: rng.chance().then(|| ir::FrameSpec {
+                        mode: if rng.chance() { ir::FrameMode::Rows } else { ir::FrameMode::Range },
+                        start: gen_frame_bound(rng),
+                        end: gen_frame_bound(rng),
+                    }),
+                }),
+            },
+            5 => ir::Operator::Sort { keys: gen_sort_keys(rng) },
+            6 => ir::Operator::Take { limit: rng.int() },
+            7 => ir::Operator::Distinct,
+            8 => ir::Operator::Union { all: rng.chance(), source: gen_source(rng, depth) },
+            9 => if rng.chance() {
+                ir::Operator::Except { source: gen_source(rng, depth) }
+            } else {
+                ir::Operator::Intersect { source: gen_source(rng, depth) }
+            },
+            10 => ir::Operator::Map { mappings: gen_map(rng, |rng| gen_expr(rng, 3)) },
+            11 => ir::Operator::Expand { expr: gen_expr(rng, 2), alias: rng.chance().then(|| rng.name()) },
+            12 => ir::Operator::Resample {
+                interval: gen_interval(rng),
+                method: rng.pick(&["mean", "last"]).to_string(),
+                on: gen_column(rng),
+                columns: None,
+            },
+            13 => ir::Operator::Agg {
+                group_key: match rng.below(3) {
+                    0 => ir::GroupKey::Tumbling { expr: gen_expr(rng, 1), interval: gen_interval(rng) },
+                    1 => ir::GroupKey::Hopping { expr: gen_expr(rng, 1), size: gen_interval(rng), slide: gen_interval(rng) },
+                    _ => ir::GroupKey::Session { expr: gen_expr(rng, 1), gap: gen_interval(rng) },
+                },
+                aggs: gen_map(rng, gen_agg),
+            },
+            14 => ir::Operator::Knn {
+                query: gen_expr(rng, 2),
+                k: rng.int(),
+                on: rng.chance().then(|| gen_column(rng)),
+                index: rng.chance().then(|| rng.name()),
+                metric: rng.chance().then(|| rng.pick(&["cosine", "l2", "ip"]).to_string()),
+            },
+            15 => ir::Operator::Rank { by: gen_expr(rng, 2) },
+            16 => ir::Operator::Neighbors {
+                start: gen_expr(rng, 2),
+                depth: rng.int(),
+                edge: rng.chance().then(|| rng.name()),
+            },
+            17 => ir::Operator::TopK { k: rng.int(), by: gen_expr(rng, 2) },
+            18 => ir::Operator::Sample { fraction: rng.float(), seed: rng.chance().then(|| rng.int()) },
+            19 => ir::Operator::Assert { condition: gen_expr(rng, 2), message: rng.chance().then(|| gen_string(rng)) },
+            20 => ir::Operator::Explain {
+                mode: rng.pick(&[ir::ExplainMode::Logical, ir::ExplainMode::Physical, ir::ExplainMode::Cost]),
+            },
+            _ => ir::Operator::Filter { condition: gen_expr(rng, 1) },
+        }
+    }
+
+    /// One to three entries keyed by column-like names
+    fn gen_map<V>(rng: &mut Rng, mut gen_value: impl FnMut(&mut Rng) -> V) -> HashMap<String, V> {
+        (0..rng.below(3) + 1)
+            .map(|_| (rng.name(), gen_value(rng)))
+            .collect()
+    }
+
+    fn gen_agg(rng: &mut Rng) -> ir::AggCall {
+        ir::AggCall {
+            func: rng.pick(&["count", "sum", "avg"]).to_string(),
+            args: (0..rng.below(2)).map(|_| gen_expr(rng, 1)).collect(),
+        }
+    }
+
+    fn gen_sort_keys(rng: &mut Rng) -> Vec<ir::SortKey> {
+        (0..rng.below(3) + 1)
+            .map(|_| ir::SortKey { expr: gen_expr(rng, 2), desc: rng.chance() })
+            .collect()
+    }
+
+    fn gen_frame_bound(rng: &mut Rng) -> ir::FrameBound {
+        match rng.below(5) {
+            0 => ir::FrameBound::UnboundedPreceding,
+            1 => ir::FrameBound::UnboundedFollowing,
+            2 => ir::FrameBound::CurrentRow,
+            3 => ir::FrameBound::Preceding(rng.below(10) as i64),
+            _ => ir::FrameBound::Following(rng.below(10) as i64),
+        }
+    }
+
+    fn gen_interval(rng: &mut Rng) -> String {
+        format!("{}{}", rng.below(60) + 1, rng.pick(&["s", "m", "h", "d"]))
+    }
+
+    fn gen_string(rng: &mut Rng) -> String {
+        rng.pick(&["", "hello", "say \"hi\"", "back\\slash", "line\nbreak\ttab\r", "\\d+", "naïve ✓"]).to_string()
+    }
+
+    fn gen_column(rng: &mut Rng) -> ir::ColumnRef {
+        ir::ColumnRef {
+            table: rng.chance().then(|| rng.pick(&["u", "o"]).to_string()),
+            column: rng.name(),
+        }
+    }
+
+    fn gen_value(rng: &mut Rng) -> ir::Value {
+        match rng.below(9) {
+            0 => ir::Value::Null,
+            1 => ir::Value::Bool(rng.chance()),
+            2 => ir::Value::Int(rng.int()),
+            3 => ir::Value::Float(rng.float()),
+            4 => ir::Value::String(gen_string(rng)),
+            5 => ir::Value::Bytes((0..rng.below(3) + 1).map(|_| rng.next() as u8).collect()),
+            6 => ir::Value::Date("2024-02-29".to_string()),
+            7 => ir::Value::Time(rng.pick(&["09:30", "23:59:59", "00:00:00.125"]).to_string()),
+            _ => ir::Value::Timestamp(rng.pick(&["2024-01-15T10:00:00Z", "2024-01-15T10:00:00.5+02:00"]).to_string()),
+        }
+    }
+
+    fn gen_expr(rng: &mut Rng, depth: usize) -> ir::Expr {
+        let boxed = |rng: &mut Rng| Box::new(gen_expr(rng, depth - 1));
+        match rng.below(if depth == 0 { 3 } else { 13 }) {
+            0 => ir::Expr::Literal { value: gen_value(rng) },
+            1 => ir::Expr::Column { col: gen_column(rng) },
+            2 => ir::Expr::Vector { values: (0..rng.below(3) + 1).map(|_| rng.float()).collect() },
+            3 | 4 => ir::Expr::BinaryOp {
+                op: rng.pick(&[
+                    ir::BinOp::Add, ir::BinOp::Sub, ir::BinOp::Mul, ir::BinOp::Div, ir::BinOp::Mod,
+                    ir::BinOp::Eq, ir::BinOp::Ne, ir::BinOp::Lt, ir::BinOp::Le, ir::BinOp::Gt, ir::BinOp::Ge,
+                    ir::BinOp::And, ir::BinOp::Or, ir::BinOp::Like, ir::BinOp::ILike,
+                ]),
+                left: boxed(rng),
+                right: boxed(rng),
+            },
+            5 => ir::Expr::UnaryOp {
+                op: if rng.chance() { ir::UnOp::Neg } else { ir::UnOp::Not },
+                expr: boxed(rng),
+            },
+            6 => ir::Expr::FuncCall {
+                func: rng.pick(&["lower", "coalesce", "abs"]).to_string(),
+                args: (0..rng.below(3)).map(|_| gen_expr(rng, depth - 1)).collect(),
+            },
+            7 => ir::Expr::FieldAccess { expr: boxed(rng), field: rng.name() },
+            8 => ir::Expr::Index { expr: boxed(rng), index: boxed(rng) },
+            9 => ir::Expr::Array { elements: (0..rng.below(3)).map(|_| gen_expr(rng, depth - 1)).collect() },
+            10 => ir::Expr::Object {
+                fields: (0..rng.below(3))
+                    .map(|_| (rng.pick(&["a", "two words", ""]).to_string(), gen_expr(rng, depth - 1)))
+                    .collect(),
+            },
+            11 => ir::Expr::InRange { expr: boxed(rng), start: boxed(rng), end: boxed(rng), inclusive: rng.chance() },
+            _ => ir::Expr::InSet {
+                expr: boxed(rng),
+                set: (0..rng.below(3)).map(|_| gen_expr(rng, depth - 1)).collect(),
+            },
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Convert AST to canonical IR
 
 use mlql_ir::{self as ir};
+use std::collections::HashMap;
 use crate::ast::*;
 
 impl Program {
@@ -16,8 +17,6 @@ impl Program {
 
 impl Pragma {
     fn to_ir(self) -> ir::Pragma {
-        use std::collections::HashMap;
-
         let options = self.options.into_iter()
             .map(|(k, v)| (k, v.to_ir()))
             .collect::<HashMap<_, _>>();
@@ -46,10 +45,10 @@ impl Pipeline {
 
 impl Source {
     fn to_ir(self) -> ir::Source {
-        match self {
-            Source::Table { name, alias } => ir::Source::Table { name, alias },
-            Source::Graph { graph_name, alias } => ir::Source::Graph { graph_name, alias },
-            Source::SubQuery { pipeline, alias } => ir::Source::SubPipeline {
+        match self.kind {
+            SourceKind::Table { name, alias } => ir::Source::Table { name, alias },
+            SourceKind::Graph { graph_name, alias } => ir::Source::Graph { graph_name, alias },
+            SourceKind::SubQuery { pipeline, alias } => ir::Source::SubPipeline {
                 pipeline: Box::new(pipeline.to_ir()),
                 alias,
             },
@@ -59,53 +58,179 @@ impl Source {
 
 impl Operator {
     fn to_ir(self) -> ir::Operator {
-        match self {
-            Operator::Select { items } => {
+        match self.kind {
+            OperatorKind::Select { items } => {
                 ir::Operator::Select {
                     projections: items.into_iter().map(|i| i.to_ir()).collect(),
                 }
             }
-            Operator::Filter { expr } => {
+            OperatorKind::Filter { expr } => {
                 ir::Operator::Filter {
                     condition: expr.to_ir(),
                 }
             }
-            Operator::Join { source, on, join_type } => {
+            OperatorKind::Join { source, on, join_type } => {
                 ir::Operator::Join {
                     source: source.to_ir(),
                     on: on.to_ir(),
                     join_type: join_type.map(|jt| jt.to_ir()),
                 }
             }
-            Operator::GroupBy { keys, aggs } => {
-                use std::collections::HashMap;
-
-                let aggs_map = aggs.into_iter()
-                    .map(|(name, func)| (name, func.to_ir()))
-                    .collect::<HashMap<_, _>>();
-
+            OperatorKind::GroupBy { keys, aggs } => {
                 ir::Operator::GroupBy {
                     keys: keys.into_iter().map(|k| k.to_ir()).collect(),
-                    aggs: aggs_map,
+                    aggs: aggs_to_ir(aggs),
                 }
             }
-            Operator::Sort { keys } => {
+            OperatorKind::Window { windows } => {
+                ir::Operator::Window {
+                    windows: windows.into_iter()
+                        .map(|(name, def)| (name, def.to_ir()))
+                        .collect::<HashMap<_, _>>(),
+                }
+            }
+            OperatorKind::Sort { keys } => {
                 ir::Operator::Sort {
                     keys: keys.into_iter().map(|k| k.to_ir()).collect(),
                 }
             }
-            Operator::Take { limit } => {
+            OperatorKind::Take { limit } => {
                 ir::Operator::Take { limit }
             }
-            Operator::Distinct => ir::Operator::Distinct,
+            OperatorKind::Distinct => ir::Operator::Distinct,
+            OperatorKind::Union { all, source } => ir::Operator::Union { all, source: source.to_ir() },
+            OperatorKind::Except { source } => ir::Operator::Except { source: source.to_ir() },
+            OperatorKind::Intersect { source } => ir::Operator::Intersect { source: source.to_ir() },
+            OperatorKind::Map { mappings } => {
+                ir::Operator::Map {
+                    mappings: mappings.into_iter()
+                        .map(|(name, expr)| (name, expr.to_ir()))
+                        .collect::<HashMap<_, _>>(),
+                }
+            }
+            OperatorKind::Expand { expr, alias } => {
+                ir::Operator::Expand {
+                    expr: expr.to_ir(),
+                    alias,
+                }
+            }
+            OperatorKind::Resample { interval, method, on } => {
+                ir::Operator::Resample {
+                    interval,
+                    method,
+                    on: on.to_ir(),
+                    columns: None,
+                }
+            }
+            OperatorKind::Agg { group_key, aggs } => {
+                ir::Operator::Agg {
+                    group_key: group_key.to_ir(),
+                    aggs: aggs_to_ir(aggs),
+                }
+            }
+            OperatorKind::Knn { query, k, on, index, metric } => {
+                ir::Operator::Knn {
+                    query: query.to_ir(),
+                    k,
+                    on: on.map(|on| on.to_ir()),
+                    index,
+                    metric,
+                }
+            }
+            OperatorKind::Rank { by } => ir::Operator::Rank { by: by.to_ir() },
+            OperatorKind::Neighbors { start, depth, edge } => {
+                ir::Operator::Neighbors {
+                    start: start.to_ir(),
+                    depth,
+                    edge,
+                }
+            }
+            OperatorKind::TopK { k, by } => ir::Operator::TopK { k, by: by.to_ir() },
+            OperatorKind::Sample { fraction, seed } => ir::Operator::Sample { fraction, seed },
+            OperatorKind::Assert { condition, message } => {
+                ir::Operator::Assert {
+                    condition: condition.to_ir(),
+                    message,
+                }
+            }
+            OperatorKind::Explain { mode } => ir::Operator::Explain { mode: mode.to_ir() },
+        }
+    }
+}
+
+fn aggs_to_ir(aggs: Vec<(String, FuncCall)>) -> HashMap<String, ir::AggCall> {
+    aggs.into_iter()
+        .map(|(name, func)| (name, func.to_ir()))
+        .collect()
+}
+
+impl WindowDef {
+    fn to_ir(self) -> ir::WindowDef {
+        ir::WindowDef {
+            func: self.func,
+            args: self.args.into_iter().map(|e| e.to_ir()).collect(),
+            partition: self.partition.map(|cols| cols.into_iter().map(|c| c.to_ir()).collect()),
+            order: self.order.map(|keys| keys.into_iter().map(|k| k.to_ir()).collect()),
+            frame: self.frame.map(|f| f.to_ir()),
+        }
+    }
+}
+
+impl FrameSpec {
+    fn to_ir(self) -> ir::FrameSpec {
+        ir::FrameSpec {
+            mode: match self.mode {
+                FrameMode::Rows => ir::FrameMode::Rows,
+                FrameMode::Range => ir::FrameMode::Range,
+            },
+            start: self.start.to_ir(),
+            end: self.end.to_ir(),
+        }
+    }
+}
+
+impl FrameBound {
+    fn to_ir(self) -> ir::FrameBound {
+        match self {
+            FrameBound::UnboundedPreceding => ir::FrameBound::UnboundedPreceding,
+            FrameBound::UnboundedFollowing => ir::FrameBound::UnboundedFollowing,
+            FrameBound::CurrentRow => ir::FrameBound::CurrentRow,
+            FrameBound::Preceding(n) => ir::FrameBound::Preceding(n),
+            FrameBound::Following(n) => ir::FrameBound::Following(n),
+        }
+    }
+}
+
+impl GroupKey {
+    fn to_ir(self) -> ir::GroupKey {
+        match self.kind {
+            GroupKeyKind::Tumbling { expr, interval } => {
+                ir::GroupKey::Tumbling { expr: expr.to_ir(), interval }
+            }
+            GroupKeyKind::Hopping { expr, size, slide } => {
+                ir::GroupKey::Hopping { expr: expr.to_ir(), size, slide }
+            }
+            GroupKeyKind::Session { expr, gap } => {
+                ir::GroupKey::Session { expr: expr.to_ir(), gap }
+            }
+        }
+    }
+}
+
+impl ExplainMode {
+    fn to_ir(self) -> ir::ExplainMode {
+        match self {
+            ExplainMode::Logical => ir::ExplainMode::Logical,
+            ExplainMode::Physical => ir::ExplainMode::Physical,
+            ExplainMode::Cost => ir::ExplainMode::Cost,
         }
     }
 }
 
 impl SelectItem {
     fn to_ir(self) -> ir::Projection {
-        match self {
-            SelectItem::Wildcard => {
+        match self.kind {
+            SelectItemKind::Wildcard => {
                 // Represent wildcard as column reference "*"
                 ir::Projection::Expr(ir::Expr::Column {
                     col: ir::ColumnRef {
@@ -114,8 +239,8 @@ impl SelectItem {
                     },
                 })
             }
-            SelectItem::Expr(expr) => ir::Projection::Expr(expr.to_ir()),
-            SelectItem::Aliased { expr, alias } => {
+            SelectItemKind::Expr(expr) => ir::Projection::Expr(expr.to_ir()),
+            SelectItemKind::Aliased { expr, alias } => {
                 ir::Projection::Aliased {
                     expr: expr.to_ir(),
                     alias,
@@ -150,28 +275,67 @@ impl JoinType {
 
 impl Expr {
     fn to_ir(self) -> ir::Expr {
-        match self {
-            Expr::Literal(v) => ir::Expr::Literal { value: v.to_ir() },
-            Expr::Column(col) => ir::Expr::Column { col: col.to_ir() },
-            Expr::BinaryOp { op, left, right } => {
+        match self.kind {
+            ExprKind::Literal(v) => ir::Expr::Literal { value: v.to_ir() },
+            ExprKind::Column(col) => ir::Expr::Column { col: col.to_ir() },
+            ExprKind::BinaryOp { op, left, right } => {
                 ir::Expr::BinaryOp {
                     op: op.to_ir(),
                     left: Box::new(left.to_ir()),
                     right: Box::new(right.to_ir()),
                 }
             }
-            Expr::UnaryOp { op, expr } => {
+            ExprKind::UnaryOp { op, expr } => {
                 ir::Expr::UnaryOp {
                     op: op.to_ir(),
                     expr: Box::new(expr.to_ir()),
                 }
             }
-            Expr::FuncCall(func) => {
+            ExprKind::FuncCall(func) => {
                 ir::Expr::FuncCall {
                     func: func.name,
                     args: func.args.into_iter().map(|e| e.to_ir()).collect(),
                 }
             }
+            ExprKind::FieldAccess { expr, field } => {
+                ir::Expr::FieldAccess {
+                    expr: Box::new(expr.to_ir()),
+                    field,
+                }
+            }
+            ExprKind::Index { expr, index } => {
+                ir::Expr::Index {
+                    expr: Box::new(expr.to_ir()),
+                    index: Box::new(index.to_ir()),
+                }
+            }
+            ExprKind::Array(elements) => {
+                ir::Expr::Array {
+                    elements: elements.into_iter().map(|e| e.to_ir()).collect(),
+                }
+            }
+            ExprKind::Object(fields) => {
+                ir::Expr::Object {
+                    fields: fields.into_iter()
+                        .map(|(name, e)| (name, e.to_ir()))
+                        .collect::<HashMap<_, _>>(),
+                }
+            }
+            ExprKind::Vector(values) => ir::Expr::Vector { values },
+            ExprKind::InRange { expr, start, end, inclusive } => {
+                ir::Expr::InRange {
+                    expr: Box::new(expr.to_ir()),
+                    start: Box::new(start.to_ir()),
+                    end: Box::new(end.to_ir()),
+                    inclusive,
+                }
+            }
+            ExprKind::InSet { expr, set } => {
+                ir::Expr::InSet {
+                    expr: Box::new(expr.to_ir()),
+                    set: set.into_iter().map(|e| e.to_ir()).collect(),
+                }
+            }
         }
     }
 }
@@ -233,6 +397,14 @@ impl Value {
             Value::Int(i) => ir::Value::Int(i),
             Value::Float(f) => ir::Value::Float(f),
             Value::String(s) => ir::Value::String(s),
+            Value::Bytes(b) => ir::Value::Bytes(b),
+            Value::Date(d) => ir::Value::Date(d),
+            Value::Time(t) => ir::Value::Time(t),
+            Value::Timestamp(ts) => ir::Value::Timestamp(ts),
+            Value::Array(items) => ir::Value::Array(items.into_iter().map(|v| v.to_ir()).collect()),
+            Value::Object(fields) => ir::Value::Object(
+                fields.into_iter().map(|(k, v)| (k, v.to_ir())).collect(),
+            ),
         }
     }
 }
@@ -0,0 +1,334 @@
+//! Full-text search: lowering `bm25()` to DuckDB's `fts` extension
+//!
+//! `from docs | rank by bm25(body, "vector database")` searches the FTS index
+//! of `docs`. The first query searching a table builds its index, over every
+//! column that query searches:
+//!
+//! ```sql
+//! PRAGMA create_fts_index('docs', 'id', 'body');
+//! SELECT COLUMNS(lambda c: c NOT IN ('score')),
+//!     "fts_main_docs".match_bm25("id", ?, fields := 'body') AS "score"
+//! FROM "docs" ORDER BY "score" DESC NULLS LAST
+//! ```
+//!
+//! The index is keyed by the table's single-column primary key, or else its
+//! `id` column. An existing index is never rebuilt, so searching a column it
+//! doesn't cover is an error until it is dropped with
+//! `PRAGMA drop_fts_index('docs')`. DuckDB doesn't update FTS indexes when the
+//! table changes either.
+
+use crate::{column_ref_to_sql, expr_to_sql, quote_ident, DuckExecutor, ExecutionError, QueryParam};
+use duckdb::Connection;
+use mlql_ir::{ColumnRef, Expr, OpLocation, Operator, Program, Projection, Source, Value};
+use std::borrow::Cow;
+
+/// SQL for a `bm25(doc, query, table, key)` call whose table and key were
+/// filled in by [`DuckExecutor::resolve_fts`]
+pub(crate) fn bm25_sql(args: &[Expr], params: &mut Vec<QueryParam>) -> Result<String, ExecutionError> {
+    let [Expr::Column { col: doc }, query, Expr::Literal { value: Value::String(table) }, Expr::Column { col: key }] = args else {
+        return Err(ExecutionError::SqlError(
+            "bm25 needs the table's full-text index; run the query through DuckExecutor, which creates it".to_string()
+        ));
+    };
+    Ok(format!(
+        "{}.match_bm25({}, {}, fields := '{}')",
+        quote_ident(&format!("fts_main_{}", table)),
+        column_ref_to_sql(key),
+        expr_to_sql(query, params)?,
+        doc.column.replace('\'', "''")
+    ))
+}
+
+/// Columns covered by the FTS index of `table`, in index order; empty when the
+/// table has no index
+pub fn fts_columns(conn: &Connection, table: &str) -> duckdb::Result<Vec<String>> {
+    let schema = format!("fts_main_{}", table);
+    let exists: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM duckdb_tables() WHERE schema_name = ? AND table_name = 'fields'",
+        [&schema],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(&format!("SELECT field FROM {}.fields ORDER BY fieldid", quote_ident(&schema)))?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Expressions of an operator that may call `bm25()`
+fn operator_exprs_mut(op: &mut Operator) -> Vec<&mut Expr> {
+    match op {
+        Operator::Select { projections } => projections.iter_mut()
+            .map(|projection| match projection {
+                Projection::Expr(expr) | Projection::Aliased { expr, .. } => expr,
+            })
+            .collect(),
+        Operator::Filter { condition } => vec![condition],
+        Operator::Sort { keys } => keys.iter_mut().map(|key| &mut key.expr).collect(),
+        Operator::Rank { by } | Operator::TopK { by, .. } => vec![by],
+        Operator::GroupBy { aggs, .. } => aggs.values_mut().flat_map(|agg| &mut agg.args).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Call `f` with the arguments of every `bm25()` call in `expr` that has not
+/// been resolved yet
+fn for_each_bm25(expr: &mut Expr, f: &mut dyn FnMut(&mut Vec<Expr>) -> Result<(), ExecutionError>) -> Result<(), ExecutionError> {
+    match expr {
+        Expr::FuncCall { func, args } if func.eq_ignore_ascii_case("bm25") && args.len() == 2 => f(args),
+        Expr::FuncCall { args, .. } | Expr::Array { elements: args } => {
+            args.iter_mut().try_for_each(|arg| for_each_bm25(arg, f))
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            for_each_bm25(left, f)?;
+            for_each_bm25(right, f)
+        }
+        Expr::UnaryOp { expr, .. } | Expr::FieldAccess { expr, .. } => for_each_bm25(expr, f),
+        Expr::Index { expr, index } => {
+            for_each_bm25(expr, f)?;
+            for_each_bm25(index, f)
+        }
+        Expr::Object { fields } => fields.values_mut().try_for_each(|value| for_each_bm25(value, f)),
+        Expr::InRange { expr, start, end, .. } => {
+            for_each_bm25(expr, f)?;
+            for_each_bm25(start, f)?;
+            for_each_bm25(end, f)
+        }
+        Expr::InSet { expr, set } => {
+            for_each_bm25(expr, f)?;
+            set.iter_mut().try_for_each(|item| for_each_bm25(item, f))
+        }
+        Expr::Literal { .. } | Expr::Column { .. } | Expr::Vector { .. } => Ok(()),
+    }
+}
+
+impl DuckExecutor {
+    /// Point each `bm25(doc, query)` call at the FTS index of the table its
+    /// pipeline reads, creating the index when the table has none.
+    ///
+    /// Only pipelines reading a table directly can search; calls inside
+    /// sub-pipelines are left as written and fail in SQL generation.
+    pub(crate) fn resolve_fts<'a>(&self, program: &'a Program) -> Result<Cow<'a, Program>, ExecutionError> {
+        let mut resolved = program.clone();
+        // Columns searched in each table, with the first call searching them
+        let mut searched: Vec<(String, String, String, OpLocation)> = Vec::new();
+        for b in 0..=program.lets.len() {
+            let binding = (b < program.lets.len()).then_some(b);
+            let pipeline = match binding {
+                Some(b) => &mut resolved.lets[b].pipeline,
+                None => &mut resolved.pipeline,
+            };
+            // Let bindings read earlier bindings as CTEs, which have no index
+            let table = match &pipeline.source {
+                Source::Table { name, alias } if !program.lets[..b].iter().any(|l| &l.name == name) => {
+                    Some((name.clone(), alias.clone().unwrap_or_else(|| name.clone())))
+                }
+                _ => None,
+            };
+
+            for (i, op) in pipeline.ops.iter_mut().enumerate() {
+                let location = OpLocation { binding, op: Some(i) };
+                for expr in operator_exprs_mut(op) {
+                    for_each_bm25(expr, &mut |args| {
+                        let (table, qualifier) = table.as_ref().ok_or_else(|| ExecutionError::SqlError(
+                            "bm25 searches a table's full-text index; the pipeline must read from a table".to_string()
+                        ))?;
+                        let Expr::Column { col: doc } = &args[0] else {
+                            return Err(ExecutionError::SqlError("bm25 searches a column; its first argument must name one".to_string()));
+                        };
+                        if doc.table.as_ref().is_some_and(|t| t != qualifier) {
+                            return Err(ExecutionError::SqlError(format!("bm25 searches columns of {}, not of {}", qualifier, doc.table.as_deref().unwrap_or_default())));
+                        }
+
+                        let key = self.fts_key_column(table, &doc.column)?;
+                        if !self.op_input_schema(program, binding, i).fields.iter().any(|f| f.name == key) {
+                            return Err(ExecutionError::SqlError(format!(
+                                "bm25 needs the key column {} of {} in its input", key, table
+                            )));
+                        }
+                        if !searched.iter().any(|(t, c, ..)| t == table && c == &doc.column) {
+                            searched.push((table.clone(), doc.column.clone(), key.clone(), location));
+                        }
+                        let key = ColumnRef { table: doc.table.clone(), column: key };
+                        args.push(Expr::Literal { value: Value::String(table.clone()) });
+                        args.push(Expr::Column { col: key });
+                        Ok(())
+                    }).map_err(|e| e.at(location))?;
+                }
+            }
+        }
+        if searched.is_empty() {
+            return Ok(Cow::Borrowed(program));
+        }
+
+        // Tables in the order they are first searched
+        let mut tables: Vec<&str> = Vec::new();
+        for (table, ..) in &searched {
+            if !tables.contains(&table.as_str()) {
+                tables.push(table);
+            }
+        }
+        for table in tables {
+            let columns: Vec<_> = searched.iter().filter(|(t, ..)| t == table).collect();
+            let indexed = fts_columns(&self.conn, table)?;
+            if indexed.is_empty() {
+                let (_, _, key, location) = columns[0];
+                let columns: Vec<&str> = columns.iter().map(|(_, c, ..)| c.as_str()).collect();
+                self.create_fts_index(table, key, &columns).map_err(|e| e.at(*location))?;
+            } else if let Some((_, column, _, location)) = columns.iter().find(|(_, c, ..)| !indexed.contains(c)) {
+                return Err(ExecutionError::SqlError(format!(
+                    "bm25: the full-text index of {} doesn't cover {}; drop it with PRAGMA drop_fts_index('{}') to rebuild it",
+                    table, column, table.replace('\'', "''")
+                )).at(*location));
+            }
+        }
+        Ok(Cow::Owned(resolved))
+    }
+
+    /// The key column of the FTS index of `table`, checking that the table
+    /// has `column` to search
+    fn fts_key_column(&self, table: &str, column: &str) -> Result<String, ExecutionError> {
+        self.conn.execute_batch("LOAD fts").map_err(|e| ExecutionError::SqlError(
+            format!("bm25 needs the DuckDB fts extension: {}", e)
+        ))?;
+
+        let schema = crate::table_schema(&self.conn, table).map_err(ExecutionError::SqlError)?;
+        if !schema.columns.iter().any(|c| c.name == column) {
+            return Err(ExecutionError::SqlError(format!("bm25: {} has no column {}", table, column)));
+        }
+        self.fts_key(table)?
+            .or_else(|| schema.columns.iter().find(|c| c.name.eq_ignore_ascii_case("id")).map(|c| c.name.clone()))
+            .ok_or_else(|| ExecutionError::SqlError(format!(
+                "bm25 needs a key column in {}; add a primary key or an id column", table
+            )))
+    }
+
+    /// Build the FTS index of `table` over `columns`
+    fn create_fts_index(&self, table: &str, key: &str, columns: &[&str]) -> Result<(), ExecutionError> {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+        let columns: Vec<String> = columns.iter().map(|c| quote(c)).collect();
+        let pragma = format!("PRAGMA create_fts_index({}, {}, {})", quote(table), quote(key), columns.join(", "));
+        tracing::info!("Creating full-text index: {}", pragma);
+        self.conn.execute_batch(&pragma)?;
+        Ok(())
+    }
+
+    /// The single-column primary key of `table`, if it has one
+    fn fts_key(&self, table: &str) -> Result<Option<String>, ExecutionError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT constraint_column_names[1] FROM duckdb_constraints()
+             WHERE table_name = ? AND constraint_type = 'PRIMARY KEY' AND len(constraint_column_names) = 1"
+        )?;
+        let mut rows = stmt.query([table])?;
+        Ok(match rows.next()? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_to_sql;
+    use mlql_registry::GraphRegistry;
+
+    fn articles(executor: &DuckExecutor) -> Result<(), Box<dyn std::error::Error>> {
+        executor.connection().execute_batch(
+            "CREATE TABLE articles (id INTEGER PRIMARY KEY, title VARCHAR, body VARCHAR);
+             INSERT INTO articles VALUES
+                (1, 'Ducks', 'Ducks swim in the pond'),
+                (2, 'Vector databases', 'A vector database stores embeddings for vector search'),
+                (3, 'Search engines', 'Full text search ranks documents by relevance'),
+                (4, 'Cooking', 'Boil the pasta for ten minutes');"
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bm25_sql() -> Result<(), Box<dyn std::error::Error>> {
+        let mut params = Vec::new();
+        let args = vec![
+            Expr::Column { col: ColumnRef { table: None, column: "body".to_string() } },
+            Expr::Literal { value: Value::String("vector".to_string()) },
+            Expr::Literal { value: Value::String("articles".to_string()) },
+            Expr::Column { col: ColumnRef { table: None, column: "id".to_string() } },
+        ];
+        assert_eq!(
+            bm25_sql(&args, &mut params)?,
+            "\"fts_main_articles\".match_bm25(\"id\", ?, fields := 'body')"
+        );
+        assert_eq!(params.len(), 1);
+
+        // Without the executor there is no index to search
+        let ir_program = mlql_ast::parse("from articles | rank by bm25(body, \"vector\")")?.to_ir();
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("bm25 is unresolved");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("needs the table's full-text index"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_by_bm25() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        articles(&executor)?;
+        assert!(fts_columns(executor.connection(), "articles")?.is_empty());
+
+        let ir_program = mlql_ast::parse(
+            "from articles | rank by bm25(body, \"vector search\") | filter score > 0 | select [id, score]"
+        )?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "score"]);
+        let ids: Vec<_> = result.rows.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(ids, vec![serde_json::json!(2), serde_json::json!(3)]);
+
+        // The first query builds the index, and later ones reuse it
+        assert_eq!(fts_columns(executor.connection(), "articles")?, vec!["body"]);
+        let ir_program = mlql_ast::parse("from articles | filter bm25(body, \"ducks\") > 0")?.to_ir();
+        assert_eq!(executor.execute_ir(&ir_program, None)?.row_count, 1);
+        assert_eq!(fts_columns(executor.connection(), "articles")?, vec!["body"]);
+
+        // It isn't rebuilt for a column it doesn't cover
+        let ir_program = mlql_ast::parse("from articles | filter bm25(title, \"ducks\") > 0")?.to_ir();
+        let err = executor.execute_ir(&ir_program, None).expect_err("title is not indexed");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("doesn't cover title"), "{}", err);
+        executor.connection().execute_batch("PRAGMA drop_fts_index('articles')")?;
+
+        // Every column searched by the query that builds it is covered
+        let ir_program = mlql_ast::parse(
+            "from articles | filter bm25(title, \"ducks\") > 0 | rank by bm25(body, \"pond\")"
+        )?.to_ir();
+        assert_eq!(executor.execute_ir(&ir_program, None)?.row_count, 1);
+        assert_eq!(fts_columns(executor.connection(), "articles")?, vec!["title", "body"]);
+
+        // The schema ignores the tables of the index
+        let schema = executor.infer_schema(&mlql_ast::parse("from articles")?.to_ir());
+        assert_eq!(schema.fields.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bm25_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        articles(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "let recent = from articles | filter id > 1;
+             from recent | rank by bm25(body, \"vector\")"
+        )?.to_ir();
+        let err = executor.execute_ir(&ir_program, None).expect_err("a let binding has no index");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("the pipeline must read from a table"), "{}", err);
+
+        let ir_program = mlql_ast::parse("from articles | select [body] | rank by bm25(body, \"vector\")")?.to_ir();
+        let err = executor.execute_ir(&ir_program, None).expect_err("the key was dropped");
+        assert!(err.to_string().contains("needs the key column id of articles"), "{}", err);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! Graph traversal: lowering `neighbors` to a recursive CTE
+//!
+//! `from graph(social) g | neighbors start: 42 depth: 2` keeps the node rows
+//! reachable from node 42 in one or two hops, with the number of hops and
+//! the ids along a shortest path, which replace input columns of the same
+//! names:
+//!
+//! ```sql
+//! SELECT "g".*, "_reached"."hops", "_reached"."path"
+//! FROM (SELECT COLUMNS(lambda c: c NOT IN ('hops', 'path')) FROM (SELECT * FROM "people" AS "g") AS "g") AS "g"
+//! JOIN (
+//!     WITH RECURSIVE "_edges"(src, dst) AS (SELECT "src", "dst" FROM "follows"),
+//!     "_walk"(node, hops, path) AS (
+//!         SELECT "id", 0, ["id"] FROM "people" WHERE "id" = ?
+//!         UNION ALL
+//!         SELECT e.dst, w.hops + 1, list_append(w.path, e.dst)
+//!         FROM "_walk" AS w JOIN "_edges" AS e ON e.src = w.node
+//!         WHERE w.hops < 2 AND NOT list_contains(w.path, e.dst)
+//!         QUALIFY row_number() OVER (PARTITION BY e.dst ORDER BY w.path) = 1
+//!     )
+//!     SELECT node, min(hops) AS hops, arg_min(path, hops) AS path
+//!     FROM "_walk" WHERE hops > 0 GROUP BY node
+//! ) AS "_reached" ON "g"."id" = "_reached"."node"
+//! ORDER BY "_reached"."hops", "g"."id"
+//! ```
+//!
+//! A walk never revisits a node on its own path, so cycles end it, and the
+//! start node itself is not a neighbor. Each hop keeps one walk per node
+//! reached, so a hop's rows are bounded by the nodes rather than growing
+//! with the number of paths; a shortest path to every node survives, since
+//! its prefixes are shortest paths too.
+
+use crate::{expr_to_sql, quote_ident, CompiledQuery, ExecutionError};
+use mlql_ir::validate::MAX_NEIGHBORS_DEPTH;
+use mlql_ir::Expr;
+use mlql_registry::{GraphDef, GraphRegistry};
+
+/// The graph registered as `name`
+pub(crate) fn graph<'a>(graphs: &'a GraphRegistry, name: &str) -> Result<&'a GraphDef, ExecutionError> {
+    graphs.get(name).ok_or_else(|| ExecutionError::SqlError(format!("Graph not found: {}", name)))
+}
+
+/// Edges to follow, as `(src, dst)` rows, in both directions for an undirected
+/// graph and of type `edge` if given
+fn edges_sql(graph: &GraphDef, edge: Option<&str>, params: &mut Vec<crate::QueryParam>) -> Result<String, ExecutionError> {
+    let filter = match (edge, &graph.edge_type) {
+        (None, _) => String::new(),
+        (Some(_), None) => {
+            return Err(ExecutionError::SqlError(format!(
+                "Graph edges in {} have no type column, so edge: cannot select them",
+                graph.edges
+            )));
+        }
+        (Some(_), Some(column)) => format!(" WHERE {} = ?", quote_ident(column)),
+    };
+    let mut direction = |from: &str, to: &str| {
+        if let Some(edge) = edge {
+            params.push(crate::QueryParam::String(edge.to_string()));
+        }
+        format!("SELECT {}, {} FROM {}{}", quote_ident(from), quote_ident(to), quote_ident(&graph.edges), filter)
+    };
+
+    let mut sql = direction(&graph.src, &graph.dst);
+    if graph.undirected {
+        sql = format!("{} UNION ALL {}", sql, direction(&graph.dst, &graph.src));
+    }
+    Ok(sql)
+}
+
+/// Rows of `input` (read as `alias`) reachable from `start` in at most
+/// `depth` hops, with `hops` and `path` columns, nearest first
+pub(crate) fn neighbors_sql(
+    input: CompiledQuery,
+    alias: &str,
+    graph: &GraphDef,
+    start: &Expr,
+    depth: i64,
+    edge: Option<&str>,
+) -> Result<(CompiledQuery, String), ExecutionError> {
+    if !(0..=MAX_NEIGHBORS_DEPTH).contains(&depth) {
+        return Err(ExecutionError::SqlError(format!(
+            "neighbors depth must be between 0 and {}, got {}",
+            MAX_NEIGHBORS_DEPTH, depth
+        )));
+    }
+
+    let mut params = input.params;
+    let edges = edges_sql(graph, edge, &mut params)?;
+    let id = quote_ident(&graph.node_id);
+    let start = expr_to_sql(start, &mut params)?;
+    let walk = format!(
+        "WITH RECURSIVE \"_edges\"(src, dst) AS ({edges}), \
+         \"_walk\"(node, hops, path) AS (\
+         SELECT {id}, 0, [{id}] FROM {nodes} WHERE {id} = {start} \
+         UNION ALL \
+         SELECT e.dst, w.hops + 1, list_append(w.path, e.dst) \
+         FROM \"_walk\" AS w JOIN \"_edges\" AS e ON e.src = w.node \
+         WHERE w.hops < {depth} AND NOT list_contains(w.path, e.dst) \
+         QUALIFY row_number() OVER (PARTITION BY e.dst ORDER BY w.path) = 1) \
+         SELECT node, min(hops) AS hops, arg_min(path, hops) AS path \
+         FROM \"_walk\" WHERE hops > 0 GROUP BY node",
+        nodes = quote_ident(&graph.nodes),
+    );
+
+    let alias = quote_ident(alias);
+    let from = CompiledQuery {
+        sql: format!(
+            "(SELECT COLUMNS(lambda c: c NOT IN ('hops', 'path')) FROM ({}) AS {alias}) AS {alias} \
+             JOIN ({walk}) AS \"_reached\" ON {alias}.{id} = \"_reached\".\"node\"",
+            input.sql
+        ),
+        params,
+    };
+    let select = format!("{alias}.*, \"_reached\".\"hops\", \"_reached\".\"path\"");
+    Ok((from, select))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir_to_sql, DuckExecutor};
+    use mlql_ir::OpLocation;
+
+    fn social() -> Result<DuckExecutor, Box<dyn std::error::Error>> {
+        let mut graph = GraphDef::new("people", "follows");
+        graph.edge_type = Some("kind".to_string());
+        let mut graphs = GraphRegistry::default();
+        graphs.register("social", graph);
+
+        let executor = DuckExecutor::new()?.with_graphs(graphs);
+        executor.connection().execute_batch(
+            "CREATE TABLE people (id INTEGER, name VARCHAR);
+             CREATE TABLE follows (src INTEGER, dst INTEGER, kind VARCHAR);
+             INSERT INTO people VALUES (1, 'ann'), (2, 'bob'), (3, 'cy'), (4, 'dee'), (5, 'eve');
+             INSERT INTO follows VALUES
+                (1, 2, 'friend'), (2, 3, 'friend'), (3, 1, 'friend'),
+                (3, 4, 'colleague'), (4, 5, 'friend');"
+        )?;
+        Ok(executor)
+    }
+
+    fn rows(executor: &DuckExecutor, mlql: &str) -> Result<Vec<Vec<serde_json::Value>>, Box<dyn std::error::Error>> {
+        let result = executor.execute_ir(&mlql_ast::parse(mlql)?.to_ir(), None)?;
+        Ok(result.rows)
+    }
+
+    #[test]
+    fn test_neighbors() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = social()?;
+
+        // The cycle back to 1 is not followed, and 5 is four hops away
+        let ir_program = mlql_ast::parse("from graph(social) g | neighbors start: 1 depth: 3")?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "name", "hops", "path"]);
+        let reached: Vec<_> = result.rows.iter().map(|row| (row[0].clone(), row[2].clone())).collect();
+        assert_eq!(reached, vec![
+            (serde_json::json!(2), serde_json::json!(1)),
+            (serde_json::json!(3), serde_json::json!(2)),
+            (serde_json::json!(4), serde_json::json!(3)),
+        ]);
+        assert_eq!(result.rows[1][3], serde_json::json!([1, 2, 3]));
+
+        // Only edges of the given type
+        let friends = rows(&executor, "from graph(social) g | neighbors start: 3 depth: 5 edge: friend | select [g.name] | sort name")?;
+        assert_eq!(friends, vec![vec![serde_json::json!("ann")], vec![serde_json::json!("bob")]]);
+
+        // Operators before neighbors choose which nodes are returned
+        let named = rows(&executor, "from graph(social) g | filter name != \"bob\" | neighbors start: 1 depth: 2 | select [id]")?;
+        assert_eq!(named, vec![vec![serde_json::json!(3)]]);
+
+        // hops and path replace input columns of the same names
+        let ir_program = mlql_ast::parse("from graph(social) g | select [id, id as hops] | neighbors start: 1 depth: 1")?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "hops", "path"]);
+        assert_eq!(result.rows, vec![vec![serde_json::json!(2), serde_json::json!(1), serde_json::json!([1, 2])]]);
+
+        // A graph source alone reads the nodes
+        assert_eq!(rows(&executor, "from graph(social) g | filter g.id > 3")?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_neighbors_undirected() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = social()?;
+        let mut graph = GraphDef::new("people", "follows");
+        graph.undirected = true;
+        let mut graphs = GraphRegistry::default();
+        graphs.register("social", graph);
+        let executor = executor.with_graphs(graphs);
+
+        let reached = rows(&executor, "from graph(social) g | neighbors start: 5 depth: 1 | select [id]")?;
+        assert_eq!(reached, vec![vec![serde_json::json!(4)]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_neighbors_complete_graph() -> Result<(), Box<dyn std::error::Error>> {
+        let mut graphs = GraphRegistry::default();
+        graphs.register("clique", GraphDef::new("nodes", "edges"));
+        let executor = DuckExecutor::new()?.with_graphs(graphs);
+
+        // Every node follows every other, so there are billions of simple paths
+        executor.connection().execute_batch(
+            "CREATE TABLE nodes AS SELECT range::INTEGER AS id FROM range(12);
+             CREATE TABLE edges AS SELECT a.id AS src, b.id AS dst FROM nodes AS a, nodes AS b WHERE a.id != b.id;"
+        )?;
+
+        let reached = rows(&executor, "from graph(clique) c | neighbors start: 0 depth: 10 | select [hops]")?;
+        assert_eq!(reached.len(), 11);
+        assert!(reached.iter().all(|row| row[0] == serde_json::json!(1)), "{:?}", reached);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_neighbors_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let graphs = GraphRegistry::default();
+
+        let ir_program = mlql_ast::parse("from graph(missing) g | neighbors start: 1 depth: 2")?.to_ir();
+        let err = ir_to_sql(&ir_program, &graphs).expect_err("no such graph");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: None }));
+        assert!(err.to_string().contains("Graph not found: missing"), "{}", err);
+
+        let ir_program = mlql_ast::parse("from people | neighbors start: 1 depth: 2")?.to_ir();
+        let err = ir_to_sql(&ir_program, &graphs).expect_err("not a graph");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+
+        let mut graphs = GraphRegistry::default();
+        graphs.register("social", GraphDef::new("people", "follows"));
+        let ir_program = mlql_ast::parse("from graph(social) g | neighbors start: 1 depth: 2 edge: friend")?.to_ir();
+        let err = ir_to_sql(&ir_program, &graphs).expect_err("the graph has no edge types");
+        assert!(err.to_string().contains("have no type column"), "{}", err);
+
+        let ir_program = mlql_ast::parse("from graph(social) g | neighbors start: 1 depth: 11")?.to_ir();
+        let err = ir_to_sql(&ir_program, &graphs).expect_err("too deep");
+        assert!(err.to_string().contains("neighbors depth must be between 0 and 10, got 11"), "{}", err);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! Vector search: lowering `knn` to DuckDB array distance functions
+//!
+//! `knn q: <0.1, 0.2, 0.3> k: 10 on: embedding metric: cosine` becomes
+//!
+//! ```sql
+//! SELECT COLUMNS(lambda c: c NOT IN ('distance')),
+//!     array_cosine_distance("embedding", CAST([0.1, 0.2, 0.3] AS FLOAT[3])) AS "distance"
+//! FROM ... ORDER BY "distance" ASC LIMIT 10
+//! ```
+//!
+//! The `distance` column replaces an input column of the same name.
+//!
+//! With the `vss` extension loaded, DuckDB answers that shape from an HNSW
+//! index on the column built with the same metric; otherwise it scans. The
+//! index is only used for a constant query vector, so a vector literal is
+//! written into the SQL rather than bound.
+
+use crate::{column_ref_to_sql, expr_to_sql, DuckExecutor, ExecutionError, QueryParam};
+use mlql_ir::{ColumnRef, DataType, Expr, OpLocation, Operator, Program};
+use std::borrow::Cow;
+
+/// DuckDB function giving the distance for a knn metric, smallest nearest.
+/// `l2` is the default.
+pub(crate) fn distance_function(metric: Option<&str>) -> Result<&'static str, ExecutionError> {
+    match metric.unwrap_or("l2") {
+        "l2" => Ok("array_distance"),
+        // 1 - array_cosine_similarity
+        "cosine" => Ok("array_cosine_distance"),
+        "ip" => Ok("array_negative_inner_product"),
+        other => Err(ExecutionError::SqlError(format!(
+            "Unknown knn metric '{}'. Supported: l2, cosine, ip",
+            other
+        ))),
+    }
+}
+
+/// SQL for the distance between the `on` column and the query vector
+pub(crate) fn distance_sql(
+    on: Option<&ColumnRef>,
+    query: &Expr,
+    metric: Option<&str>,
+    params: &mut Vec<QueryParam>,
+) -> Result<String, ExecutionError> {
+    let function = distance_function(metric)?;
+    let on = on.ok_or_else(|| ExecutionError::SqlError(
+        "knn needs the vector column to search; add on: <column>".to_string()
+    ))?;
+    let query = match query {
+        Expr::Vector { values } if !values.is_empty() && values.iter().all(|v| v.is_finite()) => {
+            let elements: Vec<String> = values.iter().map(f64::to_string).collect();
+            format!("CAST([{}] AS FLOAT[{}])", elements.join(", "), values.len())
+        }
+        query => expr_to_sql(query, params)?,
+    };
+    Ok(format!("{}({}, {})", function, column_ref_to_sql(on), query))
+}
+
+/// Column and metric of an HNSW index
+#[derive(Debug, PartialEq)]
+struct HnswIndex {
+    column: String,
+    metric: String,
+}
+
+/// Read the column and metric from an index's `CREATE INDEX` statement, or
+/// `None` if it is not an HNSW index.
+///
+/// DuckDB's `l2sq` metric orders rows the same as `l2`.
+fn parse_hnsw_index(sql: &str) -> Option<HnswIndex> {
+    // ASCII uppercasing keeps byte offsets, so positions carry over to `sql`
+    let upper = sql.to_ascii_uppercase();
+    let using = upper.find("USING HNSW")?;
+    let open = using + sql[using..].find('(')?;
+    let close = open + sql[open..].find(')')?;
+    let column = sql[open + 1..close].trim().trim_matches('"').to_string();
+
+    let metric = upper[close..].find("METRIC").and_then(|at| {
+        let rest = &sql[close + at..];
+        let start = rest.find('\'')? + 1;
+        let len = rest[start..].find('\'')?;
+        Some(rest[start..start + len].to_ascii_lowercase())
+    });
+    let metric = match metric.as_deref() {
+        None | Some("l2sq") => "l2".to_string(),
+        Some(metric) => metric.to_string(),
+    };
+    Some(HnswIndex { column, metric })
+}
+
+impl DuckExecutor {
+    /// Fill in the column and metric of each `knn` operator from the HNSW index
+    /// it names, or the column from the input's only vector column.
+    ///
+    /// Loads the `vss` extension when an index is named; if it can't be
+    /// loaded the search scans instead. Operators inside sub-pipelines are left
+    /// as written and need `on:`.
+    pub(crate) fn resolve_knn<'a>(&self, program: &'a Program) -> Result<Cow<'a, Program>, ExecutionError> {
+        let needs_resolving = |op: &Operator| matches!(op,
+            Operator::Knn { on: None, .. } | Operator::Knn { index: Some(_), .. });
+        let pipelines = program.lets.iter().map(|binding| &binding.pipeline).chain([&program.pipeline]);
+        if !pipelines.flat_map(|pipeline| &pipeline.ops).any(needs_resolving) {
+            return Ok(Cow::Borrowed(program));
+        }
+
+        let mut resolved = program.clone();
+        for b in 0..=program.lets.len() {
+            let binding = (b < program.lets.len()).then_some(b);
+            let pipeline = match binding {
+                Some(b) => &mut resolved.lets[b].pipeline,
+                None => &mut resolved.pipeline,
+            };
+            for (i, op) in pipeline.ops.iter_mut().enumerate() {
+                let Operator::Knn { on, index: index_name, metric, .. } = op else {
+                    continue;
+                };
+                let location = OpLocation { binding, op: Some(i) };
+
+                let index = match index_name {
+                    Some(name) => Some(self.hnsw_index(name).map_err(|e| e.at(location))?),
+                    None => None,
+                };
+                if on.is_none() {
+                    *on = Some(match &index {
+                        Some(index) => ColumnRef { table: None, column: index.column.clone() },
+                        None => self.vector_column(program, binding, i).map_err(|e| e.at(location))?,
+                    });
+                }
+                // DuckDB only searches the index with the metric it was built for
+                match (&metric, index) {
+                    (None, Some(index)) => *metric = Some(index.metric),
+                    (Some(m), Some(index)) if *m != index.metric => {
+                        return Err(ExecutionError::SqlError(format!(
+                            "knn metric '{}' differs from the '{}' metric of index '{}'",
+                            m,
+                            index.metric,
+                            index_name.as_deref().unwrap_or_default(),
+                        )).at(location));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Cow::Owned(resolved))
+    }
+
+    /// Look up the HNSW index `name`, loading `vss` so DuckDB can use it
+    fn hnsw_index(&self, name: &str) -> Result<HnswIndex, ExecutionError> {
+        if let Err(e) = self.conn.execute_batch("LOAD vss") {
+            tracing::warn!("Cannot load the vss extension, knn will scan instead of using index {}: {}", name, e);
+        }
+
+        let sql: Option<String> = match self.conn.query_row(
+            "SELECT sql FROM duckdb_indexes() WHERE index_name = ?",
+            [name],
+            |row| row.get(0),
+        ) {
+            Ok(sql) => sql,
+            Err(duckdb::Error::QueryReturnedNoRows) => {
+                return Err(ExecutionError::SqlError(format!("Index '{}' not found", name)));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        sql.as_deref()
+            .and_then(parse_hnsw_index)
+            .ok_or_else(|| ExecutionError::SqlError(format!("Index '{}' is not an HNSW index", name)))
+    }
+
+    /// The only vector column in the input of operator `op` of a pipeline
+    fn vector_column(&self, program: &Program, binding: Option<usize>, op: usize) -> Result<ColumnRef, ExecutionError> {
+        let schema = self.op_input_schema(program, binding, op);
+        let vectors: Vec<&str> = schema.fields.iter()
+            .filter(|f| matches!(f.data_type, DataType::Vector(_)))
+            .map(|f| f.name.as_str())
+            .collect();
+        match vectors.as_slice() {
+            [column] => Ok(ColumnRef { table: None, column: column.to_string() }),
+            [] => Err(ExecutionError::SqlError(
+                "knn input has no vector column; add on: <column>".to_string()
+            )),
+            _ => Err(ExecutionError::SqlError(format!(
+                "knn input has several vector columns ({}); choose one with on:",
+                vectors.join(", ")
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_to_sql;
+    use mlql_registry::GraphRegistry;
+
+    fn docs(executor: &DuckExecutor) -> Result<(), Box<dyn std::error::Error>> {
+        executor.connection().execute_batch(
+            "CREATE TABLE docs (id INTEGER, title VARCHAR, embedding FLOAT[3]);
+             INSERT INTO docs VALUES
+                (1, 'north', [0.0, 1.0, 0.0]),
+                (2, 'east', [1.0, 0.0, 0.0]),
+                (3, 'far east', [10.0, 0.0, 0.0]),
+                (4, 'north east', [1.0, 0.5, 0.0]);"
+        )?;
+        Ok(())
+    }
+
+    fn ids(result: &crate::QueryResult) -> Vec<serde_json::Value> {
+        result.rows.iter().map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    fn test_knn_sql() -> Result<(), Box<dyn std::error::Error>> {
+        let ir_program = mlql_ast::parse(
+            "from docs | filter id > 1 | knn q: <1, 0, 0> k: 2 on: embedding metric: cosine"
+        )?.to_ir();
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert_eq!(
+            query.sql,
+            "SELECT COLUMNS(lambda c: c NOT IN ('distance')), \
+             array_cosine_distance(\"embedding\", CAST([1, 0, 0] AS FLOAT[3])) AS \"distance\" \
+             FROM \"docs\" WHERE (\"id\" > ?) ORDER BY \"distance\" ASC LIMIT 2"
+        );
+        assert_eq!(query.params, vec![QueryParam::Int(1)]);
+
+        // Without a column there is nothing to compare
+        let ir_program = mlql_ast::parse("from docs | knn q: <1, 0, 0> k: 2")?.to_ir();
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("knn needs a column");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+
+        let ir_program = mlql_ast::parse("from docs | knn q: <1, 0, 0> k: 2 on: embedding metric: dot")?.to_ir();
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("dot is not a metric");
+        assert!(err.to_string().contains("Unknown knn metric 'dot'"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_knn() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        docs(&executor)?;
+
+        // l2: the far east point is furthest
+        let ir_program = mlql_ast::parse("from docs | knn q: <1, 0, 0> k: 3 on: embedding")?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "title", "embedding", "distance"]);
+        assert_eq!(ids(&result), vec![serde_json::json!(2), serde_json::json!(4), serde_json::json!(1)]);
+        assert_eq!(result.rows[0][3], serde_json::json!(0.0));
+
+        // cosine: the far east point points the same way as the query
+        let ir_program = mlql_ast::parse("from docs | knn q: <1, 0, 0> k: 2 metric: cosine | sort id")?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(ids(&result), vec![serde_json::json!(2), serde_json::json!(3)]);
+
+        // The column comes from the input when it has a single vector column
+        let ir_program = mlql_ast::parse(
+            "let near = from docs | select [id, embedding] | knn q: <0, 1, 0> k: 1 metric: ip;
+             from near"
+        )?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(ids(&result), vec![serde_json::json!(1)]);
+
+        // A second search replaces the first one's distance
+        let ir_program = mlql_ast::parse(
+            "from docs | knn q: <1, 0, 0> k: 3 on: embedding | knn q: <0, 1, 0> k: 1 on: embedding"
+        )?.to_ir();
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "title", "embedding", "distance"]);
+        assert_eq!(ids(&result), vec![serde_json::json!(1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_knn_index_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        docs(&executor)?;
+        executor.connection().execute_batch("CREATE INDEX docs_id ON docs (id)")?;
+
+        let ir_program = mlql_ast::parse("from docs | knn q: <1, 0, 0> k: 1 index: missing")?.to_ir();
+        let err = executor.execute_ir(&ir_program, None).expect_err("no such index");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("Index 'missing' not found"), "{}", err);
+
+        let ir_program = mlql_ast::parse("from docs | knn q: <1, 0, 0> k: 1 index: docs_id")?.to_ir();
+        let err = executor.execute_ir(&ir_program, None).expect_err("not a vector index");
+        assert!(err.to_string().contains("Index 'docs_id' is not an HNSW index"), "{}", err);
+
+        // The parser rejects `<>`, but IR JSON may hold an empty vector
+        let ir_program: mlql_ir::Program = serde_json::from_value(serde_json::json!({
+            "pipeline": {
+                "source": {"type": "Table", "name": "docs"},
+                "ops": [{"op": "Knn", "query": {"type": "Vector", "values": []}, "k": 1, "on": {"column": "embedding"}}]
+            }
+        }))?;
+        let err = executor.execute_ir(&ir_program, None).expect_err("empty vector");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("vector literal must have at least one element"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_knn_hnsw_index() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        docs(&executor)?;
+        executor.connection().execute_batch(
+            "LOAD vss;
+             CREATE INDEX docs_hnsw ON docs USING HNSW (embedding) WITH (metric = 'cosine');"
+        )?;
+
+        // The column and metric come from the index, and DuckDB searches it
+        let ir_program = mlql_ast::parse("from docs | knn q: <0, 1, 0> k: 1 index: docs_hnsw")?.to_ir();
+        let resolved = executor.resolve_knn(&ir_program)?;
+        let query = ir_to_sql(&resolved, &GraphRegistry::default())?;
+        assert!(query.params.is_empty(), "{:?}", query.params);
+        let plan: String = executor.connection().query_row(&format!("EXPLAIN {}", query.sql), [], |row| row.get(1))?;
+        assert!(plan.contains("HNSW_INDEX_SCAN"), "{}", plan);
+        assert_eq!(ids(&executor.execute_ir(&ir_program, None)?), vec![serde_json::json!(1)]);
+
+        // Another metric could not use the index
+        let ir_program = mlql_ast::parse("from docs | knn q: <0, 1, 0> k: 1 index: docs_hnsw metric: l2")?.to_ir();
+        let err = executor.execute_ir(&ir_program, None).expect_err("the index is for cosine");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("knn metric 'l2' differs from the 'cosine' metric of index 'docs_hnsw'"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_hnsw_index() {
+        assert_eq!(
+            parse_hnsw_index("CREATE INDEX idx ON docs USING HNSW (embedding) WITH (metric = 'cosine');"),
+            Some(HnswIndex { column: "embedding".to_string(), metric: "cosine".to_string() })
+        );
+        assert_eq!(
+            parse_hnsw_index("CREATE INDEX idx ON docs using hnsw (\"Embedding\");"),
+            Some(HnswIndex { column: "Embedding".to_string(), metric: "l2".to_string() })
+        );
+        assert_eq!(
+            parse_hnsw_index("CREATE INDEX idx ON docs USING HNSW (embedding) WITH (metric = 'l2sq')").unwrap().metric,
+            "l2"
+        );
+        assert_eq!(parse_hnsw_index("CREATE INDEX docs_id ON docs (id);"), None);
+    }
+}
@@ -1,8 +1,18 @@
 //! DuckDB executor for Substrait plans
 
 use duckdb::{Connection, Result as DuckResult};
+use mlql_ir::OpLocation;
+use mlql_registry::{GraphDef, GraphRegistry};
+use std::time::Instant;
 use thiserror::Error;
 
+mod fts;
+mod graph;
+mod knn;
+mod timeseries;
+
+pub use fts::fts_columns;
+
 #[derive(Debug, Error)]
 pub enum ExecutionError {
     #[error("Database error: {0}")]
@@ -16,64 +26,242 @@ pub enum ExecutionError {
 
     #[error("SQL generation failed: {0}")]
     SqlError(String),
+
+    /// An error raised while generating SQL for the source or operator at `location`
+    #[error("{location}: {error}")]
+    At { location: OpLocation, error: Box<ExecutionError> },
+}
+
+impl ExecutionError {
+    /// Attach the location of the failing source or operator
+    fn at(self, location: OpLocation) -> Self {
+        match self {
+            ExecutionError::At { .. } => self,
+            error => ExecutionError::At { location, error: Box::new(error) },
+        }
+    }
+
+    /// The error without its location, to be attached to an enclosing operator
+    fn into_root(self) -> Self {
+        match self {
+            ExecutionError::At { error, .. } => error.into_root(),
+            other => other,
+        }
+    }
+
+    /// Location of the source or operator that failed, if known
+    pub fn location(&self) -> Option<OpLocation> {
+        match self {
+            ExecutionError::At { location, .. } => Some(*location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location
+    pub fn root(&self) -> &ExecutionError {
+        match self {
+            ExecutionError::At { error, .. } => error.root(),
+            other => other,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct ExecutionBudget {
     pub max_time_ms: Option<u64>,
     pub max_memory_mb: Option<u64>,
     pub max_rows: Option<u64>,
 }
 
+impl ExecutionBudget {
+    /// Build a budget from the recognized pragma keys
+    /// (`timeout`, `memory_mb`, `budget.rows_out`)
+    pub fn from_pragma(pragma: &mlql_ir::Pragma) -> Self {
+        Self {
+            max_time_ms: pragma.timeout_ms(),
+            max_memory_mb: pragma.memory_mb(),
+            max_rows: pragma.rows_out(),
+        }
+    }
+
+    /// Fill any unset limits from `fallback`
+    pub fn or(self, fallback: ExecutionBudget) -> Self {
+        Self {
+            max_time_ms: self.max_time_ms.or(fallback.max_time_ms),
+            max_memory_mb: self.max_memory_mb.or(fallback.max_memory_mb),
+            max_rows: self.max_rows.or(fallback.max_rows),
+        }
+    }
+
+    /// Apply connection-level limits (memory) before running a query
+    ///
+    /// Returns the memory limit that was in effect before, which the caller
+    /// passes to [`ExecutionBudget::restore`] once the query is done so the
+    /// limit doesn't carry over to later queries on the same connection.
+    pub fn apply(&self, conn: &Connection) -> Result<Option<String>, ExecutionError> {
+        let Some(max_memory_mb) = self.max_memory_mb else {
+            return Ok(None);
+        };
+        let previous: String = conn.query_row(
+            "SELECT current_setting('memory_limit')", [], |row| row.get(0),
+        )?;
+        let pragma = format!("PRAGMA memory_limit='{}MB'", max_memory_mb);
+        conn.execute_batch(&pragma)?;
+        Ok(Some(previous))
+    }
+
+    /// Put back the memory limit returned by [`ExecutionBudget::apply`]
+    pub fn restore(conn: &Connection, memory_limit: &str) -> Result<(), ExecutionError> {
+        let pragma = format!("PRAGMA memory_limit='{}'", memory_limit.replace('\'', "''"));
+        conn.execute_batch(&pragma)?;
+        Ok(())
+    }
+
+    /// Check row and time limits while results are being fetched.
+    ///
+    /// DuckDB has no statement timeout, so the time limit is enforced
+    /// between fetched rows rather than by interrupting the query.
+    pub fn check(&self, rows: usize, started: Instant) -> Result<(), ExecutionError> {
+        if let Some(max_rows) = self.max_rows {
+            if rows as u64 > max_rows {
+                return Err(ExecutionError::BudgetExceeded(
+                    format!("Max rows ({}) exceeded", max_rows)
+                ));
+            }
+        }
+        if let Some(max_time_ms) = self.max_time_ms {
+            if started.elapsed().as_millis() > max_time_ms as u128 {
+                return Err(ExecutionError::Timeout);
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct DuckExecutor {
     conn: Connection,
+    /// Graphs that `from graph(<name>)` can read
+    graphs: GraphRegistry,
 }
 
 impl DuckExecutor {
     pub fn new() -> DuckResult<Self> {
         let conn = Connection::open_in_memory()?;
-        Ok(Self { conn })
+        Ok(Self::from_connection(conn))
     }
 
     pub fn open<P: AsRef<std::path::Path>>(path: P) -> DuckResult<Self> {
         let conn = Connection::open(path)?;
-        Ok(Self { conn })
+        Ok(Self::from_connection(conn))
     }
 
     pub fn from_connection(conn: Connection) -> Self {
-        Self { conn }
+        Self { conn, graphs: GraphRegistry::default() }
+    }
+
+    /// Use `graphs` for graph sources
+    pub fn with_graphs(mut self, graphs: GraphRegistry) -> Self {
+        self.graphs = graphs;
+        self
+    }
+
+    pub fn graphs(&self) -> &GraphRegistry {
+        &self.graphs
     }
 
     /// Execute MLQL IR program by converting to SQL
+    ///
+    /// Limits set in `budget` take precedence over those in the program's pragma.
     pub fn execute_ir(
         &self,
         program: &mlql_ir::Program,
         budget: Option<ExecutionBudget>,
     ) -> Result<QueryResult, ExecutionError> {
-        // Apply budget constraints
-        if let Some(ref budget) = budget {
-            self.apply_budget(budget)?;
+        let budget = match &program.pragma {
+            Some(pragma) => {
+                let unknown = pragma.unknown_keys();
+                if !unknown.is_empty() {
+                    tracing::warn!("Ignoring unknown pragma keys: {:?}", unknown);
+                }
+                Some(budget.unwrap_or_default().or(ExecutionBudget::from_pragma(pragma)))
+            }
+            None => budget,
+        };
+
+        // Apply budget constraints, and undo them whether or not the query
+        // succeeds since the connection outlives it
+        let previous_memory_limit = match &budget {
+            Some(budget) => self.apply_budget(budget)?,
+            None => None,
+        };
+        let result = self.execute_resolved(program, budget);
+        if let Some(memory_limit) = previous_memory_limit {
+            let restored = ExecutionBudget::restore(&self.conn, &memory_limit);
+            // A query error takes precedence over a failed restore
+            if result.is_ok() {
+                restored?;
+            }
         }
+        result
+    }
 
-        // Convert IR to SQL
-        let sql = ir_to_sql(program)?;
+    /// Resolve, compile and run a program once its budget has been applied
+    fn execute_resolved(
+        &self,
+        program: &mlql_ir::Program,
+        budget: Option<ExecutionBudget>,
+    ) -> Result<QueryResult, ExecutionError> {
+        // Convert IR to SQL, once knn operators know their vector column,
+        // bm25() calls their full-text index and resample its fill columns
+        let program = self.resolve_knn(program)?;
+        let program = self.resolve_fts(&program)?;
+        let program = self.resolve_resample(&program)?;
+        let query = ir_to_sql(&program, &self.graphs)?;
 
-        tracing::info!("Generated SQL: {}", sql);
+        tracing::info!("Generated SQL: {} params: {:?}", query.sql, query.params);
 
         // Execute SQL query
-        let mut result = self.execute_sql(&sql, budget)?;
-        result.sql = Some(sql);
+        let schema = self.infer_schema(&program);
+        let mut result = self.execute_sql(&query, budget)?;
+        if result.columns.is_empty() {
+            // No rows to read column names from
+            result.columns = schema.fields.iter().map(|f| f.name.clone()).collect();
+        }
+        result.sql = Some(query.sql);
+        result.schema = Some(schema);
         Ok(result)
     }
 
-    /// Execute SQL query directly
+    /// Output schema of `program` against the tables in this database
+    pub fn infer_schema(&self, program: &mlql_ir::Program) -> mlql_ir::Schema {
+        mlql_ir::infer_schema(program, self)
+    }
+
+    /// Schema of the rows reaching operator `op` of the main pipeline, or of
+    /// let binding `binding`
+    fn op_input_schema(&self, program: &mlql_ir::Program, binding: Option<usize>, op: usize) -> mlql_ir::Schema {
+        let (lets, pipeline) = match binding {
+            Some(b) => (&program.lets[..b], &program.lets[b].pipeline),
+            None => (&program.lets[..], &program.pipeline),
+        };
+        self.infer_schema(&mlql_ir::Program {
+            pragma: None,
+            lets: lets.to_vec(),
+            pipeline: mlql_ir::Pipeline { source: pipeline.source.clone(), ops: pipeline.ops[..op].to_vec() },
+        })
+    }
+
+    /// Execute a compiled query as a prepared statement with its parameters bound
     fn execute_sql(
         &self,
-        sql: &str,
+        query: &CompiledQuery,
         budget: Option<ExecutionBudget>,
     ) -> Result<QueryResult, ExecutionError> {
+        let started = Instant::now();
+
         // Execute query and collect rows
-        let mut stmt = self.conn.prepare(sql)?;
-        let mut rows = stmt.query([])?;
+        let mut stmt = self.conn.prepare(&query.sql)?;
+        let mut rows = stmt.query(duckdb::params_from_iter(&query.params))?;
 
         // Collect rows
         let mut result_rows = Vec::new();
@@ -102,6 +290,11 @@ impl DuckExecutor {
                     duckdb::types::ValueRef::SmallInt(i) => serde_json::Value::from(i),
                     duckdb::types::ValueRef::Int(i) => serde_json::Value::from(i),
                     duckdb::types::ValueRef::BigInt(i) => serde_json::Value::from(i),
+                    // sum() over integers is a HUGEINT
+                    duckdb::types::ValueRef::HugeInt(i) => match i64::try_from(i) {
+                        Ok(i) => serde_json::Value::from(i),
+                        Err(_) => serde_json::json!(i as f64),
+                    },
                     duckdb::types::ValueRef::Float(f) => serde_json::json!(f),
                     duckdb::types::ValueRef::Double(f) => serde_json::json!(f),
                     duckdb::types::ValueRef::Text(bytes) => {
@@ -109,7 +302,14 @@ impl DuckExecutor {
                         let s = std::str::from_utf8(bytes).unwrap_or("");
                         serde_json::Value::String(s.to_string())
                     },
-                    _ => serde_json::Value::Null,  // TODO: Handle more types
+                    // Timestamps and dates as DuckDB prints them
+                    duckdb::types::ValueRef::Timestamp(unit, t) => timestamp_to_json(unit, t),
+                    duckdb::types::ValueRef::Date32(days) => date_to_json(days),
+                    // Lists (neighbors paths) and fixed-size arrays (embeddings)
+                    _ => match row.get::<_, duckdb::types::Value>(i)? {
+                        value @ (duckdb::types::Value::List(_) | duckdb::types::Value::Array(_)) => value_to_json(value),
+                        _ => serde_json::Value::Null,  // TODO: Handle more types
+                    },
                 };
 
                 json_row.push(value);
@@ -118,43 +318,122 @@ impl DuckExecutor {
             result_rows.push(json_row);
             row_count += 1;
 
-            // Check row budget
+            // Check row and time budget
             if let Some(ref budget) = budget {
-                if let Some(max_rows) = budget.max_rows {
-                    if row_count >= max_rows as usize {
-                        return Err(ExecutionError::BudgetExceeded(
-                            format!("Max rows ({}) exceeded", max_rows)
-                        ));
-                    }
-                }
+                budget.check(row_count, started)?;
             }
         }
 
+        // The query may run past the time limit before producing any rows
+        if let Some(ref budget) = budget {
+            budget.check(row_count, started)?;
+        }
+
         Ok(QueryResult {
             columns: column_names,
             rows: result_rows,
             row_count,
             sql: None,
+            schema: None,
         })
     }
 
-    fn apply_budget(&self, budget: &ExecutionBudget) -> Result<(), ExecutionError> {
-        // Set PRAGMAs for resource limits
-        if let Some(max_memory_mb) = budget.max_memory_mb {
-            let pragma = format!("PRAGMA memory_limit='{}MB'", max_memory_mb);
-            self.conn.execute_batch(&pragma)
-                .map_err(|e| ExecutionError::Database(e))?;
+    fn apply_budget(&self, budget: &ExecutionBudget) -> Result<Option<String>, ExecutionError> {
+        // Row and time limits are checked while fetching in execute_sql
+        budget.apply(&self.conn)
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+/// JSON for a list element; types the row conversion doesn't handle are null
+fn value_to_json(value: duckdb::types::Value) -> serde_json::Value {
+    use duckdb::types::Value;
+
+    match value {
+        Value::Boolean(b) => serde_json::Value::Bool(b),
+        Value::TinyInt(i) => serde_json::Value::from(i),
+        Value::SmallInt(i) => serde_json::Value::from(i),
+        Value::Int(i) => serde_json::Value::from(i),
+        Value::BigInt(i) => serde_json::Value::from(i),
+        Value::Float(f) => serde_json::json!(f),
+        Value::Double(f) => serde_json::json!(f),
+        Value::Text(s) => serde_json::Value::String(s),
+        Value::Timestamp(unit, t) => timestamp_to_json(unit, t),
+        Value::Date32(days) => date_to_json(days),
+        Value::List(items) | Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(value_to_json).collect())
         }
+        _ => serde_json::Value::Null,
+    }
+}
 
-        // TODO: Set timeout (requires DuckDB interrupt mechanism)
-        // TODO: Set max rows (via FetchRel in Substrait plan)
+/// `2024-01-01 00:30:00`, as DuckDB prints a timestamp; null when out of range
+fn timestamp_to_json(unit: duckdb::types::TimeUnit, t: i64) -> serde_json::Value {
+    use duckdb::types::TimeUnit;
 
-        Ok(())
+    let micros = match unit {
+        TimeUnit::Second => t.checked_mul(1_000_000),
+        TimeUnit::Millisecond => t.checked_mul(1_000),
+        TimeUnit::Microsecond => Some(t),
+        TimeUnit::Nanosecond => Some(t / 1_000),
+    };
+    micros.and_then(chrono::DateTime::from_timestamp_micros)
+        .map_or(serde_json::Value::Null, |t| serde_json::Value::String(t.naive_utc().to_string()))
+}
+
+/// `2024-01-01`; null when out of range
+fn date_to_json(days: i32) -> serde_json::Value {
+    chrono::NaiveDate::from_ymd_opt(1970, 1, 1)
+        .and_then(|epoch| epoch.checked_add_signed(chrono::Duration::days(days.into())))
+        .map_or(serde_json::Value::Null, |date| serde_json::Value::String(date.to_string()))
+}
+
+impl mlql_ir::substrait::SchemaProvider for DuckExecutor {
+    fn get_table_schema(&self, table_name: &str) -> Result<mlql_ir::substrait::TableSchema, String> {
+        table_schema(&self.conn, table_name)
     }
+}
 
-    pub fn connection(&self) -> &Connection {
-        &self.conn
+/// Look up a table's columns in the DuckDB information_schema
+///
+/// DuckDB type names, including nested `LIST`, `STRUCT` and `MAP` types, are
+/// parsed with [`mlql_ir::DataType::from_sql`].
+pub fn table_schema(conn: &Connection, table_name: &str) -> Result<mlql_ir::substrait::TableSchema, String> {
+    let query = "
+        SELECT column_name, data_type, is_nullable
+        FROM information_schema.columns
+        WHERE table_name = ? AND table_schema = current_schema()
+        ORDER BY ordinal_position
+    ";
+
+    let mut stmt = conn.prepare(query)
+        .map_err(|e| format!("Failed to prepare schema query: {}", e))?;
+
+    let columns: Result<Vec<_>, _> = stmt
+        .query_map([table_name], |row| {
+            Ok(mlql_ir::substrait::ColumnInfo {
+                name: row.get(0)?,
+                data_type: mlql_ir::DataType::from_sql(&row.get::<_, String>(1)?),
+                nullable: row.get::<_, String>(2)? == "YES",
+            })
+        })
+        .map_err(|e| format!("Schema query failed: {}", e))?
+        .collect();
+
+    let columns = columns
+        .map_err(|e| format!("Failed to read schema rows: {}", e))?;
+
+    if columns.is_empty() {
+        return Err(format!("Table '{}' not found in database", table_name));
     }
+
+    Ok(mlql_ir::substrait::TableSchema {
+        name: table_name.to_string(),
+        columns,
+    })
 }
 
 #[derive(Debug)]
@@ -163,6 +442,45 @@ pub struct QueryResult {
     pub rows: Vec<Vec<serde_json::Value>>,
     pub row_count: usize,
     pub sql: Option<String>,
+    /// Output schema inferred from the program, when run from IR
+    pub schema: Option<mlql_ir::Schema>,
+}
+
+/// SQL generated from MLQL IR
+///
+/// Literals never appear in `sql`; each one is a `?` placeholder bound to
+/// the value at the same position in `params`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledQuery {
+    pub sql: String,
+    pub params: Vec<QueryParam>,
+}
+
+/// Value bound to a `?` placeholder of a [`CompiledQuery`]
+///
+/// Dates, times and timestamps are bound as strings and cast in SQL; arrays
+/// and objects become list and struct expressions with one placeholder per
+/// element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryParam {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl duckdb::ToSql for QueryParam {
+    fn to_sql(&self) -> DuckResult<duckdb::types::ToSqlOutput<'_>> {
+        use duckdb::types::{ToSqlOutput, Value, ValueRef};
+        Ok(match self {
+            QueryParam::Bool(b) => ToSqlOutput::Owned(Value::Boolean(*b)),
+            QueryParam::Int(i) => ToSqlOutput::Owned(Value::BigInt(*i)),
+            QueryParam::Float(f) => ToSqlOutput::Owned(Value::Double(*f)),
+            QueryParam::String(s) => ToSqlOutput::Borrowed(ValueRef::Text(s.as_bytes())),
+            QueryParam::Bytes(b) => ToSqlOutput::Borrowed(ValueRef::Blob(b)),
+        })
+    }
 }
 
 impl Default for DuckExecutor {
@@ -172,61 +490,214 @@ impl Default for DuckExecutor {
 }
 
 /// Convert MLQL IR to DuckDB SQL
-fn ir_to_sql(program: &mlql_ir::Program) -> Result<String, ExecutionError> {
-    let pipeline = &program.pipeline;
+///
+/// Let bindings become common table expressions (`WITH name AS (...)`), and
+/// graph sources read the tables registered in `graphs`. Literals are bound
+/// as parameters, in the order their placeholders appear.
+fn ir_to_sql(program: &mlql_ir::Program, graphs: &GraphRegistry) -> Result<CompiledQuery, ExecutionError> {
+    let mut scope = LetScope { bindings: Vec::new(), graphs };
+    let mut ctes = Vec::new();
+    let mut params = Vec::new();
+
+    for (idx, binding) in program.lets.iter().enumerate() {
+        let body = pipeline_to_sql(&binding.pipeline, Some(idx), &scope)?;
+
+        // A CTE may not share its name with a table read by the bindings so far
+        // (e.g. `let users = from users | ...`), with an earlier CTE or with
+        // a graph's table
+        let clashes = scope.resolve(&binding.name).is_some()
+            || program.lets[..=idx].iter().any(|b| reads_table(&b.pipeline, &binding.name))
+            || graphs.iter().any(|(_, graph)| graph.nodes == binding.name || graph.edges == binding.name);
+        let cte_name = if clashes {
+            format!("{}_{}", binding.name, idx)
+        } else {
+            binding.name.clone()
+        };
+
+        ctes.push(format!("{} AS ({})", quote_ident(&cte_name), body.sql));
+        params.extend(body.params);
+        scope.bindings.push((binding.name.clone(), cte_name));
+    }
+
+    let query = pipeline_to_sql(&program.pipeline, None, &scope)?;
+    params.extend(query.params);
+    let sql = if ctes.is_empty() {
+        query.sql
+    } else {
+        format!("WITH {} {}", ctes.join(", "), query.sql)
+    };
+    Ok(CompiledQuery { sql, params })
+}
+
+/// CTE names of the let bindings visible to a pipeline, and the graphs
+struct LetScope<'a> {
+    /// (binding name, CTE name); later bindings shadow earlier ones
+    bindings: Vec<(String, String)>,
+    graphs: &'a GraphRegistry,
+}
+
+impl LetScope<'_> {
+    fn resolve(&self, name: &str) -> Option<&str> {
+        self.bindings.iter().rev()
+            .find(|(binding, _)| binding == name)
+            .map(|(_, cte)| cte.as_str())
+    }
+
+    /// FROM/JOIN item for a table source, reading the CTE if `name` is a let binding
+    fn table_sql(&self, name: &str, alias: &Option<String>) -> String {
+        let table = self.resolve(name).unwrap_or(name);
+        match alias {
+            Some(a) => format!("{} AS {}", quote_ident(table), quote_ident(a)),
+            // Keep the binding name visible for qualified column references
+            None if table != name => format!("{} AS {}", quote_ident(table), quote_ident(name)),
+            None => quote_ident(table),
+        }
+    }
+}
+
+/// Whether a pipeline reads `name` as its source, a join source or a set
+/// operation source, including inside sub-pipelines
+fn reads_table(pipeline: &mlql_ir::Pipeline, name: &str) -> bool {
+    let reads = |source: &mlql_ir::Source| match source {
+        mlql_ir::Source::Table { name: n, .. } => n == name,
+        mlql_ir::Source::SubPipeline { pipeline, .. } => reads_table(pipeline, name),
+        mlql_ir::Source::Graph { .. } => false,
+    };
+    reads(&pipeline.source)
+        || pipeline.ops.iter().any(|op| match op {
+            mlql_ir::Operator::Join { source, .. }
+            | mlql_ir::Operator::Union { source, .. }
+            | mlql_ir::Operator::Except { source }
+            | mlql_ir::Operator::Intersect { source } => reads(source),
+            _ => false,
+        })
+}
 
+/// Convert a single pipeline to a SELECT statement
+///
+/// `binding` is the index of the let binding being converted (`None` for the
+/// main pipeline) and is attached to errors as their [`OpLocation`].
+fn pipeline_to_sql(pipeline: &mlql_ir::Pipeline, binding: Option<usize>, scope: &LetScope) -> Result<CompiledQuery, ExecutionError> {
     // Build SQL from operators, starting with the source table
-    let table_name = match &pipeline.source {
-        mlql_ir::Source::Table { name, alias } => {
-            if let Some(a) = alias {
-                format!("\"{}\" AS \"{}\"", name, a)
-            } else {
-                format!("\"{}\"", name)
-            }
+    let location = OpLocation { binding, op: None };
+    let table = source_sql(&pipeline.source, binding, scope)
+        .map_err(|e| e.into_root().at(location))?;
+    let (qualifier, graph) = match &pipeline.source {
+        mlql_ir::Source::Table { name, alias } => (Some(alias.as_deref().unwrap_or(name)), None),
+        mlql_ir::Source::SubPipeline { alias, .. } => (alias.as_deref(), None),
+        mlql_ir::Source::Graph { graph_name, alias } => {
+            (Some(alias.as_str()), scope.graphs.get(graph_name))
         }
-        _ => return Err(ExecutionError::SqlError("Unsupported source type".to_string())),
     };
 
     // Build the SQL query by processing operators
-    build_sql_query(&table_name, &pipeline.ops)
+    build_sql_query(table, qualifier, graph, &pipeline.ops, binding, scope)
+}
+
+/// FROM/JOIN item for a source: a table or CTE, or a parenthesized
+/// sub-pipeline under its alias
+///
+/// Operators inside a sub-pipeline have no location of their own, so callers
+/// attach errors to the enclosing source or operator.
+fn source_sql(source: &mlql_ir::Source, binding: Option<usize>, scope: &LetScope) -> Result<CompiledQuery, ExecutionError> {
+    match source {
+        mlql_ir::Source::Table { name, alias } => Ok(CompiledQuery { sql: scope.table_sql(name, alias), params: Vec::new() }),
+        mlql_ir::Source::SubPipeline { pipeline, alias } => {
+            let query = pipeline_to_sql(pipeline, binding, scope)?;
+            let sql = match alias {
+                Some(a) => format!("({}) AS {}", query.sql, quote_ident(a)),
+                None => format!("({})", query.sql),
+            };
+            Ok(CompiledQuery { sql, params: query.params })
+        }
+        // A graph's rows are its nodes
+        mlql_ir::Source::Graph { graph_name, alias } => {
+            let graph = graph::graph(scope.graphs, graph_name)?;
+            Ok(CompiledQuery { sql: format!("{} AS {}", quote_ident(&graph.nodes), quote_ident(alias)), params: Vec::new() })
+        }
+    }
+}
+
+/// SELECT statement reading the right-hand source of a set operation
+fn source_query_sql(source: &mlql_ir::Source, binding: Option<usize>, scope: &LetScope) -> Result<CompiledQuery, ExecutionError> {
+    match source {
+        mlql_ir::Source::Table { name, alias } => Ok(CompiledQuery {
+            sql: format!("SELECT * FROM {}", scope.table_sql(name, alias)),
+            params: Vec::new(),
+        }),
+        mlql_ir::Source::SubPipeline { pipeline, .. } => pipeline_to_sql(pipeline, binding, scope),
+        mlql_ir::Source::Graph { graph_name, .. } => {
+            let graph = graph::graph(scope.graphs, graph_name)?;
+            Ok(CompiledQuery { sql: format!("SELECT * FROM {}", quote_ident(&graph.nodes)), params: Vec::new() })
+        }
+    }
 }
 
 /// Build SQL query from table and operators
-fn build_sql_query(table: &str, operators: &[mlql_ir::Operator]) -> Result<String, ExecutionError> {
-    let mut select_clause = "*".to_string();
-    let mut from_clause = table.to_string();
-    let mut where_clause = None;
-    let mut group_clause = None;
-    let mut order_clause = None;
-    let mut limit_clause = None;
-    let mut distinct = false;
+///
+/// Operators are applied in pipeline order. Each one is merged into the
+/// SELECT built so far when SQL's clause order gives the same result (a
+/// `sort` after a `filter` is just an ORDER BY); otherwise the query so far
+/// becomes a subquery and the operator starts a new SELECT around it.
+///
+/// `graph` is the graph the pipeline reads, for `neighbors`.
+fn build_sql_query(table: CompiledQuery, qualifier: Option<&str>, graph: Option<&GraphDef>, operators: &[mlql_ir::Operator], binding: Option<usize>, scope: &LetScope) -> Result<CompiledQuery, ExecutionError> {
+    let mut query = SqlQuery::new(table, qualifier.map(str::to_string));
 
     // Process operators in order
-    for op in operators {
+    for (i, op) in operators.iter().enumerate() {
+        let location = OpLocation { binding, op: Some(i) };
+        if !query.can_merge(op) {
+            // Keep the source's name so qualified column references still resolve
+            let alias = query.qualifier.clone().unwrap_or_else(|| format!("_q{}", i));
+            query = query.wrap(&alias);
+        }
+
+        let SqlQuery {
+            select_clause,
+            from_clause,
+            where_clause,
+            group_clause,
+            order_clause,
+            limit,
+            distinct,
+            qualifier,
+            select_params,
+            from_params,
+            where_params,
+            order_params,
+        } = &mut query;
         match op {
             mlql_ir::Operator::Select { projections } => {
                 // Build SELECT list
-                let select_items: Vec<String> = projections.iter().map(|proj| {
+                let mut params = Vec::new();
+                let select_items = projections.iter().map(|proj| {
                     match proj {
                         mlql_ir::Projection::Expr(expr) => {
                             // Check if it's a wildcard (column named "*")
                             if let mlql_ir::Expr::Column { col } = expr {
                                 if col.column == "*" && col.table.is_none() {
-                                    return "*".to_string();
+                                    return Ok("*".to_string());
                                 }
                             }
-                            expr_to_sql(expr)
+                            expr_to_sql(expr, &mut params)
                         }
                         mlql_ir::Projection::Aliased { expr, alias } => {
-                            format!("{} AS {}", expr_to_sql(expr), alias)
+                            Ok(format!("{} AS {}", expr_to_sql(expr, &mut params)?, quote_ident(alias)))
                         }
                     }
-                }).collect();
+                }).collect::<Result<Vec<_>, _>>().map_err(|e| e.at(location))?;
 
-                select_clause = select_items.join(", ");
+                *select_clause = select_items.join(", ");
+                *select_params = params;
             }
             mlql_ir::Operator::Filter { condition } => {
-                where_clause = Some(expr_to_sql(condition));
+                // Consecutive filters all apply
+                let condition = expr_to_sql(condition, where_params).map_err(|e| e.at(location))?;
+                *where_clause = Some(match where_clause.take() {
+                    Some(existing) => format!("{} AND {}", existing, condition),
+                    None => condition,
+                });
             }
             mlql_ir::Operator::Join { source, on, join_type } => {
                 // Build JOIN clause
@@ -236,27 +707,21 @@ fn build_sql_query(table: &str, operators: &[mlql_ir::Operator]) -> Result<Strin
                     Some(mlql_ir::JoinType::Right) => "RIGHT JOIN",
                     Some(mlql_ir::JoinType::Full) => "FULL OUTER JOIN",
                     Some(mlql_ir::JoinType::Cross) => "CROSS JOIN",
-                    Some(mlql_ir::JoinType::Semi) => return Err(ExecutionError::SqlError("SEMI JOIN not yet supported".to_string())),
-                    Some(mlql_ir::JoinType::Anti) => return Err(ExecutionError::SqlError("ANTI JOIN not yet supported".to_string())),
+                    Some(mlql_ir::JoinType::Semi) => return Err(ExecutionError::SqlError("SEMI JOIN not yet supported".to_string()).at(location)),
+                    Some(mlql_ir::JoinType::Anti) => return Err(ExecutionError::SqlError("ANTI JOIN not yet supported".to_string()).at(location)),
                 };
 
-                // Get the source table/alias
-                let source_sql = match source {
-                    mlql_ir::Source::Table { name, alias } => {
-                        if let Some(a) = alias {
-                            format!("{} AS {}", name, a)
-                        } else {
-                            name.clone()
-                        }
-                    }
-                    _ => return Err(ExecutionError::SqlError("Unsupported JOIN source type".to_string())),
-                };
+                // Get the source table, CTE or sub-pipeline
+                let source = source_sql(source, binding, scope)
+                    .map_err(|e| e.into_root().at(location))?;
+                from_params.extend(source.params);
 
                 // Build ON condition
-                let on_condition = expr_to_sql(on);
+                let on_condition = expr_to_sql(on, from_params).map_err(|e| e.at(location))?;
 
-                // Append to FROM clause
-                from_clause.push_str(&format!(" {} {} ON {}", join_type_sql, source_sql, on_condition));
+                // Append to FROM clause; the rows now come from several sources
+                from_clause.push_str(&format!(" {} {} ON {}", join_type_sql, source.sql, on_condition));
+                *qualifier = None;
             }
             mlql_ir::Operator::GroupBy { keys, aggs } => {
                 // Build GROUP BY keys
@@ -266,95 +731,432 @@ fn build_sql_query(table: &str, operators: &[mlql_ir::Operator]) -> Result<Strin
 
                 // Build SELECT clause with keys + aggregates
                 let mut select_items = group_keys.clone();
+                let mut params = Vec::new();
+                select_items.extend(aggs_to_sql(aggs, &mut params).map_err(|e| e.at(location))?);
 
-                for (alias, agg_call) in aggs.iter() {
-                    let agg_func = &agg_call.func;
-                    let agg_args: Vec<String> = agg_call.args.iter()
-                        .map(expr_to_sql)
-                        .collect();
-
-                    let agg_expr = if agg_args.is_empty() {
-                        // count(*) case
-                        format!("{}(*)", agg_func)
-                    } else {
-                        format!("{}({})", agg_func, agg_args.join(", "))
-                    };
-
-                    select_items.push(format!("{} AS {}", agg_expr, alias));
-                }
-
-                select_clause = select_items.join(", ");
+                *select_clause = select_items.join(", ");
+                *select_params = params;
                 // Only set group_clause if there are actual grouping keys
                 if !group_keys.is_empty() {
-                    group_clause = Some(group_keys.join(", "));
+                    *group_clause = Some(group_keys.join(", "));
                 }
             }
-            mlql_ir::Operator::Sort { keys } => {
-                let order_items: Vec<String> = keys.iter().map(|key| {
-                    let expr = expr_to_sql(&key.expr);
-                    if key.desc {
-                        format!("{} DESC", expr)
-                    } else {
-                        format!("{} ASC", expr)
-                    }
-                }).collect();
+            mlql_ir::Operator::Window { windows } => {
+                // Windows in name order, matching mlql_ir::infer_schema
+                let mut windows: Vec<_> = windows.iter().collect();
+                windows.sort_by(|a, b| a.0.cmp(b.0));
+
+                let window_items = windows.iter()
+                    .map(|(name, def)| Ok((name.as_str(), window_to_sql(def, select_params)?)))
+                    .collect::<Result<Vec<_>, ExecutionError>>()
+                    .map_err(|e| e.at(location))?;
 
-                order_clause = Some(order_items.join(", "));
+                *select_clause = replace_columns_sql(&window_items);
+            }
+            mlql_ir::Operator::Sort { keys } => {
+                // A later sort takes precedence; rows it ties keep the
+                // earlier order, as with a stable sort
+                let mut params = Vec::new();
+                let mut order_items = keys.iter()
+                    .map(|key| sort_key_to_sql(key, &mut params))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.at(location))?;
+                order_items.extend(order_clause.take());
+                params.append(order_params);
+
+                *order_clause = Some(order_items.join(", "));
+                *order_params = params;
             }
-            mlql_ir::Operator::Take { limit } => {
-                limit_clause = Some(limit.to_string());
+            mlql_ir::Operator::Take { limit: n } => {
+                *limit = Some(limit.map_or(*n, |existing| existing.min(*n)));
             }
             mlql_ir::Operator::Distinct => {
-                distinct = true;
+                *distinct = true;
+            }
+            mlql_ir::Operator::Agg { group_key, aggs } => {
+                query = timeseries::window_agg_sql(query, group_key, aggs, i).map_err(|e| e.at(location))?;
+            }
+            mlql_ir::Operator::Resample { interval, method, on, columns } => {
+                let alias = qualifier.clone().unwrap_or_else(|| format!("_q{}", i));
+                let from = timeseries::resample_sql(query.to_sql(), &alias, interval, method, on, columns.as_deref())
+                    .map_err(|e| e.at(location))?;
+
+                // In time order
+                query = SqlQuery::new(from, Some(alias));
+                query.order_clause = Some(quote_ident(&on.column));
+            }
+            mlql_ir::Operator::Neighbors { start, depth, edge } => {
+                let graph = graph.ok_or_else(|| ExecutionError::SqlError(
+                    "neighbors follows the edges of a graph; read from graph(<name>)".to_string()
+                ).at(location))?;
+                let alias = qualifier.clone().unwrap_or_else(|| format!("_q{}", i));
+                let (from, select) = graph::neighbors_sql(query.to_sql(), &alias, graph, start, *depth, edge.as_deref())
+                    .map_err(|e| e.at(location))?;
+
+                // Nearest first
+                query = SqlQuery::new(from, Some(alias.clone()));
+                query.select_clause = select;
+                query.order_clause = Some(format!("\"_reached\".\"hops\", {}.{}", quote_ident(&alias), quote_ident(&graph.node_id)));
+            }
+            mlql_ir::Operator::Rank { by } => {
+                let score = expr_to_sql(by, select_params).map_err(|e| e.at(location))?;
+                *select_clause = replace_columns_sql(&[("score", score)]);
+
+                // Best first; an earlier sort orders rows with the same score
+                let mut order_items = vec!["\"score\" DESC NULLS LAST".to_string()];
+                order_items.extend(order_clause.take());
+                *order_clause = Some(order_items.join(", "));
             }
-            _ => return Err(ExecutionError::SqlError(format!("Unsupported operator: {:?}", op))),
+            mlql_ir::Operator::Knn { query: q, k, on, metric, .. } => {
+                if *k < 1 {
+                    return Err(ExecutionError::SqlError(format!("knn k must be positive, got {}", k)).at(location));
+                }
+                let distance = knn::distance_sql(on.as_ref(), q, metric.as_deref(), select_params)
+                    .map_err(|e| e.at(location))?;
+                *select_clause = replace_columns_sql(&[("distance", distance)]);
+
+                // Nearest first; an earlier sort orders rows at the same distance
+                let mut order_items = vec!["\"distance\" ASC".to_string()];
+                order_items.extend(order_clause.take());
+                *order_clause = Some(order_items.join(", "));
+                *limit = Some(*k);
+            }
+            mlql_ir::Operator::Union { source, .. }
+            | mlql_ir::Operator::Except { source }
+            | mlql_ir::Operator::Intersect { source } => {
+                let set_op = match op {
+                    mlql_ir::Operator::Union { all: true, .. } => "UNION ALL",
+                    mlql_ir::Operator::Union { .. } => "UNION",
+                    mlql_ir::Operator::Except { .. } => "EXCEPT",
+                    _ => "INTERSECT",
+                };
+                let right = source_query_sql(source, binding, scope)
+                    .map_err(|e| e.into_root().at(location))?;
+
+                // Columns are matched by position and DuckDB coerces them to
+                // a common type. Parentheses keep each side's ORDER BY and LIMIT.
+                let left = query.to_sql();
+                let mut params = left.params;
+                params.extend(right.params);
+                let combined = format!("(({}) {} ({})) AS \"_q{}\"", left.sql, set_op, right.sql, i);
+                query = SqlQuery::new(CompiledQuery { sql: combined, params }, None);
+            }
+            _ => return Err(ExecutionError::SqlError(format!("Unsupported operator: {:?}", op)).at(location)),
         }
     }
 
-    // Build final SQL
-    let distinct_sql = if distinct { "DISTINCT " } else { "" };
-    let mut sql = format!("SELECT {}{} FROM {}", distinct_sql, select_clause, from_clause);
+    Ok(query.to_sql())
+}
+
+/// Clauses of a single SELECT statement
+struct SqlQuery {
+    select_clause: String,
+    from_clause: String,
+    where_clause: Option<String>,
+    group_clause: Option<String>,
+    order_clause: Option<String>,
+    limit: Option<i64>,
+    distinct: bool,
+    /// Table or alias qualifying every column, while the rows come from a single source
+    qualifier: Option<String>,
+    // Values bound to the placeholders of each clause, in text order
+    select_params: Vec<QueryParam>,
+    from_params: Vec<QueryParam>,
+    where_params: Vec<QueryParam>,
+    order_params: Vec<QueryParam>,
+}
+
+impl SqlQuery {
+    fn new(from: CompiledQuery, qualifier: Option<String>) -> Self {
+        Self {
+            select_clause: "*".to_string(),
+            from_clause: from.sql,
+            where_clause: None,
+            group_clause: None,
+            order_clause: None,
+            limit: None,
+            distinct: false,
+            qualifier,
+            select_params: Vec::new(),
+            from_params: from.params,
+            where_params: Vec::new(),
+            order_params: Vec::new(),
+        }
+    }
+
+    /// Whether `op` can be added to this SELECT without changing its meaning.
+    ///
+    /// SQL evaluates FROM/JOIN, WHERE, GROUP BY, the select list (including
+    /// window functions), DISTINCT, ORDER BY and LIMIT in that order, so an
+    /// operator merges when it comes no earlier in that order than the
+    /// clauses already used.
+    fn can_merge(&self, op: &mlql_ir::Operator) -> bool {
+        // The select list only reads the FROM columns (a grouped or windowed
+        // query has computed columns of its own)
+        let plain = self.select_clause == "*" && !self.distinct;
+        match op {
+            // ORDER BY prefers select-list aliases to FROM columns, so an
+            // alias named like a sort key would change the order
+            mlql_ir::Operator::Select { projections } => {
+                plain && !self.order_clause.as_ref().is_some_and(|order| {
+                    projections.iter().any(|proj| matches!(
+                        proj,
+                        mlql_ir::Projection::Aliased { alias, .. } if order.contains(&quote_ident(alias))
+                    ))
+                })
+            }
+            mlql_ir::Operator::Filter { .. } | mlql_ir::Operator::Window { .. } => plain && self.limit.is_none(),
+            // A filter before the join would drop unmatched rows of the right side
+            mlql_ir::Operator::Join { join_type, .. } => {
+                plain && self.limit.is_none()
+                    && (self.where_clause.is_none()
+                        || !matches!(join_type, Some(mlql_ir::JoinType::Right | mlql_ir::JoinType::Full)))
+            }
+            // ORDER BY may only name grouped columns
+            mlql_ir::Operator::GroupBy { .. } | mlql_ir::Operator::Agg { .. } => {
+                plain && self.order_clause.is_none() && self.limit.is_none()
+            }
+            mlql_ir::Operator::Sort { .. } => self.limit.is_none(),
+            mlql_ir::Operator::Rank { .. } | mlql_ir::Operator::Knn { .. } => plain && self.limit.is_none(),
+            // SELECT DISTINCT may only be ordered by selected columns
+            mlql_ir::Operator::Distinct => {
+                self.limit.is_none() && (self.order_clause.is_none() || self.select_clause == "*")
+            }
+            // Set operations read the whole query so far
+            _ => true,
+        }
+    }
 
-    if let Some(where_sql) = where_clause {
-        sql.push_str(&format!(" WHERE {}", where_sql));
+    /// A query reading the result of this one as subquery `alias`
+    ///
+    /// The subquery keeps its ORDER BY, and a select or filter around it
+    /// returns rows in that order: DuckDB preserves the order of an ordered
+    /// subquery through the projections and filters reading it, even with
+    /// `preserve_insertion_order` disabled. The outer query has no ORDER BY
+    /// of its own, since the sort keys may not be among its columns.
+    fn wrap(self, alias: &str) -> Self {
+        let inner = self.to_sql();
+        let from = CompiledQuery {
+            sql: format!("({}) AS {}", inner.sql, quote_ident(alias)),
+            params: inner.params,
+        };
+        Self::new(from, Some(alias.to_string()))
     }
 
-    if let Some(group_sql) = group_clause {
-        sql.push_str(&format!(" GROUP BY {}", group_sql));
+    fn to_sql(&self) -> CompiledQuery {
+        let distinct_sql = if self.distinct { "DISTINCT " } else { "" };
+        let mut sql = format!("SELECT {}{} FROM {}", distinct_sql, self.select_clause, self.from_clause);
+
+        if let Some(where_sql) = &self.where_clause {
+            sql.push_str(&format!(" WHERE {}", where_sql));
+        }
+
+        if let Some(group_sql) = &self.group_clause {
+            sql.push_str(&format!(" GROUP BY {}", group_sql));
+        }
+
+        if let Some(order_sql) = &self.order_clause {
+            sql.push_str(&format!(" ORDER BY {}", order_sql));
+        }
+
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let params = [&self.select_params, &self.from_params, &self.where_params, &self.order_params]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        CompiledQuery { sql, params }
     }
+}
 
-    if let Some(order_sql) = order_clause {
-        sql.push_str(&format!(" ORDER BY {}", order_sql));
+/// `func(args) AS name` for each aggregate, in name order to match
+/// mlql_ir::infer_schema
+fn aggs_to_sql(aggs: &std::collections::HashMap<String, mlql_ir::AggCall>, params: &mut Vec<QueryParam>) -> Result<Vec<String>, ExecutionError> {
+    let mut aggs: Vec<_> = aggs.iter().collect();
+    aggs.sort_by(|a, b| a.0.cmp(b.0));
+
+    aggs.into_iter().map(|(alias, agg_call)| {
+        let agg_func = function_name(&agg_call.func)?;
+        let agg_args = exprs_to_sql(&agg_call.args, params)?;
+        if agg_args.is_empty() {
+            // count(*) case
+            Ok(format!("{}(*) AS {}", agg_func, quote_ident(alias)))
+        } else {
+            Ok(format!("{}({}) AS {}", agg_func, agg_args, quote_ident(alias)))
+        }
+    }).collect()
+}
+
+/// Select list of the input columns followed by each `(name, expression)`.
+/// A computed column named like an input column replaces it and moves last,
+/// as in mlql_ir::infer_schema.
+///
+/// `* EXCLUDE` fails for names that are not input columns, so the input
+/// columns are filtered by name instead.
+fn replace_columns_sql(items: &[(&str, String)]) -> String {
+    let names: Vec<String> = items.iter().map(|(name, _)| format!("'{}'", name.replace('\'', "''"))).collect();
+    let mut select_items = vec![format!("COLUMNS(lambda c: c NOT IN ({}))", names.join(", "))];
+    select_items.extend(items.iter().map(|(name, sql)| format!("{} AS {}", sql, quote_ident(name))));
+    select_items.join(", ")
+}
+
+/// `func(args) OVER (PARTITION BY ... ORDER BY ... ROWS BETWEEN ... AND ...)`
+fn window_to_sql(def: &mlql_ir::WindowDef, params: &mut Vec<QueryParam>) -> Result<String, ExecutionError> {
+    let call = if def.args.is_empty() && def.func.eq_ignore_ascii_case("count") {
+        "count(*)".to_string()
+    } else {
+        format!("{}({})", function_name(&def.func)?, exprs_to_sql(&def.args, params)?)
+    };
+
+    let mut over = Vec::new();
+    if let Some(partition) = &def.partition {
+        let keys: Vec<String> = partition.iter().map(column_ref_to_sql).collect();
+        over.push(format!("PARTITION BY {}", keys.join(", ")));
     }
+    if let Some(order) = &def.order {
+        let keys = order.iter()
+            .map(|key| sort_key_to_sql(key, params))
+            .collect::<Result<Vec<_>, _>>()?;
+        over.push(format!("ORDER BY {}", keys.join(", ")));
+    }
+    if let Some(frame) = &def.frame {
+        let mode = match frame.mode {
+            mlql_ir::FrameMode::Rows => "ROWS",
+            mlql_ir::FrameMode::Range => "RANGE",
+        };
+        over.push(format!("{} BETWEEN {} AND {}", mode, frame_bound_to_sql(&frame.start), frame_bound_to_sql(&frame.end)));
+    }
+
+    Ok(format!("{} OVER ({})", call, over.join(" ")))
+}
 
-    if let Some(limit_sql) = limit_clause {
-        sql.push_str(&format!(" LIMIT {}", limit_sql));
+fn frame_bound_to_sql(bound: &mlql_ir::FrameBound) -> String {
+    match bound {
+        mlql_ir::FrameBound::UnboundedPreceding => "UNBOUNDED PRECEDING".to_string(),
+        mlql_ir::FrameBound::UnboundedFollowing => "UNBOUNDED FOLLOWING".to_string(),
+        mlql_ir::FrameBound::CurrentRow => "CURRENT ROW".to_string(),
+        mlql_ir::FrameBound::Preceding(n) => format!("{} PRECEDING", n),
+        mlql_ir::FrameBound::Following(n) => format!("{} FOLLOWING", n),
     }
+}
 
-    Ok(sql)
+fn sort_key_to_sql(key: &mlql_ir::SortKey, params: &mut Vec<QueryParam>) -> Result<String, ExecutionError> {
+    let expr = expr_to_sql(&key.expr, params)?;
+    if key.desc {
+        Ok(format!("{} DESC", expr))
+    } else {
+        Ok(format!("{} ASC", expr))
+    }
 }
 
-fn expr_to_sql(expr: &mlql_ir::Expr) -> String {
-    match expr {
+
+/// SQL for an expression, pushing the values of its literals onto `params`
+///
+/// List indexes are 1-based, as in DuckDB.
+fn expr_to_sql(expr: &mlql_ir::Expr, params: &mut Vec<QueryParam>) -> Result<String, ExecutionError> {
+    Ok(match expr {
         mlql_ir::Expr::Column { col } => column_ref_to_sql(col),
-        mlql_ir::Expr::Literal { value } => literal_to_sql(value),
+        mlql_ir::Expr::Literal { value } => literal_to_sql(value, params),
         mlql_ir::Expr::BinaryOp { op, left, right } => {
-            format!("({} {} {})", expr_to_sql(left), binop_to_sql(op), expr_to_sql(right))
+            let left = expr_to_sql(left, params)?;
+            let right = expr_to_sql(right, params)?;
+            format!("({} {} {})", left, binop_to_sql(op), right)
+        }
+        mlql_ir::Expr::UnaryOp { op, expr } => {
+            let op = match op {
+                mlql_ir::UnOp::Neg => "-",
+                mlql_ir::UnOp::Not => "NOT ",
+            };
+            format!("({}{})", op, expr_to_sql(expr, params)?)
         }
+        mlql_ir::Expr::FuncCall { func, args } if func.eq_ignore_ascii_case("bm25") => fts::bm25_sql(args, params)?,
         mlql_ir::Expr::FuncCall { func, args } => {
-            let arg_strs: Vec<String> = args.iter().map(expr_to_sql).collect();
-            format!("{}({})", func, arg_strs.join(", "))
+            format!("{}({})", function_name(func)?, exprs_to_sql(args, params)?)
+        }
+        // Parentheses keep `("s")."f"` from reading as column f of table s
+        mlql_ir::Expr::FieldAccess { expr, field } => {
+            format!("({}).{}", expr_to_sql(expr, params)?, quote_ident(field))
+        }
+        mlql_ir::Expr::Index { expr, index } => {
+            let base = expr_to_sql(expr, params)?;
+            format!("({})[{}]", base, expr_to_sql(index, params)?)
+        }
+        mlql_ir::Expr::Array { elements } => format!("[{}]", exprs_to_sql(elements, params)?),
+        mlql_ir::Expr::Object { fields } => {
+            // Fields in name order so the same object always gives the same SQL
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            let fields = fields.into_iter()
+                .map(|(name, value)| Ok(format!("{} := {}", quote_ident(name), expr_to_sql(value, params)?)))
+                .collect::<Result<Vec<_>, ExecutionError>>()?;
+            format!("struct_pack({})", fields.join(", "))
+        }
+        // Embeddings are FLOAT[n] arrays, so array_distance() and friends accept the query
+        mlql_ir::Expr::Vector { values } => {
+            if values.is_empty() {
+                return Err(ExecutionError::SqlError("vector literal must have at least one element".to_string()));
+            }
+            let elements: Vec<&str> = values.iter()
+                .map(|v| {
+                    params.push(QueryParam::Float(*v));
+                    "?"
+                })
+                .collect();
+            format!("CAST([{}] AS FLOAT[{}])", elements.join(", "), values.len())
+        }
+        mlql_ir::Expr::InRange { expr, start, end, inclusive } => {
+            // `expr` appears twice for a half-open range, and so do its parameters
+            let value = expr_to_sql(expr, params)?;
+            let start = expr_to_sql(start, params)?;
+            if *inclusive {
+                format!("({} BETWEEN {} AND {})", value, start, expr_to_sql(end, params)?)
+            } else {
+                let value_again = expr_to_sql(expr, params)?;
+                format!("(({} >= {}) AND ({} < {}))", value, start, value_again, expr_to_sql(end, params)?)
+            }
         }
-        _ => "NULL".to_string(),  // TODO: Handle more expression types
+        // SQL has no empty IN list
+        mlql_ir::Expr::InSet { set, .. } if set.is_empty() => "FALSE".to_string(),
+        mlql_ir::Expr::InSet { expr, set } => {
+            let value = expr_to_sql(expr, params)?;
+            format!("({} IN ({}))", value, exprs_to_sql(set, params)?)
+        }
+    })
+}
+
+/// Comma-separated SQL for a list of expressions
+fn exprs_to_sql(exprs: &[mlql_ir::Expr], params: &mut Vec<QueryParam>) -> Result<String, ExecutionError> {
+    let sql = exprs.iter()
+        .map(|e| expr_to_sql(e, params))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sql.join(", "))
+}
+
+/// A function name, which is written into the SQL unquoted
+fn function_name(func: &str) -> Result<&str, ExecutionError> {
+    let mut chars = func.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(func)
+    } else {
+        Err(ExecutionError::SqlError(format!("Invalid function name: {:?}", func)))
     }
 }
 
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
 fn column_ref_to_sql(col: &mlql_ir::ColumnRef) -> String {
     // Quote identifiers to handle special characters
     if let Some(ref table) = col.table {
-        format!("\"{}\".\"{}\"", table, col.column)
+        format!("{}.{}", quote_ident(table), quote_ident(&col.column))
     } else {
-        format!("\"{}\"", col.column)
+        quote_ident(&col.column)
     }
 }
 
@@ -378,15 +1180,37 @@ fn binop_to_sql(op: &mlql_ir::BinOp) -> &'static str {
     }
 }
 
-fn literal_to_sql(val: &mlql_ir::Value) -> String {
-    match val {
-        mlql_ir::Value::Null => "NULL".to_string(),
-        mlql_ir::Value::Bool(b) => if *b { "TRUE".to_string() } else { "FALSE".to_string() },
-        mlql_ir::Value::Int(i) => i.to_string(),
-        mlql_ir::Value::Float(f) => f.to_string(),
-        mlql_ir::Value::String(s) => format!("'{}'", s.replace("'", "''")),  // Escape quotes
-        _ => "NULL".to_string(),  // TODO: Handle more value types
-    }
+/// Placeholder for a literal, binding its value
+///
+/// Values DuckDB can't bind directly are cast from a string parameter or
+/// built from one placeholder per element.
+fn literal_to_sql(val: &mlql_ir::Value, params: &mut Vec<QueryParam>) -> String {
+    let (param, sql) = match val {
+        mlql_ir::Value::Null => return "NULL".to_string(),
+        mlql_ir::Value::Bool(b) => (QueryParam::Bool(*b), "?"),
+        mlql_ir::Value::Int(i) => (QueryParam::Int(*i), "?"),
+        mlql_ir::Value::Float(f) => (QueryParam::Float(*f), "?"),
+        mlql_ir::Value::String(s) => (QueryParam::String(s.clone()), "?"),
+        mlql_ir::Value::Bytes(b) => (QueryParam::Bytes(b.clone()), "?"),
+        mlql_ir::Value::Date(s) => (QueryParam::String(s.clone()), "CAST(? AS DATE)"),
+        mlql_ir::Value::Time(s) => (QueryParam::String(s.clone()), "CAST(? AS TIME)"),
+        mlql_ir::Value::Timestamp(s) => (QueryParam::String(s.clone()), "CAST(? AS TIMESTAMP)"),
+        mlql_ir::Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(|item| literal_to_sql(item, params)).collect();
+            return format!("[{}]", items.join(", "));
+        }
+        mlql_ir::Value::Object(fields) => {
+            // Fields in name order so the same object always gives the same SQL
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            let fields: Vec<String> = fields.into_iter()
+                .map(|(name, value)| format!("{} := {}", quote_ident(name), literal_to_sql(value, params)))
+                .collect();
+            return format!("struct_pack({})", fields.join(", "));
+        }
+    };
+    params.push(param);
+    sql.to_string()
 }
 
 #[cfg(test)]
@@ -575,6 +1399,148 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_literals_are_bound_as_params() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER, name VARCHAR, joined DATE);
+             INSERT INTO users VALUES (1, 'O''Brien', '2024-03-01'), (2, 'Bob', '2023-06-15');"
+        )?;
+
+        // Quotes and SQL in a string literal are data, never SQL text
+        let ir_program = mlql_ast::parse(
+            "from users | filter name == \"O'Brien\" || name == \"x' OR 1=1 --\" | select [id, name]"
+        )?.to_ir();
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert!(!query.sql.contains("Brien"), "SQL: {}", query.sql);
+        assert_eq!(query.params, vec![
+            QueryParam::String("O'Brien".to_string()),
+            QueryParam::String("x' OR 1=1 --".to_string()),
+        ]);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.rows, vec![vec![serde_json::json!(1), serde_json::json!("O'Brien")]]);
+
+        // Dates are cast from a string parameter; params follow the placeholders
+        // through subqueries and in clause order
+        let ir_program = mlql_ir::Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: mlql_ir::Pipeline {
+                source: mlql_ir::Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![
+                    mlql_ir::Operator::Take { limit: 10 },
+                    mlql_ir::Operator::Filter {
+                        condition: mlql_ir::Expr::BinaryOp {
+                            op: mlql_ir::BinOp::Gt,
+                            left: Box::new(mlql_ir::Expr::Column { col: mlql_ir::ColumnRef { table: None, column: "joined".to_string() } }),
+                            right: Box::new(mlql_ir::Expr::Literal { value: mlql_ir::Value::Date("2024-01-01".to_string()) }),
+                        },
+                    },
+                    mlql_ir::Operator::Select {
+                        projections: vec![mlql_ir::Projection::Aliased {
+                            expr: mlql_ir::Expr::BinaryOp {
+                                op: mlql_ir::BinOp::Add,
+                                left: Box::new(mlql_ir::Expr::Column { col: mlql_ir::ColumnRef { table: None, column: "id".to_string() } }),
+                                right: Box::new(mlql_ir::Expr::Literal { value: mlql_ir::Value::Int(100) }),
+                            },
+                            alias: "code".to_string(),
+                        }],
+                    },
+                ],
+            },
+        };
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert!(query.sql.contains("(\"joined\" > CAST(? AS DATE))"), "SQL: {}", query.sql);
+        assert!(query.sql.starts_with("SELECT (\"id\" + ?) AS \"code\" FROM"), "SQL: {}", query.sql);
+        assert_eq!(query.params, vec![QueryParam::Int(100), QueryParam::String("2024-01-01".to_string())]);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.rows, vec![vec![serde_json::json!(101)]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identifiers_are_quoted() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // Names in IR JSON may hold quotes, but none of them ends an identifier
+        let count = "n\" FROM employees; DROP TABLE employees; --";
+        let ir_program: mlql_ir::Program = serde_json::from_value(serde_json::json!({
+            "lets": [{"name": "ops\"", "pipeline": {
+                "source": {"type": "Table", "name": "employees", "alias": "e\""},
+                "ops": [{"op": "Filter", "condition": {
+                    "type": "BinaryOp", "op": "Eq",
+                    "left": {"type": "Column", "col": {"table": "e\"", "column": "dept"}},
+                    "right": {"type": "Literal", "value": "ops"}
+                }}]
+            }}],
+            "pipeline": {
+                "source": {"type": "Table", "name": "ops\""},
+                "ops": [{"op": "GroupBy", "keys": [{"column": "dept"}], "aggs": {count: {"func": "count"}}}]
+            }
+        }))?;
+
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert!(query.sql.contains("count(*) AS \"n\"\" FROM employees; DROP TABLE employees; --\""), "SQL: {}", query.sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["dept", count]);
+        assert_eq!(result.rows, vec![vec![serde_json::json!("ops"), serde_json::json!(2)]]);
+        assert_eq!(executor.execute_ir(&mlql_ast::parse("from employees")?.to_ir(), None)?.row_count, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_and_membership_expressions() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE events (
+                id INTEGER, kind VARCHAR, score INTEGER, ok BOOLEAN,
+                payload STRUCT(user STRUCT(name VARCHAR)), tags VARCHAR[], embedding FLOAT[3]
+             );
+             INSERT INTO events VALUES
+                (1, 'a', 1, true, {'user': {'name': 'ann'}}, ['x', 'y'], [1, 0, 0]),
+                (2, 'b', 5, false, {'user': {'name': 'bo'}}, ['z'], [0, 1, 0]),
+                (3, 'c', 10, true, {'user': {'name': 'cy'}}, [], [0, 0, 1]);"
+        )?;
+
+        let ir_program = mlql_ast::parse(
+            "from events
+             | filter !(kind in (\"c\", \"d\")) && score in [1, 10) && id in [1, 2]
+             | select [id, payload.user.name as who, tags[1] as first_tag, -score as neg, !ok as not_ok,
+                       [score, id] as pair, {n: id} as obj, array_distance(embedding, <1, 0, 0>) as dist]
+             | sort id"
+        )?.to_ir();
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert!(query.sql.contains("(\"id\" BETWEEN ? AND ?)"), "SQL: {}", query.sql);
+        assert!(query.sql.contains("((\"score\" >= ?) AND (\"score\" < ?))"), "SQL: {}", query.sql);
+        assert!(query.sql.contains("CAST([?, ?, ?] AS FLOAT[3])"), "SQL: {}", query.sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "who", "first_tag", "neg", "not_ok", "pair", "obj", "dist"]);
+        assert_eq!(result.row_count, 2);
+        assert_eq!(result.rows[0][..5], [serde_json::json!(1), serde_json::json!("ann"), serde_json::json!("x"), serde_json::json!(-1), serde_json::json!(false)]);
+        assert_eq!(result.rows[1][..5], [serde_json::json!(2), serde_json::json!("bo"), serde_json::json!("z"), serde_json::json!(-5), serde_json::json!(true)]);
+        assert_eq!(result.rows[0][7], serde_json::json!(0.0));
+
+        // Function names are written into the SQL, so they must be identifiers
+        let mut ir_program = mlql_ast::parse("from events | select [upper(kind) as k]")?.to_ir();
+        if let mlql_ir::Operator::Select { projections } = &mut ir_program.pipeline.ops[0] {
+            if let mlql_ir::Projection::Aliased { expr: mlql_ir::Expr::FuncCall { func, .. }, .. } = &mut projections[0] {
+                *func = "upper(kind)); DROP TABLE events; --".to_string();
+            }
+        }
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("not a function name");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.root().to_string().contains("Invalid function name"), "{}", err);
+
+        Ok(())
+    }
+
     #[test]
     fn test_llm_json_direct_execution() -> Result<(), Box<dyn std::error::Error>> {
         // Setup
@@ -867,11 +1833,63 @@ mod tests {
         println!("GroupBy Multi-Agg Results: {:?}", result);
         assert_eq!(result.row_count, 2);
 
-        // Check columns (order may vary due to HashMap)
-        assert!(result.columns.contains(&"product".to_string()));
-        assert!(result.columns.contains(&"total_qty".to_string()));
-        assert!(result.columns.contains(&"avg_price".to_string()));
-        assert_eq!(result.columns.len(), 3);
+        // Keys first, then aggregates in name order, as in the inferred schema
+        assert_eq!(result.columns, vec!["product", "avg_price", "total_qty"]);
+        let schema = result.schema.expect("schema should be inferred");
+        let names: Vec<&str> = schema.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, result.columns);
+        assert_eq!(schema.fields[2].data_type, mlql_ir::DataType::Int64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_schema_parses_nested_types() -> Result<(), Box<dyn std::error::Error>> {
+        use mlql_ir::DataType;
+
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE docs (
+                id BIGINT NOT NULL,
+                price DECIMAL(10,2),
+                tags VARCHAR[],
+                embedding FLOAT[3],
+                meta STRUCT(author VARCHAR, published DATE),
+                counts MAP(VARCHAR, INTEGER)
+            );"
+        )?;
+
+        let schema = table_schema(executor.connection(), "docs")?;
+        let types: Vec<(&str, &DataType, bool)> = schema.columns.iter()
+            .map(|c| (c.name.as_str(), &c.data_type, c.nullable))
+            .collect();
+        assert_eq!(types, vec![
+            ("id", &DataType::Int64, false),
+            ("price", &DataType::Decimal { precision: 10, scale: 2 }, true),
+            ("tags", &DataType::Array(Box::new(DataType::String)), true),
+            ("embedding", &DataType::Vector(Some(3)), true),
+            ("meta", &DataType::Struct(vec![
+                mlql_ir::FieldType { name: "author".to_string(), data_type: DataType::String, nullable: true },
+                mlql_ir::FieldType { name: "published".to_string(), data_type: DataType::Date, nullable: true },
+            ]), true),
+            ("counts", &DataType::Map { key: Box::new(DataType::String), value: Box::new(DataType::Int32) }, true),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_result_has_columns() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER NOT NULL, name VARCHAR, age INTEGER);"
+        )?;
+
+        let program = mlql_ast::parse("from users | filter age > 100 | select [name, age]")?.to_ir();
+        let result = executor.execute_ir(&program, None)?;
+
+        assert_eq!(result.row_count, 0);
+        assert_eq!(result.columns, vec!["name", "age"]);
 
         Ok(())
     }
@@ -1051,17 +2069,541 @@ mod tests {
     }
 
     #[test]
-    fn test_union_note() -> Result<(), Box<dyn std::error::Error>> {
-        // Note: UNION/EXCEPT/INTERSECT are binary set operations that combine two queries.
-        // In SQL: SELECT * FROM t1 UNION SELECT * FROM t2
-        // However, in the current pipeline architecture, operators modify a single pipeline.
-        //
-        // Proper implementation would require:
-        // 1. Either: Store a second pipeline/source in the operator
-        // 2. Or: Generate SQL differently for set operations
-        //
-        // For now, documenting this limitation. These operators would be better
-        // implemented as a separate query combiner at a higher level.
+    fn test_join_sub_pipelines() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER, name VARCHAR, age INTEGER);
+             CREATE TABLE orders (id INTEGER, user_id INTEGER, amount INTEGER);
+             INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', 15), (3, 'Carol', 40);
+             INSERT INTO orders VALUES (101, 1, 100), (102, 1, 150), (103, 2, 200), (104, 3, 50);"
+        )?;
+
+        let ir_program = mlql_ast::parse(
+            "from (from orders | group by user_id { total: sum(amount) }) totals
+             | join (from users | filter age > 18) adults on totals.user_id == adults.id
+             | select [adults.name, totals.total]
+             | sort name"
+        )?.to_ir();
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("GROUP BY \"user_id\") AS \"totals\" INNER JOIN (SELECT * FROM \"users\" WHERE"), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["name", "total"]);
+        assert_eq!(result.rows, vec![
+            vec![serde_json::json!("Alice"), serde_json::json!(250)],
+            vec![serde_json::json!("Carol"), serde_json::json!(50)],
+        ]);
+
+        // Errors inside a sub-pipeline are reported at the join that reads it
+        let ir_program = mlql_ast::parse(
+            "from users | filter age > 18
+             | join (from orders | map { doubled: amount * 2 }) o on users.id == o.user_id"
+        )?.to_ir();
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("map is not supported in SQL");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(1) }));
+
+        Ok(())
+    }
+
+    fn set_tables(executor: &DuckExecutor) -> Result<(), Box<dyn std::error::Error>> {
+        executor.connection().execute_batch(
+            "CREATE TABLE a (id INTEGER, name VARCHAR);
+             CREATE TABLE b (id BIGINT, name VARCHAR);
+             INSERT INTO a VALUES (1, 'x'), (2, 'y'), (3, 'z');
+             INSERT INTO b VALUES (2, 'y'), (3, 'w'), (4, 'v');"
+        )?;
+        Ok(())
+    }
+
+    fn ids(result: &QueryResult) -> Vec<serde_json::Value> {
+        result.rows.iter().map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    fn test_union() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        set_tables(&executor)?;
+
+        let ir_program = mlql_ast::parse("from a | union from b | sort id, name")?.to_ir();
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("((SELECT * FROM \"a\") UNION (SELECT * FROM \"b\"))"), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "name"]);
+        assert_eq!(ids(&result), vec![
+            serde_json::json!(1), serde_json::json!(2), serde_json::json!(3), serde_json::json!(3), serde_json::json!(4),
+        ]);
+
+        // INTEGER and BIGINT columns are coerced to BIGINT
+        let schema = result.schema.expect("IR results carry a schema");
+        assert_eq!(schema.fields[0].data_type, mlql_ir::DataType::Int64);
+
+        let ir_program = mlql_ast::parse("from a | union all from b")?.to_ir();
+        assert_eq!(executor.execute_ir(&ir_program, None)?.row_count, 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_except_let_binding() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        set_tables(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "let seen = from b | filter id < 3;
+             from a | except from seen | sort id"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(ids(&result), vec![serde_json::json!(1), serde_json::json!(3)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_intersect_sub_pipeline() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        set_tables(&executor)?;
+
+        // Operators after the set operation apply to the combined rows
+        let ir_program = mlql_ast::parse(
+            "from a | select [id]
+             | intersect from (from b | select [id] | filter id > 2)
+             | select [id as found]"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["found"]);
+        assert_eq!(result.rows, vec![vec![serde_json::json!(3)]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pragma_rows_out_budget() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER, name VARCHAR, age INTEGER);
+             INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', 25), (3, 'Carol', 35);"
+        )?;
+
+        // Exactly at the limit is fine
+        let ir_program = mlql_ast::parse("pragma { budget: {rows_out: 3} } from users")?.to_ir();
+        assert_eq!(executor.execute_ir(&ir_program, None)?.row_count, 3);
+
+        // One row over the limit fails
+        let ir_program = mlql_ast::parse("pragma { budget: {rows_out: 2} } from users")?.to_ir();
+        match executor.execute_ir(&ir_program, None) {
+            Err(ExecutionError::BudgetExceeded(_)) => {}
+            other => panic!("Expected BudgetExceeded, got {:?}", other),
+        }
+
+        // An explicit budget overrides the pragma
+        let budget = ExecutionBudget { max_rows: Some(10), ..Default::default() };
+        assert_eq!(executor.execute_ir(&ir_program, Some(budget))?.row_count, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pragma_memory_limit_is_restored() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER, name VARCHAR, age INTEGER);
+             INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', 25), (3, 'Carol', 35);"
+        )?;
+        let memory_limit = |executor: &DuckExecutor| -> duckdb::Result<String> {
+            executor.connection().query_row(
+                "SELECT current_setting('memory_limit')", [], |row| row.get(0),
+            )
+        };
+        let original = memory_limit(&executor)?;
+
+        let ir_program = mlql_ast::parse("pragma { memory_mb: 64 } from users")?.to_ir();
+        assert_eq!(executor.execute_ir(&ir_program, None)?.row_count, 3);
+        assert_eq!(memory_limit(&executor)?, original);
+
+        // The next query on the same connection runs without the limit
+        let ir_program = mlql_ast::parse("from users")?.to_ir();
+        assert_eq!(executor.execute_ir(&ir_program, None)?.row_count, 3);
+        assert_eq!(memory_limit(&executor)?, original);
+
+        // Restored when the query fails too
+        let ir_program = mlql_ast::parse("pragma { memory_mb: 64 } from missing")?.to_ir();
+        assert!(executor.execute_ir(&ir_program, None).is_err());
+        assert_eq!(memory_limit(&executor)?, original);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_let_bindings_become_ctes() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER, name VARCHAR, age INTEGER);
+             INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', 25), (3, 'Carol', 35);"
+        )?;
+
+        let ir_program = mlql_ast::parse(
+            "let adults = from users | filter age >= 30;
+             let names = from adults | select [name];
+             from names | sort name"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.starts_with("WITH \"adults\" AS (SELECT * FROM \"users\""), "SQL: {}", sql);
+        assert!(sql.contains("\"names\" AS (SELECT \"name\" FROM \"adults\")"), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["name"]);
+        assert_eq!(result.rows, vec![vec![serde_json::json!("Alice")], vec![serde_json::json!("Carol")]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_let_binding_shadows_table() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE users (id INTEGER, name VARCHAR, age INTEGER);
+             INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', 25);"
+        )?;
+
+        let ir_program = mlql_ast::parse(
+            "let users = from users | filter age > 26
+             from users | select [users.name]"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.starts_with("WITH \"users_0\" AS ("), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.row_count, 1);
+        assert_eq!(result.rows[0][0], serde_json::json!("Alice"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_error_reports_location() -> Result<(), Box<dyn std::error::Error>> {
+        let ir_program = mlql_ast::parse(
+            "let sampled = from users | sort -age | sample 0.5
+             from sampled"
+        )?.to_ir();
+
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("sample is not supported in SQL");
+        assert_eq!(err.location(), Some(OpLocation { binding: Some(0), op: Some(1) }));
+        assert!(err.to_string().starts_with("lets[0].ops[1]: SQL generation failed"), "{}", err);
+
+        Ok(())
+    }
+
+    fn employees(executor: &DuckExecutor) -> Result<(), Box<dyn std::error::Error>> {
+        executor.connection().execute_batch(
+            "CREATE TABLE employees (id INTEGER, dept VARCHAR, salary INTEGER);
+             INSERT INTO employees VALUES
+                (1, 'eng', 100), (2, 'eng', 120), (3, 'eng', 120),
+                (4, 'ops', 80), (5, 'ops', 90);"
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_ranking() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | window {
+                 rn: row_number() over part: dept order: -salary, id,
+                 rk: rank() over part: dept order: -salary,
+                 drk: dense_rank() over part: dept order: -salary
+               }
+             | sort id"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("row_number() OVER (PARTITION BY \"dept\" ORDER BY \"salary\" DESC, \"id\" ASC) AS \"rn\""), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "dept", "salary", "drk", "rk", "rn"]);
+        let ranks: Vec<_> = result.rows.iter().map(|row| (row[3].clone(), row[4].clone(), row[5].clone())).collect();
+        let json = |d: i64, r: i64, n: i64| (serde_json::json!(d), serde_json::json!(r), serde_json::json!(n));
+        assert_eq!(ranks, vec![json(2, 3, 3), json(1, 1, 1), json(1, 1, 2), json(2, 2, 2), json(1, 1, 1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_lag_lead() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | window {
+                 prev: lag(salary) over part: dept order: id,
+                 next: lead(salary, 1) over part: dept order: id
+               }
+             | sort id"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "dept", "salary", "next", "prev"]);
+        let pairs: Vec<_> = result.rows.iter().map(|row| (row[3].clone(), row[4].clone())).collect();
+        let null = serde_json::Value::Null;
+        assert_eq!(pairs, vec![
+            (serde_json::json!(120), null.clone()),
+            (serde_json::json!(120), serde_json::json!(100)),
+            (null.clone(), serde_json::json!(120)),
+            (serde_json::json!(90), null.clone()),
+            (null, serde_json::json!(80)),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_frames() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | window {
+                 running: sum(salary) over part: dept order: id frame: rows [unbounded_preceding, current_row],
+                 moving: avg(salary) over order: id frame: rows [1 preceding, 1 following],
+                 first: first_value(salary) over part: dept order: id frame: rows [unbounded_preceding, unbounded_following],
+                 last: last_value(salary) over part: dept order: id frame: range [unbounded_preceding, unbounded_following]
+               }
+             | sort id"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW"), "SQL: {}", sql);
+        assert!(sql.contains("ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING"), "SQL: {}", sql);
+        assert!(sql.contains("RANGE BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING"), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "dept", "salary", "first", "last", "moving", "running"]);
+        let column = |idx: usize| -> Vec<f64> {
+            result.rows.iter().map(|row| row[idx].as_f64().unwrap()).collect()
+        };
+        assert_eq!(column(3), vec![100.0, 100.0, 100.0, 80.0, 80.0]);
+        assert_eq!(column(4), vec![120.0, 120.0, 120.0, 90.0, 90.0]);
+        for (actual, expected) in column(5).into_iter().zip([110.0, 340.0 / 3.0, 320.0 / 3.0, 290.0 / 3.0, 85.0]) {
+            assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+        }
+        assert_eq!(column(6), vec![100.0, 220.0, 340.0, 80.0, 170.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_after_window_uses_subquery() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // Top earner per department
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | window { rn: row_number() over part: dept order: -salary, id }
+             | filter rn == 1
+             | select [dept, salary]
+             | sort dept"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("FROM (SELECT COLUMNS(lambda c: c NOT IN ('rn')), row_number() OVER"), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.rows, vec![
+            vec![serde_json::json!("eng"), serde_json::json!(120)],
+            vec![serde_json::json!("ops"), serde_json::json!(90)],
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_over_grouped_rows() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | group by dept { total: sum(salary) }
+             | window { pos: rank() over order: -total }
+             | sort pos"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["dept", "total", "pos"]);
+        assert_eq!(result.rows[0][0], serde_json::json!("eng"));
+        assert_eq!(result.rows[1][0], serde_json::json!("ops"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_replacing_column() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // The window named salary replaces the input column and moves last
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | window { salary: max(salary) over part: dept }
+             | sort id"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        let schema: Vec<_> = executor.infer_schema(&ir_program).fields.into_iter().map(|f| f.name).collect();
+        assert_eq!(result.columns, vec!["id", "dept", "salary"]);
+        assert_eq!(result.columns, schema);
+        let top: Vec<_> = result.rows.iter().map(|row| row[2].clone()).collect();
+        assert_eq!(top, vec![serde_json::json!(120), serde_json::json!(120), serde_json::json!(120), serde_json::json!(90), serde_json::json!(90)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_after_group_by() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // The first filter reads employees, the second reads the groups
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | filter salary > 80
+             | group by dept { total: sum(salary) }
+             | filter total > 100"
+        )?.to_ir();
+
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert!(query.sql.contains("WHERE (\"salary\" > ?) GROUP BY \"dept\") AS \"employees\" WHERE (\"total\" > ?)"), "SQL: {}", query.sql);
+        assert_eq!(query.params, vec![QueryParam::Int(80), QueryParam::Int(100)]);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.rows, vec![vec![serde_json::json!("eng"), serde_json::json!(340)]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_after_take() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // Takes the first two ids, then orders just those by salary
+        let ir_program = mlql_ast::parse(
+            "from employees | sort id | take 4 | take 2 | sort -salary | select [id]"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("(SELECT * FROM \"employees\" ORDER BY \"id\" ASC LIMIT 2) AS \"employees\" ORDER BY \"salary\" DESC"), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.rows, vec![vec![serde_json::json!(2)], vec![serde_json::json!(1)]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_after_select() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // The second select reads the columns of the first
+        let ir_program = mlql_ast::parse(
+            "from employees
+             | select [id, salary * 2 as doubled]
+             | select [doubled as pay]
+             | filter pay > 200
+             | sort pay"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["pay"]);
+        assert_eq!(result.rows, vec![vec![serde_json::json!(240)], vec![serde_json::json!(240)]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_alias_shadowing_sort_key() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        // Ordered by salary, not by the id renamed to salary
+        let ir_program = mlql_ast::parse(
+            "from employees | sort salary, id | select [id as salary]"
+        )?.to_ir();
+
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.contains("FROM (SELECT * FROM \"employees\" ORDER BY \"salary\" ASC, \"id\" ASC) AS \"employees\""), "SQL: {}", sql);
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        let ids: Vec<_> = result.rows.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(ids, [4, 5, 1, 2, 3].map(|id| serde_json::json!(id)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrapped_sort_order_without_insertion_order() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        // Enough rows to be read by several threads
+        executor.connection().execute_batch(
+            "SET threads = 4;
+             SET preserve_insertion_order = false;
+             CREATE TABLE nums AS SELECT range AS id, (range * 7919) % 100003 AS bucket FROM range(100000);"
+        )?;
+        let ids = |sql: &str| -> duckdb::Result<Vec<serde_json::Value>> {
+            let mut stmt = executor.connection().prepare(sql)?;
+            let ids = stmt.query_map([], |row| row.get::<_, i64>(0))?
+                .map(|id| id.map(|id| serde_json::json!(id)))
+                .collect();
+            ids
+        };
+
+        // The select is wrapped around the sort, which only the subquery orders
+        let ir_program = mlql_ast::parse("from nums | sort bucket | select [id as bucket]")?.to_ir();
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(sql.ends_with("AS \"nums\""), "SQL: {}", sql);
+        let result = executor.execute_ir(&ir_program, None)?;
+        let actual: Vec<_> = result.rows.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(actual, ids("SELECT id FROM nums ORDER BY bucket")?);
+
+        // Likewise a filter after a limit
+        let ir_program = mlql_ast::parse("from nums | sort bucket | take 50000 | filter id > 20000")?.to_ir();
+        let sql = ir_to_sql(&ir_program, &GraphRegistry::default())?.sql;
+        assert!(!sql.ends_with("LIMIT 50000"), "SQL: {}", sql);
+        let result = executor.execute_ir(&ir_program, None)?;
+        let actual: Vec<_> = result.rows.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(
+            actual,
+            ids("SELECT id FROM (SELECT * FROM nums ORDER BY bucket LIMIT 50000) WHERE id > 20000 ORDER BY bucket")?,
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_replaces_score() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        employees(&executor)?;
+
+        let ir_program = mlql_ast::parse(
+            "from employees | select [id, salary as score] | rank by id"
+        )?.to_ir();
+
+        let result = executor.execute_ir(&ir_program, None)?;
+        assert_eq!(result.columns, vec!["id", "score"]);
+        let scores: Vec<_> = result.rows.iter().map(|row| row[1].clone()).collect();
+        assert_eq!(scores, [5, 4, 3, 2, 1].map(|id| serde_json::json!(id)));
+
         Ok(())
     }
 }
@@ -0,0 +1,381 @@
+//! Time series: lowering `agg by` windows and `resample`
+//!
+//! `agg by tumbling(ts, 1h) { n: count() }` groups rows into hourly buckets
+//! with `time_bucket`:
+//!
+//! ```sql
+//! SELECT time_bucket(INTERVAL 1 HOUR, "ts") AS "ts", count(*) AS n
+//! FROM "events" WHERE "ts" IS NOT NULL GROUP BY 1 ORDER BY 1
+//! ```
+//!
+//! `hopping(ts, 10m, 5m)` windows overlap, so each row is joined to the start
+//! of every window containing it. `session(ts, 30m)` numbers the sessions with
+//! window functions, starting a new one after a 30 minute gap, and groups by
+//! that number.
+//!
+//! `resample 1h method: ffill on: ts` keeps the latest row of each hour and
+//! fills the hours without rows from a generated series of bucket starts,
+//! ASOF joined to the rows before (and, for `linear`, after) them.
+
+use crate::{aggs_to_sql, column_ref_to_sql, expr_to_sql, quote_ident, CompiledQuery, DuckExecutor, ExecutionError, SqlQuery};
+use mlql_ir::{AggCall, ColumnRef, Expr, GroupKey, Operator, Program};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A window or resample interval such as `5m`
+#[derive(Debug, PartialEq)]
+struct Interval {
+    count: u64,
+    unit: &'static str,
+    seconds: u64,
+}
+
+impl Interval {
+    /// Parse a positive count followed by `s`, `m`, `h` or `d`
+    fn parse(interval: &str) -> Result<Self, ExecutionError> {
+        let invalid = || ExecutionError::SqlError(format!(
+            "Invalid interval '{}'; expected a count followed by s, m, h or d", interval
+        ));
+        let unit = interval.chars().last().ok_or_else(invalid)?;
+        let (unit, unit_seconds) = match unit {
+            's' => ("SECOND", 1),
+            'm' => ("MINUTE", 60),
+            'h' => ("HOUR", 3600),
+            'd' => ("DAY", 86400),
+            _ => return Err(invalid()),
+        };
+        let count = interval[..interval.len() - 1].parse::<u64>().ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(invalid)?;
+        let seconds = count.checked_mul(unit_seconds).filter(|s| i64::try_from(*s).is_ok()).ok_or_else(invalid)?;
+        Ok(Self { count, unit, seconds })
+    }
+
+    fn sql(&self) -> String {
+        format!("INTERVAL {} {}", self.count, self.unit)
+    }
+}
+
+/// Name of the window key column: the column grouped on, or `bucket`
+fn key_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Column { col } => col.column.clone(),
+        _ => "bucket".to_string(),
+    }
+}
+
+/// Add `agg by <group_key> { aggs }` to `query`, the query built so far for
+/// operator `i`. Rows whose key is null belong to no window.
+pub(crate) fn window_agg_sql(
+    mut query: SqlQuery,
+    group_key: &GroupKey,
+    aggs: &HashMap<String, AggCall>,
+    i: usize,
+) -> Result<SqlQuery, ExecutionError> {
+    let mut params = Vec::new();
+    let window = match group_key {
+        GroupKey::Tumbling { expr, interval } => {
+            let interval = Interval::parse(interval)?;
+            let key = expr_to_sql(expr, &mut params)?;
+            let not_null = format!("{} IS NOT NULL", expr_to_sql(expr, &mut query.where_params)?);
+            query.where_clause = Some(match query.where_clause.take() {
+                Some(existing) => format!("{} AND {}", existing, not_null),
+                None => not_null,
+            });
+            format!("time_bucket({}, {}) AS {}", interval.sql(), key, quote_ident(&key_name(expr)))
+        }
+        GroupKey::Hopping { expr, size, slide } => {
+            let size = Interval::parse(size)?;
+            let slide = Interval::parse(slide)?;
+            // A row is in the window starting at its slide bucket and in up
+            // to size / slide - 1 earlier ones
+            let offsets = size.seconds.div_ceil(slide.seconds);
+            let key = expr_to_sql(expr, &mut query.from_params)?;
+            query.from_clause.push_str(&format!(
+                " CROSS JOIN LATERAL (SELECT time_bucket({}, {}) - to_seconds(\"_i\" * {}) AS \"_window\" \
+                 FROM range({}) AS \"_offsets\"(\"_i\")) AS \"_hop\"",
+                slide.sql(), key, slide.seconds, offsets
+            ));
+            let contains = format!(
+                "\"_hop\".\"_window\" + {} > {}",
+                size.sql(), expr_to_sql(expr, &mut query.where_params)?
+            );
+            query.where_clause = Some(match query.where_clause.take() {
+                Some(existing) => format!("{} AND {}", existing, contains),
+                None => contains,
+            });
+            format!("\"_hop\".\"_window\" AS {}", quote_ident(&key_name(expr)))
+        }
+        GroupKey::Session { expr, gap } => {
+            let gap = Interval::parse(gap)?;
+            let key = expr_to_sql(expr, &mut query.select_params)?;
+            query.select_clause.push_str(&format!(", {} AS \"_t\"", key));
+
+            // A session starts at a row more than `gap` after the one before
+            // it, and its number counts the starts so far
+            let alias = query.qualifier.clone().unwrap_or_else(|| format!("_q{}", i));
+            query = query.wrap(&alias);
+            query.where_clause = Some("\"_t\" IS NOT NULL".to_string());
+            query.select_clause = format!(
+                "*, CAST(coalesce(\"_t\" > lag(\"_t\") OVER (ORDER BY \"_t\") + {}, true) AS INTEGER) AS \"_starts\"",
+                gap.sql()
+            );
+            query = query.wrap(&alias);
+            query.select_clause = "*, sum(\"_starts\") OVER (ORDER BY \"_t\") AS \"_session\"".to_string();
+            query = query.wrap(&alias);
+            query.group_clause = Some("\"_session\"".to_string());
+            format!("min(\"_t\") AS {}", quote_ident(&key_name(expr)))
+        }
+    };
+
+    let mut select_items = vec![window];
+    select_items.extend(aggs_to_sql(aggs, &mut params)?);
+    query.select_clause = select_items.join(", ");
+    query.select_params = params;
+    if query.group_clause.is_none() {
+        query.group_clause = Some("1".to_string());
+    }
+    // Windows in time order
+    query.order_clause = Some("1".to_string());
+    Ok(query)
+}
+
+/// SQL for `resample <interval> method: <method> on: <on>` over `input`,
+/// as a FROM item named `alias`
+pub(crate) fn resample_sql(
+    input: CompiledQuery,
+    alias: &str,
+    interval: &str,
+    method: &str,
+    on: &ColumnRef,
+    columns: Option<&[String]>,
+) -> Result<CompiledQuery, ExecutionError> {
+    let interval = Interval::parse(interval)?.sql();
+    let columns = match (method, columns) {
+        ("ffill", _) => &[][..],
+        ("linear" | "zero", Some(columns)) => columns,
+        ("linear" | "zero", None) => {
+            return Err(ExecutionError::SqlError(format!(
+                "resample needs the columns to fill with {}; list them in columns or run the query through DuckExecutor",
+                method
+            )));
+        }
+        (other, _) => {
+            return Err(ExecutionError::SqlError(format!(
+                "Unknown resample method '{}'. Supported: ffill, linear, zero",
+                other
+            )));
+        }
+    };
+
+    let time = column_ref_to_sql(on);
+    let t = quote_ident(&on.column);
+    // The row's bucket, or a value interpolated between the rows around it
+    let filled = columns.iter().map(|column| {
+        let c = quote_ident(column);
+        let gap = match method {
+            "zero" => "0".to_string(),
+            _ => format!(
+                "\"_r\".{c} + (\"_n\".{c} - \"_r\".{c}) * (epoch(\"_g\".\"_bucket\") - epoch(\"_r\".{t})) / (epoch(\"_n\".{t}) - epoch(\"_r\".{t}))"
+            ),
+        };
+        format!("CASE WHEN \"_r\".{t} = \"_g\".\"_bucket\" THEN \"_r\".{c} ELSE {gap} END AS {c}")
+    });
+    let replace: Vec<String> = std::iter::once(format!("\"_g\".\"_bucket\" AS {t}")).chain(filled).collect();
+    let next = if method == "linear" {
+        format!(" ASOF LEFT JOIN \"_rows\" AS \"_n\" ON \"_g\".\"_bucket\" <= \"_n\".{t}")
+    } else {
+        String::new()
+    };
+
+    let alias = quote_ident(alias);
+    let sql = format!(
+        "(WITH \"_rows\" AS (\
+         SELECT * REPLACE (time_bucket({interval}, {t}) AS {t}) FROM (\
+         SELECT * FROM ({input}) AS {alias} WHERE {time} IS NOT NULL \
+         QUALIFY row_number() OVER (PARTITION BY time_bucket({interval}, {time}) ORDER BY {time} DESC) = 1\
+         ) AS \"_latest\"), \
+         \"_grid\" AS (SELECT unnest(generate_series(\"_first\", \"_last\", {interval})) AS \"_bucket\" \
+         FROM (SELECT min({t}) AS \"_first\", max({t}) AS \"_last\" FROM \"_rows\")) \
+         SELECT \"_r\".* REPLACE ({replace}) FROM \"_grid\" AS \"_g\" \
+         ASOF LEFT JOIN \"_rows\" AS \"_r\" ON \"_g\".\"_bucket\" >= \"_r\".{t}{next}) AS {alias}",
+        input = input.sql,
+        replace = replace.join(", "),
+    );
+    Ok(CompiledQuery { sql, params: input.params })
+}
+
+impl DuckExecutor {
+    /// Fill in the columns of each `resample` with a `linear` or `zero`
+    /// method from the numeric columns of its input, other than `on`.
+    ///
+    /// Operators inside sub-pipelines are left as written and need columns.
+    pub(crate) fn resolve_resample<'a>(&self, program: &'a Program) -> Result<Cow<'a, Program>, ExecutionError> {
+        let needs_resolving = |op: &Operator| matches!(op,
+            Operator::Resample { method, columns: None, .. } if method != "ffill");
+        let pipelines = program.lets.iter().map(|binding| &binding.pipeline).chain([&program.pipeline]);
+        if !pipelines.flat_map(|pipeline| &pipeline.ops).any(needs_resolving) {
+            return Ok(Cow::Borrowed(program));
+        }
+
+        let mut resolved = program.clone();
+        for b in 0..=program.lets.len() {
+            let binding = (b < program.lets.len()).then_some(b);
+            let pipeline = match binding {
+                Some(b) => &mut resolved.lets[b].pipeline,
+                None => &mut resolved.pipeline,
+            };
+            for (i, op) in pipeline.ops.iter_mut().enumerate() {
+                if !needs_resolving(op) {
+                    continue;
+                }
+                let Operator::Resample { on, columns, .. } = op else {
+                    continue;
+                };
+                let numeric: Vec<String> = self.op_input_schema(program, binding, i).fields.into_iter()
+                    .filter(|f| f.name != on.column && f.data_type.is_numeric())
+                    .map(|f| f.name)
+                    .collect();
+                // Without any, SQL generation reports the missing columns
+                if !numeric.is_empty() {
+                    *columns = Some(numeric);
+                }
+            }
+        }
+        Ok(Cow::Owned(resolved))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_to_sql;
+    use mlql_ir::OpLocation;
+    use mlql_registry::GraphRegistry;
+    use serde_json::json;
+
+    fn metrics() -> Result<DuckExecutor, Box<dyn std::error::Error>> {
+        let executor = DuckExecutor::new()?;
+        executor.connection().execute_batch(
+            "CREATE TABLE metrics (ts TIMESTAMP, host VARCHAR, v INTEGER);
+             INSERT INTO metrics VALUES
+                ('2024-01-01 00:00:00', 'a', 1),
+                ('2024-01-01 00:20:00', 'a', 3),
+                ('2024-01-01 01:10:00', 'a', 5),
+                ('2024-01-01 03:30:00', 'b', 9),
+                (NULL, 'c', 100);"
+        )?;
+        Ok(executor)
+    }
+
+    fn rows(executor: &DuckExecutor, mlql: &str) -> Result<Vec<Vec<serde_json::Value>>, Box<dyn std::error::Error>> {
+        let result = executor.execute_ir(&mlql_ast::parse(mlql)?.to_ir(), None)?;
+        Ok(result.rows)
+    }
+
+    #[test]
+    fn test_interval_parse() {
+        let interval = Interval::parse("5m").unwrap();
+        assert_eq!((interval.sql().as_str(), interval.seconds), ("INTERVAL 5 MINUTE", 300));
+        assert_eq!(Interval::parse("2d").unwrap().seconds, 172_800);
+        for invalid in ["", "5", "m", "0s", "-1h", "5w", "5é"] {
+            assert!(Interval::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_window_agg_sql() -> Result<(), Box<dyn std::error::Error>> {
+        let ir_program = mlql_ast::parse("from metrics | filter v > 1 | agg by tumbling(ts, 1h) { total: sum(v) }")?.to_ir();
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert_eq!(
+            query.sql,
+            "SELECT time_bucket(INTERVAL 1 HOUR, \"ts\") AS \"ts\", sum(\"v\") AS \"total\" \
+             FROM \"metrics\" WHERE (\"v\" > ?) AND \"ts\" IS NOT NULL GROUP BY 1 ORDER BY 1"
+        );
+
+        let ir_program = mlql_ast::parse("from metrics | agg by hopping(ts, 10m, 5m) { n: count() }")?.to_ir();
+        let query = ir_to_sql(&ir_program, &GraphRegistry::default())?;
+        assert!(query.sql.contains("FROM range(2)"), "SQL: {}", query.sql);
+
+        // Intervals from IR that did not come through the grammar
+        let mut ir_program = mlql_ast::parse("from metrics | agg by session(ts, 30m) { n: count() }")?.to_ir();
+        if let Operator::Agg { group_key: GroupKey::Session { gap, .. }, .. } = &mut ir_program.pipeline.ops[0] {
+            *gap = "30 minutes".to_string();
+        }
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("not an interval");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("Invalid interval '30 minutes'"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_agg() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = metrics()?;
+
+        // Rows without a time are in no window
+        let tumbling = rows(&executor, "from metrics | agg by tumbling(ts, 1h) { n: count(), total: sum(v) }")?;
+        assert_eq!(tumbling, vec![
+            vec![json!("2024-01-01 00:00:00"), json!(2), json!(4)],
+            vec![json!("2024-01-01 01:00:00"), json!(1), json!(5)],
+            vec![json!("2024-01-01 03:00:00"), json!(1), json!(9)],
+        ]);
+
+        // Hour-long windows every half hour hold each row twice
+        let hopping = rows(&executor, "from metrics | agg by hopping(ts, 1h, 30m) { n: count() }")?;
+        assert_eq!(hopping, vec![
+            vec![json!("2023-12-31 23:30:00"), json!(2)],
+            vec![json!("2024-01-01 00:00:00"), json!(2)],
+            vec![json!("2024-01-01 00:30:00"), json!(1)],
+            vec![json!("2024-01-01 01:00:00"), json!(1)],
+            vec![json!("2024-01-01 03:00:00"), json!(1)],
+            vec![json!("2024-01-01 03:30:00"), json!(1)],
+        ]);
+
+        // 00:20 is within 30 minutes of 00:00, 01:10 is not within 30 minutes of 00:20
+        let sessions = rows(&executor, "from metrics m | agg by session(m.ts, 30m) { n: count(), until: max(m.ts) }")?;
+        assert_eq!(sessions, vec![
+            vec![json!("2024-01-01 00:00:00"), json!(2), json!("2024-01-01 00:20:00")],
+            vec![json!("2024-01-01 01:10:00"), json!(1), json!("2024-01-01 01:10:00")],
+            vec![json!("2024-01-01 03:30:00"), json!(1), json!("2024-01-01 03:30:00")],
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample() -> Result<(), Box<dyn std::error::Error>> {
+        let executor = metrics()?;
+        let resample = |method: &str| rows(&executor, &format!(
+            "from metrics | filter host == \"a\" | resample 30m method: {} on: ts", method
+        ));
+
+        // The 00:00 bucket keeps its latest row and 00:30 has none
+        assert_eq!(resample("ffill")?, vec![
+            vec![json!("2024-01-01 00:00:00"), json!("a"), json!(3)],
+            vec![json!("2024-01-01 00:30:00"), json!("a"), json!(3)],
+            vec![json!("2024-01-01 01:00:00"), json!("a"), json!(5)],
+        ]);
+        assert_eq!(resample("zero")?[1], vec![json!("2024-01-01 00:30:00"), json!("a"), json!(0)]);
+        let linear = resample("linear")?;
+        let values: Vec<_> = linear.iter().map(|row| row[2].clone()).collect();
+        assert_eq!(values, vec![json!(3.0), json!(4.0), json!(5.0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_errors() -> Result<(), Box<dyn std::error::Error>> {
+        // Only the executor knows which columns to fill
+        let ir_program = mlql_ast::parse("from metrics | resample 1h method: zero on: ts")?.to_ir();
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("no columns to fill");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("resample needs the columns to fill with zero"), "{}", err);
+
+        let ir_program = mlql_ast::parse("from metrics | resample 1h method: spline on: ts")?.to_ir();
+        let err = ir_to_sql(&ir_program, &GraphRegistry::default()).expect_err("spline is not a method");
+        assert!(err.to_string().contains("Unknown resample method 'spline'"), "{}", err);
+
+        Ok(())
+    }
+}
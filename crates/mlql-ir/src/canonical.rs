@@ -0,0 +1,56 @@
+//! Canonical JSON encoding of IR values
+//!
+//! Plain `serde_json` output follows `HashMap` iteration order, so two equal
+//! programs can serialize differently. The canonical form is compact JSON
+//! with:
+//!
+//! - object keys sorted,
+//! - `-0.0` written as `0.0`,
+//! - fields holding their default value (`None`, empty lists, `false` flags)
+//!   omitted, via the `skip_serializing_if` attributes on the IR types.
+//!
+//! [`Program::fingerprint`](crate::Program::fingerprint) hashes this form.
+
+use serde::Serialize;
+use serde_json::Value as Json;
+
+/// Serialize `value` to canonical JSON
+pub fn canonical_json<T: Serialize + ?Sized>(value: &T) -> String {
+    let json = serde_json::to_value(value).expect("IR should always serialize");
+    let mut out = String::new();
+    write_json(&json, &mut out);
+    out
+}
+
+fn write_json(json: &Json, out: &mut String) {
+    match json {
+        Json::Object(map) => {
+            // Sort explicitly: `serde_json/preserve_order` keeps insertion order
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Json::from(key.as_str()).to_string());
+                out.push(':');
+                write_json(value, out);
+            }
+            out.push('}');
+        }
+        Json::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(item, out);
+            }
+            out.push(']');
+        }
+        Json::Number(n) if n.is_f64() && n.as_f64() == Some(0.0) => out.push_str("0.0"),
+        other => out.push_str(&other.to_string()),
+    }
+}
@@ -7,10 +7,14 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 
+mod canonical;
 mod types;
+pub use canonical::canonical_json;
 pub use types::*;
 
 pub mod substrait;
+pub mod validate;
+pub use validate::infer_schema;
 
 /// Top-level MLQL program
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,21 +29,89 @@ pub struct Program {
 }
 
 impl Program {
-    /// Calculate fingerprint (SHA-256) for deterministic caching
+    /// Canonical JSON encoding: equal programs always produce the same text,
+    /// whatever the iteration order of their maps. See [`canonical_json`].
+    pub fn canonical_json(&self) -> String {
+        canonical_json(self)
+    }
+
+    /// Calculate fingerprint (SHA-256 of [`Program::canonical_json`]) for deterministic caching
     pub fn fingerprint(&self) -> String {
-        let json = serde_json::to_string(self).expect("IR should always serialize");
         let mut hasher = Sha256::new();
-        hasher.update(json.as_bytes());
+        hasher.update(self.canonical_json().as_bytes());
         format!("{:x}", hasher.finalize())
     }
 }
 
 /// Pragma configuration block
+///
+/// Recognized keys:
+///
+/// | Key               | Value                   | Meaning                              |
+/// |-------------------|-------------------------|--------------------------------------|
+/// | `timeout`         | int (ms)                | Wall-clock limit for query execution |
+/// | `budget.rows_out` | int                     | Maximum number of result rows        |
+/// | `memory_mb`       | int (MB)                | DuckDB memory limit for the query    |
+/// | `execution_mode`  | `"sql"` / `"substrait"` | Backend used by the server           |
+///
+/// Other keys are preserved in `options` but ignored by the executors.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pragma {
     pub options: HashMap<String, Value>,
 }
 
+impl Pragma {
+    /// Top-level keys understood by the executors
+    pub const KNOWN_KEYS: &'static [&'static str] =
+        &["timeout", "budget", "memory_mb", "execution_mode"];
+
+    /// `timeout` in milliseconds
+    pub fn timeout_ms(&self) -> Option<u64> {
+        self.uint("timeout")
+    }
+
+    /// `budget.rows_out`: maximum number of rows returned
+    pub fn rows_out(&self) -> Option<u64> {
+        match self.options.get("budget") {
+            Some(Value::Object(budget)) => match budget.get("rows_out") {
+                Some(Value::Int(n)) => u64::try_from(*n).ok(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// `memory_mb`: memory limit in megabytes
+    pub fn memory_mb(&self) -> Option<u64> {
+        self.uint("memory_mb")
+    }
+
+    /// `execution_mode`: requested backend ("sql" or "substrait")
+    pub fn execution_mode(&self) -> Option<&str> {
+        match self.options.get("execution_mode") {
+            Some(Value::String(mode)) => Some(mode),
+            _ => None,
+        }
+    }
+
+    /// Keys that are not in [`Pragma::KNOWN_KEYS`]
+    pub fn unknown_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.options.keys()
+            .map(String::as_str)
+            .filter(|k| !Self::KNOWN_KEYS.contains(k))
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn uint(&self, key: &str) -> Option<u64> {
+        match self.options.get(key) {
+            Some(Value::Int(n)) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+}
+
 /// Let binding for reusable pipelines
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LetBinding {
@@ -56,6 +128,34 @@ pub struct Pipeline {
     pub ops: Vec<Operator>,
 }
 
+/// Position of a source or operator within a [`Program`]
+///
+/// Used by translation and execution errors to point back at the operator
+/// that failed; `mlql_ast::SourceMap` maps it to a span of the MLQL text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OpLocation {
+    /// Index into `Program::lets`, or `None` for the main pipeline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binding: Option<usize>,
+
+    /// Index into `Pipeline::ops`, or `None` for the pipeline source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub op: Option<usize>,
+}
+
+impl std::fmt::Display for OpLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.binding {
+            Some(i) => write!(f, "lets[{}]", i)?,
+            None => write!(f, "pipeline")?,
+        }
+        match self.op {
+            Some(i) => write!(f, ".ops[{}]", i),
+            None => write!(f, ".source"),
+        }
+    }
+}
+
 /// Data source
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -107,11 +207,16 @@ pub enum Operator {
     },
     Distinct,
     Union {
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "is_false")]
         all: bool,
+        source: Source,
+    },
+    Except {
+        source: Source,
+    },
+    Intersect {
+        source: Source,
     },
-    Except,
-    Intersect,
     Map {
         mappings: HashMap<String, Expr>,
     },
@@ -120,10 +225,18 @@ pub enum Operator {
         #[serde(skip_serializing_if = "Option::is_none")]
         alias: Option<String>,
     },
+    /// One row per `interval` of the `on` column, from its first value to
+    /// its last, with the latest row in each interval. `method` (`ffill`,
+    /// `linear` or `zero`) fills the intervals without rows.
     Resample {
         interval: String,
         method: String,
         on: ColumnRef,
+        /// Columns filled by `method`; when absent, the input's numeric
+        /// columns other than `on`. Other columns carry their last value
+        /// forward.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        columns: Option<Vec<String>>,
     },
     Agg {
         group_key: GroupKey,
@@ -132,11 +245,16 @@ pub enum Operator {
     Knn {
         query: Expr,
         k: i64,
+        /// Vector column compared with `query`; when absent, the column of
+        /// `index` or the input's only vector column
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on: Option<ColumnRef>,
         #[serde(skip_serializing_if = "Option::is_none")]
         index: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         metric: Option<String>,
     },
+    /// Rows ordered by `by`, highest first, which is added as a `score` column
     Rank {
         by: Expr,
     },
@@ -193,10 +311,14 @@ pub enum Projection {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SortKey {
     pub expr: Expr,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub desc: bool,
 }
 
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WindowDef {
     pub func: String,
@@ -328,6 +450,71 @@ mod tests {
         assert_eq!(program1.fingerprint(), program2.fingerprint());
     }
 
+    #[test]
+    fn test_fingerprint_ignores_map_order() {
+        let names: Vec<String> = (0..32).map(|i| format!("col{}", i)).collect();
+        let program = |names: &mut dyn Iterator<Item = &String>| Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "t".to_string(), alias: None },
+                ops: vec![Operator::Map {
+                    mappings: names
+                        .map(|n| (n.clone(), Expr::Column { col: ColumnRef { table: None, column: n.clone() } }))
+                        .collect(),
+                }],
+            },
+        };
+
+        let forward = program(&mut names.iter());
+        let backward = program(&mut names.iter().rev());
+        assert_eq!(forward.canonical_json(), backward.canonical_json());
+        assert_eq!(forward.fingerprint(), backward.fingerprint());
+
+        let json = forward.canonical_json();
+        assert!(json.find("\"col10\"").unwrap() < json.find("\"col2\"").unwrap());
+    }
+
+    #[test]
+    fn test_canonical_json_normalizes_defaults_and_zero() {
+        let program = |zero: f64, all: Option<bool>| {
+            let mut ops = vec![
+                Operator::Sort {
+                    keys: vec![SortKey {
+                        expr: Expr::Literal { value: Value::Float(zero) },
+                        desc: false,
+                    }],
+                },
+            ];
+            if let Some(all) = all {
+                ops.push(Operator::Union {
+                    all,
+                    source: Source::Table { name: "u".to_string(), alias: None },
+                });
+            }
+            Program {
+                pragma: None,
+                lets: vec![],
+                pipeline: Pipeline {
+                    source: Source::Table { name: "t".to_string(), alias: None },
+                    ops,
+                },
+            }
+        };
+
+        let json = program(0.0, Some(false)).canonical_json();
+        assert_eq!(
+            json,
+            r#"{"pipeline":{"ops":[{"keys":[{"expr":{"type":"Literal","value":0.0}}],"op":"Sort"},{"op":"Union","source":{"name":"u","type":"Table"}}],"source":{"name":"t","type":"Table"}}}"#
+        );
+        assert_eq!(program(-0.0, Some(false)).fingerprint(), program(0.0, Some(false)).fingerprint());
+        assert_ne!(program(0.0, Some(true)).fingerprint(), program(0.0, Some(false)).fingerprint());
+
+        // Elided defaults still deserialize
+        let parsed: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.fingerprint(), program(0.0, Some(false)).fingerprint());
+    }
+
     #[test]
     fn test_json_round_trip() {
         let program = Program {
@@ -584,4 +771,42 @@ mod tests {
             _ => panic!("Expected Filter operator"),
         }
     }
+
+    #[test]
+    fn test_data_type_from_sql() {
+        let field = |name: &str, data_type| FieldType { name: name.to_string(), data_type, nullable: true };
+
+        assert_eq!(DataType::from_sql("integer"), DataType::Int32);
+        assert_eq!(DataType::from_sql("DECIMAL(10,2)"), DataType::Decimal { precision: 10, scale: 2 });
+        assert_eq!(DataType::from_sql("DECIMAL(4)"), DataType::Decimal { precision: 4, scale: 0 });
+        assert_eq!(DataType::from_sql("TIMESTAMP WITH TIME ZONE"), DataType::TimestampTz);
+        assert_eq!(DataType::from_sql("TIMESTAMP_NS"), DataType::Timestamp);
+        assert_eq!(DataType::from_sql("TIME WITH TIME ZONE"), DataType::Time);
+        assert_eq!(DataType::from_sql("VARCHAR[][]"), DataType::Array(Box::new(DataType::Array(Box::new(DataType::String)))));
+        assert_eq!(DataType::from_sql("FLOAT[384]"), DataType::Vector(Some(384)));
+        assert_eq!(DataType::from_sql("FLOAT[ANY]"), DataType::Vector(None));
+        assert_eq!(DataType::from_sql("INTEGER[3]"), DataType::Array(Box::new(DataType::Int32)));
+        // Vectors are read as lists of fp32, so doubles stay doubles
+        assert_eq!(DataType::from_sql("DOUBLE[3]"), DataType::Array(Box::new(DataType::Float64)));
+        assert_eq!(
+            DataType::from_sql("MAP(VARCHAR, DECIMAL(10,2)[])"),
+            DataType::Map {
+                key: Box::new(DataType::String),
+                value: Box::new(DataType::Array(Box::new(DataType::Decimal { precision: 10, scale: 2 }))),
+            }
+        );
+        assert_eq!(
+            DataType::from_sql(r#"STRUCT(id INTEGER, "full, ""quoted"" name" VARCHAR, tags STRUCT(a DATE, b BOOLEAN)[])"#),
+            DataType::Struct(vec![
+                field("id", DataType::Int32),
+                field("full, \"quoted\" name", DataType::String),
+                field("tags", DataType::Array(Box::new(DataType::Struct(vec![
+                    field("a", DataType::Date),
+                    field("b", DataType::Bool),
+                ])))),
+            ])
+        );
+        assert_eq!(DataType::from_sql("UNION(a INTEGER)"), DataType::Unknown);
+        assert_eq!(DataType::from_sql("GEOMETRY"), DataType::Unknown);
+    }
 }
@@ -0,0 +1,210 @@
+//! Substrait extension functions emitted by the translator
+//!
+//! MLQL scalar functions, operators, aggregates and window functions lower to
+//! functions declared in the Substrait standard extension files. Each plan
+//! declares the extension file of every function it uses, so a consumer can
+//! resolve the function against the right definition.
+//!
+//! Functions with no standard equivalent (`date_trunc`, `regexp_matches`,
+//! `ilike`) are declared under [`DUCKDB_FUNCTIONS`]. DuckDB's Substrait consumer
+//! resolves functions by name, so they execute as the DuckDB built-ins.
+//!
+//! Custom functions such as `mask` are resolved through an [`ExtensionResolver`]
+//! and declared under the URI of their own extension document.
+
+use crate::DataType;
+
+/// Standard extension file URIs
+pub const FUNCTIONS_ARITHMETIC: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_arithmetic.yaml";
+pub const FUNCTIONS_AGGREGATE_GENERIC: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_aggregate_generic.yaml";
+pub const FUNCTIONS_BOOLEAN: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_boolean.yaml";
+pub const FUNCTIONS_COMPARISON: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_comparison.yaml";
+pub const FUNCTIONS_DATETIME: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_datetime.yaml";
+pub const FUNCTIONS_ROUNDING: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_rounding.yaml";
+pub const FUNCTIONS_STRING: &str = "https://github.com/substrait-io/substrait/blob/main/extensions/functions_string.yaml";
+
+/// DuckDB built-ins without a standard Substrait declaration
+pub const DUCKDB_FUNCTIONS: &str = "mlql:duckdb:v1";
+
+/// Extension file declaring each standard function the translator emits
+const STANDARD_FUNCTIONS: &[(&str, &str)] = &[
+    // Comparison
+    ("equal", FUNCTIONS_COMPARISON),
+    ("not_equal", FUNCTIONS_COMPARISON),
+    ("lt", FUNCTIONS_COMPARISON),
+    ("lte", FUNCTIONS_COMPARISON),
+    ("gt", FUNCTIONS_COMPARISON),
+    ("gte", FUNCTIONS_COMPARISON),
+    ("coalesce", FUNCTIONS_COMPARISON),
+    // Boolean
+    ("and", FUNCTIONS_BOOLEAN),
+    ("or", FUNCTIONS_BOOLEAN),
+    ("not", FUNCTIONS_BOOLEAN),
+    // Arithmetic, including numeric aggregates and window functions
+    ("add", FUNCTIONS_ARITHMETIC),
+    ("subtract", FUNCTIONS_ARITHMETIC),
+    ("multiply", FUNCTIONS_ARITHMETIC),
+    ("divide", FUNCTIONS_ARITHMETIC),
+    ("modulus", FUNCTIONS_ARITHMETIC),
+    ("negate", FUNCTIONS_ARITHMETIC),
+    ("abs", FUNCTIONS_ARITHMETIC),
+    ("sum", FUNCTIONS_ARITHMETIC),
+    ("avg", FUNCTIONS_ARITHMETIC),
+    ("min", FUNCTIONS_ARITHMETIC),
+    ("max", FUNCTIONS_ARITHMETIC),
+    ("row_number", FUNCTIONS_ARITHMETIC),
+    ("rank", FUNCTIONS_ARITHMETIC),
+    ("dense_rank", FUNCTIONS_ARITHMETIC),
+    ("percent_rank", FUNCTIONS_ARITHMETIC),
+    ("ntile", FUNCTIONS_ARITHMETIC),
+    ("lag", FUNCTIONS_ARITHMETIC),
+    ("lead", FUNCTIONS_ARITHMETIC),
+    ("first_value", FUNCTIONS_ARITHMETIC),
+    ("last_value", FUNCTIONS_ARITHMETIC),
+    // Generic aggregates
+    ("count", FUNCTIONS_AGGREGATE_GENERIC),
+    // Rounding
+    ("round", FUNCTIONS_ROUNDING),
+    // String
+    ("upper", FUNCTIONS_STRING),
+    ("lower", FUNCTIONS_STRING),
+    ("substring", FUNCTIONS_STRING),
+    ("char_length", FUNCTIONS_STRING),
+    ("concat", FUNCTIONS_STRING),
+    ("like", FUNCTIONS_STRING),
+    // Date and time
+    ("extract", FUNCTIONS_DATETIME),
+    ("strftime", FUNCTIONS_DATETIME),
+];
+
+/// Extension file URI declaring the Substrait function `name`
+///
+/// Functions outside the standard extension files are DuckDB built-ins.
+pub fn extension_uri(name: &str) -> &'static str {
+    STANDARD_FUNCTIONS.iter()
+        .find(|(function, _)| *function == name)
+        .map_or(DUCKDB_FUNCTIONS, |(_, uri)| uri)
+}
+
+/// A custom function declared in an extension document outside the standard files
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionFunction {
+    /// URI of the extension document declaring the function, e.g. `mlql:mask:v1`
+    pub uri: String,
+    /// Compound function name, e.g. `mask:str`
+    pub signature: String,
+}
+
+/// Source of custom extension functions for the translator
+/// (`mlql_registry::FunctionRegistry` implements it)
+pub trait ExtensionResolver {
+    /// Resolve a call to `name` with arguments of the given types.
+    ///
+    /// Returns `Ok(None)` when `name` is not a custom function, so the standard
+    /// mapping in [`SCALAR_FUNCTIONS`] applies, and an error when it is one but
+    /// no overload accepts the arguments.
+    fn resolve_extension(&self, name: &str, args: &[DataType]) -> Result<Option<ExtensionFunction>, String>;
+}
+
+/// An MLQL scalar function and the Substrait function it lowers to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScalarFunctionMapping {
+    /// Function name in MLQL
+    pub mlql_name: &'static str,
+    /// Function name in the extension file
+    pub name: &'static str,
+    /// Argument types of the declared signature, as Substrait short type names.
+    /// `req` is an enum argument, passed as a string literal in MLQL.
+    pub args: &'static [&'static str],
+    /// The last argument may repeat
+    pub variadic: bool,
+}
+
+impl ScalarFunctionMapping {
+    /// Compound function name, e.g. `substring:str_i32_i32`
+    pub fn signature(&self) -> String {
+        format!("{}:{}", self.name, self.args.join("_"))
+    }
+
+    fn accepts(&self, arg_count: usize) -> bool {
+        arg_count == self.args.len() || (self.variadic && arg_count >= self.args.len())
+    }
+}
+
+const fn scalar(mlql_name: &'static str, name: &'static str, args: &'static [&'static str]) -> ScalarFunctionMapping {
+    ScalarFunctionMapping { mlql_name, name, args, variadic: false }
+}
+
+const fn variadic(mlql_name: &'static str, name: &'static str, args: &'static [&'static str]) -> ScalarFunctionMapping {
+    ScalarFunctionMapping { mlql_name, name, args, variadic: true }
+}
+
+/// MLQL scalar functions with a Substrait lowering, one entry per overload
+pub const SCALAR_FUNCTIONS: &[ScalarFunctionMapping] = &[
+    scalar("upper", "upper", &["str"]),
+    scalar("lower", "lower", &["str"]),
+    scalar("substr", "substring", &["str", "i32"]),
+    scalar("substr", "substring", &["str", "i32", "i32"]),
+    variadic("coalesce", "coalesce", &["any1"]),
+    scalar("abs", "abs", &["fp64"]),
+    scalar("round", "round", &["fp64"]),
+    scalar("round", "round", &["fp64", "i32"]),
+    scalar("date_trunc", "date_trunc", &["str", "ts"]),
+    scalar("extract", "extract", &["req", "ts"]),
+    scalar("strftime", "strftime", &["ts", "str"]),
+    scalar("length", "char_length", &["str"]),
+    variadic("concat", "concat", &["str"]),
+    scalar("regexp_matches", "regexp_matches", &["str", "str"]),
+];
+
+/// Find the overload of the MLQL function `mlql_name` taking `arg_count` arguments
+pub fn scalar_function_mapping(mlql_name: &str, arg_count: usize) -> Result<&'static ScalarFunctionMapping, String> {
+    let overloads: Vec<_> = SCALAR_FUNCTIONS.iter().filter(|f| f.mlql_name == mlql_name).collect();
+    if overloads.is_empty() {
+        let mut names: Vec<_> = SCALAR_FUNCTIONS.iter().map(|f| f.mlql_name).collect();
+        names.dedup();
+        return Err(format!(
+            "Function '{}' has no Substrait mapping. Supported: {}",
+            mlql_name,
+            names.join(", ")
+        ));
+    }
+
+    overloads.iter().copied().find(|f| f.accepts(arg_count)).ok_or_else(|| {
+        let arities: Vec<_> = overloads.iter()
+            .map(|f| if f.variadic { format!("{}+", f.args.len()) } else { f.args.len().to_string() })
+            .collect();
+        format!(
+            "Function '{}' takes {} arguments, got {}",
+            mlql_name,
+            arities.join(" or "),
+            arg_count
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_function_overloads() {
+        assert_eq!(scalar_function_mapping("substr", 2).unwrap().signature(), "substring:str_i32");
+        assert_eq!(scalar_function_mapping("substr", 3).unwrap().signature(), "substring:str_i32_i32");
+        assert_eq!(scalar_function_mapping("concat", 4).unwrap().signature(), "concat:str");
+        assert_eq!(scalar_function_mapping("length", 1).unwrap().name, "char_length");
+
+        let err = scalar_function_mapping("substr", 1).unwrap_err();
+        assert_eq!(err, "Function 'substr' takes 2 or 3 arguments, got 1");
+        let err = scalar_function_mapping("mask", 1).unwrap_err();
+        assert!(err.starts_with("Function 'mask' has no Substrait mapping"), "{}", err);
+    }
+
+    #[test]
+    fn test_extension_uris() {
+        assert_eq!(extension_uri("upper"), FUNCTIONS_STRING);
+        assert_eq!(extension_uri("count"), FUNCTIONS_AGGREGATE_GENERIC);
+        assert_eq!(extension_uri("gte"), FUNCTIONS_COMPARISON);
+        assert_eq!(extension_uri("date_trunc"), DUCKDB_FUNCTIONS);
+    }
+}
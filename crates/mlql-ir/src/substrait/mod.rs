@@ -22,6 +22,7 @@
 //! ```rust
 //! use mlql_ir::{Program, Pipeline, Source, Operator};
 //! use mlql_ir::substrait::{SubstraitTranslator, MockSchemaProvider, TableSchema, ColumnInfo};
+//! use mlql_ir::DataType;
 //! use prost::Message;
 //!
 //! // Set up schema provider with table metadata
@@ -29,9 +30,9 @@
 //! schema_provider.add_table(TableSchema {
 //!     name: "users".to_string(),
 //!     columns: vec![
-//!         ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-//!         ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-//!         ColumnInfo { name: "age".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+//!         ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+//!         ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+//!         ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
 //!     ],
 //! });
 //!
@@ -64,19 +65,57 @@
 //! | MLQL Operator | Substrait Relation | Status |
 //! |---------------|-------------------|---------|
 //! | `from table` | `ReadRel` | ✅ Complete |
+//! | `from (pipeline) alias` | Inlined relation tree | ✅ Complete |
 //! | `filter` | `FilterRel` | ✅ Complete |
 //! | `select` | `ProjectRel` | ✅ Complete |
 //! | `sort` | `SortRel` | ✅ Complete |
 //! | `take` | `FetchRel` | ✅ Complete |
 //! | `distinct` | `AggregateRel` | ✅ Complete |
 //! | `group by` | `AggregateRel` | ✅ Complete (sum, count, avg, min, max) |
-//! | `join` | `JoinRel` | ✅ Complete |
+//! | `join` | `JoinRel` | ✅ Complete (tables, let bindings and sub-pipelines) |
+//! | `window` | `ProjectRel` of window functions | ✅ Complete |
+//! | `union`/`except`/`intersect` | `SetRel` | ✅ Complete (inputs cast to common types) |
+//!
+//! ## Expressions
+//!
+//! Every IR expression is lowered: function calls and operators become
+//! `ScalarFunction`s, `in [a, b)` ranges become comparisons, `in (...)` sets
+//! become `SingularOrList`, arrays and objects become nested list and struct
+//! expressions, and field access and indexing become nested field references
+//! (struct fields, map keys, and 1-based list elements with a literal index).
+//! Date, time and timestamp literals are converted to days and microseconds
+//! since the Unix epoch.
+//!
+//! ## Functions
+//!
+//! Scalar function calls lower through [`SCALAR_FUNCTIONS`], which maps each
+//! MLQL function to a function and signature in a Substrait extension file:
+//!
+//! | MLQL | Substrait | Extension file |
+//! |------|-----------|----------------|
+//! | `upper`, `lower` | `upper:str`, `lower:str` | `functions_string.yaml` |
+//! | `substr` | `substring:str_i32[_i32]` | `functions_string.yaml` |
+//! | `length` | `char_length:str` | `functions_string.yaml` |
+//! | `concat` | `concat:str` (variadic) | `functions_string.yaml` |
+//! | `coalesce` | `coalesce:any1` (variadic) | `functions_comparison.yaml` |
+//! | `abs` | `abs:fp64` | `functions_arithmetic.yaml` |
+//! | `round` | `round:fp64[_i32]` | `functions_rounding.yaml` |
+//! | `extract` | `extract:req_ts` (component is an enum) | `functions_datetime.yaml` |
+//! | `strftime` | `strftime:ts_str` | `functions_datetime.yaml` |
+//! | `date_trunc`, `regexp_matches` | DuckDB built-ins | `mlql:duckdb:v1` |
+//!
+//! The plan declares the extension file of every function it uses, including
+//! operators, aggregates and window functions.
+//!
+//! Custom functions are resolved through an [`ExtensionResolver`] given to
+//! [`SubstraitTranslator::with_extensions`], usually `mlql_registry::FunctionRegistry`.
+//! They are declared under the URI of their extension document (e.g.
+//! `mlql:mask:v1`), which the registry can export as Substrait extension YAML.
+//! Calls to any other function fail to translate.
 //!
 //! ## Future Work
 //!
-//! - Window functions (`WindowRel`)
-//! - Set operations (`SetRel` for UNION/EXCEPT/INTERSECT)
-//! - Subquery sources (`SubPipeline`)
+//! - Graph sources
 //!
 //! # Schema Provider
 //!
@@ -89,6 +128,7 @@
 //!
 //! ```rust
 //! use mlql_ir::substrait::{SchemaProvider, TableSchema, ColumnInfo};
+//! use mlql_ir::DataType;
 //!
 //! struct MySchemaProvider {
 //!     // Your schema storage
@@ -100,7 +140,7 @@
 //!         Ok(TableSchema {
 //!             name: table_name.to_string(),
 //!             columns: vec![
-//!                 ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false },
+//!                 ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
 //!             ],
 //!         })
 //!     }
@@ -111,10 +151,16 @@
 //!
 //! Translation can fail with [`TranslateError`] for:
 //! - **Schema errors**: Unknown table or column
-//! - **Unsupported operators**: Window, Union (not yet implemented)
-//! - **Translation errors**: Invalid expression structure
+//! - **Schema errors**: Set operation inputs with different column counts or
+//!   incompatible types
+//! - **Unsupported operators**: Map, Expand, Knn and the other operators not listed above
+//! - **Translation errors**: Invalid expression structure, such as indexing a
+//!   list with a non-literal or an unknown struct field
+//! - **Unknown columns**: Reported with the columns available at that point
 //!
-//! All errors include descriptive context for debugging.
+//! Errors are wrapped in [`TranslateError::At`] with the [`OpLocation`](crate::OpLocation)
+//! of the failing source or operator, which `mlql_ast::SourceMap` maps back to
+//! a span of the original MLQL text.
 //!
 //! # Testing
 //!
@@ -128,8 +174,10 @@
 //! env DUCKDB_CUSTOM_BUILD=1 cargo test --package mlql-ir --test substrait_operators
 //! ```
 
+mod functions;
 mod schema;
 mod translator;
 
+pub use functions::{ExtensionFunction, ExtensionResolver, ScalarFunctionMapping, SCALAR_FUNCTIONS, scalar_function_mapping, extension_uri};
 pub use schema::{SchemaProvider, TableSchema, ColumnInfo, MockSchemaProvider};
 pub use translator::{SubstraitTranslator, TranslateError};
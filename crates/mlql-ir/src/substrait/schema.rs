@@ -6,15 +6,18 @@
 
 use std::collections::HashMap;
 
+use crate::DataType;
+
 /// Column metadata describing a single column in a table.
 ///
-/// Contains the column name, data type (as a SQL type string), and nullability flag.
+/// Contains the column name, data type, and nullability flag. Providers backed
+/// by a database parse its type names with [`DataType::from_sql`].
 #[derive(Debug, Clone)]
 pub struct ColumnInfo {
     /// Column name (e.g., "id", "name", "age")
     pub name: String,
-    /// Data type as SQL type string (e.g., "INTEGER", "VARCHAR", "TIMESTAMP")
-    pub data_type: String, // TODO: Use proper type enum
+    /// Column type (e.g., `DataType::Int32`, `DataType::Decimal { precision: 10, scale: 2 }`)
+    pub data_type: DataType,
     /// Whether the column allows NULL values
     pub nullable: bool,
 }
@@ -41,6 +44,7 @@ pub struct TableSchema {
 ///
 /// ```rust
 /// use mlql_ir::substrait::{SchemaProvider, TableSchema, ColumnInfo};
+/// use mlql_ir::DataType;
 ///
 /// struct MySchemaProvider {
 ///     // Your schema storage
@@ -53,8 +57,8 @@ pub struct TableSchema {
 ///             "users" => Ok(TableSchema {
 ///                 name: "users".to_string(),
 ///                 columns: vec![
-///                     ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false },
-///                     ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
+///                     ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
+///                     ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
 ///                 ],
 ///             }),
 ///             _ => Err(format!("Unknown table: {}", table_name)),
@@ -77,10 +81,11 @@ pub trait SchemaProvider {
     ///
     /// ```rust
     /// # use mlql_ir::substrait::{SchemaProvider, MockSchemaProvider, TableSchema, ColumnInfo};
+    /// # use mlql_ir::DataType;
     /// # let mut provider = MockSchemaProvider::new();
     /// # provider.add_table(TableSchema {
     /// #     name: "users".to_string(),
-    /// #     columns: vec![ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false }],
+    /// #     columns: vec![ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false }],
     /// # });
     /// let schema = provider.get_table_schema("users").expect("Table not found");
     /// assert_eq!(schema.name, "users");
@@ -98,13 +103,14 @@ pub trait SchemaProvider {
 ///
 /// ```rust
 /// use mlql_ir::substrait::{MockSchemaProvider, TableSchema, ColumnInfo, SchemaProvider};
+/// use mlql_ir::DataType;
 ///
 /// let mut provider = MockSchemaProvider::new();
 /// provider.add_table(TableSchema {
 ///     name: "users".to_string(),
 ///     columns: vec![
-///         ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false },
-///         ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
+///         ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
+///         ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
 ///     ],
 /// });
 ///
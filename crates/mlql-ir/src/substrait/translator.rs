@@ -1,9 +1,10 @@
 //! Core Substrait translator
 
-use crate::{Program, Pipeline, Source, Operator, Expr, Value, BinOp, UnOp, ColumnRef, Projection, SortKey, AggCall, JoinType};
-use super::schema::SchemaProvider;
+use crate::{Program, Pipeline, Source, Operator, Expr, Value, BinOp, UnOp, ColumnRef, Projection, SortKey, AggCall, WindowDef, FrameMode, FrameBound, JoinType, LetBinding, OpLocation, DataType, FieldType, Schema, infer_schema};
+use super::functions::{extension_uri, scalar_function_mapping, ExtensionResolver};
+use super::schema::{ColumnInfo, SchemaProvider};
 use substrait::proto::Plan;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
 #[derive(Debug, thiserror::Error)]
@@ -16,6 +17,125 @@ pub enum TranslateError {
 
     #[error("Translation error: {0}")]
     Translation(String),
+
+    #[error("Column '{column}' not found. Available columns: {}", available.join(", "))]
+    UnknownColumn { column: String, available: Vec<String> },
+
+    /// An error raised while translating the source or operator at `location`
+    #[error("{location}: {error}")]
+    At { location: OpLocation, error: Box<TranslateError> },
+}
+
+impl TranslateError {
+    fn unknown_column(column: &str, schema: &[SchemaColumn]) -> Self {
+        TranslateError::UnknownColumn {
+            column: column.to_string(),
+            available: schema.iter().map(|c| c.name.clone()).collect(),
+        }
+    }
+
+    /// Location of the operator that failed, if known
+    pub fn location(&self) -> Option<OpLocation> {
+        match self {
+            TranslateError::At { location, .. } => Some(*location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location
+    pub fn root(&self) -> &TranslateError {
+        match self {
+            TranslateError::At { error, .. } => error.root(),
+            other => other,
+        }
+    }
+
+    /// Alternatives that would have been accepted, for diagnostics
+    pub fn expected(&self) -> Vec<String> {
+        match self.root() {
+            TranslateError::UnknownColumn { available, .. } => available.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A column of the relation flowing between two operators, with the table or
+/// alias that qualifies it (`None` for columns computed after the source)
+#[derive(Debug, Clone, PartialEq)]
+struct SchemaColumn {
+    table: Option<String>,
+    name: String,
+    /// Needed to resolve struct fields by name; `Unknown` until inferred
+    data_type: DataType,
+}
+
+impl SchemaColumn {
+    fn unqualified(name: &str) -> Self {
+        SchemaColumn { table: None, name: name.to_string(), data_type: DataType::Unknown }
+    }
+}
+
+/// Index of the column `col` refers to, resolved like the validator does:
+/// a qualified reference prefers the exact qualifier, then unqualified columns
+fn column_index(schema: &[SchemaColumn], col: &ColumnRef) -> Result<usize, TranslateError> {
+    let named = |c: &SchemaColumn| c.name == col.column;
+    let index = match &col.table {
+        Some(table) => schema.iter()
+            .position(|c| named(c) && c.table.as_deref() == Some(table))
+            .or_else(|| schema.iter().position(|c| named(c) && c.table.is_none())),
+        None => schema.iter().position(named),
+    };
+    index.ok_or_else(|| {
+        let column = match &col.table {
+            Some(table) => format!("{}.{}", table, col.column),
+            None => col.column.clone(),
+        };
+        TranslateError::unknown_column(&column, schema)
+    })
+}
+
+/// For `s.f` where `s` is a struct column rather than a table, the index of
+/// `s` and of its field `f`, as the validator resolves it
+fn struct_field_index(schema: &[SchemaColumn], col: &ColumnRef) -> Option<(usize, usize)> {
+    let table = col.table.as_ref()?;
+    let mut structs = schema.iter().enumerate().filter(|(_, c)| &c.name == table);
+    match (structs.next(), structs.next()) {
+        (Some((idx, SchemaColumn { data_type: DataType::Struct(fields), .. })), None) => {
+            fields.iter().position(|f| f.name == col.column).map(|field| (idx, field))
+        }
+        _ => None,
+    }
+}
+
+/// Type of a column or of a field or element nested in one; `Unknown` for
+/// other expressions
+fn expr_type(expr: &Expr, schema: &[SchemaColumn]) -> DataType {
+    match expr {
+        Expr::Column { col } => match column_index(schema, col) {
+            Ok(idx) => schema[idx].data_type.clone(),
+            Err(_) => match struct_field_index(schema, col) {
+                Some((idx, field)) => match &schema[idx].data_type {
+                    DataType::Struct(fields) => fields[field].data_type.clone(),
+                    _ => DataType::Unknown,
+                },
+                None => DataType::Unknown,
+            },
+        },
+        Expr::FieldAccess { expr, field } => match expr_type(expr, schema) {
+            DataType::Struct(fields) => fields.into_iter()
+                .find(|f| &f.name == field)
+                .map_or(DataType::Unknown, |f| f.data_type),
+            DataType::Map { value, .. } => *value,
+            _ => DataType::Unknown,
+        },
+        Expr::Index { expr, .. } => match expr_type(expr, schema) {
+            DataType::Array(element) => *element,
+            DataType::Vector(_) => DataType::Float32,
+            DataType::Map { value, .. } => *value,
+            _ => DataType::Unknown,
+        },
+        _ => DataType::Unknown,
+    }
 }
 
 /// Function registry for tracking which Substrait functions are used
@@ -25,6 +145,8 @@ struct FunctionRegistry {
     functions: HashMap<String, u32>,
     /// Next available anchor
     next_anchor: u32,
+    /// Extension URI of custom functions, by function name; others use the standard files
+    extension_uris: HashMap<String, String>,
 }
 
 impl FunctionRegistry {
@@ -32,6 +154,21 @@ impl FunctionRegistry {
         Self {
             functions: HashMap::new(),
             next_anchor: 1, // Start at 1 (0 is reserved)
+            extension_uris: HashMap::new(),
+        }
+    }
+
+    /// Register a custom function declared in the extension document at `uri`
+    fn register_extension(&mut self, function_name: &str, uri: &str) -> u32 {
+        self.extension_uris.insert(function_name.to_string(), uri.to_string());
+        self.register(function_name)
+    }
+
+    /// Extension URI declaring a registered function
+    fn uri(&self, function_name: &str) -> &str {
+        match self.extension_uris.get(function_name) {
+            Some(uri) => uri,
+            None => extension_uri(function_name.split(':').next().unwrap_or(function_name)),
         }
     }
 
@@ -65,6 +202,7 @@ impl FunctionRegistry {
 /// ```rust
 /// use mlql_ir::{Program, Pipeline, Source};
 /// use mlql_ir::substrait::{SubstraitTranslator, MockSchemaProvider, TableSchema, ColumnInfo};
+/// use mlql_ir::DataType;
 /// use prost::Message;
 ///
 /// // Set up schema
@@ -72,8 +210,8 @@ impl FunctionRegistry {
 /// schema_provider.add_table(TableSchema {
 ///     name: "users".to_string(),
 ///     columns: vec![
-///         ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-///         ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
+///         ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+///         ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
 ///     ],
 /// });
 ///
@@ -104,7 +242,21 @@ impl FunctionRegistry {
 /// - `group by key { agg: sum(x) }` → `[key, agg]`
 /// - `join orders on id == order_id` → `[left_cols..., right_cols...]`
 ///
-/// Schema tracking ensures correct field references throughout the plan.
+/// Columns keep the alias (or table name) of the source they came from, so
+/// `join (from orders | ...) o on u.id == o.user_id` resolves `o.user_id` on the
+/// right even when both sides have a `user_id`. Schema tracking ensures correct
+/// field references throughout the plan.
+///
+/// # Let Bindings
+///
+/// `let name = <pipeline>` bindings are inlined: a `from name` or `join name`
+/// that refers to a binding is replaced by the binding's relation tree, and the
+/// binding's output columns become the schema at that point. A binding only sees
+/// the bindings declared before it, so `let users = from users | ...` reads the
+/// base `users` table.
+///
+/// Sub-pipeline sources (`from (from x | ...) alias`, or the same as a join or
+/// set operation source) are inlined the same way.
 ///
 /// # Operator Mapping
 ///
@@ -133,6 +285,14 @@ pub struct SubstraitTranslator<'a> {
     schema_provider: &'a dyn SchemaProvider,
     /// Function registry (using RefCell for interior mutability)
     function_registry: RefCell<FunctionRegistry>,
+    /// Let bindings of the program being translated
+    lets: RefCell<Vec<LetBinding>>,
+    /// Number of let bindings visible from the pipeline being translated
+    let_scope: Cell<usize>,
+    /// Let binding being translated, or `None` for the main pipeline
+    binding: Cell<Option<usize>>,
+    /// Resolver for custom functions, such as those in `mlql_registry`
+    extensions: Option<&'a dyn ExtensionResolver>,
 }
 
 impl<'a> SubstraitTranslator<'a> {
@@ -144,9 +304,22 @@ impl<'a> SubstraitTranslator<'a> {
         Self {
             schema_provider,
             function_registry: RefCell::new(FunctionRegistry::new()),
+            lets: RefCell::new(Vec::new()),
+            let_scope: Cell::new(0),
+            binding: Cell::new(None),
+            extensions: None,
         }
     }
 
+    /// Resolve calls to custom functions through `extensions`.
+    ///
+    /// Those functions are declared under the URI of their own extension
+    /// document instead of a Substrait standard file.
+    pub fn with_extensions(mut self, extensions: &'a dyn ExtensionResolver) -> Self {
+        self.extensions = Some(extensions);
+        self
+    }
+
     /// Translate an MLQL IR Program to a Substrait Plan.
     ///
     /// # Arguments
@@ -163,11 +336,12 @@ impl<'a> SubstraitTranslator<'a> {
     /// ```rust
     /// # use mlql_ir::{Program, Pipeline, Source};
     /// # use mlql_ir::substrait::{SubstraitTranslator, MockSchemaProvider, TableSchema, ColumnInfo};
+    /// # use mlql_ir::DataType;
     /// # let mut schema_provider = MockSchemaProvider::new();
     /// # schema_provider.add_table(TableSchema {
     /// #     name: "users".to_string(),
     /// #     columns: vec![
-    /// #         ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+    /// #         ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
     /// #     ],
     /// # });
     /// # let program = Program {
@@ -182,11 +356,17 @@ impl<'a> SubstraitTranslator<'a> {
     /// let plan = translator.translate(&program).expect("Translation failed");
     /// ```
     pub fn translate(&self, program: &Program) -> Result<Plan, TranslateError> {
+        // The main pipeline sees every let binding
+        *self.lets.borrow_mut() = program.lets.clone();
+        self.let_scope.set(program.lets.len());
+        self.binding.set(None);
+
         // Translate the main pipeline to a relation
         let root_rel = self.translate_pipeline(&program.pipeline)?;
 
         // Calculate the FINAL output column names based on the pipeline
-        let names = self.get_pipeline_output_names(&program.pipeline)?;
+        let mut names = Vec::new();
+        root_names(&infer_schema(program, self.schema_provider).fields, &mut names);
 
         // Wrap in PlanRel
         let plan_rel = substrait::proto::PlanRel {
@@ -227,199 +407,267 @@ impl<'a> SubstraitTranslator<'a> {
             return (vec![], vec![]);
         }
 
-        // Create extension URI for Substrait standard functions
-        // Note: functions_arithmetic.yaml includes both arithmetic AND aggregate functions (sum, count, avg, etc.)
-        let extension_uri = substrait::proto::extensions::SimpleExtensionUri {
-            extension_uri_anchor: 1,
-            uri: "https://github.com/substrait-io/substrait/blob/main/extensions/functions_arithmetic.yaml".to_string(),
-        };
+        // Declare each function's extension file once, in order of first use
+        let mut extension_uris: Vec<substrait::proto::extensions::SimpleExtensionUri> = Vec::new();
+        let extensions = functions.iter().map(|(signature, anchor)| {
+            let uri = registry.uri(signature);
+            let uri_anchor = match extension_uris.iter().find(|u| u.uri == uri) {
+                Some(existing) => existing.extension_uri_anchor,
+                None => {
+                    let uri_anchor = extension_uris.len() as u32 + 1;
+                    extension_uris.push(substrait::proto::extensions::SimpleExtensionUri {
+                        extension_uri_anchor: uri_anchor,
+                        uri: uri.to_string(),
+                    });
+                    uri_anchor
+                }
+            };
 
-        // Create extension function declarations
-        let extensions = functions.iter().map(|(function_name, anchor)| {
             substrait::proto::extensions::SimpleExtensionDeclaration {
                 mapping_type: Some(substrait::proto::extensions::simple_extension_declaration::MappingType::ExtensionFunction(
                     substrait::proto::extensions::simple_extension_declaration::ExtensionFunction {
-                        extension_uri_reference: 1, // Reference to the URI
+                        extension_uri_reference: uri_anchor,
                         extension_urn_reference: 0, // Deprecated field (0 = not used)
                         function_anchor: *anchor,
-                        name: function_name.clone(),
+                        name: signature.clone(),
                     }
                 )),
             }
         }).collect();
 
-        (vec![extension_uri], extensions)
+        (extension_uris, extensions)
+    }
+
+    /// Look up the let binding a table name refers to in the current scope.
+    ///
+    /// Returns the binding's own scope (the bindings declared before it) and its pipeline.
+    fn resolve_let(&self, name: &str) -> Option<(usize, Pipeline)> {
+        let lets = self.lets.borrow();
+        lets[..self.let_scope.get()]
+            .iter()
+            .rposition(|binding| binding.name == name)
+            .map(|idx| (idx, lets[idx].pipeline.clone()))
+    }
+
+    /// Run `f` inside let binding `scope`, with only the bindings declared before it visible
+    fn in_let_scope<T>(&self, scope: usize, f: impl FnOnce() -> T) -> T {
+        let saved = self.let_scope.replace(scope);
+        let saved_binding = self.binding.replace(Some(scope));
+        let result = f();
+        self.let_scope.set(saved);
+        self.binding.set(saved_binding);
+        result
+    }
+
+    /// Attach the location of operator `op` (`None` for the source) to an error.
+    ///
+    /// Errors from an inlined let binding already carry the binding's location
+    /// and are passed through unchanged.
+    fn at(&self, op: Option<usize>) -> impl Fn(TranslateError) -> TranslateError + '_ {
+        move |error| match error {
+            TranslateError::At { .. } => error,
+            error => TranslateError::At {
+                location: OpLocation { binding: self.binding.get(), op },
+                error: Box::new(error),
+            },
+        }
     }
 
-    /// Get the output column names for a source
-    fn get_output_names(&self, source: &Source) -> Result<Vec<String>, TranslateError> {
+    /// Whether a source is an inlined relation tree (a let binding or a
+    /// sub-pipeline) rather than a base table read
+    fn is_inlined_source(&self, source: &Source) -> bool {
         match source {
-            Source::Table { name, alias: _ } => {
-                let schema = self.schema_provider
-                    .get_table_schema(name)
-                    .map_err(TranslateError::Schema)?;
-                Ok(schema.columns.iter().map(|c| c.name.clone()).collect())
-            }
-            _ => Err(TranslateError::UnsupportedOperator("Only Table sources supported currently".to_string())),
+            Source::Table { name, .. } => self.resolve_let(name).is_some(),
+            Source::SubPipeline { .. } => true,
+            Source::Graph { .. } => false,
         }
     }
 
-    /// Calculate the FINAL output schema of a pipeline after all operators
-    fn get_pipeline_output_names(&self, pipeline: &Pipeline) -> Result<Vec<String>, TranslateError> {
-        let mut current_schema = self.get_output_names(&pipeline.source)?;
+    /// Get the output columns of a source, qualified by its alias (or table
+    /// or binding name)
+    fn source_columns(&self, source: &Source) -> Result<Vec<SchemaColumn>, TranslateError> {
+        let (columns, qualifier): (Vec<(String, DataType)>, _) = match source {
+            Source::Table { name, alias } => {
+                let columns = if let Some((scope, pipeline)) = self.resolve_let(name) {
+                    self.in_let_scope(scope, || self.pipeline_schema(&pipeline))
+                        .fields.into_iter().map(|f| (f.name, f.data_type)).collect()
+                } else {
+                    let schema = self.schema_provider
+                        .get_table_schema(name)
+                        .map_err(TranslateError::Schema)?;
+                    schema.columns.into_iter().map(|c| (c.name, c.data_type)).collect()
+                };
+                (columns, Some(alias.as_ref().unwrap_or(name)))
+            }
+            Source::SubPipeline { pipeline, alias } => {
+                let columns = self.pipeline_schema(pipeline)
+                    .fields.into_iter().map(|f| (f.name, f.data_type)).collect();
+                (columns, alias.as_ref())
+            }
+            Source::Graph { .. } => {
+                return Err(TranslateError::UnsupportedOperator("Graph sources not yet supported".to_string()));
+            }
+        };
+
+        Ok(columns.into_iter()
+            .map(|(name, data_type)| SchemaColumn { table: qualifier.cloned(), name, data_type })
+            .collect())
+    }
 
-        // Trace through operators to calculate final schema
-        for op in &pipeline.ops {
-            current_schema = match op {
-                Operator::Select { projections } => {
-                    // Select changes the schema to the projected columns
-                    let mut result = Vec::new();
-                    for (idx, proj) in projections.iter().enumerate() {
-                        match proj {
-                            Projection::Expr(Expr::Column { col }) => {
-                                result.push(col.column.clone());
-                            }
-                            Projection::Aliased { alias, .. } => {
-                                result.push(alias.clone());
-                            }
-                            Projection::Expr(_) => {
-                                // For non-column expressions without alias, generate name
-                                result.push(format!("expr_{}", idx));
-                            }
-                        }
-                    }
-                    result
-                }
-                Operator::GroupBy { keys, aggs } => {
-                    // GroupBy output: grouping keys + aggregate aliases
-                    let mut output = Vec::new();
-                    for key in keys {
-                        output.push(key.column.clone());
-                    }
-                    for (alias, _) in aggs {
-                        output.push(alias.clone());
-                    }
-                    output
-                }
-                Operator::Join { source, .. } => {
-                    // Join output: [left_columns..., right_columns...]
-                    let right_schema = self.get_output_names(source)?;
-                    let mut output = current_schema.clone();
-                    output.extend(right_schema);
-                    output
-                }
-                // Most operators preserve the schema
-                Operator::Filter { .. } |
-                Operator::Sort { .. } |
-                Operator::Take { .. } |
-                Operator::Distinct => {
-                    current_schema // No change
-                }
-                _ => {
-                    return Err(TranslateError::UnsupportedOperator(format!("Output schema calculation not implemented for operator: {:?}", op)));
-                }
-            };
-        }
+    /// Output schema of a pipeline, with the let bindings in the current scope visible
+    fn pipeline_schema(&self, pipeline: &Pipeline) -> Schema {
+        let program = Program {
+            pragma: None,
+            lets: self.lets.borrow()[..self.let_scope.get()].to_vec(),
+            pipeline: pipeline.clone(),
+        };
+        infer_schema(&program, self.schema_provider)
+    }
 
-        Ok(current_schema)
+    /// Translate a sub-pipeline source into an inlined relation tree.
+    ///
+    /// Operators inside a sub-pipeline have no location of their own, so its
+    /// errors are reported at the enclosing source or operator. Errors from a
+    /// let binding it reads keep the binding's location.
+    fn translate_sub_pipeline(&self, pipeline: &Pipeline) -> Result<substrait::proto::Rel, TranslateError> {
+        self.translate_pipeline(pipeline).map_err(|error| match error {
+            TranslateError::At { location, error } if location.binding == self.binding.get() => *error,
+            error => error,
+        })
     }
 
     fn translate_pipeline(&self, pipeline: &Pipeline) -> Result<substrait::proto::Rel, TranslateError> {
         // Check if we need ReadRel projection (for Select or GroupBy operators)
         // For DuckDB Substrait compatibility, column projections must be in ReadRel, not ProjectRel
-        let (projection_fields, skip_first_select) = if let Some(projection) = self.calculate_select_projection(pipeline)? {
+        // Let bindings and sub-pipelines are inlined relations, not ReadRels, so they get no projection
+        let (projection_fields, skip_first_select) = if self.is_inlined_source(&pipeline.source) {
+            (None, false)
+        } else if let Some(projection) = self.calculate_select_projection(pipeline).map_err(self.at(Some(0)))? {
             (Some(projection), true)  // Put Select projection in ReadRel and skip the Select operator
-        } else if pipeline.ops.iter().any(|op| matches!(op, Operator::GroupBy { .. })) {
-            (self.calculate_groupby_projection(pipeline)?, false)
+        } else if let Some(group_idx) = pipeline.ops.iter().position(|op| matches!(op, Operator::GroupBy { .. })) {
+            (self.calculate_groupby_projection(pipeline).map_err(self.at(Some(group_idx)))?, false)
         } else {
             (None, false)
         };
 
         // Start with the source and get the initial schema
-        let mut rel = self.translate_source_with_projection(&pipeline.source, projection_fields.as_ref())?;
+        let mut rel = self.translate_source_with_projection(&pipeline.source, projection_fields.as_ref())
+            .map_err(self.at(None))?;
 
         // Get the schema context from the source
-        let mut current_schema = if let Some(ref fields) = projection_fields {
+        let full_schema = self.source_columns(&pipeline.source).map_err(self.at(None))?;
+        let mut current_schema: Vec<SchemaColumn> = if let Some(ref fields) = projection_fields {
             // If projection is applied, schema is the projected columns
-            let full_schema = self.get_output_names(&pipeline.source)?;
             fields.iter().map(|&idx| full_schema[idx].clone()).collect()
         } else {
-            self.get_output_names(&pipeline.source)?
+            full_schema
         };
 
         // Apply operators on top of the source relation, updating schema as we go
         let mut skip_next_select = skip_first_select;
-        for op in &pipeline.ops {
-            // Skip the first Select operator if we already applied its projection in ReadRel
+        for (i, op) in pipeline.ops.iter().enumerate() {
+            // Skip the first Select operator if we already applied its projection in ReadRel.
+            // The projected columns are already the Select's output.
             if skip_next_select && matches!(op, Operator::Select { .. }) {
                 skip_next_select = false;
-                // Update schema for the skipped Select
-                current_schema = match op {
-                    Operator::Select { projections } => {
-                        let mut result = Vec::new();
-                        for (idx, proj) in projections.iter().enumerate() {
-                            match proj {
-                                Projection::Expr(Expr::Column { col }) => {
-                                    result.push(col.column.clone());
-                                }
-                                Projection::Aliased { alias, .. } => {
-                                    result.push(alias.clone());
-                                }
-                                Projection::Expr(_) => {
-                                    result.push(format!("expr_{}", idx));
-                                }
-                            }
-                        }
-                        result
-                    }
-                    _ => current_schema
-                };
                 continue;  // Skip translating this operator
             }
 
-            rel = self.translate_operator(op, rel, &current_schema)?;
+            rel = match op {
+                // Set operations coerce both inputs to common column types
+                Operator::Union { source, .. } | Operator::Except { source } | Operator::Intersect { source } => {
+                    let left = self.pipeline_schema(&Pipeline {
+                        source: pipeline.source.clone(),
+                        ops: pipeline.ops[..i].to_vec(),
+                    });
+                    self.translate_set_operation(op, rel, &left, source)
+                }
+                _ => self.translate_operator(op, rel, &current_schema),
+            }.map_err(self.at(Some(i)))?;
 
             // Update schema after operators that change it
             current_schema = match op {
                 Operator::Select { projections } => {
-                    // Select changes the schema to the projected columns
+                    // Select changes the schema to the projected columns;
+                    // plain column references keep their qualifier
                     let mut result = Vec::new();
                     for (idx, proj) in projections.iter().enumerate() {
                         match proj {
-                            Projection::Expr(Expr::Column { col }) => {
-                                result.push(col.column.clone());
-                            }
+                            Projection::Expr(Expr::Column { col }) => match column_index(&current_schema, col) {
+                                Ok(idx) => result.push(current_schema[idx].clone()),
+                                // A struct field is a computed column named after the field
+                                Err(_) if struct_field_index(&current_schema, col).is_some() => {
+                                    result.push(SchemaColumn::unqualified(&col.column));
+                                }
+                                Err(e) => return Err(self.at(Some(i))(e)),
+                            },
                             Projection::Aliased { alias, .. } => {
-                                result.push(alias.clone());
+                                result.push(SchemaColumn::unqualified(alias));
                             }
                             Projection::Expr(_) => {
-                                result.push(format!("expr_{}", idx));
+                                result.push(SchemaColumn::unqualified(&format!("expr_{}", idx)));
                             }
                         }
                     }
                     result
                 }
                 Operator::GroupBy { keys, aggs } => {
-                    // GroupBy output: grouping keys + aggregate aliases
+                    // GroupBy output: grouping keys + aggregate aliases in name order
                     let mut output = Vec::new();
                     for key in keys {
-                        output.push(key.column.clone());
+                        let idx = column_index(&current_schema, key).map_err(self.at(Some(i)))?;
+                        output.push(current_schema[idx].clone());
+                    }
+                    for (alias, _) in sorted(aggs) {
+                        output.push(SchemaColumn::unqualified(alias));
                     }
-                    for (alias, _) in aggs {
-                        output.push(alias.clone());
+                    output
+                }
+                Operator::Window { windows } => {
+                    // A window replacing an input column moves it to the end
+                    let mut output: Vec<SchemaColumn> = current_schema.iter()
+                        .filter(|c| !windows.contains_key(&c.name))
+                        .cloned()
+                        .collect();
+                    for (name, _) in sorted(windows) {
+                        output.push(SchemaColumn::unqualified(name));
                     }
                     output
                 }
+                // Semi and anti joins only filter the left side
+                Operator::Join { join_type: Some(JoinType::Semi | JoinType::Anti), .. } => current_schema,
                 Operator::Join { source, .. } => {
                     // Join output: [left_columns..., right_columns...]
-                    let right_schema = self.get_output_names(source)?;
-                    let mut output = current_schema.clone();
+                    let right_schema = self.source_columns(source).map_err(self.at(Some(i)))?;
+                    let mut output = current_schema;
                     output.extend(right_schema);
                     output
                 }
+                // Set operations take their (unqualified) column names from the left
+                Operator::Union { .. } | Operator::Except { .. } | Operator::Intersect { .. } => {
+                    current_schema.iter().map(|c| SchemaColumn::unqualified(&c.name)).collect()
+                }
                 // Most operators preserve the schema
                 _ => current_schema
             };
+
+            // Computed columns take their types from the inferred output schema
+            let computes_columns = matches!(op,
+                Operator::Select { .. } | Operator::GroupBy { .. } | Operator::Window { .. }
+                | Operator::Union { .. } | Operator::Except { .. } | Operator::Intersect { .. });
+            if computes_columns {
+                let output = self.pipeline_schema(&Pipeline {
+                    source: pipeline.source.clone(),
+                    ops: pipeline.ops[..=i].to_vec(),
+                });
+                if output.fields.len() == current_schema.len() {
+                    for (column, field) in current_schema.iter_mut().zip(output.fields) {
+                        if column.data_type == DataType::Unknown {
+                            column.data_type = field.data_type;
+                        }
+                    }
+                }
+            }
         }
 
         Ok(rel)
@@ -428,14 +676,17 @@ impl<'a> SubstraitTranslator<'a> {
     fn calculate_groupby_projection(&self, pipeline: &Pipeline) -> Result<Option<Vec<usize>>, TranslateError> {
         // Find GroupBy operator and collect needed columns
         for op in &pipeline.ops {
+            // Columns after a window or set operation aren't (only) the source table's
+            if matches!(op, Operator::Window { .. } | Operator::Union { .. } | Operator::Except { .. } | Operator::Intersect { .. }) {
+                return Ok(None);
+            }
             if let Operator::GroupBy { keys, aggs } = op {
-                let full_schema = self.get_output_names(&pipeline.source)?;
+                let full_schema = self.source_columns(&pipeline.source)?;
                 let mut needed_indices = Vec::new();
 
                 // Add grouping key column indices
                 for key in keys {
-                    let idx = full_schema.iter().position(|name| name == &key.column)
-                        .ok_or_else(|| TranslateError::Translation(format!("Column '{}' not found", key.column)))?;
+                    let idx = column_index(&full_schema, key)?;
                     if !needed_indices.contains(&idx) {
                         needed_indices.push(idx);
                     }
@@ -445,8 +696,7 @@ impl<'a> SubstraitTranslator<'a> {
                 for agg_call in aggs.values() {
                     for expr in &agg_call.args {
                         if let Expr::Column { col } = expr {
-                            let idx = full_schema.iter().position(|name| name == &col.column)
-                                .ok_or_else(|| TranslateError::Translation(format!("Column '{}' not found", col.column)))?;
+                            let idx = column_index(&full_schema, col)?;
                             if !needed_indices.contains(&idx) {
                                 needed_indices.push(idx);
                             }
@@ -464,17 +714,20 @@ impl<'a> SubstraitTranslator<'a> {
         // Check if the first operator is a Select with only column references (no expressions)
         // If so, we can optimize by putting the projection in ReadRel instead of using ProjectRel
         if let Some(Operator::Select { projections }) = pipeline.ops.first() {
-            let full_schema = self.get_output_names(&pipeline.source)?;
+            let full_schema = self.source_columns(&pipeline.source)?;
             let mut projection_indices = Vec::new();
 
             // Check if all projections are simple column references
             for proj in projections {
                 match proj {
                     Projection::Expr(Expr::Column { col }) => {
-                        // Find the column index in the source schema
-                        let idx = full_schema.iter().position(|name| name == &col.column)
-                            .ok_or_else(|| TranslateError::Translation(format!("Column '{}' not found in schema", col.column)))?;
-                        projection_indices.push(idx);
+                        // Find the column index in the source schema; struct
+                        // fields need a ProjectRel
+                        match column_index(&full_schema, col) {
+                            Ok(idx) => projection_indices.push(idx),
+                            Err(_) if struct_field_index(&full_schema, col).is_some() => return Ok(None),
+                            Err(e) => return Err(e),
+                        }
                     }
                     // For now, only handle simple column references
                     // Expressions and aliases need ProjectRel
@@ -491,6 +744,16 @@ impl<'a> SubstraitTranslator<'a> {
     fn translate_source_with_projection(&self, source: &Source, projection: Option<&Vec<usize>>) -> Result<substrait::proto::Rel, TranslateError> {
         match source {
             Source::Table { name, alias: _ } => {
+                // Inline the relation tree of a let binding
+                if let Some((scope, pipeline)) = self.resolve_let(name) {
+                    if projection.is_some() {
+                        return Err(TranslateError::Translation(
+                            format!("Cannot push a projection into let binding '{}'", name)
+                        ));
+                    }
+                    return self.in_let_scope(scope, || self.translate_pipeline(&pipeline));
+                }
+
                 // Look up schema from provider
                 let schema = self.schema_provider
                     .get_table_schema(name)
@@ -498,11 +761,11 @@ impl<'a> SubstraitTranslator<'a> {
 
                 // Build NamedStruct for base_schema
                 let named_struct = substrait::proto::NamedStruct {
-                    names: schema.columns.iter().map(|c| c.name.clone()).collect(),
+                    names: column_names(&schema.columns),
                     r#struct: Some(substrait::proto::r#type::Struct {
                         types: schema.columns.iter().map(|c| {
-                            self.map_type(&c.data_type, c.nullable)
-                        }).collect(),
+                            self.map_read_type(&c.data_type, c.nullable)
+                        }).collect::<Result<_, _>>()?,
                         type_variation_reference: 0,
                         nullability: substrait::proto::r#type::Nullability::Required as i32,
                     }),
@@ -543,11 +806,20 @@ impl<'a> SubstraitTranslator<'a> {
                     rel_type: Some(substrait::proto::rel::RelType::Read(Box::new(read_rel))),
                 })
             }
-            _ => Err(TranslateError::UnsupportedOperator("Only Table sources supported currently".to_string())),
+            // Inline the relation tree of a sub-pipeline
+            Source::SubPipeline { pipeline, .. } => {
+                if projection.is_some() {
+                    return Err(TranslateError::Translation(
+                        "Cannot push a projection into a sub-pipeline".to_string()
+                    ));
+                }
+                self.translate_sub_pipeline(pipeline)
+            }
+            Source::Graph { .. } => Err(TranslateError::UnsupportedOperator("Graph sources not yet supported".to_string())),
         }
     }
 
-    fn translate_operator(&self, op: &Operator, input: substrait::proto::Rel, schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
+    fn translate_operator(&self, op: &Operator, input: substrait::proto::Rel, schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
         match op {
             Operator::Filter { condition } => self.translate_filter(input, condition, schema),
             Operator::Select { projections } => self.translate_select(input, projections, schema),
@@ -555,12 +827,13 @@ impl<'a> SubstraitTranslator<'a> {
             Operator::Take { limit } => self.translate_take(input, *limit),
             Operator::Distinct => self.translate_distinct(input, schema),
             Operator::GroupBy { keys, aggs } => self.translate_groupby(input, keys, aggs, schema),
+            Operator::Window { windows } => self.translate_window(input, windows, schema),
             Operator::Join { source, on, join_type } => self.translate_join(input, source, on, join_type, schema),
             _ => Err(TranslateError::UnsupportedOperator(format!("Operator {:?} not yet supported", op))),
         }
     }
 
-    fn translate_filter(&self, input: substrait::proto::Rel, condition: &Expr, schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
+    fn translate_filter(&self, input: substrait::proto::Rel, condition: &Expr, schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
         // Convert the condition expression to a Substrait expression
         let substrait_condition = self.translate_expr(condition, schema)?;
 
@@ -578,7 +851,7 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_select(&self, input: substrait::proto::Rel, projections: &[Projection], schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
+    fn translate_select(&self, input: substrait::proto::Rel, projections: &[Projection], schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
         // Convert each projection to a Substrait expression
         let expressions: Result<Vec<_>, _> = projections.iter().map(|proj| {
             match proj {
@@ -607,24 +880,9 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_sort(&self, input: substrait::proto::Rel, keys: &[SortKey], schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
+    fn translate_sort(&self, input: substrait::proto::Rel, keys: &[SortKey], schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
         // Convert each sort key to a Substrait SortField
-        let sorts: Result<Vec<_>, _> = keys.iter().map(|key| {
-            let expr = self.translate_expr(&key.expr, schema)?;
-
-            // Map MLQL desc flag to Substrait SortDirection
-            // Protobuf enum values: ASC_NULLS_FIRST=1, ASC_NULLS_LAST=2, DESC_NULLS_FIRST=3, DESC_NULLS_LAST=4
-            let direction = if key.desc {
-                4  // SORT_DIRECTION_DESC_NULLS_LAST
-            } else {
-                1  // SORT_DIRECTION_ASC_NULLS_FIRST
-            };
-
-            Ok(substrait::proto::SortField {
-                expr: Some(expr),
-                sort_kind: Some(substrait::proto::sort_field::SortKind::Direction(direction)),
-            })
-        }).collect();
+        let sorts: Result<Vec<_>, _> = keys.iter().map(|key| self.translate_sort_field(key, schema)).collect();
 
         let sorts = sorts?;
 
@@ -642,6 +900,23 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
+    fn translate_sort_field(&self, key: &SortKey, schema: &[SchemaColumn]) -> Result<substrait::proto::SortField, TranslateError> {
+        let expr = self.translate_expr(&key.expr, schema)?;
+
+        // Map MLQL desc flag to Substrait SortDirection
+        // Protobuf enum values: ASC_NULLS_FIRST=1, ASC_NULLS_LAST=2, DESC_NULLS_FIRST=3, DESC_NULLS_LAST=4
+        let direction = if key.desc {
+            4  // SORT_DIRECTION_DESC_NULLS_LAST
+        } else {
+            1  // SORT_DIRECTION_ASC_NULLS_FIRST
+        };
+
+        Ok(substrait::proto::SortField {
+            expr: Some(expr),
+            sort_kind: Some(substrait::proto::sort_field::SortKind::Direction(direction)),
+        })
+    }
+
     fn translate_take(&self, input: substrait::proto::Rel, limit: i64) -> Result<substrait::proto::Rel, TranslateError> {
         // Create FetchRel (Substrait's LIMIT operator)
         // NOTE: We use the DEPRECATED oneof variants because DuckDB v1.3 substrait extension
@@ -661,7 +936,7 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_distinct(&self, input: substrait::proto::Rel, schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
+    fn translate_distinct(&self, input: substrait::proto::Rel, schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
         // DISTINCT is implemented as an AggregateRel with grouping on all columns and no measures
         // This is the standard Substrait pattern for deduplication
         //
@@ -719,10 +994,10 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_join(&self, left_input: substrait::proto::Rel, right_source: &Source, condition: &Expr, join_type: &Option<JoinType>, left_schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
-        // Translate the right source (typically a table)
+    fn translate_join(&self, left_input: substrait::proto::Rel, right_source: &Source, condition: &Expr, join_type: &Option<JoinType>, left_schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
+        // Translate the right source (a table, let binding or sub-pipeline)
         let right_rel = self.translate_source_with_projection(right_source, None)?;
-        let right_schema = self.get_output_names(right_source)?;
+        let right_schema = self.source_columns(right_source)?;
 
         // Combined schema: [left_cols..., right_cols...]
         let mut combined_schema = left_schema.to_vec();
@@ -762,7 +1037,102 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_groupby(&self, input: substrait::proto::Rel, keys: &[ColumnRef], aggs: &HashMap<String, AggCall>, schema: &[String]) -> Result<substrait::proto::Rel, TranslateError> {
+    fn translate_set_operation(&self, op: &Operator, left_input: substrait::proto::Rel, left: &Schema, right_source: &Source) -> Result<substrait::proto::Rel, TranslateError> {
+        use substrait::proto::set_rel::SetOp;
+
+        // DuckDB maps these to EXCEPT and INTERSECT (see from_substrait.cpp)
+        let (name, set_op) = match op {
+            Operator::Union { all: true, .. } => ("union", SetOp::UnionAll),
+            Operator::Union { all: false, .. } => ("union", SetOp::UnionDistinct),
+            Operator::Except { .. } => ("except", SetOp::MinusPrimary),
+            _ => ("intersect", SetOp::IntersectionPrimary),
+        };
+
+        let right_input = self.translate_source_with_projection(right_source, None)?;
+        let right = self.pipeline_schema(&Pipeline { source: right_source.clone(), ops: vec![] });
+
+        if left.fields.len() != right.fields.len() {
+            return Err(TranslateError::Schema(format!(
+                "{} inputs have {} and {} columns", name, left.fields.len(), right.fields.len()
+            )));
+        }
+
+        // Both inputs must produce the same types, so cast columns to their common type
+        let mut target_types = Vec::new();
+        for (l, r) in left.fields.iter().zip(&right.fields) {
+            let common = l.data_type.common_supertype(&r.data_type).ok_or_else(|| TranslateError::Schema(format!(
+                "{} column '{}' has incompatible types {:?} and {:?}", name, l.name, l.data_type, r.data_type
+            )))?;
+            target_types.push((common, l.nullable || r.nullable));
+        }
+
+        let set_rel = substrait::proto::SetRel {
+            common: None,
+            inputs: vec![
+                self.coerce(left_input, &left.fields, &target_types)?,
+                self.coerce(right_input, &right.fields, &target_types)?,
+            ],
+            op: set_op as i32,
+            advanced_extension: None,
+        };
+
+        // Wrap in Rel
+        Ok(substrait::proto::Rel {
+            rel_type: Some(substrait::proto::rel::RelType::Set(set_rel)),
+        })
+    }
+
+    /// Cast the columns of `input` whose type differs from the target type.
+    /// Inputs that already match are returned as is.
+    fn coerce(&self, input: substrait::proto::Rel, fields: &[FieldType], target_types: &[(DataType, bool)]) -> Result<substrait::proto::Rel, TranslateError> {
+        let needs_cast = |field: &FieldType, target: &DataType| {
+            &field.data_type != target && !target.is_unknown_or_null()
+        };
+        if !fields.iter().zip(target_types).any(|(field, (target, _))| needs_cast(field, target)) {
+            return Ok(input);
+        }
+
+        let expressions: Result<Vec<_>, _> = fields.iter().zip(target_types).enumerate()
+            .map(|(idx, (field, (target, nullable)))| {
+                let column = field_reference(idx, false);
+                if !needs_cast(field, target) {
+                    return Ok(column);
+                }
+                Ok(substrait::proto::Expression {
+                    rex_type: Some(substrait::proto::expression::RexType::Cast(Box::new(
+                        substrait::proto::expression::Cast {
+                            r#type: Some(self.map_type(target, *nullable)?),
+                            input: Some(Box::new(column)),
+                            failure_behavior: 0, // FAILURE_BEHAVIOR_UNSPECIFIED
+                        }
+                    ))),
+                })
+            })
+            .collect();
+
+        // Emit only the (cast) expressions, not the input columns
+        let project_rel = substrait::proto::ProjectRel {
+            common: Some(substrait::proto::RelCommon {
+                hint: None,
+                advanced_extension: None,
+                emit_kind: Some(substrait::proto::rel_common::EmitKind::Emit(
+                    substrait::proto::rel_common::Emit {
+                        output_mapping: (fields.len()..2 * fields.len()).map(|idx| idx as i32).collect(),
+                    }
+                )),
+            }),
+            input: Some(Box::new(input)),
+            expressions: expressions?,
+            advanced_extension: None,
+        };
+
+        // Wrap in Rel
+        Ok(substrait::proto::Rel {
+            rel_type: Some(substrait::proto::rel::RelType::Project(Box::new(project_rel))),
+        })
+    }
+
+    fn translate_groupby(&self, input: substrait::proto::Rel, keys: &[ColumnRef], aggs: &HashMap<String, AggCall>, schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
         // GroupBy translates to AggregateRel with:
         // - grouping_expressions: the grouping keys
         // - measures: the aggregate functions
@@ -775,8 +1145,7 @@ impl<'a> SubstraitTranslator<'a> {
         // Create grouping expressions from the keys with rootReference
         let grouping_expressions: Result<Vec<_>, _> = keys.iter().map(|key| {
             // Find the column index in the projected schema
-            let idx = schema.iter().position(|name| name == &key.column)
-                .ok_or_else(|| TranslateError::Translation(format!("Column '{}' not found in schema", key.column)))?;
+            let idx = column_index(schema, key)?;
 
             // Create field reference WITH rootReference (DuckDB format)
             Ok(substrait::proto::Expression {
@@ -809,8 +1178,9 @@ impl<'a> SubstraitTranslator<'a> {
             expression_references: vec![],
         };
 
-        // Create measures (aggregate functions) with rootReference
-        let measures: Result<Vec<_>, _> = aggs.iter().map(|(name, agg_call)| {
+        // Create measures (aggregate functions) with rootReference, in name order
+        // to match the output schema
+        let measures: Result<Vec<_>, _> = sorted(aggs).into_iter().map(|(name, agg_call)| {
             self.translate_aggregate_with_root(agg_call, schema, name)
         }).collect();
 
@@ -832,44 +1202,100 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_aggregate_with_root(&self, agg_call: &AggCall, schema: &[String], _name: &str) -> Result<substrait::proto::aggregate_rel::Measure, TranslateError> {
-        // Translate aggregate function arguments with rootReference
-        let arguments: Result<Vec<_>, _> = agg_call.args.iter().map(|expr| {
-            let expr_result = match expr {
-                Expr::Column { col } => self.translate_column_ref_with_root(col, schema, true)?,
-                _ => self.translate_expr(expr, schema)?,
-            };
+    fn translate_window(&self, input: substrait::proto::Rel, windows: &HashMap<String, WindowDef>, schema: &[SchemaColumn]) -> Result<substrait::proto::Rel, TranslateError> {
+        // Window functions are expressions in a ProjectRel, which appends them
+        // to the input columns. Windows are added in name order to match the
+        // output schema.
+        let windows = sorted(windows);
+        let expressions: Result<Vec<_>, _> = windows.iter()
+            .map(|(_, def)| self.translate_window_function(def, schema))
+            .collect();
+
+        let expressions = expressions?;
+
+        // A window named like an input column replaces it, so drop the old
+        // column from the output
+        let common = if schema.iter().any(|c| windows.iter().any(|(window, _)| *window == &c.name)) {
+            let kept = schema.iter().enumerate()
+                .filter(|(_, c)| !windows.iter().any(|(window, _)| *window == &c.name))
+                .map(|(idx, _)| idx as i32);
+            let added = (0..windows.len()).map(|idx| (schema.len() + idx) as i32);
+            Some(substrait::proto::RelCommon {
+                hint: None,
+                advanced_extension: None,
+                emit_kind: Some(substrait::proto::rel_common::EmitKind::Emit(
+                    substrait::proto::rel_common::Emit {
+                        output_mapping: kept.chain(added).collect(),
+                    }
+                )),
+            })
+        } else {
+            None
+        };
+
+        // Create ProjectRel
+        let project_rel = substrait::proto::ProjectRel {
+            common,
+            input: Some(Box::new(input)),
+            expressions,
+            advanced_extension: None,
+        };
+
+        // Wrap in Rel
+        Ok(substrait::proto::Rel {
+            rel_type: Some(substrait::proto::rel::RelType::Project(Box::new(project_rel))),
+        })
+    }
+
+    fn translate_window_function(&self, def: &WindowDef, schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
+        use substrait::proto::expression::window_function::{bound, Bound, BoundsType};
+
+        let arguments: Result<Vec<_>, _> = def.args.iter().map(|expr| {
             Ok(substrait::proto::FunctionArgument {
-                arg_type: Some(substrait::proto::function_argument::ArgType::Value(expr_result)),
+                arg_type: Some(substrait::proto::function_argument::ArgType::Value(self.translate_expr(expr, schema)?)),
             })
         }).collect();
 
         let arguments = arguments?;
 
-        // Determine function signature and output type based on aggregate function
-        let (function_sig, output_type) = match agg_call.func.as_str() {
-            "sum" => (
-                "sum:i32".to_string(),
-                Some(substrait::proto::Type {
-                    kind: Some(substrait::proto::r#type::Kind::I64(
-                        substrait::proto::r#type::I64 {
-                            type_variation_reference: 0,
-                            nullability: substrait::proto::r#type::Nullability::Nullable as i32,
-                        }
-                    )),
-                })
+        let partitions: Result<Vec<_>, _> = def.partition.iter().flatten()
+            .map(|col| self.translate_column_ref(col, schema))
+            .collect();
+
+        let sorts: Result<Vec<_>, _> = def.order.iter().flatten()
+            .map(|key| self.translate_sort_field(key, schema))
+            .collect();
+
+        // Determine function signature and output type based on window function
+        let i64_type = |nullability: substrait::proto::r#type::Nullability| substrait::proto::Type {
+            kind: Some(substrait::proto::r#type::Kind::I64(
+                substrait::proto::r#type::I64 {
+                    type_variation_reference: 0,
+                    nullability: nullability as i32,
+                }
+            )),
+        };
+        let (function_sig, output_type) = match def.func.as_str() {
+            "row_number" | "rank" | "dense_rank" => (
+                def.func.clone(),
+                Some(i64_type(substrait::proto::r#type::Nullability::Required)),
             ),
-            "count" => (
-                "count:i32".to_string(),
+            "percent_rank" => (
+                def.func.clone(),
                 Some(substrait::proto::Type {
-                    kind: Some(substrait::proto::r#type::Kind::I64(
-                        substrait::proto::r#type::I64 {
+                    kind: Some(substrait::proto::r#type::Kind::Fp64(
+                        substrait::proto::r#type::Fp64 {
                             type_variation_reference: 0,
                             nullability: substrait::proto::r#type::Nullability::Required as i32,
                         }
                     )),
-                })
+                }),
             ),
+            "ntile" => ("ntile:i32".to_string(), Some(i64_type(substrait::proto::r#type::Nullability::Nullable))),
+            "lag" | "lead" | "first_value" | "last_value" => (format!("{}:any", def.func), None),
+            // Aggregates over a window, as in translate_aggregate_with_root
+            "sum" => ("sum:i32".to_string(), Some(i64_type(substrait::proto::r#type::Nullability::Nullable))),
+            "count" => ("count:i32".to_string(), Some(i64_type(substrait::proto::r#type::Nullability::Required))),
             "avg" => (
                 "avg:i32".to_string(),
                 Some(substrait::proto::Type {
@@ -879,14 +1305,114 @@ impl<'a> SubstraitTranslator<'a> {
                             nullability: substrait::proto::r#type::Nullability::Nullable as i32,
                         }
                     )),
-                })
+                }),
             ),
-            "min" => (
-                "min:i32".to_string(),
-                Some(substrait::proto::Type {
-                    kind: Some(substrait::proto::r#type::Kind::I32(
-                        substrait::proto::r#type::I32 {
-                            type_variation_reference: 0,
+            "min" | "max" => (format!("{}:i32", def.func), None),
+            _ => return Err(TranslateError::UnsupportedOperator(
+                format!("Window function '{}' not yet supported. Supported: row_number, rank, dense_rank, percent_rank, ntile, lag, lead, first_value, last_value, sum, count, avg, min, max", def.func)
+            )),
+        };
+
+        let to_bound = |frame_bound: &FrameBound| Bound {
+            kind: Some(match frame_bound {
+                FrameBound::UnboundedPreceding | FrameBound::UnboundedFollowing => bound::Kind::Unbounded(bound::Unbounded {}),
+                FrameBound::CurrentRow => bound::Kind::CurrentRow(bound::CurrentRow {}),
+                FrameBound::Preceding(offset) => bound::Kind::Preceding(bound::Preceding { offset: *offset }),
+                FrameBound::Following(offset) => bound::Kind::Following(bound::Following { offset: *offset }),
+            }),
+        };
+
+        // Without a frame the engine's default frame applies
+        let (bounds_type, lower_bound, upper_bound) = match &def.frame {
+            Some(frame) => {
+                let bounds_type = match frame.mode {
+                    FrameMode::Rows => BoundsType::Rows,
+                    FrameMode::Range => BoundsType::Range,
+                };
+                (bounds_type, Some(to_bound(&frame.start)), Some(to_bound(&frame.end)))
+            }
+            None => (BoundsType::Unspecified, None, None),
+        };
+
+        // Register the window function and get its anchor
+        let function_anchor = self.function_registry.borrow_mut().register(&function_sig);
+
+        let window_function = substrait::proto::expression::WindowFunction {
+            function_reference: function_anchor,
+            arguments,
+            options: vec![],
+            output_type,
+            phase: 0, // AGGREGATION_PHASE_UNSPECIFIED
+            sorts: sorts?,
+            invocation: 0, // AGGREGATION_INVOCATION_UNSPECIFIED
+            partitions: partitions?,
+            bounds_type: bounds_type as i32,
+            lower_bound,
+            upper_bound,
+            #[allow(deprecated)]
+            args: vec![], // Deprecated field
+        };
+
+        Ok(substrait::proto::Expression {
+            rex_type: Some(substrait::proto::expression::RexType::WindowFunction(window_function)),
+        })
+    }
+
+    fn translate_aggregate_with_root(&self, agg_call: &AggCall, schema: &[SchemaColumn], _name: &str) -> Result<substrait::proto::aggregate_rel::Measure, TranslateError> {
+        // Translate aggregate function arguments with rootReference
+        let arguments: Result<Vec<_>, _> = agg_call.args.iter().map(|expr| {
+            let expr_result = match expr {
+                Expr::Column { col } => self.translate_column_ref_with_root(col, schema, true)?,
+                _ => self.translate_expr(expr, schema)?,
+            };
+            Ok(substrait::proto::FunctionArgument {
+                arg_type: Some(substrait::proto::function_argument::ArgType::Value(expr_result)),
+            })
+        }).collect();
+
+        let arguments = arguments?;
+
+        // Determine function signature and output type based on aggregate function
+        let (function_sig, output_type) = match agg_call.func.as_str() {
+            "sum" => (
+                "sum:i32".to_string(),
+                Some(substrait::proto::Type {
+                    kind: Some(substrait::proto::r#type::Kind::I64(
+                        substrait::proto::r#type::I64 {
+                            type_variation_reference: 0,
+                            nullability: substrait::proto::r#type::Nullability::Nullable as i32,
+                        }
+                    )),
+                })
+            ),
+            "count" => (
+                "count:i32".to_string(),
+                Some(substrait::proto::Type {
+                    kind: Some(substrait::proto::r#type::Kind::I64(
+                        substrait::proto::r#type::I64 {
+                            type_variation_reference: 0,
+                            nullability: substrait::proto::r#type::Nullability::Required as i32,
+                        }
+                    )),
+                })
+            ),
+            "avg" => (
+                "avg:i32".to_string(),
+                Some(substrait::proto::Type {
+                    kind: Some(substrait::proto::r#type::Kind::Fp64(
+                        substrait::proto::r#type::Fp64 {
+                            type_variation_reference: 0,
+                            nullability: substrait::proto::r#type::Nullability::Nullable as i32,
+                        }
+                    )),
+                })
+            ),
+            "min" => (
+                "min:i32".to_string(),
+                Some(substrait::proto::Type {
+                    kind: Some(substrait::proto::r#type::Kind::I32(
+                        substrait::proto::r#type::I32 {
+                            type_variation_reference: 0,
                             nullability: substrait::proto::r#type::Nullability::Nullable as i32,
                         }
                     )),
@@ -903,9 +1429,12 @@ impl<'a> SubstraitTranslator<'a> {
                     )),
                 })
             ),
-            _ => return Err(TranslateError::UnsupportedOperator(
-                format!("Aggregate function '{}' not yet supported. Supported: sum, count, avg, min, max", agg_call.func)
-            )),
+            _ => match self.resolve_extension(&agg_call.func, &agg_call.args, schema)? {
+                Some(signature) => (signature, None),
+                None => return Err(TranslateError::UnsupportedOperator(
+                    format!("Aggregate function '{}' not yet supported. Supported: sum, count, avg, min, max", agg_call.func)
+                )),
+            },
         };
 
         // Register the aggregate function and get its anchor
@@ -932,7 +1461,7 @@ impl<'a> SubstraitTranslator<'a> {
 
     // NOTE: Currently unused - kept for potential future use with non-root aggregate translation
     #[allow(dead_code)]
-    fn translate_aggregate(&self, agg_call: &AggCall, schema: &[String], _name: &str) -> Result<substrait::proto::aggregate_rel::Measure, TranslateError> {
+    fn translate_aggregate(&self, agg_call: &AggCall, schema: &[SchemaColumn], _name: &str) -> Result<substrait::proto::aggregate_rel::Measure, TranslateError> {
         // Translate aggregate function arguments
         let arguments: Result<Vec<_>, _> = agg_call.args.iter().map(|expr| {
             let expr_result = self.translate_expr(expr, schema)?;
@@ -978,283 +1507,556 @@ impl<'a> SubstraitTranslator<'a> {
         })
     }
 
-    fn translate_expr(&self, expr: &Expr, schema: &[String]) -> Result<substrait::proto::Expression, TranslateError> {
+    fn translate_expr(&self, expr: &Expr, schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
+        use substrait::proto::expression::{nested, Nested, RexType, SingularOrList};
+
+        let nested = |nested_type| substrait::proto::Expression {
+            rex_type: Some(RexType::Nested(Nested {
+                nullable: false,
+                type_variation_reference: 0,
+                nested_type: Some(nested_type),
+            })),
+        };
+
         match expr {
             Expr::Literal { value } => self.translate_literal(value),
             Expr::Column { col } => self.translate_column_ref(col, schema),
             Expr::BinaryOp { op, left, right } => self.translate_binary_op(op, left, right, schema),
             Expr::UnaryOp { op, expr } => self.translate_unary_op(op, expr, schema),
-            _ => Err(TranslateError::UnsupportedOperator(format!("Expression {:?} not yet supported", expr))),
+            Expr::FuncCall { func, args } => self.translate_function_call(func, args, schema),
+            Expr::FieldAccess { .. } | Expr::Index { .. } => self.translate_nested_reference(expr, schema),
+            Expr::Array { elements } => Ok(nested(nested::NestedType::List(nested::List {
+                values: self.translate_exprs(elements, schema)?,
+            }))),
+            // Struct fields are positional, in name order like `infer_schema`
+            Expr::Object { fields } => Ok(nested(nested::NestedType::Struct(nested::Struct {
+                fields: sorted(fields).into_iter()
+                    .map(|(_, e)| self.translate_expr(e, schema))
+                    .collect::<Result<_, _>>()?,
+            }))),
+            // Embeddings are lists of floats, as in `map_type`
+            Expr::Vector { values } => Ok(literal_expression(substrait::proto::expression::Literal {
+                nullable: false,
+                type_variation_reference: 0,
+                literal_type: Some(substrait::proto::expression::literal::LiteralType::List(
+                    substrait::proto::expression::literal::List {
+                        values: values.iter().map(|v| substrait::proto::expression::Literal {
+                            nullable: false,
+                            type_variation_reference: 0,
+                            literal_type: Some(substrait::proto::expression::literal::LiteralType::Fp32(*v as f32)),
+                        }).collect(),
+                    }
+                )),
+            })),
+            // `x in [a, b]` is `x >= a && x <= b`; `x in [a, b)` is `x >= a && x < b`
+            Expr::InRange { expr, start, end, inclusive } => {
+                let compare = |op, bound: &Expr| Box::new(Expr::BinaryOp {
+                    op,
+                    left: expr.clone(),
+                    right: Box::new(bound.clone()),
+                });
+                let upper = if *inclusive { BinOp::Le } else { BinOp::Lt };
+                self.translate_binary_op(&BinOp::And, &compare(BinOp::Ge, start), &compare(upper, end), schema)
+            }
+            Expr::InSet { set, .. } if set.is_empty() => self.translate_literal(&Value::Bool(false)),
+            Expr::InSet { expr, set } => Ok(substrait::proto::Expression {
+                rex_type: Some(RexType::SingularOrList(Box::new(SingularOrList {
+                    value: Some(Box::new(self.translate_expr(expr, schema)?)),
+                    options: self.translate_exprs(set, schema)?,
+                }))),
+            }),
         }
     }
 
+    fn translate_exprs(&self, exprs: &[Expr], schema: &[SchemaColumn]) -> Result<Vec<substrait::proto::Expression>, TranslateError> {
+        exprs.iter().map(|e| self.translate_expr(e, schema)).collect()
+    }
+
+    /// `s.field`, `m.key`, `list[i]` or `m[key]` as a reference into the nested value.
+    ///
+    /// The base's type must be known to find a struct field's position, and
+    /// list indexes and map keys must be literals. List indexes start at 1.
+    fn translate_nested_reference(&self, expr: &Expr, schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
+        use substrait::proto::expression::reference_segment::{ListElement, MapKey, ReferenceType, StructField};
+
+        let map_key = |key: &Value| -> Result<ReferenceType, TranslateError> {
+            Ok(ReferenceType::MapKey(Box::new(MapKey {
+                map_key: Some(self.literal(key)?),
+                child: None,
+            })))
+        };
+
+        let (base, segment) = match expr {
+            Expr::FieldAccess { expr: base, field } => {
+                let segment = match expr_type(base, schema) {
+                    DataType::Struct(fields) => match fields.iter().position(|f| &f.name == field) {
+                        Some(idx) => ReferenceType::StructField(Box::new(StructField { field: idx as i32, child: None })),
+                        None => return Err(TranslateError::Translation(format!("Struct has no field '{}'", field))),
+                    },
+                    DataType::Map { .. } => map_key(&Value::String(field.clone()))?,
+                    other => return Err(TranslateError::Translation(
+                        format!("Cannot access field '{}' of a value of type {:?}", field, other)
+                    )),
+                };
+                (base, segment)
+            }
+            Expr::Index { expr: base, index } => {
+                let segment = match (expr_type(base, schema), &**index) {
+                    (DataType::Map { .. }, Expr::Literal { value }) => map_key(value)?,
+                    (DataType::Array(_) | DataType::Vector(_), Expr::Literal { value: Value::Int(i) }) => {
+                        let offset = i.checked_sub(1)
+                            .filter(|offset| *offset >= 0)
+                            .and_then(|offset| i32::try_from(offset).ok())
+                            .ok_or_else(|| TranslateError::Translation(format!("List index {} out of range", i)))?;
+                        ReferenceType::ListElement(Box::new(ListElement { offset, child: None }))
+                    }
+                    (base_type, index) => return Err(TranslateError::Translation(format!(
+                        "Cannot index a value of type {:?} with {:?}; lists need an integer literal and maps a literal key",
+                        base_type, index
+                    ))),
+                };
+                (base, segment)
+            }
+            _ => unreachable!("only field access and indexing are nested references"),
+        };
+
+        Ok(nested_reference(self.translate_expr(base, schema)?, segment))
+    }
+
     fn translate_literal(&self, value: &Value) -> Result<substrait::proto::Expression, TranslateError> {
-        let literal = match value {
-            Value::Null => substrait::proto::expression::Literal {
-                nullable: true,
-                type_variation_reference: 0,
-                literal_type: Some(substrait::proto::expression::literal::LiteralType::Null(
-                    substrait::proto::Type { kind: None }
-                )),
-            },
-            Value::Bool(b) => substrait::proto::expression::Literal {
-                nullable: false,
-                type_variation_reference: 0,
-                literal_type: Some(substrait::proto::expression::literal::LiteralType::Boolean(*b)),
-            },
-            Value::Int(i) => substrait::proto::expression::Literal {
-                nullable: false,
-                type_variation_reference: 0,
-                literal_type: Some(substrait::proto::expression::literal::LiteralType::I64(*i)),
-            },
-            Value::Float(f) => substrait::proto::expression::Literal {
-                nullable: false,
-                type_variation_reference: 0,
-                literal_type: Some(substrait::proto::expression::literal::LiteralType::Fp64(*f)),
-            },
-            Value::String(s) => substrait::proto::expression::Literal {
-                nullable: false,
-                type_variation_reference: 0,
-                literal_type: Some(substrait::proto::expression::literal::LiteralType::String(s.clone())),
-            },
-            _ => return Err(TranslateError::UnsupportedOperator(format!("Literal value {:?} not yet supported", value))),
+        Ok(literal_expression(self.literal(value)?))
+    }
+
+    /// Temporal literals are ISO strings; timestamps with an offset are converted to UTC
+    #[allow(deprecated)]
+    fn literal(&self, value: &Value) -> Result<substrait::proto::expression::Literal, TranslateError> {
+        use substrait::proto::expression::literal::{self, LiteralType};
+
+        let invalid = |kind: &str, s: &str| TranslateError::Translation(format!("Invalid {} literal '{}'", kind, s));
+        let literal_type = match value {
+            Value::Null => {
+                return Ok(substrait::proto::expression::Literal {
+                    nullable: true,
+                    type_variation_reference: 0,
+                    literal_type: Some(LiteralType::Null(substrait::proto::Type { kind: None })),
+                });
+            }
+            Value::Bool(b) => LiteralType::Boolean(*b),
+            Value::Int(i) => LiteralType::I64(*i),
+            Value::Float(f) => LiteralType::Fp64(*f),
+            Value::String(s) => LiteralType::String(s.clone()),
+            Value::Bytes(b) => LiteralType::Binary(b.clone()),
+            Value::Date(s) => LiteralType::Date(date_days(s).ok_or_else(|| invalid("date", s))?),
+            Value::Time(s) => LiteralType::Time(time_micros(s).ok_or_else(|| invalid("time", s))?),
+            Value::Timestamp(s) => LiteralType::Timestamp(timestamp_micros(s).ok_or_else(|| invalid("timestamp", s))?),
+            // An empty list literal needs an element type, which an IR value doesn't have
+            Value::Array(items) if items.is_empty() => {
+                return Err(TranslateError::Translation("Empty array literals are not supported".to_string()));
+            }
+            Value::Array(items) => LiteralType::List(literal::List {
+                values: items.iter().map(|v| self.literal(v)).collect::<Result<_, _>>()?,
+            }),
+            // Struct fields are positional, in name order like `infer_schema`
+            Value::Object(fields) => LiteralType::Struct(literal::Struct {
+                fields: sorted(fields).into_iter()
+                    .map(|(_, v)| self.literal(v))
+                    .collect::<Result<_, _>>()?,
+            }),
         };
 
-        Ok(substrait::proto::Expression {
-            rex_type: Some(substrait::proto::expression::RexType::Literal(literal)),
+        Ok(substrait::proto::expression::Literal {
+            nullable: false,
+            type_variation_reference: 0,
+            literal_type: Some(literal_type),
         })
     }
 
-    fn translate_column_ref(&self, col: &ColumnRef, schema: &[String]) -> Result<substrait::proto::Expression, TranslateError> {
+    fn translate_column_ref(&self, col: &ColumnRef, schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
         self.translate_column_ref_with_root(col, schema, false)
     }
 
-    fn translate_column_ref_with_root(&self, col: &ColumnRef, schema: &[String], use_root_reference: bool) -> Result<substrait::proto::Expression, TranslateError> {
-        // Resolve column name to field index
-        let column_name = &col.column;
+    fn translate_column_ref_with_root(&self, col: &ColumnRef, schema: &[SchemaColumn], use_root_reference: bool) -> Result<substrait::proto::Expression, TranslateError> {
+        use substrait::proto::expression::reference_segment::{ReferenceType, StructField};
 
-        // Find the column index in the schema
-        let field_index = schema.iter()
-            .position(|name| name == column_name)
-            .ok_or_else(|| TranslateError::Translation(
-                format!("Column '{}' not found in schema. Available columns: {:?}", column_name, schema)
-            ))?;
+        // Resolve the (possibly qualified) column to its field index
+        match column_index(schema, col) {
+            Ok(field_index) => Ok(field_reference(field_index, use_root_reference)),
+            Err(error) => match struct_field_index(schema, col) {
+                Some((field_index, nested)) => Ok(nested_reference(
+                    field_reference(field_index, use_root_reference),
+                    ReferenceType::StructField(Box::new(StructField { field: nested as i32, child: None })),
+                )),
+                None => Err(error),
+            },
+        }
+    }
 
+    fn translate_binary_op(&self, op: &BinOp, left: &Expr, right: &Expr, schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
+        let left_expr = self.translate_expr(left, schema)?;
+        let right_expr = self.translate_expr(right, schema)?;
 
-        // Create a FieldReference (direct field reference by index)
-        let field_ref = substrait::proto::expression::FieldReference {
-            reference_type: Some(substrait::proto::expression::field_reference::ReferenceType::DirectReference(
-                substrait::proto::expression::ReferenceSegment {
-                    reference_type: Some(substrait::proto::expression::reference_segment::ReferenceType::StructField(
-                        Box::new(substrait::proto::expression::reference_segment::StructField {
-                            field: field_index as i32,
-                            child: None,
-                        })
-                    )),
-                }
-            )),
-            root_type: if use_root_reference {
-                Some(substrait::proto::expression::field_reference::RootType::RootReference(
-                    substrait::proto::expression::field_reference::RootReference {}
-                ))
-            } else {
-                None
-            },
+        // Map MLQL binary operator to Substrait function name and signature.
+        // For now, assume i32 types for comparisons and arithmetic (TODO: infer actual types)
+        // DuckDB function signature format: "function_name:arg1_type_arg2_type"
+        let function_signature = match op {
+            BinOp::Eq => "equal:i32_i32",
+            BinOp::Ne => "not_equal:i32_i32",
+            BinOp::Lt => "lt:i32_i32",
+            BinOp::Le => "lte:i32_i32",
+            BinOp::Gt => "gt:i32_i32",
+            BinOp::Ge => "gte:i32_i32",
+            BinOp::And => "and:i32_i32",
+            BinOp::Or => "or:i32_i32",
+            BinOp::Add => "add:i32_i32",
+            BinOp::Sub => "subtract:i32_i32",
+            BinOp::Mul => "multiply:i32_i32",
+            BinOp::Div => "divide:i32_i32",
+            BinOp::Mod => "modulus:i32_i32",
+            BinOp::Like => "like:str_str",
+            BinOp::ILike => "ilike:str_str",
         };
 
-        Ok(substrait::proto::Expression {
-            rex_type: Some(substrait::proto::expression::RexType::Selection(Box::new(field_ref))),
-        })
+        Ok(self.scalar_function(function_signature, vec![left_expr, right_expr]))
     }
 
-    #[allow(deprecated)]
-    fn translate_binary_op(&self, op: &BinOp, left: &Expr, right: &Expr, schema: &[String]) -> Result<substrait::proto::Expression, TranslateError> {
-        let left_expr = Box::new(self.translate_expr(left, schema)?);
-        let right_expr = Box::new(self.translate_expr(right, schema)?);
-
-        // Map MLQL binary operator to Substrait function base name
-        let function_base_name = match op {
-            BinOp::Eq => "equal",
-            BinOp::Ne => "not_equal",
-            BinOp::Lt => "lt",
-            BinOp::Le => "lte",
-            BinOp::Gt => "gt",
-            BinOp::Ge => "gte",
-            BinOp::And => "and",
-            BinOp::Or => "or",
-            BinOp::Add => "add",
-            BinOp::Sub => "subtract",
-            BinOp::Mul => "multiply",
-            BinOp::Div => "divide",
-            BinOp::Like => "like",
-            BinOp::ILike => "ilike",
-            _ => return Err(TranslateError::UnsupportedOperator(format!("Binary operator {:?} not yet supported", op))),
-        };
-
-        // For now, assume i32 types for comparisons (TODO: infer actual types)
-        // DuckDB function signature format: "function_name:arg1_type_arg2_type"
-        let function_signature = format!("{}:i32_i32", function_base_name);
-
-        // Register the function and get its anchor
-        let function_anchor = self.function_registry.borrow_mut().register(&function_signature);
+    fn translate_unary_op(&self, op: &UnOp, expr: &Expr, schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
+        let inner_expr = self.translate_expr(expr, schema)?;
 
-        // Create scalar function call
-        let scalar_function = substrait::proto::expression::ScalarFunction {
-            function_reference: function_anchor,
-            arguments: vec![
-                substrait::proto::FunctionArgument {
-                    arg_type: Some(substrait::proto::function_argument::ArgType::Value(*left_expr)),
-                },
-                substrait::proto::FunctionArgument {
-                    arg_type: Some(substrait::proto::function_argument::ArgType::Value(*right_expr)),
-                },
-            ],
-            output_type: None, // Type inference
-            options: vec![],
-            args: vec![], // Deprecated field
+        // For now, assume bool type for not, i32 for negate
+        let function_signature = match op {
+            UnOp::Not => "not:bool",
+            UnOp::Neg => "negate:i32",
         };
 
-        Ok(substrait::proto::Expression {
-            rex_type: Some(substrait::proto::expression::RexType::ScalarFunction(scalar_function)),
-        })
+        Ok(self.scalar_function(function_signature, vec![inner_expr]))
     }
 
-    #[allow(deprecated)]
-    fn translate_unary_op(&self, op: &UnOp, expr: &Expr, schema: &[String]) -> Result<substrait::proto::Expression, TranslateError> {
-        let inner_expr = Box::new(self.translate_expr(expr, schema)?);
+    /// Lower a call to an MLQL scalar function through the extension mapping table
+    fn translate_function_call(&self, func: &str, args: &[Expr], schema: &[SchemaColumn]) -> Result<substrait::proto::Expression, TranslateError> {
+        use substrait::proto::function_argument::ArgType;
 
-        let function_base_name = match op {
-            UnOp::Not => "not",
-            UnOp::Neg => "negate",
-        };
+        if let Some(signature) = self.resolve_extension(func, args, schema)? {
+            let arguments = self.translate_exprs(args, schema)?;
+            return Ok(self.scalar_function(&signature, arguments));
+        }
 
-        // For now, assume bool type for not, i32 for negate
-        let function_signature = match op {
-            UnOp::Not => format!("{}:bool", function_base_name),
-            UnOp::Neg => format!("{}:i32", function_base_name),
+        let mapping = scalar_function_mapping(func, args.len()).map_err(TranslateError::Translation)?;
+        let arguments = args.iter().enumerate().map(|(i, arg)| {
+            // Enum arguments such as extract's component are string literals in MLQL
+            if mapping.args.get(i) == Some(&"req") {
+                return match arg {
+                    Expr::Literal { value: Value::String(option) } => Ok(ArgType::Enum(option.clone())),
+                    _ => Err(TranslateError::Translation(format!(
+                        "Argument {} of '{}' must be a string literal",
+                        i + 1,
+                        func
+                    ))),
+                };
+            }
+            Ok(ArgType::Value(self.translate_expr(arg, schema)?))
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(self.function_call(&mapping.signature(), arguments))
+    }
+
+    /// Resolve a call to a custom function, registering the URI of its extension document.
+    ///
+    /// Returns the function's compound name, or `None` if `func` is not a custom function.
+    fn resolve_extension(&self, func: &str, args: &[Expr], schema: &[SchemaColumn]) -> Result<Option<String>, TranslateError> {
+        let Some(extensions) = self.extensions else {
+            return Ok(None);
         };
+        let arg_types: Vec<DataType> = args.iter().map(|arg| expr_type(arg, schema)).collect();
+        let function = extensions.resolve_extension(func, &arg_types).map_err(TranslateError::Translation)?;
+        Ok(function.map(|function| {
+            self.function_registry.borrow_mut().register_extension(&function.signature, &function.uri);
+            function.signature
+        }))
+    }
 
+    /// Call to the scalar function `signature` with value arguments
+    fn scalar_function(&self, signature: &str, arguments: Vec<substrait::proto::Expression>) -> substrait::proto::Expression {
+        let arguments = arguments.into_iter()
+            .map(substrait::proto::function_argument::ArgType::Value)
+            .collect();
+        self.function_call(signature, arguments)
+    }
+
+    /// Call to the scalar function `signature`, registering it as an extension function
+    #[allow(deprecated)]
+    fn function_call(&self, signature: &str, arguments: Vec<substrait::proto::function_argument::ArgType>) -> substrait::proto::Expression {
         // Register the function and get its anchor
-        let function_anchor = self.function_registry.borrow_mut().register(&function_signature);
+        let function_anchor = self.function_registry.borrow_mut().register(signature);
 
-        // Create scalar function call
         let scalar_function = substrait::proto::expression::ScalarFunction {
             function_reference: function_anchor,
-            arguments: vec![
-                substrait::proto::FunctionArgument {
-                    arg_type: Some(substrait::proto::function_argument::ArgType::Value(*inner_expr)),
-                },
-            ],
-            output_type: None,
+            arguments: arguments.into_iter()
+                .map(|arg_type| substrait::proto::FunctionArgument { arg_type: Some(arg_type) })
+                .collect(),
+            output_type: None, // Type inference
             options: vec![],
-            args: vec![],
+            args: vec![], // Deprecated field
         };
 
-        Ok(substrait::proto::Expression {
+        substrait::proto::Expression {
             rex_type: Some(substrait::proto::expression::RexType::ScalarFunction(scalar_function)),
-        })
+        }
     }
 
-    // NOTE: Currently unused - kept for potential future use with nested source translation
-    #[allow(dead_code)]
-    fn translate_source(&self, source: &Source) -> Result<substrait::proto::Rel, TranslateError> {
-        match source {
-            Source::Table { name, alias: _ } => {
-                // Look up schema from provider
-                let schema = self.schema_provider
-                    .get_table_schema(name)
-                    .map_err(TranslateError::Schema)?;
+    /// Substrait type of a table column in a ReadRel base schema.
+    ///
+    /// Columns of types MLQL doesn't model (`BIT`, `UNION`, `GEOMETRY`, ...)
+    /// are declared as strings: DuckDB reads the table by name, so the table
+    /// stays usable as long as queries don't compute with those columns.
+    fn map_read_type(&self, data_type: &DataType, nullable: bool) -> Result<substrait::proto::Type, TranslateError> {
+        match data_type {
+            DataType::Null | DataType::Unknown => self.map_type(&DataType::String, nullable),
+            _ => self.map_type(data_type, nullable),
+        }
+    }
 
-                // Build NamedStruct for base_schema
-                let named_struct = substrait::proto::NamedStruct {
-                    names: schema.columns.iter().map(|c| c.name.clone()).collect(),
-                    r#struct: Some(substrait::proto::r#type::Struct {
-                        types: schema.columns.iter().map(|c| {
-                            // Map column type to Substrait type
-                            self.map_type(&c.data_type, c.nullable)
-                        }).collect(),
-                        type_variation_reference: 0,
-                        nullability: substrait::proto::r#type::Nullability::Unspecified as i32,
-                    }),
-                };
+    /// Map a column type to its Substrait type.
+    ///
+    /// Unsigned integers widen to the next signed type (`UBIGINT` to
+    /// `DECIMAL(20,0)`), since Substrait has no unsigned types. `Null` and
+    /// `Unknown` have no Substrait equivalent and are rejected.
+    #[allow(deprecated)]
+    fn map_type(&self, data_type: &DataType, nullable: bool) -> Result<substrait::proto::Type, TranslateError> {
+        use substrait::proto::r#type;
 
-                // Create ReadRel with NamedTable
-                let read_rel = substrait::proto::ReadRel {
-                    common: None, // RelCommon - optional
-                    base_schema: Some(named_struct),
-                    filter: None,
-                    best_effort_filter: None,
-                    projection: None,
-                    advanced_extension: None,
-                    read_type: Some(substrait::proto::read_rel::ReadType::NamedTable(
-                        substrait::proto::read_rel::NamedTable {
-                            names: vec![name.clone()],
-                            advanced_extension: None,
-                        },
-                    )),
-                };
+        let nullability = if nullable {
+            r#type::Nullability::Nullable as i32
+        } else {
+            r#type::Nullability::Required as i32
+        };
+        let decimal = |precision: i32, scale: i32| r#type::Kind::Decimal(r#type::Decimal {
+            scale,
+            precision,
+            type_variation_reference: 0,
+            nullability,
+        });
 
-                Ok(substrait::proto::Rel {
-                    rel_type: Some(substrait::proto::rel::RelType::Read(Box::new(read_rel))),
-                })
+        let kind = match data_type {
+            DataType::Bool => r#type::Kind::Bool(r#type::Boolean { type_variation_reference: 0, nullability }),
+            DataType::Int8 => r#type::Kind::I8(r#type::I8 { type_variation_reference: 0, nullability }),
+            DataType::Int16 | DataType::UInt8 => r#type::Kind::I16(r#type::I16 { type_variation_reference: 0, nullability }),
+            DataType::Int32 | DataType::UInt16 => r#type::Kind::I32(r#type::I32 { type_variation_reference: 0, nullability }),
+            DataType::Int64 | DataType::UInt32 => r#type::Kind::I64(r#type::I64 { type_variation_reference: 0, nullability }),
+            DataType::UInt64 => decimal(20, 0),
+            DataType::Float32 => r#type::Kind::Fp32(r#type::Fp32 { type_variation_reference: 0, nullability }),
+            DataType::Float64 => r#type::Kind::Fp64(r#type::Fp64 { type_variation_reference: 0, nullability }),
+            DataType::Decimal { precision, scale } => decimal(*precision as i32, *scale as i32),
+            DataType::String | DataType::Varchar(None) => r#type::Kind::String(r#type::String { type_variation_reference: 0, nullability }),
+            DataType::Varchar(Some(length)) => r#type::Kind::Varchar(r#type::VarChar {
+                length: *length as i32,
+                type_variation_reference: 0,
+                nullability,
+            }),
+            DataType::Blob => r#type::Kind::Binary(r#type::Binary { type_variation_reference: 0, nullability }),
+            DataType::Date => r#type::Kind::Date(r#type::Date { type_variation_reference: 0, nullability }),
+            DataType::Time => r#type::Kind::Time(r#type::Time { type_variation_reference: 0, nullability }),
+            DataType::Timestamp => r#type::Kind::Timestamp(r#type::Timestamp { type_variation_reference: 0, nullability }),
+            DataType::TimestampTz => r#type::Kind::TimestampTz(r#type::TimestampTz { type_variation_reference: 0, nullability }),
+            DataType::Interval => r#type::Kind::IntervalDay(r#type::IntervalDay {
+                type_variation_reference: 0,
+                nullability,
+                precision: Some(6),
+            }),
+            DataType::Array(element) => r#type::Kind::List(Box::new(r#type::List {
+                r#type: Some(Box::new(self.map_type(element, true)?)),
+                type_variation_reference: 0,
+                nullability,
+            })),
+            // Embeddings are read as lists of floats
+            DataType::Vector(_) => r#type::Kind::List(Box::new(r#type::List {
+                r#type: Some(Box::new(self.map_type(&DataType::Float32, true)?)),
+                type_variation_reference: 0,
+                nullability,
+            })),
+            DataType::Struct(fields) => r#type::Kind::Struct(r#type::Struct {
+                types: fields.iter()
+                    .map(|f| self.map_type(&f.data_type, f.nullable))
+                    .collect::<Result<_, _>>()?,
+                type_variation_reference: 0,
+                nullability,
+            }),
+            DataType::Map { key, value } => r#type::Kind::Map(Box::new(r#type::Map {
+                key: Some(Box::new(self.map_type(key, false)?)),
+                value: Some(Box::new(self.map_type(value, true)?)),
+                type_variation_reference: 0,
+                nullability,
+            })),
+            DataType::Null | DataType::Unknown => {
+                return Err(TranslateError::Schema(format!("No Substrait type for {:?}", data_type)));
             }
-            _ => Err(TranslateError::UnsupportedOperator("Only Table sources supported currently".to_string())),
-        }
+        };
+
+        Ok(substrait::proto::Type {
+            kind: Some(kind),
+        })
     }
+}
 
-    /// Map MLQL type string to Substrait Type
-    fn map_type(&self, type_str: &str, nullable: bool) -> substrait::proto::Type {
-        let nullability = if nullable {
-            substrait::proto::r#type::Nullability::Nullable as i32
+/// Aggregates or windows in output order: sorted by name, like `infer_schema`
+fn sorted<T>(map: &HashMap<String, T>) -> Vec<(&String, &T)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+/// A direct reference to input field `index`
+fn field_reference(index: usize, use_root_reference: bool) -> substrait::proto::Expression {
+    let field_ref = substrait::proto::expression::FieldReference {
+        reference_type: Some(substrait::proto::expression::field_reference::ReferenceType::DirectReference(
+            substrait::proto::expression::ReferenceSegment {
+                reference_type: Some(substrait::proto::expression::reference_segment::ReferenceType::StructField(
+                    Box::new(substrait::proto::expression::reference_segment::StructField {
+                        field: index as i32,
+                        child: None,
+                    })
+                )),
+            }
+        )),
+        root_type: if use_root_reference {
+            Some(substrait::proto::expression::field_reference::RootType::RootReference(
+                substrait::proto::expression::field_reference::RootReference {}
+            ))
         } else {
-            substrait::proto::r#type::Nullability::Required as i32
-        };
+            None
+        },
+    };
 
-        let kind = match type_str.to_uppercase().as_str() {
-            "INTEGER" | "INT" | "INT32" => substrait::proto::r#type::Kind::I32(
-                substrait::proto::r#type::I32 {
-                    type_variation_reference: 0,
-                    nullability,
-                },
-            ),
-            "BIGINT" | "INT64" => substrait::proto::r#type::Kind::I64(
-                substrait::proto::r#type::I64 {
-                    type_variation_reference: 0,
-                    nullability,
-                },
-            ),
-            "VARCHAR" | "STRING" | "TEXT" => substrait::proto::r#type::Kind::String(
-                substrait::proto::r#type::String {
-                    type_variation_reference: 0,
-                    nullability,
-                },
-            ),
-            "DOUBLE" | "FLOAT64" => substrait::proto::r#type::Kind::Fp64(
-                substrait::proto::r#type::Fp64 {
-                    type_variation_reference: 0,
-                    nullability,
-                },
-            ),
-            "FLOAT" | "FLOAT32" => substrait::proto::r#type::Kind::Fp32(
-                substrait::proto::r#type::Fp32 {
-                    type_variation_reference: 0,
-                    nullability,
-                },
-            ),
-            _ => {
-                // Default to string for unknown types
-                substrait::proto::r#type::Kind::String(
-                    substrait::proto::r#type::String {
-                        type_variation_reference: 0,
-                        nullability,
-                    },
-                )
+    substrait::proto::Expression {
+        rex_type: Some(substrait::proto::expression::RexType::Selection(Box::new(field_ref))),
+    }
+}
+
+/// `base` extended by `segment`: a direct field reference gets a deeper child
+/// segment, and any other expression becomes the root of a new reference
+fn nested_reference(
+    base: substrait::proto::Expression,
+    segment: substrait::proto::expression::reference_segment::ReferenceType,
+) -> substrait::proto::Expression {
+    use substrait::proto::expression::{field_reference, FieldReference, ReferenceSegment, RexType};
+
+    let field_ref = match base.rex_type {
+        Some(RexType::Selection(mut field_ref)) => match &mut field_ref.reference_type {
+            Some(field_reference::ReferenceType::DirectReference(root)) => {
+                append_segment(root, segment);
+                field_ref
             }
-        };
+            _ => Box::new(FieldReference {
+                reference_type: Some(field_reference::ReferenceType::DirectReference(ReferenceSegment {
+                    reference_type: Some(segment),
+                })),
+                root_type: Some(field_reference::RootType::Expression(Box::new(substrait::proto::Expression {
+                    rex_type: Some(RexType::Selection(field_ref)),
+                }))),
+            }),
+        },
+        rex_type => Box::new(FieldReference {
+            reference_type: Some(field_reference::ReferenceType::DirectReference(ReferenceSegment {
+                reference_type: Some(segment),
+            })),
+            root_type: Some(field_reference::RootType::Expression(Box::new(substrait::proto::Expression { rex_type }))),
+        }),
+    };
+
+    substrait::proto::Expression {
+        rex_type: Some(RexType::Selection(field_ref)),
+    }
+}
 
-        substrait::proto::Type {
-            kind: Some(kind),
+/// Append `segment` as the innermost child of a reference path
+fn append_segment(
+    path: &mut substrait::proto::expression::ReferenceSegment,
+    segment: substrait::proto::expression::reference_segment::ReferenceType,
+) {
+    use substrait::proto::expression::reference_segment::ReferenceType;
+
+    let child = match &mut path.reference_type {
+        Some(ReferenceType::StructField(field)) => &mut field.child,
+        Some(ReferenceType::MapKey(key)) => &mut key.child,
+        Some(ReferenceType::ListElement(element)) => &mut element.child,
+        None => {
+            path.reference_type = Some(segment);
+            return;
         }
+    };
+    match child {
+        Some(child) => append_segment(child, segment),
+        None => *child = Some(Box::new(substrait::proto::expression::ReferenceSegment {
+            reference_type: Some(segment),
+        })),
+    }
+}
+
+fn literal_expression(literal: substrait::proto::expression::Literal) -> substrait::proto::Expression {
+    substrait::proto::Expression {
+        rex_type: Some(substrait::proto::expression::RexType::Literal(literal)),
+    }
+}
+
+/// Days since the Unix epoch of an ISO date (`2024-01-15`)
+fn date_days(s: &str) -> Option<i32> {
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    i32::try_from(date.signed_duration_since(chrono::NaiveDate::from_ymd_opt(1970, 1, 1)?).num_days()).ok()
+}
+
+/// Microseconds since midnight of an ISO time (`10:30`, `10:30:00.25`)
+fn time_micros(s: &str) -> Option<i64> {
+    let time = chrono::NaiveTime::parse_from_str(s, "%H:%M:%S%.f")
+        .or_else(|_| chrono::NaiveTime::parse_from_str(s, "%H:%M"))
+        .ok()?;
+    time.signed_duration_since(chrono::NaiveTime::from_hms_opt(0, 0, 0)?).num_microseconds()
+}
+
+/// Microseconds since the Unix epoch of an ISO timestamp, converted to UTC
+/// when it has an offset (`2024-01-15T10:30:00Z`, `2024-01-15T10:30+02:00`)
+fn timestamp_micros(s: &str) -> Option<i64> {
+    let (date, time) = s.split_once('T')?;
+    let (time, offset_secs) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else if let Some(pos) = time.find(['+', '-']) {
+        let (time, offset) = time.split_at(pos);
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let secs = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        (time, if offset.starts_with('-') { -secs } else { secs })
+    } else {
+        (time, 0)
+    };
+    let days = i64::from(date_days(date)?);
+    Some(days * 86_400_000_000 + time_micros(time)? - offset_secs * 1_000_000)
+}
+
+/// Names for `RelRoot::names`: each column followed, depth first, by the
+/// names of the struct fields nested in its type
+fn root_names(fields: &[FieldType], names: &mut Vec<String>) {
+    for field in fields {
+        names.push(field.name.clone());
+        nested_names(&field.data_type, names);
+    }
+}
+
+/// Names for a table's `NamedStruct`, in the same order as [`root_names`]
+fn column_names(columns: &[ColumnInfo]) -> Vec<String> {
+    let mut names = Vec::new();
+    for column in columns {
+        names.push(column.name.clone());
+        nested_names(&column.data_type, &mut names);
+    }
+    names
+}
+
+fn nested_names(data_type: &DataType, names: &mut Vec<String>) {
+    match data_type {
+        DataType::Struct(fields) => root_names(fields, names),
+        DataType::Array(element) => nested_names(element, names),
+        DataType::Map { key, value } => {
+            nested_names(key, names);
+            nested_names(value, names);
+        }
+        _ => {}
     }
 }
 
@@ -1273,12 +2075,12 @@ mod tests {
             columns: vec![
                 ColumnInfo {
                     name: "id".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: false,
                 },
                 ColumnInfo {
                     name: "name".to_string(),
-                    data_type: "VARCHAR".to_string(),
+                    data_type: DataType::String,
                     nullable: true,
                 },
             ],
@@ -1326,12 +2128,12 @@ mod tests {
             columns: vec![
                 ColumnInfo {
                     name: "id".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: false,
                 },
                 ColumnInfo {
                     name: "name".to_string(),
-                    data_type: "VARCHAR".to_string(),
+                    data_type: DataType::String,
                     nullable: true,
                 },
             ],
@@ -1414,17 +2216,17 @@ mod tests {
             columns: vec![
                 ColumnInfo {
                     name: "id".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: false,
                 },
                 ColumnInfo {
                     name: "name".to_string(),
-                    data_type: "VARCHAR".to_string(),
+                    data_type: DataType::String,
                     nullable: true,
                 },
                 ColumnInfo {
                     name: "age".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: true,
                 },
             ],
@@ -1493,17 +2295,17 @@ mod tests {
             columns: vec![
                 ColumnInfo {
                     name: "id".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: false,
                 },
                 ColumnInfo {
                     name: "name".to_string(),
-                    data_type: "VARCHAR".to_string(),
+                    data_type: DataType::String,
                     nullable: true,
                 },
                 ColumnInfo {
                     name: "age".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: true,
                 },
             ],
@@ -1574,17 +2376,17 @@ mod tests {
             columns: vec![
                 ColumnInfo {
                     name: "id".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: false,
                 },
                 ColumnInfo {
                     name: "name".to_string(),
-                    data_type: "VARCHAR".to_string(),
+                    data_type: DataType::String,
                     nullable: true,
                 },
                 ColumnInfo {
                     name: "age".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: true,
                 },
             ],
@@ -1662,17 +2464,17 @@ mod tests {
             columns: vec![
                 ColumnInfo {
                     name: "id".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: false,
                 },
                 ColumnInfo {
                     name: "name".to_string(),
-                    data_type: "VARCHAR".to_string(),
+                    data_type: DataType::String,
                     nullable: true,
                 },
                 ColumnInfo {
                     name: "age".to_string(),
-                    data_type: "INTEGER".to_string(),
+                    data_type: DataType::Int32,
                     nullable: true,
                 },
             ],
@@ -1718,4 +2520,835 @@ mod tests {
         println!("{}", plan_json);
         println!("   Plan size: {} bytes", plan_bytes.len());
     }
+
+    fn users_schema_provider() -> MockSchemaProvider {
+        let mut schema_provider = MockSchemaProvider::new();
+        schema_provider.add_table(TableSchema {
+            name: "users".to_string(),
+            columns: vec![
+                ColumnInfo {
+                    name: "id".to_string(),
+                    data_type: DataType::Int32,
+                    nullable: false,
+                },
+                ColumnInfo {
+                    name: "name".to_string(),
+                    data_type: DataType::String,
+                    nullable: true,
+                },
+                ColumnInfo {
+                    name: "age".to_string(),
+                    data_type: DataType::Int32,
+                    nullable: true,
+                },
+            ],
+        });
+        schema_provider
+    }
+
+    fn root_of(plan: &Plan) -> &substrait::proto::RelRoot {
+        match &plan.relations[0].rel_type {
+            Some(substrait::proto::plan_rel::RelType::Root(root)) => root,
+            other => panic!("Expected Root relation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_let_binding_is_inlined() {
+        let schema_provider = users_schema_provider();
+
+        // let adults = from users | filter age > 18 | select [id, name]
+        // from adults | sort name
+        let program = Program {
+            pragma: None,
+            lets: vec![LetBinding {
+                name: "adults".to_string(),
+                pipeline: Pipeline {
+                    source: Source::Table { name: "users".to_string(), alias: None },
+                    ops: vec![
+                        Operator::Filter {
+                            condition: Expr::BinaryOp {
+                                op: BinOp::Gt,
+                                left: Box::new(Expr::Column { col: ColumnRef { table: None, column: "age".to_string() } }),
+                                right: Box::new(Expr::Literal { value: Value::Int(18) }),
+                            },
+                        },
+                        Operator::Select {
+                            projections: vec![
+                                Projection::Expr(Expr::Column { col: ColumnRef { table: None, column: "id".to_string() } }),
+                                Projection::Expr(Expr::Column { col: ColumnRef { table: None, column: "name".to_string() } }),
+                            ],
+                        },
+                    ],
+                },
+            }],
+            pipeline: Pipeline {
+                source: Source::Table { name: "adults".to_string(), alias: None },
+                ops: vec![Operator::Sort {
+                    keys: vec![SortKey {
+                        expr: Expr::Column { col: ColumnRef { table: None, column: "name".to_string() } },
+                        desc: false,
+                    }],
+                }],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program).expect("Translation should succeed");
+        let root = root_of(&plan);
+
+        // Output columns come from the binding's select
+        assert_eq!(root.names, vec!["id", "name"]);
+
+        // Sort sits directly on the binding's relation tree (Project over Filter over Read)
+        let sort = match &root.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Sort(sort)) => sort,
+            other => panic!("Expected SortRel, got {:?}", other),
+        };
+        assert!(matches!(
+            sort.input.as_ref().unwrap().rel_type,
+            Some(substrait::proto::rel::RelType::Project(_))
+        ));
+    }
+
+    #[test]
+    fn test_group_by_measures_follow_sorted_names() {
+        let schema_provider = users_schema_provider();
+
+        // from users | group by name { total: sum(age), n: count() }
+        let mut aggs = HashMap::new();
+        aggs.insert("total".to_string(), AggCall {
+            func: "sum".to_string(),
+            args: vec![Expr::Column { col: ColumnRef { table: None, column: "age".to_string() } }],
+        });
+        aggs.insert("n".to_string(), AggCall { func: "count".to_string(), args: vec![] });
+        let program = Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![Operator::GroupBy {
+                    keys: vec![ColumnRef { table: None, column: "name".to_string() }],
+                    aggs,
+                }],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program).expect("Translation should succeed");
+        let root = root_of(&plan);
+
+        assert_eq!(root.names, vec!["name", "n", "total"]);
+        let aggregate = match &root.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Aggregate(aggregate)) => aggregate,
+            other => panic!("Expected AggregateRel, got {:?}", other),
+        };
+        let arg_counts: Vec<usize> = aggregate.measures.iter()
+            .map(|m| m.measure.as_ref().unwrap().arguments.len())
+            .collect();
+        assert_eq!(arg_counts, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_function_calls_declare_extension_files() {
+        use super::super::functions::{FUNCTIONS_COMPARISON, FUNCTIONS_DATETIME, FUNCTIONS_STRING};
+        use substrait::proto::extensions::simple_extension_declaration::MappingType;
+        use substrait::proto::function_argument::ArgType;
+        use substrait::proto::expression::RexType;
+
+        let schema_provider = users_schema_provider();
+        let name = || Expr::Column { col: ColumnRef { table: None, column: "name".to_string() } };
+        let string = |s: &str| Expr::Literal { value: Value::String(s.to_string()) };
+        let call = |func: &str, args: Vec<Expr>| Expr::FuncCall { func: func.to_string(), args };
+
+        // from users | filter age > 30
+        // | select [upper(name) as loud, substr(name, 1, 2) as initials, extract("year", age) as year]
+        let program = |projections: Vec<Projection>| Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![
+                    Operator::Filter {
+                        condition: Expr::BinaryOp {
+                            op: BinOp::Gt,
+                            left: Box::new(Expr::Column { col: ColumnRef { table: None, column: "age".to_string() } }),
+                            right: Box::new(Expr::Literal { value: Value::Int(30) }),
+                        },
+                    },
+                    Operator::Select { projections },
+                ],
+            },
+        };
+        let aliased = |expr: Expr, alias: &str| Projection::Aliased { expr, alias: alias.to_string() };
+        let projections = vec![
+            aliased(call("upper", vec![name()]), "loud"),
+            aliased(call("substr", vec![name(), Expr::Literal { value: Value::Int(1) }, Expr::Literal { value: Value::Int(2) }]), "initials"),
+            aliased(call("extract", vec![string("year"), Expr::Column {
+                col: ColumnRef { table: None, column: "age".to_string() },
+            }]), "year"),
+        ];
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program(projections)).expect("Translation should succeed");
+
+        let uris: Vec<_> = plan.extension_uris.iter().map(|u| (u.extension_uri_anchor, u.uri.as_str())).collect();
+        assert_eq!(uris, vec![(1, FUNCTIONS_COMPARISON), (2, FUNCTIONS_STRING), (3, FUNCTIONS_DATETIME)]);
+        let declarations: Vec<_> = plan.extensions.iter().map(|e| match &e.mapping_type {
+            Some(MappingType::ExtensionFunction(f)) => (f.name.as_str(), f.extension_uri_reference),
+            other => panic!("Expected an extension function, got {:?}", other),
+        }).collect();
+        assert_eq!(declarations, vec![
+            ("gt:i32_i32", 1),
+            ("upper:str", 2),
+            ("substring:str_i32_i32", 2),
+            ("extract:req_ts", 3),
+        ]);
+
+        // The component of extract is an enum argument
+        let project = match &root_of(&plan).input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Project(project)) => project,
+            other => panic!("Expected ProjectRel, got {:?}", other),
+        };
+        match &project.expressions[2].rex_type {
+            Some(RexType::ScalarFunction(extract)) => {
+                assert_eq!(extract.arguments[0].arg_type, Some(ArgType::Enum("year".to_string())));
+            }
+            other => panic!("Expected ScalarFunction, got {:?}", other),
+        }
+
+        let err = SubstraitTranslator::new(&schema_provider)
+            .translate(&program(vec![aliased(call("substr", vec![name()]), "s")]))
+            .expect_err("substr needs a start position");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(1) }));
+        assert!(err.root().to_string().contains("takes 2 or 3 arguments, got 1"), "{}", err);
+    }
+
+    #[test]
+    fn test_custom_functions_use_extension_uri() {
+        use super::super::functions::{ExtensionFunction, FUNCTIONS_STRING};
+
+        /// Resolves `mask(String)` to a custom extension
+        struct MaskResolver;
+
+        impl ExtensionResolver for MaskResolver {
+            fn resolve_extension(&self, name: &str, args: &[DataType]) -> Result<Option<ExtensionFunction>, String> {
+                match (name, args) {
+                    ("mask", [DataType::String]) => Ok(Some(ExtensionFunction {
+                        uri: "mlql:mask:v1".to_string(),
+                        signature: "mask:str".to_string(),
+                    })),
+                    ("mask", _) => Err(format!("mask takes a string, got {:?}", args)),
+                    _ => Ok(None),
+                }
+            }
+        }
+
+        let schema_provider = users_schema_provider();
+        let column = |name: &str| Expr::Column { col: ColumnRef { table: None, column: name.to_string() } };
+        // from users | select [mask(<column>) as masked, upper(name) as loud]
+        let program = |masked: &str| Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![Operator::Select {
+                    projections: vec![
+                        Projection::Aliased {
+                            expr: Expr::FuncCall { func: "mask".to_string(), args: vec![column(masked)] },
+                            alias: "masked".to_string(),
+                        },
+                        Projection::Aliased {
+                            expr: Expr::FuncCall { func: "upper".to_string(), args: vec![column("name")] },
+                            alias: "loud".to_string(),
+                        },
+                    ],
+                }],
+            },
+        };
+
+        let plan = SubstraitTranslator::new(&schema_provider)
+            .with_extensions(&MaskResolver)
+            .translate(&program("name"))
+            .expect("Translation should succeed");
+        let uris: Vec<_> = plan.extension_uris.iter().map(|u| u.uri.as_str()).collect();
+        assert_eq!(uris, vec!["mlql:mask:v1", FUNCTIONS_STRING]);
+
+        let err = SubstraitTranslator::new(&schema_provider)
+            .with_extensions(&MaskResolver)
+            .translate(&program("age"))
+            .expect_err("mask takes a string");
+        assert!(err.root().to_string().contains("mask takes a string, got [Int32]"), "{}", err);
+
+        // Without a resolver, custom functions have no lowering
+        let err = SubstraitTranslator::new(&schema_provider)
+            .translate(&program("name"))
+            .expect_err("mask is a custom function");
+        assert!(err.root().to_string().contains("Function 'mask' has no Substrait mapping"), "{}", err);
+    }
+
+    #[test]
+    fn test_window_functions_in_project() {
+        use substrait::proto::expression::window_function::{bound, BoundsType};
+        use substrait::proto::expression::RexType;
+
+        let schema_provider = users_schema_provider();
+
+        // from users | window {
+        //     rn: row_number() over part: name order: -age,
+        //     age: sum(age) over part: name order: id frame: rows [unbounded_preceding, current_row]
+        // }
+        let column = |name: &str| ColumnRef { table: None, column: name.to_string() };
+        let mut windows = HashMap::new();
+        windows.insert("rn".to_string(), WindowDef {
+            func: "row_number".to_string(),
+            args: vec![],
+            partition: Some(vec![column("name")]),
+            order: Some(vec![SortKey { expr: Expr::Column { col: column("age") }, desc: true }]),
+            frame: None,
+        });
+        windows.insert("age".to_string(), WindowDef {
+            func: "sum".to_string(),
+            args: vec![Expr::Column { col: column("age") }],
+            partition: Some(vec![column("name")]),
+            order: Some(vec![SortKey { expr: Expr::Column { col: column("id") }, desc: false }]),
+            frame: Some(crate::FrameSpec {
+                mode: FrameMode::Rows,
+                start: FrameBound::UnboundedPreceding,
+                end: FrameBound::CurrentRow,
+            }),
+        });
+        let program = Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![Operator::Window { windows }],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program).expect("Translation should succeed");
+        let root = root_of(&plan);
+
+        // The window named `age` replaces the input column
+        assert_eq!(root.names, vec!["id", "name", "age", "rn"]);
+        let project = match &root.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Project(project)) => project,
+            other => panic!("Expected ProjectRel, got {:?}", other),
+        };
+        match project.common.as_ref().and_then(|common| common.emit_kind.as_ref()) {
+            Some(substrait::proto::rel_common::EmitKind::Emit(emit)) => assert_eq!(emit.output_mapping, vec![0, 1, 3, 4]),
+            other => panic!("Expected an emit mapping, got {:?}", other),
+        }
+
+        let functions: Vec<_> = project.expressions.iter().map(|expr| match &expr.rex_type {
+            Some(RexType::WindowFunction(function)) => function,
+            other => panic!("Expected WindowFunction, got {:?}", other),
+        }).collect();
+
+        // Windows in name order: age, rn
+        let running_sum = functions[0];
+        assert_eq!(running_sum.arguments.len(), 1);
+        assert_eq!(running_sum.partitions.len(), 1);
+        assert_eq!(running_sum.bounds_type, BoundsType::Rows as i32);
+        assert!(matches!(running_sum.lower_bound.as_ref().unwrap().kind, Some(bound::Kind::Unbounded(_))));
+        assert!(matches!(running_sum.upper_bound.as_ref().unwrap().kind, Some(bound::Kind::CurrentRow(_))));
+
+        let row_number = functions[1];
+        assert!(row_number.arguments.is_empty());
+        assert_eq!(row_number.sorts.len(), 1);
+        assert_eq!(row_number.bounds_type, BoundsType::Unspecified as i32);
+        assert!(row_number.lower_bound.is_none());
+
+        let registered: Vec<String> = translator.function_registry.borrow().get_functions()
+            .into_iter().map(|(name, _)| name).collect();
+        assert!(registered.contains(&"row_number".to_string()), "{:?}", registered);
+        assert!(registered.contains(&"sum:i32".to_string()), "{:?}", registered);
+    }
+
+    #[test]
+    fn test_nested_and_membership_expressions() {
+        use substrait::proto::expression::field_reference::ReferenceType as Direct;
+        use substrait::proto::expression::literal::LiteralType;
+        use substrait::proto::expression::reference_segment::ReferenceType;
+        use substrait::proto::expression::{ReferenceSegment, RexType};
+
+        let mut schema_provider = MockSchemaProvider::new();
+        let field = |name: &str, data_type: DataType| FieldType { name: name.to_string(), data_type, nullable: true };
+        schema_provider.add_table(TableSchema {
+            name: "events".to_string(),
+            columns: vec![
+                ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
+                ColumnInfo {
+                    name: "payload".to_string(),
+                    data_type: DataType::Struct(vec![field("user", DataType::Struct(vec![
+                        field("age", DataType::Int32),
+                        field("name", DataType::String),
+                    ]))]),
+                    nullable: true,
+                },
+                ColumnInfo { name: "tags".to_string(), data_type: DataType::Array(Box::new(DataType::String)), nullable: true },
+                ColumnInfo {
+                    name: "attrs".to_string(),
+                    data_type: DataType::Map { key: Box::new(DataType::String), value: Box::new(DataType::String) },
+                    nullable: true,
+                },
+                ColumnInfo { name: "day".to_string(), data_type: DataType::Date, nullable: true },
+            ],
+        });
+        let col = |table: Option<&str>, column: &str| Expr::Column {
+            col: ColumnRef { table: table.map(str::to_string), column: column.to_string() },
+        };
+        let literal = |value: Value| Box::new(Expr::Literal { value });
+        let aliased = |expr: Expr, alias: &str| Projection::Aliased { expr, alias: alias.to_string() };
+
+        // from events | filter id in (1, 2) && day >= 2024-01-15
+        // | select [payload.user.name as who, tags[1] as first_tag, attrs["k"] as attr,
+        //           id in [1, 10) as small, upper(payload.user.name) as loud, [id, id] as pair]
+        let program = |first_tag: Expr| Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "events".to_string(), alias: None },
+                ops: vec![
+                    Operator::Filter {
+                        condition: Expr::BinaryOp {
+                            op: BinOp::And,
+                            left: Box::new(Expr::InSet {
+                                expr: Box::new(col(None, "id")),
+                                set: vec![*literal(Value::Int(1)), *literal(Value::Int(2))],
+                            }),
+                            right: Box::new(Expr::BinaryOp {
+                                op: BinOp::Ge,
+                                left: Box::new(col(None, "day")),
+                                right: literal(Value::Date("2024-01-15".to_string())),
+                            }),
+                        },
+                    },
+                    Operator::Select {
+                        projections: vec![
+                            aliased(Expr::FieldAccess {
+                                expr: Box::new(col(Some("payload"), "user")),
+                                field: "name".to_string(),
+                            }, "who"),
+                            aliased(first_tag, "first_tag"),
+                            aliased(Expr::Index {
+                                expr: Box::new(col(None, "attrs")),
+                                index: literal(Value::String("k".to_string())),
+                            }, "attr"),
+                            aliased(Expr::InRange {
+                                expr: Box::new(col(None, "id")),
+                                start: literal(Value::Int(1)),
+                                end: literal(Value::Int(10)),
+                                inclusive: false,
+                            }, "small"),
+                            aliased(Expr::FuncCall {
+                                func: "upper".to_string(),
+                                args: vec![Expr::FieldAccess {
+                                    expr: Box::new(col(Some("payload"), "user")),
+                                    field: "name".to_string(),
+                                }],
+                            }, "loud"),
+                            aliased(Expr::Array { elements: vec![col(None, "id"), col(None, "id")] }, "pair"),
+                        ],
+                    },
+                ],
+            },
+        };
+        let tags_1 = Expr::Index { expr: Box::new(col(None, "tags")), index: literal(Value::Int(1)) };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program(tags_1)).expect("Translation should succeed");
+        let root = root_of(&plan);
+        assert_eq!(root.names, vec!["who", "first_tag", "attr", "small", "loud", "pair"]);
+
+        let project = match &root.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Project(project)) => project,
+            other => panic!("Expected ProjectRel, got {:?}", other),
+        };
+
+        // Reference segments of a direct field reference, outermost first
+        fn path(expr: &substrait::proto::Expression) -> Vec<String> {
+            let mut segment: Option<&ReferenceSegment> = match &expr.rex_type {
+                Some(RexType::Selection(field_ref)) => match &field_ref.reference_type {
+                    Some(Direct::DirectReference(segment)) => Some(segment),
+                    other => panic!("Expected a direct reference, got {:?}", other),
+                },
+                other => panic!("Expected a field reference, got {:?}", other),
+            };
+            let mut path = Vec::new();
+            while let Some(current) = segment {
+                segment = match &current.reference_type {
+                    Some(ReferenceType::StructField(f)) => {
+                        path.push(format!("field {}", f.field));
+                        f.child.as_deref()
+                    }
+                    Some(ReferenceType::ListElement(e)) => {
+                        path.push(format!("element {}", e.offset));
+                        e.child.as_deref()
+                    }
+                    Some(ReferenceType::MapKey(k)) => {
+                        path.push(format!("key {:?}", k.map_key.as_ref().unwrap().literal_type));
+                        k.child.as_deref()
+                    }
+                    None => None,
+                };
+            }
+            path
+        }
+
+        // Struct fields by position, 1-based list indexes, literal map keys
+        assert_eq!(path(&project.expressions[0]), vec!["field 1", "field 0", "field 1"]);
+        assert_eq!(path(&project.expressions[1]), vec!["field 2", "element 0"]);
+        assert_eq!(path(&project.expressions[2]), vec!["field 3".to_string(), format!("key {:?}", Some(LiteralType::String("k".to_string())))]);
+        assert!(matches!(&project.expressions[5].rex_type, Some(RexType::Nested(_))));
+
+        // The filter's `in` set is a SingularOrList and the date a day count
+        let filter = match &project.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Filter(filter)) => filter,
+            other => panic!("Expected FilterRel, got {:?}", other),
+        };
+        let conjuncts: Vec<_> = match &filter.condition.as_ref().unwrap().rex_type {
+            Some(RexType::ScalarFunction(and)) => and.arguments.iter().map(|arg| match &arg.arg_type {
+                Some(substrait::proto::function_argument::ArgType::Value(value)) => value,
+                other => panic!("Expected a value argument, got {:?}", other),
+            }).collect(),
+            other => panic!("Expected ScalarFunction, got {:?}", other),
+        };
+        assert!(matches!(&conjuncts[0].rex_type, Some(RexType::SingularOrList(list)) if list.options.len() == 2));
+        match &conjuncts[1].rex_type {
+            Some(RexType::ScalarFunction(ge)) => assert!(matches!(&ge.arguments[1].arg_type,
+                Some(substrait::proto::function_argument::ArgType::Value(substrait::proto::Expression {
+                    rex_type: Some(RexType::Literal(substrait::proto::expression::Literal {
+                        literal_type: Some(LiteralType::Date(19737)), ..
+                    })),
+                })))),
+            other => panic!("Expected ScalarFunction, got {:?}", other),
+        }
+
+        let registered: Vec<String> = translator.function_registry.borrow().get_functions()
+            .into_iter().map(|(name, _)| name).collect();
+        assert!(registered.contains(&"upper:str".to_string()), "{:?}", registered);
+
+        // A list index must be a positive literal
+        let tags_0 = Expr::Index { expr: Box::new(col(None, "tags")), index: literal(Value::Int(0)) };
+        let err = SubstraitTranslator::new(&schema_provider).translate(&program(tags_0))
+            .expect_err("list indexes start at 1");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(1) }));
+        assert!(err.root().to_string().contains("List index 0 out of range"), "{}", err);
+
+        assert_eq!(timestamp_micros("1970-01-02T01:00+01:00"), Some(86_400_000_000));
+        assert_eq!(timestamp_micros("1970-01-01T00:00:01.5Z"), Some(1_500_000));
+        assert_eq!(date_days("2024-02-30"), None);
+    }
+
+    #[test]
+    fn test_set_operations_cast_to_common_types() {
+        use substrait::proto::rel::RelType;
+        use substrait::proto::set_rel::SetOp;
+
+        let mut schema_provider = users_schema_provider();
+        schema_provider.add_table(TableSchema {
+            name: "archived_users".to_string(),
+            columns: vec![
+                ColumnInfo { name: "id".to_string(), data_type: DataType::Int64, nullable: false },
+                ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+                ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
+            ],
+        });
+        let program = |op: Operator| Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![op],
+            },
+        };
+
+        // from users | union from archived_users
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program(Operator::Union {
+            all: false,
+            source: Source::Table { name: "archived_users".to_string(), alias: None },
+        })).expect("Translation should succeed");
+        let root = root_of(&plan);
+        assert_eq!(root.names, vec!["id", "name", "age"]);
+
+        let set = match &root.input.as_ref().unwrap().rel_type {
+            Some(RelType::Set(set)) => set,
+            other => panic!("Expected SetRel, got {:?}", other),
+        };
+        assert_eq!(set.op, SetOp::UnionDistinct as i32);
+
+        // users.id is cast from i32 to i64; archived_users already matches
+        let project = match &set.inputs[0].rel_type {
+            Some(RelType::Project(project)) => project,
+            other => panic!("Expected ProjectRel, got {:?}", other),
+        };
+        match project.common.as_ref().and_then(|common| common.emit_kind.as_ref()) {
+            Some(substrait::proto::rel_common::EmitKind::Emit(emit)) => assert_eq!(emit.output_mapping, vec![3, 4, 5]),
+            other => panic!("Expected an emit mapping, got {:?}", other),
+        }
+        match &project.expressions[0].rex_type {
+            Some(substrait::proto::expression::RexType::Cast(cast)) => assert!(matches!(
+                cast.r#type.as_ref().and_then(|t| t.kind.as_ref()),
+                Some(substrait::proto::r#type::Kind::I64(_))
+            )),
+            other => panic!("Expected Cast, got {:?}", other),
+        }
+        assert!(matches!(set.inputs[1].rel_type, Some(RelType::Read(_))));
+
+        // from users | except from (from users | select [id])
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let err = translator.translate(&program(Operator::Except {
+            source: Source::SubPipeline {
+                pipeline: Box::new(Pipeline {
+                    source: Source::Table { name: "users".to_string(), alias: None },
+                    ops: vec![Operator::Select {
+                        projections: vec![Projection::Expr(Expr::Column {
+                            col: ColumnRef { table: None, column: "id".to_string() },
+                        })],
+                    }],
+                }),
+                alias: None,
+            },
+        })).expect_err("Column counts differ");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(0) }));
+        assert!(err.to_string().contains("except inputs have 3 and 1 columns"), "{}", err);
+    }
+
+    #[test]
+    fn test_join_sub_pipelines() {
+        use substrait::proto::rel::RelType;
+        use substrait::proto::expression::RexType;
+
+        let mut schema_provider = MockSchemaProvider::new();
+        schema_provider.add_table(TableSchema {
+            name: "orders".to_string(),
+            columns: vec![
+                ColumnInfo { name: "order_id".to_string(), data_type: DataType::Int32, nullable: false },
+                ColumnInfo { name: "user_id".to_string(), data_type: DataType::Int32, nullable: false },
+                ColumnInfo { name: "amount".to_string(), data_type: DataType::Int32, nullable: true },
+            ],
+        });
+        let col = |table: &str, column: &str| Expr::Column {
+            col: ColumnRef { table: Some(table.to_string()), column: column.to_string() },
+        };
+        let sub_pipeline = |ops: Vec<Operator>, alias: &str| Source::SubPipeline {
+            pipeline: Box::new(Pipeline {
+                source: Source::Table { name: "orders".to_string(), alias: None },
+                ops,
+            }),
+            alias: Some(alias.to_string()),
+        };
+
+        // from (from orders | group by user_id { total: sum(amount) }) totals
+        // | filter totals.total > 0
+        // | join (from orders | filter amount > 100) big on totals.user_id == big.user_id
+        let greater = |column: Expr, value: i64| Expr::BinaryOp {
+            op: BinOp::Gt,
+            left: Box::new(column),
+            right: Box::new(Expr::Literal { value: Value::Int(value) }),
+        };
+        let program = |big_filter: Expr| Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: sub_pipeline(vec![Operator::GroupBy {
+                    keys: vec![ColumnRef { table: None, column: "user_id".to_string() }],
+                    aggs: HashMap::from([("total".to_string(), AggCall {
+                        func: "sum".to_string(),
+                        args: vec![col("orders", "amount")],
+                    })]),
+                }], "totals"),
+                ops: vec![
+                    Operator::Filter { condition: greater(col("totals", "total"), 0) },
+                    Operator::Join {
+                        source: sub_pipeline(vec![Operator::Filter { condition: big_filter }], "big"),
+                        on: Expr::BinaryOp {
+                            op: BinOp::Eq,
+                            left: Box::new(col("totals", "user_id")),
+                            right: Box::new(col("big", "user_id")),
+                        },
+                        join_type: None,
+                    },
+                ],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program(greater(col("orders", "amount"), 100)))
+            .expect("Translation should succeed");
+        let root = root_of(&plan);
+        assert_eq!(root.names, vec!["user_id", "total", "order_id", "user_id", "amount"]);
+
+        let join = match &root.input.as_ref().unwrap().rel_type {
+            Some(RelType::Join(join)) => join,
+            other => panic!("Expected JoinRel, got {:?}", other),
+        };
+        // Both sub-pipelines are inlined rather than read
+        let filter = match &join.left.as_ref().unwrap().rel_type {
+            Some(RelType::Filter(filter)) => filter,
+            other => panic!("Expected FilterRel, got {:?}", other),
+        };
+        assert!(matches!(filter.input.as_ref().unwrap().rel_type, Some(RelType::Aggregate(_))));
+        assert!(matches!(join.right.as_ref().unwrap().rel_type, Some(RelType::Filter(_))));
+
+        // big.user_id is the right input's user_id, after the two left columns
+        let fields: Vec<i32> = match &join.expression.as_ref().unwrap().rex_type {
+            Some(RexType::ScalarFunction(function)) => function.arguments.iter().map(|arg| {
+                match &arg.arg_type {
+                    Some(substrait::proto::function_argument::ArgType::Value(substrait::proto::Expression {
+                        rex_type: Some(RexType::Selection(field_ref)),
+                    })) => match &field_ref.reference_type {
+                        Some(substrait::proto::expression::field_reference::ReferenceType::DirectReference(segment)) => {
+                            match &segment.reference_type {
+                                Some(substrait::proto::expression::reference_segment::ReferenceType::StructField(field)) => field.field,
+                                other => panic!("Expected a struct field, got {:?}", other),
+                            }
+                        }
+                        other => panic!("Expected a direct reference, got {:?}", other),
+                    },
+                    other => panic!("Expected a field reference, got {:?}", other),
+                }
+            }).collect(),
+            other => panic!("Expected ScalarFunction, got {:?}", other),
+        };
+        assert_eq!(fields, vec![0, 3]);
+
+        // Errors inside a sub-pipeline are reported at the join that reads it
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let err = translator.translate(&program(greater(col("orders", "amnt"), 100)))
+            .expect_err("Unknown column");
+        assert_eq!(err.location(), Some(OpLocation { binding: None, op: Some(1) }));
+        assert!(matches!(err.root(), TranslateError::UnknownColumn { column, .. } if column == "orders.amnt"));
+    }
+
+    #[test]
+    fn test_read_schema_uses_column_types() {
+        use substrait::proto::r#type::Kind;
+
+        let mut schema_provider = MockSchemaProvider::new();
+        schema_provider.add_table(TableSchema {
+            name: "events".to_string(),
+            columns: vec![
+                ColumnInfo { name: "ok".to_string(), data_type: DataType::Bool, nullable: false },
+                ColumnInfo { name: "amount".to_string(), data_type: DataType::Decimal { precision: 10, scale: 2 }, nullable: true },
+                ColumnInfo { name: "day".to_string(), data_type: DataType::Date, nullable: true },
+                ColumnInfo {
+                    name: "meta".to_string(),
+                    data_type: DataType::Struct(vec![
+                        FieldType { name: "source".to_string(), data_type: DataType::String, nullable: true },
+                    ]),
+                    nullable: true,
+                },
+                ColumnInfo { name: "tags".to_string(), data_type: DataType::Array(Box::new(DataType::String)), nullable: true },
+                // GEOMETRY, which MLQL doesn't model
+                ColumnInfo { name: "shape".to_string(), data_type: DataType::Unknown, nullable: true },
+            ],
+        });
+        let program = Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table { name: "events".to_string(), alias: None },
+                ops: vec![],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program).expect("Translation should succeed");
+        let root = root_of(&plan);
+        assert_eq!(root.names, vec!["ok", "amount", "day", "meta", "source", "tags", "shape"]);
+
+        let read = match &root.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Read(read)) => read,
+            other => panic!("Expected ReadRel, got {:?}", other),
+        };
+        let base_schema = read.base_schema.as_ref().unwrap();
+        assert_eq!(base_schema.names, root.names);
+
+        let kinds: Vec<&Kind> = base_schema.r#struct.as_ref().unwrap().types.iter()
+            .map(|t| t.kind.as_ref().unwrap())
+            .collect();
+        assert!(matches!(kinds[0], Kind::Bool(_)));
+        assert!(matches!(kinds[1], Kind::Decimal(d) if d.precision == 10 && d.scale == 2));
+        assert!(matches!(kinds[2], Kind::Date(_)));
+        assert!(matches!(kinds[3], Kind::Struct(s) if s.types.len() == 1));
+        assert!(matches!(kinds[4], Kind::List(_)));
+        assert!(matches!(kinds[5], Kind::String(_)));
+    }
+
+    #[test]
+    fn test_let_binding_can_shadow_table() {
+        let schema_provider = users_schema_provider();
+
+        // let users = from users | take 5
+        // from users
+        let program = Program {
+            pragma: None,
+            lets: vec![LetBinding {
+                name: "users".to_string(),
+                pipeline: Pipeline {
+                    source: Source::Table { name: "users".to_string(), alias: None },
+                    ops: vec![Operator::Take { limit: 5 }],
+                },
+            }],
+            pipeline: Pipeline {
+                source: Source::Table { name: "users".to_string(), alias: None },
+                ops: vec![],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let plan = translator.translate(&program).expect("Translation should succeed");
+        let root = root_of(&plan);
+
+        assert_eq!(root.names, vec!["id", "name", "age"]);
+        let fetch = match &root.input.as_ref().unwrap().rel_type {
+            Some(substrait::proto::rel::RelType::Fetch(fetch)) => fetch,
+            other => panic!("Expected FetchRel, got {:?}", other),
+        };
+        assert!(matches!(
+            fetch.input.as_ref().unwrap().rel_type,
+            Some(substrait::proto::rel::RelType::Read(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_column_reports_location() {
+        let schema_provider = users_schema_provider();
+
+        // let named = from users | filter nmae == "x"
+        // from named | take 1
+        let program = Program {
+            pragma: None,
+            lets: vec![LetBinding {
+                name: "named".to_string(),
+                pipeline: Pipeline {
+                    source: Source::Table { name: "users".to_string(), alias: None },
+                    ops: vec![Operator::Filter {
+                        condition: Expr::BinaryOp {
+                            op: BinOp::Eq,
+                            left: Box::new(Expr::Column { col: ColumnRef { table: None, column: "nmae".to_string() } }),
+                            right: Box::new(Expr::Literal { value: Value::String("x".to_string()) }),
+                        },
+                    }],
+                },
+            }],
+            pipeline: Pipeline {
+                source: Source::Table { name: "named".to_string(), alias: None },
+                ops: vec![Operator::Take { limit: 1 }],
+            },
+        };
+
+        let translator = SubstraitTranslator::new(&schema_provider);
+        let err = translator.translate(&program).expect_err("Unknown column should fail");
+
+        assert_eq!(err.location(), Some(OpLocation { binding: Some(0), op: Some(0) }));
+        assert!(matches!(err.root(), TranslateError::UnknownColumn { column, .. } if column == "nmae"));
+        assert_eq!(err.expected(), vec!["id", "name", "age"]);
+        assert!(err.to_string().starts_with("lets[0].ops[0]: Column 'nmae' not found"));
+    }
 }
@@ -45,6 +45,217 @@ pub enum DataType {
     Unknown,
 }
 
+impl DataType {
+    /// Parse a SQL type name as DuckDB writes it, such as `INTEGER`,
+    /// `DECIMAL(10,2)`, `VARCHAR[]`, `FLOAT[384]`, `FLOAT[ANY]`, `MAP(VARCHAR, INTEGER)` or
+    /// `STRUCT(id INTEGER, "full name" VARCHAR)`.
+    ///
+    /// Fixed-size `FLOAT` arrays are embeddings and become [`DataType::Vector`];
+    /// other arrays, including `DOUBLE[n]`, keep their element type.
+    /// Names that are not recognized map to [`DataType::Unknown`].
+    pub fn from_sql(name: &str) -> DataType {
+        let name = name.trim();
+
+        // Lists (`INTEGER[]`) and fixed-size arrays (`FLOAT[384]`)
+        if let Some(body) = name.strip_suffix(']') {
+            if let Some(open) = body.rfind('[') {
+                let element = DataType::from_sql(&body[..open]);
+                let size = body[open + 1..].trim();
+                return match (element, size.parse::<usize>()) {
+                    (DataType::Float32, Ok(size)) => DataType::Vector(Some(size)),
+                    // Function signatures take arrays of any size as `FLOAT[ANY]`
+                    (DataType::Float32, _) if size.eq_ignore_ascii_case("ANY") => DataType::Vector(None),
+                    (element, _) => DataType::Array(Box::new(element)),
+                };
+            }
+        }
+
+        // Parameterized types
+        if let (Some(open), Some(body)) = (name.find('('), name.strip_suffix(')')) {
+            let args = split_type_args(&body[open + 1..]);
+            return match name[..open].trim().to_ascii_uppercase().as_str() {
+                "DECIMAL" | "NUMERIC" => {
+                    let precision = args.first().and_then(|p| p.parse().ok());
+                    let scale = args.get(1).map_or(Some(0), |s| s.parse().ok());
+                    match (precision, scale) {
+                        (Some(precision), Some(scale)) => DataType::Decimal { precision, scale },
+                        _ => DataType::Unknown,
+                    }
+                }
+                "VARCHAR" => DataType::Varchar(args.first().and_then(|n| n.parse().ok())),
+                "LIST" if args.len() == 1 => DataType::Array(Box::new(DataType::from_sql(args[0]))),
+                "MAP" if args.len() == 2 => DataType::Map {
+                    key: Box::new(DataType::from_sql(args[0])),
+                    value: Box::new(DataType::from_sql(args[1])),
+                },
+                "STRUCT" | "ROW" => {
+                    let fields: Option<Vec<FieldType>> = args.iter().map(|arg| parse_struct_field(arg)).collect();
+                    fields.map_or(DataType::Unknown, DataType::Struct)
+                }
+                "ENUM" => DataType::String,
+                _ => DataType::Unknown,
+            };
+        }
+
+        match name.to_ascii_uppercase().as_str() {
+            "BOOLEAN" | "BOOL" => DataType::Bool,
+            "TINYINT" | "INT1" => DataType::Int8,
+            "SMALLINT" | "INT2" => DataType::Int16,
+            "INTEGER" | "INT" | "INT4" => DataType::Int32,
+            "BIGINT" | "INT8" => DataType::Int64,
+            "UTINYINT" => DataType::UInt8,
+            "USMALLINT" => DataType::UInt16,
+            "UINTEGER" => DataType::UInt32,
+            "UBIGINT" => DataType::UInt64,
+            "HUGEINT" => DataType::Decimal { precision: 38, scale: 0 },
+            "FLOAT" | "REAL" | "FLOAT4" => DataType::Float32,
+            "DOUBLE" | "FLOAT8" => DataType::Float64,
+            "DECIMAL" | "NUMERIC" => DataType::Decimal { precision: 18, scale: 3 },
+            "VARCHAR" | "TEXT" | "STRING" | "CHAR" | "BPCHAR" | "UUID" | "JSON" => DataType::String,
+            "BLOB" | "BYTEA" => DataType::Blob,
+            "DATE" => DataType::Date,
+            "TIME" | "TIME WITH TIME ZONE" | "TIMETZ" => DataType::Time,
+            // Second, millisecond and nanosecond precisions are still timestamps
+            "TIMESTAMP" | "DATETIME" | "TIMESTAMP_S" | "TIMESTAMP_MS" | "TIMESTAMP_NS" => DataType::Timestamp,
+            "TIMESTAMP WITH TIME ZONE" | "TIMESTAMPTZ" => DataType::TimestampTz,
+            "INTERVAL" => DataType::Interval,
+            _ => DataType::Unknown,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+                | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64
+        )
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || matches!(self, DataType::Float32 | DataType::Float64 | DataType::Decimal { .. })
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, DataType::String | DataType::Varchar(_))
+    }
+
+    pub fn is_temporal(&self) -> bool {
+        matches!(self, DataType::Date | DataType::Time | DataType::Timestamp | DataType::TimestampTz)
+    }
+
+    /// `Unknown` and `Null` are compatible with every type
+    pub fn is_unknown_or_null(&self) -> bool {
+        matches!(self, DataType::Unknown | DataType::Null)
+    }
+
+    /// Smallest type both `self` and `other` convert to, if any.
+    ///
+    /// `Null` takes the other type; `Unknown` stays unknown. String values
+    /// compare against temporal types, as date literals are often strings.
+    pub fn common_supertype(&self, other: &DataType) -> Option<DataType> {
+        use DataType::*;
+
+        match (self, other) {
+            (a, b) if a == b => Some(a.clone()),
+            (Unknown, _) | (_, Unknown) => Some(Unknown),
+            (Null, t) | (t, Null) => Some(t.clone()),
+            (a, b) if a.is_numeric() && b.is_numeric() => Some(wider_numeric(a, b)),
+            (a, b) if a.is_string() && b.is_string() => Some(String),
+            (Date, Timestamp) | (Timestamp, Date) => Some(Timestamp),
+            (Timestamp | Date, TimestampTz) | (TimestampTz, Timestamp | Date) => Some(TimestampTz),
+            (t, s) | (s, t) if t.is_temporal() && s.is_string() => Some(t.clone()),
+            (Array(a), Array(b)) => a.common_supertype(b).map(|t| Array(Box::new(t))),
+            (Vector(_), Vector(_)) => Some(Vector(None)),
+            _ => None,
+        }
+    }
+}
+
+fn wider_numeric(a: &DataType, b: &DataType) -> DataType {
+    use DataType::*;
+
+    fn int_rank(t: &DataType) -> u8 {
+        match t {
+            Int8 | UInt8 => 1,
+            Int16 | UInt16 => 2,
+            Int32 | UInt32 => 3,
+            _ => 4,
+        }
+    }
+
+    match (a, b) {
+        (Float32, Float32) => Float32,
+        (Float32 | Float64, _) | (_, Float32 | Float64) => Float64,
+        (Decimal { precision: p1, scale: s1 }, Decimal { precision: p2, scale: s2 }) => {
+            Decimal { precision: (*p1).max(*p2), scale: (*s1).max(*s2) }
+        }
+        (d @ Decimal { .. }, _) | (_, d @ Decimal { .. }) => d.clone(),
+        // Mixed signedness widens to a signed type one step up
+        (x, y) if x.is_integer() && y.is_integer() => {
+            let unsigned = |t: &DataType| matches!(t, UInt8 | UInt16 | UInt32 | UInt64);
+            let rank = int_rank(x).max(int_rank(y)) + u8::from(unsigned(x) != unsigned(y));
+            match (rank, unsigned(x) && unsigned(y)) {
+                (1, true) => UInt8,
+                (2, true) => UInt16,
+                (3, true) => UInt32,
+                (_, true) => UInt64,
+                (1, false) => Int8,
+                (2, false) => Int16,
+                (3, false) => Int32,
+                _ => Int64,
+            }
+        }
+        _ => Float64,
+    }
+}
+
+/// Split the arguments of a parameterized type on top-level commas
+fn split_type_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                parts.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(args[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+/// Parse a struct field such as `id INTEGER` or `"full name" VARCHAR`
+fn parse_struct_field(field: &str) -> Option<FieldType> {
+    let (name, data_type) = match field.strip_prefix('"') {
+        Some(rest) => {
+            // `""` escapes a quote inside a quoted name
+            let mut name = String::new();
+            let mut chars = rest.char_indices().peekable();
+            loop {
+                match chars.next()? {
+                    (_, '"') if chars.peek().map(|&(_, c)| c) == Some('"') => {
+                        chars.next();
+                        name.push('"');
+                    }
+                    (i, '"') => break (name, &rest[i + 1..]),
+                    (_, c) => name.push(c),
+                }
+            }
+        }
+        None => {
+            let (name, data_type) = field.split_once(char::is_whitespace)?;
+            (name.to_string(), data_type)
+        }
+    };
+    Some(FieldType { name, data_type: DataType::from_sql(data_type), nullable: true })
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FieldType {
     pub name: String,
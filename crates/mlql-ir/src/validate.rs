@@ -0,0 +1,1438 @@
+//! Semantic validation and type checking
+//!
+//! [`Validator`] checks a [`Program`] before it reaches a backend:
+//!
+//! - tables are resolved through a [`SchemaProvider`] and let bindings
+//! - every column reference is resolved (ambiguity is an error)
+//! - every expression gets a [`DataType`]; operands must be compatible
+//! - function calls are checked through a [`FunctionResolver`]
+//!   (`mlql_registry::FunctionRegistry` implements it)
+//! - columns that are neither grouped nor aggregated are rejected after
+//!   `group by`
+//! - both inputs of `union`, `except` and `intersect` need the same number
+//!   of columns, with types that can be coerced to a common type
+//!
+//! It keeps going after an error, so all problems are reported at once,
+//! each tagged with the [`OpLocation`] of the operator it was found in.
+//! Tables that cannot be resolved (and graph sources) are treated as having
+//! unknown columns, so one missing table does not cascade into more errors.
+//!
+//! [`infer_schema`] runs the same checks without a registry and returns the
+//! output schema even when some of them fail.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::substrait::SchemaProvider;
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ValidationError {
+    #[error("Table not found: {0}")]
+    TableNotFound(String),
+
+    #[error("Column '{column}' not found. Available columns: {}", available.join(", "))]
+    ColumnNotFound { column: String, available: Vec<String> },
+
+    #[error("Column '{0}' is ambiguous")]
+    AmbiguousColumn(String),
+
+    #[error("Column '{0}' must be a group by key or used in an aggregate")]
+    NotAggregated(String),
+
+    #[error("Function not found: {0}")]
+    FunctionNotFound(String),
+
+    #[error("Invalid arguments for {func}: {message}")]
+    InvalidArguments { func: String, message: String },
+
+    #[error("{0} is not an aggregate function")]
+    NotAnAggregate(String),
+
+    #[error("{0} is not a window function")]
+    NotAWindowFunction(String),
+
+    #[error("{0} can only be used in group by, agg or window")]
+    MisplacedAggregate(String),
+
+    #[error("Type mismatch in {context}: expected {expected}, got {actual:?}")]
+    TypeMismatch { context: String, expected: String, actual: DataType },
+
+    #[error("Incompatible types in {context}: {left:?} and {right:?}")]
+    IncompatibleTypes { context: String, left: DataType, right: DataType },
+
+    #[error("Column count mismatch in {context}: {left} and {right}")]
+    ColumnCountMismatch { context: String, left: usize, right: usize },
+
+    #[error("No field '{field}' in {data_type:?}")]
+    UnknownField { field: String, data_type: DataType },
+
+    #[error("Invalid {what}: {message}")]
+    InvalidArgument { what: String, message: String },
+
+    /// An error found in the source or operator at `location`
+    #[error("{location}: {error}")]
+    At { location: OpLocation, error: Box<ValidationError> },
+}
+
+impl ValidationError {
+    /// Location of the operator the error was found in, if known
+    pub fn location(&self) -> Option<OpLocation> {
+        match self {
+            ValidationError::At { location, .. } => Some(*location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location
+    pub fn root(&self) -> &ValidationError {
+        match self {
+            ValidationError::At { error, .. } => error.root(),
+            other => other,
+        }
+    }
+
+    /// Alternatives that would have been accepted, for diagnostics
+    pub fn expected(&self) -> Vec<String> {
+        match self.root() {
+            ValidationError::ColumnNotFound { available, .. } => available.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Every problem found in a program, in source order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// What the validator needs to know about a resolved function call
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub return_type: DataType,
+    pub is_aggregate: bool,
+    pub is_window: bool,
+}
+
+/// Source of function signatures for the validator
+pub trait FunctionResolver {
+    /// Resolve a call to `name` with arguments of the given types.
+    ///
+    /// Fails with [`ValidationError::FunctionNotFound`] or
+    /// [`ValidationError::InvalidArguments`].
+    fn resolve_function(&self, name: &str, args: &[DataType]) -> Result<FunctionType, ValidationError>;
+}
+
+/// Type checker for IR programs
+pub struct Validator<'a> {
+    schemas: &'a dyn SchemaProvider,
+    functions: &'a dyn FunctionResolver,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(schemas: &'a dyn SchemaProvider, functions: &'a dyn FunctionResolver) -> Self {
+        Self { schemas, functions }
+    }
+
+    /// Validate `program` and return its output schema
+    pub fn validate(&self, program: &Program) -> Result<Schema, ValidationErrors> {
+        let (schema, errors) = Check::run(self.schemas, self.functions, program);
+        if errors.is_empty() {
+            Ok(schema)
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+}
+
+/// Output schema of `program`: the name, type and nullability of every column.
+///
+/// Unlike [`Validator::validate`] this never fails. Columns whose type cannot
+/// be worked out (unknown tables or functions, invalid expressions) are
+/// `Unknown`, and function return types come from a built-in table of
+/// common SQL functions rather than a registry.
+pub fn infer_schema(program: &Program, schemas: &dyn SchemaProvider) -> Schema {
+    Check::run(schemas, &Builtins, program).0
+}
+
+/// Distance metrics of the `knn` operator: Euclidean, cosine and negative inner product
+const KNN_METRICS: &[&str] = &["l2", "cosine", "ip"];
+
+const RESAMPLE_METHODS: &[&str] = &["ffill", "linear", "zero"];
+
+/// Deepest `neighbors` traversal; each hop joins every node reached so far
+/// with its edges again
+pub const MAX_NEIGHBORS_DEPTH: i64 = 10;
+
+/// Return types of common SQL functions, for [`infer_schema`]
+struct Builtins;
+
+impl FunctionResolver for Builtins {
+    fn resolve_function(&self, name: &str, args: &[DataType]) -> Result<FunctionType, ValidationError> {
+        let first = args.first().cloned().unwrap_or(DataType::Unknown);
+        let (return_type, is_aggregate, is_window) = match name.to_lowercase().as_str() {
+            "count" | "count_distinct" => (DataType::Int64, true, false),
+            "sum" if first.is_integer() => (DataType::Int64, true, false),
+            "sum" => match first {
+                DataType::Decimal { scale, .. } => (DataType::Decimal { precision: 38, scale }, true, false),
+                DataType::Unknown => (DataType::Unknown, true, false),
+                _ => (DataType::Float64, true, false),
+            },
+            "avg" | "mean" | "stddev" | "variance" | "median" | "approx_p" => (DataType::Float64, true, false),
+            "min" | "max" | "first" | "last" | "any_value" => (first, true, false),
+            "row_number" | "rank" | "dense_rank" | "ntile" => (DataType::Int64, false, true),
+            "percent_rank" | "cume_dist" => (DataType::Float64, false, true),
+            "lag" | "lead" | "first_value" | "last_value" | "nth_value" => (first, false, true),
+            "lower" | "upper" | "trim" | "concat" | "mask" => (DataType::String, false, false),
+            "length" => (DataType::Int64, false, false),
+            "abs" | "round" | "floor" | "ceil" => (first, false, false),
+            "bm25" | "similarity" => (DataType::Float64, false, false),
+            "coalesce" => {
+                let common = args.iter()
+                    .try_fold(DataType::Null, |common, t| common.common_supertype(t))
+                    .unwrap_or(DataType::Unknown);
+                (common, false, false)
+            }
+            _ => return Err(ValidationError::FunctionNotFound(name.to_string())),
+        };
+        Ok(FunctionType { return_type, is_aggregate, is_window })
+    }
+}
+
+/// A column visible to expressions, with the table or alias that qualifies it
+#[derive(Debug, Clone)]
+struct Column {
+    table: Option<String>,
+    field: FieldType,
+}
+
+/// The columns flowing between two operators
+#[derive(Debug, Clone, Default)]
+struct Relation {
+    columns: Vec<Column>,
+    /// The columns are not known (missing table, graph source), so any
+    /// reference resolves to `Unknown`
+    opaque: bool,
+    /// Columns dropped by the preceding `group by`, for a better error
+    ungrouped: Vec<String>,
+}
+
+impl Relation {
+    fn opaque() -> Self {
+        Relation { opaque: true, ..Default::default() }
+    }
+
+    fn schema(&self) -> Schema {
+        Schema::new(self.columns.iter().map(|c| c.field.clone()).collect())
+    }
+
+    /// Qualify every column with `table`, as a source alias does
+    fn qualified(mut self, table: Option<&str>) -> Self {
+        for column in &mut self.columns {
+            column.table = table.map(str::to_string);
+        }
+        self.ungrouped.clear();
+        self
+    }
+
+    /// Add a column, replacing any existing column with the same name
+    fn set(&mut self, field: FieldType) {
+        self.columns.retain(|c| c.field.name != field.name);
+        self.columns.push(Column { table: None, field });
+    }
+
+    /// `s.f` where `s` is a struct column rather than a table: field `f` of `s`
+    fn struct_field(&self, col: &ColumnRef) -> Option<FieldType> {
+        let table = col.table.as_ref()?;
+        let mut structs = self.columns.iter().filter(|c| &c.field.name == table);
+        match (structs.next(), structs.next()) {
+            (Some(Column { field: FieldType { data_type: DataType::Struct(fields), .. }, .. }), None) => {
+                fields.iter().find(|f| f.name == col.column).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve(&self, col: &ColumnRef) -> Result<Column, ValidationError> {
+        let named = |c: &&Column| c.field.name == col.column;
+        let mut matches: Vec<&Column> = match &col.table {
+            // Exact qualifier first; columns computed after the source have none
+            Some(table) => {
+                let exact: Vec<_> = self.columns.iter()
+                    .filter(named)
+                    .filter(|c| c.table.as_deref() == Some(table))
+                    .collect();
+                if exact.is_empty() {
+                    self.columns.iter().filter(named).filter(|c| c.table.is_none()).collect()
+                } else {
+                    exact
+                }
+            }
+            None => self.columns.iter().filter(named).collect(),
+        };
+
+        if matches.is_empty() {
+            if let Some(field) = self.struct_field(col) {
+                return Ok(Column { table: None, field });
+            }
+        }
+
+        match matches.len() {
+            0 if self.opaque => Ok(Column {
+                table: col.table.clone(),
+                field: FieldType { name: col.column.clone(), data_type: DataType::Unknown, nullable: true },
+            }),
+            0 if self.ungrouped.contains(&col.column) => Err(ValidationError::NotAggregated(col.column.clone())),
+            0 => Err(ValidationError::ColumnNotFound {
+                column: column_name(col),
+                available: self.columns.iter().map(|c| c.field.name.clone()).collect(),
+            }),
+            1 => Ok(matches.remove(0).clone()),
+            _ => Err(ValidationError::AmbiguousColumn(column_name(col))),
+        }
+    }
+}
+
+fn column_name(col: &ColumnRef) -> String {
+    match &col.table {
+        Some(table) => format!("{}.{}", table, col.column),
+        None => col.column.clone(),
+    }
+}
+
+/// State of one validation run
+struct Check<'a> {
+    schemas: &'a dyn SchemaProvider,
+    functions: &'a dyn FunctionResolver,
+    lets: HashMap<String, Relation>,
+    location: OpLocation,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> Check<'a> {
+    /// Check every binding and the main pipeline, returning the output schema
+    /// and the errors found
+    fn run(
+        schemas: &'a dyn SchemaProvider,
+        functions: &'a dyn FunctionResolver,
+        program: &Program,
+    ) -> (Schema, Vec<ValidationError>) {
+        let mut check = Check {
+            schemas,
+            functions,
+            lets: HashMap::new(),
+            location: OpLocation::default(),
+            errors: Vec::new(),
+        };
+
+        for (i, binding) in program.lets.iter().enumerate() {
+            let relation = check.pipeline(&binding.pipeline, Some(Some(i)));
+            check.lets.insert(binding.name.clone(), relation);
+        }
+        let relation = check.pipeline(&program.pipeline, Some(None));
+        (relation.schema(), check.errors)
+    }
+
+    fn error(&mut self, error: ValidationError) {
+        self.errors.push(ValidationError::At { location: self.location, error: Box::new(error) });
+    }
+
+    /// Check a pipeline. Top-level pipelines pass the binding they belong to
+    /// and move the error location along; nested ones keep the enclosing
+    /// operator's location.
+    fn pipeline(&mut self, pipeline: &Pipeline, binding: Option<Option<usize>>) -> Relation {
+        if let Some(binding) = binding {
+            self.location = OpLocation { binding, op: None };
+        }
+        let mut relation = self.source(&pipeline.source);
+        for (i, op) in pipeline.ops.iter().enumerate() {
+            if binding.is_some() {
+                self.location.op = Some(i);
+            }
+            relation = self.operator(op, relation);
+        }
+        relation
+    }
+
+    fn source(&mut self, source: &Source) -> Relation {
+        match source {
+            Source::Table { name, alias } => {
+                let qualifier = Some(alias.as_deref().unwrap_or(name));
+                if let Some(relation) = self.lets.get(name) {
+                    return relation.clone().qualified(qualifier);
+                }
+                match self.schemas.get_table_schema(name) {
+                    Ok(table) => {
+                        let columns = table.columns.into_iter()
+                            .map(|c| Column {
+                                table: None,
+                                field: FieldType {
+                                    name: c.name,
+                                    data_type: c.data_type,
+                                    nullable: c.nullable,
+                                },
+                            })
+                            .collect();
+                        Relation { columns, ..Default::default() }.qualified(qualifier)
+                    }
+                    Err(_) => {
+                        self.error(ValidationError::TableNotFound(name.clone()));
+                        Relation::opaque()
+                    }
+                }
+            }
+            Source::Graph { .. } => Relation::opaque(),
+            Source::SubPipeline { pipeline, alias } => {
+                self.pipeline(pipeline, None).qualified(alias.as_deref())
+            }
+        }
+    }
+
+    fn operator(&mut self, op: &Operator, input: Relation) -> Relation {
+        match op {
+            Operator::Select { projections } => {
+                let mut output = Relation::default();
+                for (idx, projection) in projections.iter().enumerate() {
+                    match projection {
+                        Projection::Expr(Expr::Column { col }) if col.column == "*" => {
+                            let before = output.columns.len();
+                            output.columns.extend(input.columns.iter()
+                                .filter(|c| col.table.is_none() || c.table == col.table)
+                                .cloned());
+                            output.opaque |= input.opaque;
+                            if output.columns.len() == before && col.table.is_some() && !input.opaque {
+                                self.error(ValidationError::ColumnNotFound {
+                                    column: column_name(col),
+                                    available: input.columns.iter().map(|c| c.field.name.clone()).collect(),
+                                });
+                            }
+                        }
+                        Projection::Expr(Expr::Column { col }) => match input.resolve(col) {
+                            Ok(column) => output.columns.push(column),
+                            Err(e) => {
+                                self.error(e);
+                                output.set(unknown_field(&col.column));
+                            }
+                        },
+                        Projection::Expr(expr) => {
+                            let field = self.field(&input, &format!("expr_{}", idx), expr);
+                            output.set(field);
+                        }
+                        Projection::Aliased { expr, alias } => {
+                            let field = self.field(&input, alias, expr);
+                            output.set(field);
+                        }
+                    }
+                }
+                output
+            }
+            Operator::Filter { condition } => {
+                self.condition(&input, condition, "filter");
+                input
+            }
+            Operator::Join { source, on, join_type } => {
+                let right = self.source(source);
+                let (left_nullable, right_nullable) = match join_type {
+                    Some(JoinType::Left) => (false, true),
+                    Some(JoinType::Right) => (true, false),
+                    Some(JoinType::Full) => (true, true),
+                    _ => (false, false),
+                };
+
+                let mut combined = Relation {
+                    columns: Vec::new(),
+                    opaque: input.opaque || right.opaque,
+                    ungrouped: Vec::new(),
+                };
+                combined.columns.extend(input.columns.iter().cloned().map(|c| nullable_if(c, left_nullable)));
+                combined.columns.extend(right.columns.iter().cloned().map(|c| nullable_if(c, right_nullable)));
+                self.condition(&combined, on, "join condition");
+
+                match join_type {
+                    // Semi and anti joins only filter the left side
+                    Some(JoinType::Semi) | Some(JoinType::Anti) => input,
+                    _ => combined,
+                }
+            }
+            Operator::GroupBy { keys, aggs } => {
+                let mut output = Relation { opaque: input.opaque, ..Default::default() };
+                for key in keys {
+                    match input.resolve(key) {
+                        Ok(column) => output.columns.push(column),
+                        Err(e) => self.error(e),
+                    }
+                }
+                for (name, agg) in sorted(aggs) {
+                    let field = self.aggregate(&input, name, agg);
+                    output.set(field);
+                }
+                output.ungrouped = input.columns.iter()
+                    .map(|c| c.field.name.clone())
+                    .filter(|name| !keys.iter().any(|k| &k.column == name))
+                    .collect();
+                output
+            }
+            Operator::Window { windows } => {
+                let mut output = input.clone();
+                for (name, def) in sorted(windows) {
+                    let field = self.window(&input, name, def);
+                    output.set(field);
+                }
+                output
+            }
+            Operator::Sort { keys } => {
+                for key in keys {
+                    self.expr(&input, &key.expr);
+                }
+                input
+            }
+            Operator::Take { limit } => {
+                if *limit < 0 {
+                    self.invalid("limit", format!("{} is negative", limit));
+                }
+                input
+            }
+            Operator::Distinct => input,
+            Operator::Union { source, .. } => {
+                let right = self.source(source);
+                self.set_operation("union", input, right, |left, right| left || right)
+            }
+            Operator::Except { source } => {
+                let right = self.source(source);
+                self.set_operation("except", input, right, |left, _| left)
+            }
+            Operator::Intersect { source } => {
+                let right = self.source(source);
+                self.set_operation("intersect", input, right, |left, right| left && right)
+            }
+            Operator::Map { mappings } => {
+                let mut output = input.clone();
+                for (name, expr) in sorted(mappings) {
+                    let field = self.field(&input, name, expr);
+                    output.set(field);
+                }
+                output
+            }
+            Operator::Expand { expr, alias } => {
+                let element = match self.expr(&input, expr) {
+                    DataType::Array(element) => *element,
+                    DataType::Vector(_) => DataType::Float32,
+                    t if t.is_unknown_or_null() => DataType::Unknown,
+                    other => {
+                        self.mismatch("expand", "an array", other);
+                        DataType::Unknown
+                    }
+                };
+                let name = match (alias, expr) {
+                    (Some(alias), _) => alias.clone(),
+                    (None, Expr::Column { col }) => col.column.clone(),
+                    (None, _) => "unnest".to_string(),
+                };
+                let mut output = input;
+                output.set(FieldType { name, data_type: element, nullable: true });
+                output
+            }
+            Operator::Resample { interval, method, on, columns } => {
+                self.interval("resample interval", interval);
+                if !RESAMPLE_METHODS.contains(&method.as_str()) {
+                    self.invalid("resample method", format!("'{}' is not one of {}", method, RESAMPLE_METHODS.join(", ")));
+                }
+                match input.resolve(on) {
+                    Ok(column) => self.expect_temporal("resample on", column.field.data_type),
+                    Err(e) => self.error(e),
+                }
+
+                // Forward filling keeps every value as it was
+                let filled: Vec<String> = match columns {
+                    _ if method == "ffill" => Vec::new(),
+                    Some(columns) => columns.clone(),
+                    None => input.columns.iter()
+                        .filter(|c| c.field.name != on.column && c.field.data_type.is_numeric())
+                        .map(|c| c.field.name.clone())
+                        .collect(),
+                };
+                let mut output = input;
+                for name in filled {
+                    let data_type = match output.resolve(&ColumnRef { table: None, column: name.clone() }) {
+                        Ok(column) => column.field.data_type,
+                        Err(e) => {
+                            self.error(e);
+                            continue;
+                        }
+                    };
+                    if !data_type.is_numeric() && !data_type.is_unknown_or_null() {
+                        self.mismatch("resample column", "a number", data_type);
+                    } else if method == "linear" {
+                        // Interpolated between the values around each gap
+                        for column in output.columns.iter_mut().filter(|c| c.field.name == name) {
+                            column.field.data_type = DataType::Float64;
+                        }
+                    }
+                }
+                output
+            }
+            Operator::Agg { group_key, aggs } => {
+                let (expr, intervals) = match group_key {
+                    GroupKey::Tumbling { expr, interval } => (expr, vec![interval]),
+                    GroupKey::Hopping { expr, size, slide } => (expr, vec![size, slide]),
+                    GroupKey::Session { expr, gap } => (expr, vec![gap]),
+                };
+                for interval in intervals {
+                    self.interval("window interval", interval);
+                }
+                let key_type = self.expr(&input, expr);
+                self.expect_temporal("agg by", key_type.clone());
+
+                let key_name = match expr {
+                    Expr::Column { col } => col.column.clone(),
+                    _ => "bucket".to_string(),
+                };
+                let mut output = Relation { opaque: input.opaque, ..Default::default() };
+                output.set(FieldType { name: key_name, data_type: key_type, nullable: false });
+                for (name, agg) in sorted(aggs) {
+                    let field = self.aggregate(&input, name, agg);
+                    output.set(field);
+                }
+                output.ungrouped = input.columns.iter().map(|c| c.field.name.clone()).collect();
+                output
+            }
+            Operator::Knn { query, k, on, index, metric } => {
+                match self.expr(&input, query) {
+                    DataType::Vector(_) | DataType::Array(_) | DataType::Unknown => {}
+                    other => self.mismatch("knn query", "a vector", other),
+                }
+                if *k < 1 {
+                    self.invalid("k", format!("{} is not positive", k));
+                }
+                if let Some(metric) = metric.as_deref().filter(|m| !KNN_METRICS.contains(m)) {
+                    self.invalid("knn metric", format!("'{}' is not one of {}", metric, KNN_METRICS.join(", ")));
+                }
+
+                match on {
+                    Some(on) => match input.resolve(on) {
+                        Ok(column) => match column.field.data_type {
+                            DataType::Vector(_) | DataType::Unknown => {}
+                            other => self.mismatch("knn on", "a vector", other),
+                        },
+                        Err(e) => self.error(e),
+                    },
+                    // Only the database knows which column an index covers
+                    None if index.is_some() || input.opaque => {}
+                    None => {
+                        let vectors: Vec<&str> = input.columns.iter()
+                            .filter(|c| matches!(c.field.data_type, DataType::Vector(_)))
+                            .map(|c| c.field.name.as_str())
+                            .collect();
+                        match vectors.len() {
+                            1 => {}
+                            0 => self.invalid("knn on", "the input has no vector column".to_string()),
+                            _ => self.invalid("knn on", format!(
+                                "the input has several vector columns ({}); choose one with on:",
+                                vectors.join(", ")
+                            )),
+                        }
+                    }
+                }
+
+                // Distances of FLOAT[n] embeddings are FLOATs
+                let mut output = input;
+                output.set(FieldType { name: "distance".to_string(), data_type: DataType::Float32, nullable: true });
+                output
+            }
+            Operator::Rank { by } => {
+                let score = self.expr(&input, by);
+                if !score.is_numeric() && !score.is_unknown_or_null() {
+                    self.mismatch("rank by", "a numeric score", score.clone());
+                }
+                let mut output = input;
+                output.set(FieldType { name: "score".to_string(), data_type: score, nullable: true });
+                output
+            }
+            Operator::Neighbors { start, depth, .. } => {
+                self.expr(&input, start);
+                if *depth < 0 {
+                    self.invalid("depth", format!("{} is negative", depth));
+                } else if *depth > MAX_NEIGHBORS_DEPTH {
+                    self.invalid("depth", format!("{} is more than {}", depth, MAX_NEIGHBORS_DEPTH));
+                }
+                // Hops from the start node and the node ids along the way
+                let mut output = input;
+                output.set(FieldType { name: "hops".to_string(), data_type: DataType::Int32, nullable: false });
+                output.set(FieldType { name: "path".to_string(), data_type: DataType::Array(Box::new(DataType::Unknown)), nullable: false });
+                output
+            }
+            Operator::TopK { k, by } => {
+                self.expr(&input, by);
+                if *k < 0 {
+                    self.invalid("k", format!("{} is negative", k));
+                }
+                input
+            }
+            Operator::Sample { fraction, .. } => {
+                if !(*fraction > 0.0 && *fraction <= 1.0) {
+                    self.invalid("sample fraction", format!("{} is not in (0, 1]", fraction));
+                }
+                input
+            }
+            Operator::Assert { condition, .. } => {
+                self.condition(&input, condition, "assert");
+                input
+            }
+            Operator::Explain { .. } => {
+                let text = |name: &str| Column {
+                    table: None,
+                    field: FieldType { name: name.to_string(), data_type: DataType::String, nullable: false },
+                };
+                Relation { columns: vec![text("explain_key"), text("explain_value")], ..Default::default() }
+            }
+        }
+    }
+
+    /// Combine two inputs column by column: names come from the left, types
+    /// are coerced to a common supertype
+    fn set_operation(
+        &mut self,
+        context: &str,
+        left: Relation,
+        right: Relation,
+        nullable: impl Fn(bool, bool) -> bool,
+    ) -> Relation {
+        let mut output = Relation { opaque: left.opaque || right.opaque, ..Default::default() };
+        if output.opaque {
+            output.columns = left.columns;
+            return output;
+        }
+        if left.columns.len() != right.columns.len() {
+            self.error(ValidationError::ColumnCountMismatch {
+                context: context.to_string(),
+                left: left.columns.len(),
+                right: right.columns.len(),
+            });
+            output.columns = left.columns;
+            return output;
+        }
+
+        for (l, r) in left.columns.into_iter().zip(right.columns) {
+            let data_type = match l.field.data_type.common_supertype(&r.field.data_type) {
+                Some(common) => common,
+                None => {
+                    self.error(ValidationError::IncompatibleTypes {
+                        context: format!("{} column '{}'", context, l.field.name),
+                        left: l.field.data_type,
+                        right: r.field.data_type,
+                    });
+                    DataType::Unknown
+                }
+            };
+            output.columns.push(Column {
+                table: None,
+                field: FieldType {
+                    name: l.field.name,
+                    data_type,
+                    nullable: nullable(l.field.nullable, r.field.nullable),
+                },
+            });
+        }
+        output
+    }
+
+    /// Output column `name` computed by `expr`
+    fn field(&mut self, input: &Relation, name: &str, expr: &Expr) -> FieldType {
+        FieldType {
+            name: name.to_string(),
+            data_type: self.expr(input, expr),
+            nullable: nullable(input, expr),
+        }
+    }
+
+    fn aggregate(&mut self, input: &Relation, name: &str, agg: &AggCall) -> FieldType {
+        let args: Vec<DataType> = agg.args.iter().map(|a| self.expr(input, a)).collect();
+        let data_type = match self.functions.resolve_function(&agg.func, &args) {
+            Ok(func) if func.is_aggregate => func.return_type,
+            Ok(_) => {
+                self.error(ValidationError::NotAnAggregate(agg.func.clone()));
+                DataType::Unknown
+            }
+            Err(e) => {
+                self.error(e);
+                DataType::Unknown
+            }
+        };
+        // Aggregates over no rows are NULL, except count
+        let nullable = !agg.func.eq_ignore_ascii_case("count");
+        FieldType { name: name.to_string(), data_type, nullable }
+    }
+
+    fn window(&mut self, input: &Relation, name: &str, def: &WindowDef) -> FieldType {
+        let args: Vec<DataType> = def.args.iter().map(|a| self.expr(input, a)).collect();
+        for col in def.partition.iter().flatten() {
+            if let Err(e) = input.resolve(col) {
+                self.error(e);
+            }
+        }
+        for key in def.order.iter().flatten() {
+            self.expr(input, &key.expr);
+        }
+
+        let data_type = match self.functions.resolve_function(&def.func, &args) {
+            Ok(func) if func.is_window || func.is_aggregate => func.return_type,
+            Ok(_) => {
+                self.error(ValidationError::NotAWindowFunction(def.func.clone()));
+                DataType::Unknown
+            }
+            Err(e) => {
+                self.error(e);
+                DataType::Unknown
+            }
+        };
+        FieldType { name: name.to_string(), data_type, nullable: true }
+    }
+
+    fn condition(&mut self, input: &Relation, condition: &Expr, context: &str) {
+        let data_type = self.expr(input, condition);
+        self.expect_bool(context, data_type);
+    }
+
+    fn expect_bool(&mut self, context: &str, data_type: DataType) {
+        if data_type != DataType::Bool && !data_type.is_unknown_or_null() {
+            self.mismatch(context, "Bool", data_type);
+        }
+    }
+
+    fn expect_temporal(&mut self, context: &str, data_type: DataType) {
+        if !data_type.is_temporal() && !data_type.is_unknown_or_null() {
+            self.mismatch(context, "a date or timestamp", data_type);
+        }
+    }
+
+    fn mismatch(&mut self, context: &str, expected: &str, actual: DataType) {
+        self.error(ValidationError::TypeMismatch {
+            context: context.to_string(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+
+    fn invalid(&mut self, what: &str, message: String) {
+        self.error(ValidationError::InvalidArgument { what: what.to_string(), message });
+    }
+
+    /// Intervals are a positive count followed by `s`, `m`, `h` or `d`
+    fn interval(&mut self, what: &str, interval: &str) {
+        let valid = interval.len() > 1
+            && interval.ends_with(['s', 'm', 'h', 'd'])
+            && interval[..interval.len() - 1].parse::<u64>().is_ok_and(|n| n > 0);
+        if !valid {
+            self.invalid(what, format!("'{}' is not a count followed by s, m, h or d", interval));
+        }
+    }
+
+    /// Infer the type of `expr`, recording any problems.
+    ///
+    /// Returns `Unknown` for expressions that failed to check, so one mistake
+    /// is reported once rather than at every enclosing expression.
+    fn expr(&mut self, input: &Relation, expr: &Expr) -> DataType {
+        match expr {
+            Expr::Literal { value } => value_type(value),
+            Expr::Column { col } => match input.resolve(col) {
+                Ok(column) => column.field.data_type,
+                Err(e) => {
+                    self.error(e);
+                    DataType::Unknown
+                }
+            },
+            Expr::BinaryOp { op, left, right } => {
+                let left = self.expr(input, left);
+                let right = self.expr(input, right);
+                self.binary_op(op, left, right)
+            }
+            Expr::UnaryOp { op: UnOp::Not, expr } => {
+                let operand = self.expr(input, expr);
+                self.expect_bool("`!`", operand);
+                DataType::Bool
+            }
+            Expr::UnaryOp { op: UnOp::Neg, expr } => {
+                let operand = self.expr(input, expr);
+                if operand.is_numeric() || operand == DataType::Interval || operand.is_unknown_or_null() {
+                    operand
+                } else {
+                    self.mismatch("`-`", "a number", operand);
+                    DataType::Unknown
+                }
+            }
+            Expr::FuncCall { func, args } => {
+                let args: Vec<DataType> = args.iter().map(|a| self.expr(input, a)).collect();
+                match self.functions.resolve_function(func, &args) {
+                    Ok(resolved) if resolved.is_aggregate || resolved.is_window => {
+                        self.error(ValidationError::MisplacedAggregate(func.clone()));
+                        DataType::Unknown
+                    }
+                    Ok(resolved) => resolved.return_type,
+                    Err(e) => {
+                        self.error(e);
+                        DataType::Unknown
+                    }
+                }
+            }
+            Expr::FieldAccess { expr, field } => match self.expr(input, expr) {
+                DataType::Struct(fields) => match fields.iter().find(|f| &f.name == field) {
+                    Some(f) => f.data_type.clone(),
+                    None => {
+                        let data_type = DataType::Struct(fields);
+                        self.error(ValidationError::UnknownField { field: field.clone(), data_type });
+                        DataType::Unknown
+                    }
+                },
+                DataType::Map { value, .. } => *value,
+                DataType::Unknown => DataType::Unknown,
+                other => {
+                    self.mismatch("field access", "a struct", other);
+                    DataType::Unknown
+                }
+            },
+            Expr::Index { expr, index } => {
+                let base = self.expr(input, expr);
+                let index = self.expr(input, index);
+                let (element, key) = match base {
+                    DataType::Array(element) => (*element, DataType::Int64),
+                    DataType::Vector(_) => (DataType::Float32, DataType::Int64),
+                    DataType::Map { key, value } => (*value, *key),
+                    DataType::String | DataType::Varchar(_) => (DataType::String, DataType::Int64),
+                    DataType::Unknown => return DataType::Unknown,
+                    other => {
+                        self.mismatch("index", "an array, map or string", other);
+                        return DataType::Unknown;
+                    }
+                };
+                if index.common_supertype(&key).is_none() {
+                    self.mismatch("index", &format!("{:?}", key), index);
+                }
+                element
+            }
+            Expr::Array { elements } => {
+                let types: Vec<DataType> = elements.iter().map(|e| self.expr(input, e)).collect();
+                DataType::Array(Box::new(self.unify("array", types)))
+            }
+            Expr::Object { fields } => {
+                let fields = sorted(fields).into_iter()
+                    .map(|(name, e)| self.field(input, name, e))
+                    .collect();
+                DataType::Struct(fields)
+            }
+            Expr::Vector { values } => DataType::Vector(Some(values.len())),
+            Expr::InRange { expr, start, end, .. } => {
+                let value = self.expr(input, expr);
+                for bound in [start, end] {
+                    let bound = self.expr(input, bound);
+                    self.comparable("`in` range", &value, bound);
+                }
+                DataType::Bool
+            }
+            Expr::InSet { expr, set } => {
+                let value = self.expr(input, expr);
+                for item in set {
+                    let item = self.expr(input, item);
+                    self.comparable("`in` set", &value, item);
+                }
+                DataType::Bool
+            }
+        }
+    }
+
+    fn binary_op(&mut self, op: &BinOp, left: DataType, right: DataType) -> DataType {
+        let symbol = format!("`{}`", binop_symbol(op));
+        match op {
+            BinOp::And | BinOp::Or => {
+                self.expect_bool(&symbol, left);
+                self.expect_bool(&symbol, right);
+                DataType::Bool
+            }
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                self.comparable(&symbol, &left, right);
+                DataType::Bool
+            }
+            BinOp::Like | BinOp::ILike => {
+                for operand in [left, right] {
+                    if !operand.is_string() && !operand.is_unknown_or_null() {
+                        self.mismatch(&symbol, "a string", operand);
+                    }
+                }
+                DataType::Bool
+            }
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                if left == DataType::Unknown || right == DataType::Unknown {
+                    return DataType::Unknown;
+                }
+                match (op, &left, &right) {
+                    (BinOp::Div, l, r) if l.is_integer() && r.is_integer() => DataType::Float64,
+                    (_, l, r) if (l.is_numeric() || *l == DataType::Null) && (r.is_numeric() || *r == DataType::Null) => {
+                        l.common_supertype(r).unwrap_or(DataType::Unknown)
+                    }
+                    // Date and time arithmetic
+                    (BinOp::Add | BinOp::Sub, t, DataType::Interval) if t.is_temporal() => t.clone(),
+                    (BinOp::Add, DataType::Interval, t) if t.is_temporal() => t.clone(),
+                    (BinOp::Add | BinOp::Sub, DataType::Interval, DataType::Interval) => DataType::Interval,
+                    (BinOp::Sub, DataType::Date, DataType::Date) => DataType::Int64,
+                    (BinOp::Sub, l, r) if l.is_temporal() && l.common_supertype(r).is_some() => DataType::Interval,
+                    _ => {
+                        self.error(ValidationError::IncompatibleTypes { context: symbol, left, right });
+                        DataType::Unknown
+                    }
+                }
+            }
+        }
+    }
+
+    fn comparable(&mut self, context: &str, left: &DataType, right: DataType) {
+        if left.common_supertype(&right).is_none() {
+            self.error(ValidationError::IncompatibleTypes {
+                context: context.to_string(),
+                left: left.clone(),
+                right,
+            });
+        }
+    }
+
+    /// Common type of a list of values, e.g. array elements
+    fn unify(&mut self, context: &str, types: Vec<DataType>) -> DataType {
+        let mut types = types.into_iter();
+        let Some(mut common) = types.next() else {
+            return DataType::Unknown;
+        };
+        for t in types {
+            match common.common_supertype(&t) {
+                Some(next) => common = next,
+                None => {
+                    self.error(ValidationError::IncompatibleTypes { context: context.to_string(), left: common, right: t });
+                    return DataType::Unknown;
+                }
+            }
+        }
+        common
+    }
+}
+
+fn nullable_if(mut column: Column, nullable: bool) -> Column {
+    column.field.nullable |= nullable;
+    column
+}
+
+fn unknown_field(name: &str) -> FieldType {
+    FieldType { name: name.to_string(), data_type: DataType::Unknown, nullable: true }
+}
+
+/// Whether `expr` can evaluate to NULL
+fn nullable(input: &Relation, expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { value } => matches!(value, Value::Null),
+        Expr::Column { col } => input.resolve(col).map_or(true, |c| c.field.nullable),
+        Expr::BinaryOp { left, right, .. } => nullable(input, left) || nullable(input, right),
+        Expr::UnaryOp { expr, .. } => nullable(input, expr),
+        Expr::Array { .. } | Expr::Object { .. } | Expr::Vector { .. } => false,
+        _ => true,
+    }
+}
+
+fn value_type(value: &Value) -> DataType {
+    match value {
+        Value::Null => DataType::Null,
+        Value::Bool(_) => DataType::Bool,
+        Value::Int(_) => DataType::Int64,
+        Value::Float(_) => DataType::Float64,
+        Value::String(_) => DataType::String,
+        Value::Bytes(_) => DataType::Blob,
+        Value::Date(_) => DataType::Date,
+        Value::Time(_) => DataType::Time,
+        Value::Timestamp(_) => DataType::Timestamp,
+        Value::Array(values) => {
+            let element = values.iter()
+                .map(value_type)
+                .try_fold(DataType::Null, |common, t| common.common_supertype(&t))
+                .unwrap_or(DataType::Unknown);
+            DataType::Array(Box::new(element))
+        }
+        Value::Object(fields) => DataType::Struct(
+            sorted(fields).into_iter()
+                .map(|(name, v)| FieldType {
+                    name: name.clone(),
+                    data_type: value_type(v),
+                    nullable: matches!(v, Value::Null),
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn binop_symbol(op: &BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Mod => "%",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+        BinOp::And => "&&",
+        BinOp::Or => "||",
+        BinOp::Like => "like",
+        BinOp::ILike => "ilike",
+    }
+}
+
+/// Map entries in name order, so errors and output columns are deterministic
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::substrait::{ColumnInfo, MockSchemaProvider, TableSchema};
+
+    /// A few functions with fixed signatures; `Unknown` arguments accept anything
+    struct TestFunctions;
+
+    impl FunctionResolver for TestFunctions {
+        fn resolve_function(&self, name: &str, args: &[DataType]) -> Result<FunctionType, ValidationError> {
+            let (params, return_type, is_aggregate) = match name {
+                "count" => (vec![], DataType::Int64, true),
+                "sum" => (vec![DataType::Unknown], DataType::Float64, true),
+                "lower" => (vec![DataType::String], DataType::String, false),
+                "bm25" => (vec![DataType::String, DataType::String], DataType::Float64, false),
+                _ => return Err(ValidationError::FunctionNotFound(name.to_string())),
+            };
+            let matches = params.len() == args.len()
+                && params.iter().zip(args).all(|(p, a)| *p == DataType::Unknown || a.common_supertype(p).is_some());
+            if !matches {
+                return Err(ValidationError::InvalidArguments {
+                    func: name.to_string(),
+                    message: format!("got {:?}", args),
+                });
+            }
+            Ok(FunctionType { return_type, is_aggregate, is_window: false })
+        }
+    }
+
+    fn provider() -> MockSchemaProvider {
+        let column = |name: &str, data_type: DataType, nullable: bool| ColumnInfo {
+            name: name.to_string(),
+            data_type,
+            nullable,
+        };
+        let mut provider = MockSchemaProvider::new();
+        provider.add_table(TableSchema {
+            name: "users".to_string(),
+            columns: vec![
+                column("id", DataType::Int32, false),
+                column("name", DataType::String, true),
+                column("age", DataType::Int32, true),
+                column("signup", DataType::Date, true),
+            ],
+        });
+        provider.add_table(TableSchema {
+            name: "docs".to_string(),
+            columns: vec![
+                column("id", DataType::Int32, false),
+                column("title", DataType::String, true),
+                column("embedding", DataType::Vector(Some(3)), true),
+            ],
+        });
+        provider.add_table(TableSchema {
+            name: "orders".to_string(),
+            columns: vec![
+                column("id", DataType::Int32, false),
+                column("user_id", DataType::Int32, false),
+                column("amount", DataType::Decimal { precision: 10, scale: 2 }, true),
+            ],
+        });
+        provider
+    }
+
+    fn validate(json: &str) -> Result<Schema, ValidationErrors> {
+        let program: Program = serde_json::from_str(json).unwrap();
+        Validator::new(&provider(), &TestFunctions).validate(&program)
+    }
+
+    fn col(name: &str) -> String {
+        match name.split_once('.') {
+            Some((table, column)) => format!(r#"{{"type": "Column", "col": {{"table": "{}", "column": "{}"}}}}"#, table, column),
+            None => format!(r#"{{"type": "Column", "col": {{"column": "{}"}}}}"#, name),
+        }
+    }
+
+    #[test]
+    fn test_output_schema_of_join_and_group_by() {
+        let schema = validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users", "alias": "u"}},
+            "ops": [
+                {{"op": "Join", "source": {{"type": "Table", "name": "orders", "alias": "o"}},
+                  "on": {{"type": "BinaryOp", "op": "Eq", "left": {}, "right": {}}}, "join_type": "Left"}},
+                {{"op": "GroupBy", "keys": [{{"table": "u", "column": "name"}}],
+                  "aggs": {{"total": {{"func": "sum", "args": [{}]}}, "n": {{"func": "count"}}}}}},
+                {{"op": "Map", "mappings": {{"shout": {{"type": "FuncCall", "func": "lower", "args": [{}]}}}}}}
+            ]
+        }}}}"#, col("u.id"), col("o.user_id"), col("o.amount"), col("name"))).unwrap();
+
+        let fields: Vec<(&str, &DataType, bool)> = schema.fields.iter()
+            .map(|f| (f.name.as_str(), &f.data_type, f.nullable))
+            .collect();
+        assert_eq!(fields, vec![
+            ("name", &DataType::String, true),
+            ("n", &DataType::Int64, false),
+            ("total", &DataType::Float64, true),
+            ("shout", &DataType::String, true),
+        ]);
+    }
+
+    #[test]
+    fn test_reports_every_error_with_its_location() {
+        let errors = validate(&format!(r#"{{
+            "lets": [{{"name": "adults", "pipeline": {{
+                "source": {{"type": "Table", "name": "users"}},
+                "ops": [{{"op": "Filter", "condition": {{"type": "BinaryOp", "op": "Add", "left": {}, "right": {{"type": "Literal", "value": 1}}}}}}]
+            }}}}],
+            "pipeline": {{
+                "source": {{"type": "Table", "name": "adults"}},
+                "ops": [
+                    {{"op": "Filter", "condition": {{"type": "BinaryOp", "op": "Gt", "left": {}, "right": {{"type": "Literal", "value": "x"}}}}}},
+                    {{"op": "GroupBy", "keys": [{{"column": "name"}}], "aggs": {{"n": {{"func": "lower", "args": [{}]}}}}}},
+                    {{"op": "Sort", "keys": [{{"expr": {}}}, {{"expr": {}}}]}},
+                    {{"op": "Join", "source": {{"type": "Table", "name": "missing"}}, "on": {}}}
+                ]
+            }}
+        }}"#, col("age"), col("agee"), col("name"), col("age"), col("n"), col("anything"))).unwrap_err();
+
+        let found: Vec<(String, String)> = errors.0.iter()
+            .map(|e| (e.location().unwrap().to_string(), e.root().to_string()))
+            .collect();
+        assert_eq!(found, vec![
+            ("lets[0].ops[0]".to_string(), "Type mismatch in filter: expected Bool, got Int64".to_string()),
+            ("pipeline.ops[0]".to_string(), "Column 'agee' not found. Available columns: id, name, age, signup".to_string()),
+            ("pipeline.ops[1]".to_string(), "lower is not an aggregate function".to_string()),
+            ("pipeline.ops[2]".to_string(), "Column 'age' must be a group by key or used in an aggregate".to_string()),
+            ("pipeline.ops[3]".to_string(), "Table not found: missing".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_type_errors_in_expressions() {
+        let errors = validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [
+                {{"op": "Filter", "condition": {{"type": "BinaryOp", "op": "Eq", "left": {}, "right": {{"type": "Literal", "value": true}}}}}},
+                {{"op": "Map", "mappings": {{"n": {{"type": "FuncCall", "func": "count", "args": []}}}}}},
+                {{"op": "Select", "projections": [{{"type": "FuncCall", "func": "lower", "args": [{}]}}]}}
+            ]
+        }}}}"#, col("id"), col("age"))).unwrap_err();
+
+        let roots: Vec<String> = errors.0.iter().map(|e| e.root().to_string()).collect();
+        assert_eq!(roots, vec![
+            "Incompatible types in `==`: Int32 and Bool",
+            "count can only be used in group by, agg or window",
+            "Invalid arguments for lower: got [Int32]",
+        ]);
+
+        // Date columns compare against date strings
+        validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [{{"op": "Filter", "condition": {{"type": "BinaryOp", "op": "Ge", "left": {}, "right": {{"type": "Literal", "value": "2024-01-01"}}}}}}]
+        }}}}"#, col("signup"))).unwrap();
+    }
+
+    #[test]
+    fn test_infer_schema_for_every_operator() {
+        let program: Program = serde_json::from_str(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [
+                {{"op": "Window", "windows": {{"rn": {{"func": "row_number", "order": [{{"expr": {}}}]}}, "prev": {{"func": "lag", "args": [{}]}}}}}},
+                {{"op": "Map", "mappings": {{"tags": {{"type": "Array", "elements": [{}, {{"type": "Literal", "value": "x"}}]}}, "half": {{"type": "BinaryOp", "op": "Div", "left": {}, "right": {{"type": "Literal", "value": 2}}}}}}}},
+                {{"op": "Expand", "expr": {}, "alias": "tag"}},
+                {{"op": "Union", "source": {{"type": "SubPipeline", "pipeline": {{"source": {{"type": "Table", "name": "users"}}, "ops": []}}}}}},
+                {{"op": "Knn", "query": {{"type": "Vector", "values": [0.1, 0.2]}}, "k": 5}},
+                {{"op": "Select", "projections": [{}, {}, {}, {}, {{"expr": {}, "alias": "unknown"}}]}}
+            ]
+        }}}}"#, col("age"), col("signup"), col("name"), col("id"), col("tags"), col("rn"), col("prev"), col("half"), col("tag"),
+            r#"{"type": "FuncCall", "func": "made_up", "args": []}"#)).unwrap();
+
+        let schema = infer_schema(&program, &provider());
+        let fields: Vec<(&str, &DataType, bool)> = schema.fields.iter()
+            .map(|f| (f.name.as_str(), &f.data_type, f.nullable))
+            .collect();
+        assert_eq!(fields, vec![
+            ("rn", &DataType::Int64, true),
+            ("prev", &DataType::Date, true),
+            ("half", &DataType::Float64, false),
+            ("tag", &DataType::String, true),
+            ("unknown", &DataType::Unknown, true),
+        ]);
+
+        // Missing tables give an empty schema rather than an error
+        let program: Program = serde_json::from_str(r#"{"pipeline": {"source": {"type": "Table", "name": "nope"}, "ops": []}}"#).unwrap();
+        assert!(infer_schema(&program, &provider()).fields.is_empty());
+    }
+
+    #[test]
+    fn test_knn() {
+        let knn = |source: &str, options: &str| validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "{}"}},
+            "ops": [{{"op": "Knn", "query": {{"type": "Vector", "values": [0.1, 0.2, 0.3]}}, "k": 3{}}}]
+        }}}}"#, source, options));
+
+        // The input's only vector column is compared, and the distance is added
+        let schema = knn("docs", r#", "metric": "cosine""#).unwrap();
+        let names: Vec<_> = schema.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "title", "embedding", "distance"]);
+        assert_eq!(schema.fields[3].data_type, DataType::Float32);
+        assert!(knn("docs", r#", "on": {"column": "embedding"}"#).is_ok());
+        assert!(knn("users", r#", "index": "users_hnsw""#).is_ok());
+
+        let errors = knn("users", r#", "metric": "manhattan""#).unwrap_err();
+        assert_eq!(errors.0.len(), 2);
+        assert_eq!(errors.0[0].root().to_string(), "Invalid knn metric: 'manhattan' is not one of l2, cosine, ip");
+        assert_eq!(errors.0[1].root().to_string(), "Invalid knn on: the input has no vector column");
+        let errors = knn("docs", r#", "on": {"column": "title"}"#).unwrap_err();
+        assert!(matches!(errors.0[0].root(), ValidationError::TypeMismatch { actual: DataType::String, .. }));
+    }
+
+    #[test]
+    fn test_rank_adds_score() {
+        let rank = |by: &str| validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "docs"}},
+            "ops": [{{"op": "Rank", "by": {}}}]
+        }}}}"#, by));
+
+        let bm25 = format!(
+            r#"{{"type": "FuncCall", "func": "bm25", "args": [{}, {{"type": "Literal", "value": "vector"}}]}}"#,
+            col("title")
+        );
+        let schema = rank(&bm25).unwrap();
+        let score = schema.fields.last().unwrap();
+        assert_eq!((score.name.as_str(), &score.data_type), ("score", &DataType::Float64));
+
+        let errors = rank(&col("title")).unwrap_err();
+        assert!(matches!(errors.0[0].root(), ValidationError::TypeMismatch { actual: DataType::String, .. }));
+    }
+
+    #[test]
+    fn test_neighbors_adds_hops_and_path() {
+        let schema = validate(r#"{"pipeline": {
+            "source": {"type": "Table", "name": "users"},
+            "ops": [{"op": "Neighbors", "start": {"type": "Literal", "value": 1}, "depth": 2}]
+        }}"#).unwrap();
+        let names: Vec<_> = schema.fields.iter().rev().take(2).map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["path", "hops"]);
+        assert_eq!(schema.fields[schema.fields.len() - 2].data_type, DataType::Int32);
+
+        let errors = validate(r#"{"pipeline": {
+            "source": {"type": "Table", "name": "users"},
+            "ops": [{"op": "Neighbors", "start": {"type": "Literal", "value": 1}, "depth": 11}]
+        }}"#).unwrap_err();
+        assert_eq!(errors.0[0].root().to_string(), "Invalid depth: 11 is more than 10");
+    }
+
+    #[test]
+    fn test_resample_fill_columns() {
+        let resample = |method: &str, columns: &str| validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [{{"op": "Resample", "interval": "1d", "method": "{}", "on": {{"column": "signup"}}{}}}]
+        }}}}"#, method, columns));
+        let types = |schema: Schema| schema.fields.into_iter().map(|f| f.data_type).collect::<Vec<_>>();
+
+        assert_eq!(types(resample("ffill", "").unwrap()), vec![DataType::Int32, DataType::String, DataType::Int32, DataType::Date]);
+        assert_eq!(types(resample("zero", "").unwrap()), vec![DataType::Int32, DataType::String, DataType::Int32, DataType::Date]);
+        // Numeric columns other than `on` are interpolated by default
+        assert_eq!(types(resample("linear", "").unwrap()), vec![DataType::Float64, DataType::String, DataType::Float64, DataType::Date]);
+        assert_eq!(
+            types(resample("linear", r#", "columns": ["age"]"#).unwrap()),
+            vec![DataType::Int32, DataType::String, DataType::Float64, DataType::Date]
+        );
+
+        let errors = resample("linear", r#", "columns": ["name"]"#).unwrap_err();
+        assert!(matches!(errors.0[0].root(), ValidationError::TypeMismatch { actual: DataType::String, .. }));
+        let errors = resample("spline", "").unwrap_err();
+        assert!(matches!(errors.0[0].root(), ValidationError::InvalidArgument { .. }));
+    }
+
+    #[test]
+    fn test_ambiguous_columns_after_join() {
+        let errors = validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [
+                {{"op": "Join", "source": {{"type": "Table", "name": "orders"}}, "on": {{"type": "BinaryOp", "op": "Eq", "left": {}, "right": {}}}}},
+                {{"op": "Select", "projections": [{}, {}]}}
+            ]
+        }}}}"#, col("users.id"), col("user_id"), col("id"), col("orders.id"))).unwrap_err();
+
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].root(), &ValidationError::AmbiguousColumn("id".to_string()));
+    }
+
+    #[test]
+    fn test_set_operations_coerce_column_types() {
+        let select = |table: &str, columns: [&str; 2]| format!(
+            r#"{{"type": "SubPipeline", "pipeline": {{"source": {{"type": "Table", "name": "{}"}}, "ops": [{{"op": "Select", "projections": [{}, {}]}}]}}}}"#,
+            table, col(columns[0]), col(columns[1]),
+        );
+
+        let schema = validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [
+                {{"op": "Select", "projections": [{}, {}]}},
+                {{"op": "Union", "all": true, "source": {}}}
+            ]
+        }}}}"#, col("id"), col("age"), select("orders", ["user_id", "amount"]))).unwrap();
+        let fields: Vec<(&str, &DataType, bool)> = schema.fields.iter()
+            .map(|f| (f.name.as_str(), &f.data_type, f.nullable))
+            .collect();
+        assert_eq!(fields, vec![
+            ("id", &DataType::Int32, false),
+            ("age", &DataType::Decimal { precision: 10, scale: 2 }, true),
+        ]);
+
+        let errors = validate(r#"{"pipeline": {
+            "source": {"type": "Table", "name": "users"},
+            "ops": [{"op": "Except", "source": {"type": "Table", "name": "orders"}}]
+        }}"#).unwrap_err();
+        assert_eq!(errors.0[0].root(), &ValidationError::ColumnCountMismatch {
+            context: "except".to_string(),
+            left: 4,
+            right: 3,
+        });
+
+        let errors = validate(&format!(r#"{{"pipeline": {{
+            "source": {{"type": "Table", "name": "users"}},
+            "ops": [
+                {{"op": "Select", "projections": [{}, {}]}},
+                {{"op": "Intersect", "source": {}}}
+            ]
+        }}}}"#, col("name"), col("id"), select("orders", ["id", "user_id"]))).unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].location(), Some(OpLocation { binding: None, op: Some(1) }));
+        assert_eq!(errors.0[0].root(), &ValidationError::IncompatibleTypes {
+            context: "intersect column 'name'".to_string(),
+            left: DataType::String,
+            right: DataType::Int32,
+        });
+    }
+}
@@ -81,6 +81,7 @@ fn test_substrait_json_execution() {
 fn test_mlql_ir_to_substrait_execution() {
     use mlql_ir::{Program, Pipeline, Source, Operator, Expr, Value, BinOp, ColumnRef};
     use mlql_ir::substrait::{SubstraitTranslator, MockSchemaProvider, TableSchema, ColumnInfo};
+    use mlql_ir::DataType;
     use prost::Message;
 
     // Setup connection
@@ -97,9 +98,9 @@ fn test_mlql_ir_to_substrait_execution() {
     schema_provider.add_table(TableSchema {
         name: "users".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "age".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -16,6 +16,7 @@
 //! Run with `--ignored` on Linux/Windows to execute these tests.
 
 use mlql_ir::substrait::{SubstraitTranslator, MockSchemaProvider, TableSchema, ColumnInfo};
+use mlql_ir::DataType;
 use mlql_ir::{Program, Pipeline, Source, Operator, Expr, Value, BinOp, ColumnRef, Projection, SortKey};
 use prost::Message;
 use std::fs;
@@ -84,17 +85,17 @@ fn test_table_scan_execution() {
         columns: vec![
             ColumnInfo {
                 name: "id".to_string(),
-                data_type: "INTEGER".to_string(),
+                data_type: DataType::Int32,
                 nullable: false,
             },
             ColumnInfo {
                 name: "name".to_string(),
-                data_type: "VARCHAR".to_string(),
+                data_type: DataType::String,
                 nullable: true,
             },
             ColumnInfo {
                 name: "age".to_string(),
-                data_type: "INTEGER".to_string(),
+                data_type: DataType::Int32,
                 nullable: true,
             },
         ],
@@ -144,9 +145,9 @@ fn test_filter_execution() {
     schema_provider.add_table(TableSchema {
         name: "users".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false },
-            ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "age".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
+            ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -202,9 +203,9 @@ fn test_select_execution() {
     schema_provider.add_table(TableSchema {
         name: "users".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false },
-            ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "age".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
+            ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -260,9 +261,9 @@ fn test_sort_execution() {
     schema_provider.add_table(TableSchema {
         name: "users".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: false },
-            ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "age".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: false },
+            ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -5,6 +5,7 @@
 use duckdb::Connection;
 use mlql_ir::{Program, Pipeline, Source, Operator};
 use mlql_ir::substrait::{SubstraitTranslator, MockSchemaProvider, TableSchema, ColumnInfo};
+use mlql_ir::DataType;
 use prost::Message;
 
 /// Load Substrait extension into test connection
@@ -36,9 +37,9 @@ fn setup_schema_provider() -> MockSchemaProvider {
     schema_provider.add_table(TableSchema {
         name: "users".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "age".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
     schema_provider
@@ -289,9 +290,9 @@ fn test_groupby() {
     schema_provider.add_table(TableSchema {
         name: "sales".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "product".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "amount".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "product".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "amount".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -371,16 +372,16 @@ fn test_join() {
     schema_provider.add_table(TableSchema {
         name: "users".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "name".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
         ],
     });
     schema_provider.add_table(TableSchema {
         name: "orders".to_string(),
         columns: vec![
-            ColumnInfo { name: "order_id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "user_id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "amount".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "order_id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "user_id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "amount".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -520,9 +521,9 @@ fn test_all_aggregates() {
     schema_provider.add_table(TableSchema {
         name: "sales".to_string(),
         columns: vec![
-            ColumnInfo { name: "id".to_string(), data_type: "INTEGER".to_string(), nullable: true },
-            ColumnInfo { name: "product".to_string(), data_type: "VARCHAR".to_string(), nullable: true },
-            ColumnInfo { name: "amount".to_string(), data_type: "INTEGER".to_string(), nullable: true },
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "product".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "amount".to_string(), data_type: DataType::Int32, nullable: true },
         ],
     });
 
@@ -641,3 +642,87 @@ fn test_all_aggregates() {
 
     println!("✅ All aggregates: sum, count, avg, min, max all working correctly");
 }
+
+#[test]
+fn test_window_replacing_column() {
+    use mlql_ir::{Expr, ColumnRef, SortKey, WindowDef};
+    use std::collections::HashMap;
+
+    let conn = Connection::open_in_memory().unwrap();
+    load_substrait_extension(&conn);
+    conn.execute_batch("
+        CREATE TABLE sales (id INTEGER, product VARCHAR, amount INTEGER);
+        INSERT INTO sales VALUES
+            (1, 'Apple', 100),
+            (2, 'Apple', 200),
+            (3, 'Banana', 50);
+    ").unwrap();
+
+    let mut schema_provider = MockSchemaProvider::new();
+    schema_provider.add_table(TableSchema {
+        name: "sales".to_string(),
+        columns: vec![
+            ColumnInfo { name: "id".to_string(), data_type: DataType::Int32, nullable: true },
+            ColumnInfo { name: "product".to_string(), data_type: DataType::String, nullable: true },
+            ColumnInfo { name: "amount".to_string(), data_type: DataType::Int32, nullable: true },
+        ],
+    });
+
+    // Test: from sales | window { amount: sum(amount) over part: product, rn: row_number() over part: product order: id }
+    // The window named amount replaces the input column
+    let column = |name: &str| ColumnRef { table: None, column: name.to_string() };
+    let mut windows = HashMap::new();
+    windows.insert("amount".to_string(), WindowDef {
+        func: "sum".to_string(),
+        args: vec![Expr::Column { col: column("amount") }],
+        partition: Some(vec![column("product")]),
+        order: None,
+        frame: None,
+    });
+    windows.insert("rn".to_string(), WindowDef {
+        func: "row_number".to_string(),
+        args: vec![],
+        partition: Some(vec![column("product")]),
+        order: Some(vec![SortKey { expr: Expr::Column { col: column("id") }, desc: false }]),
+        frame: None,
+    });
+
+    let program = Program {
+        pragma: None,
+        lets: vec![],
+        pipeline: Pipeline {
+            source: Source::Table {
+                name: "sales".to_string(),
+                alias: None,
+            },
+            ops: vec![Operator::Window { windows }],
+        },
+    };
+
+    let translator = SubstraitTranslator::new(&schema_provider);
+    let plan = translator.translate(&program).expect("Translation should succeed");
+
+    // The plan survives serialization unchanged
+    let mut plan_bytes = Vec::new();
+    plan.encode(&mut plan_bytes).expect("Serialization should succeed");
+    let decoded = substrait::proto::Plan::decode(plan_bytes.as_slice()).expect("Deserialization should succeed");
+    assert_eq!(decoded, plan);
+
+    // Execute and get results
+    let mut stmt = conn.prepare("SELECT * FROM from_substrait(?) ORDER BY id").unwrap();
+    let results: Vec<(i32, String, i64, i64)> = stmt
+        .query_map([plan_bytes], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(stmt.column_names(), vec!["id", "product", "amount", "rn"]);
+
+    println!("Window results: {:?}", results);
+    assert_eq!(results, vec![
+        (1, "Apple".to_string(), 300, 1),
+        (2, "Apple".to_string(), 300, 2),
+        (3, "Banana".to_string(), 50, 1),
+    ]);
+
+    println!("✅ Window: replaced amount with the product total");
+}
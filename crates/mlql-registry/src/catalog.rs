@@ -0,0 +1,241 @@
+//! Function catalogs: user-defined functions loaded from YAML or JSON
+//!
+//! A catalog declares DuckDB macros and UDFs so the validator, the Substrait
+//! translator and the LLM prompt know about them:
+//!
+//! ```yaml
+//! functions:
+//!   - name: revenue_band
+//!     args: [DOUBLE]
+//!     returns: VARCHAR
+//!     description: Bucket revenue into low, mid and high
+//!     examples: ["revenue_band(total)"]
+//!   - name: p90
+//!     args: [DOUBLE]
+//!     returns: DOUBLE
+//!     aggregate: true
+//!     substrait_uri: "mlql:p90:v1"
+//!   - name: first_present
+//!     args: [ANY]
+//!     varargs: ANY
+//!     returns: ANY
+//! ```
+//!
+//! Types are DuckDB type names (`VARCHAR`, `DECIMAL(18, 2)`, `FLOAT[384]`);
+//! `ANY` is a polymorphic parameter, and `varargs` is the type of any further
+//! arguments. A function with several overloads is listed once per overload.
+
+use crate::{FunctionRegistry, FunctionSignature, RegistryError};
+use mlql_ir::DataType;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A function catalog file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCatalog {
+    pub functions: Vec<CatalogFunction>,
+}
+
+/// One overload of a function in a catalog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogFunction {
+    pub name: String,
+    /// Argument type names
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Type name of any further arguments, for variadic functions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub varargs: Option<String>,
+    /// Return type name
+    pub returns: String,
+    #[serde(default)]
+    pub aggregate: bool,
+    #[serde(default)]
+    pub window: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+    /// Extension URI, for functions declared outside the Substrait standard files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub substrait_uri: Option<String>,
+}
+
+impl FunctionCatalog {
+    pub fn from_yaml(yaml: &str) -> Result<Self, RegistryError> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RegistryError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Read a catalog file: JSON if its extension is `.json`, YAML otherwise
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::from_json(&contents),
+            _ => Self::from_yaml(&contents),
+        }
+    }
+
+    /// The catalog's functions as registry signatures
+    pub fn signatures(&self) -> Result<Vec<FunctionSignature>, RegistryError> {
+        self.functions.iter().map(CatalogFunction::signature).collect()
+    }
+}
+
+impl CatalogFunction {
+    fn signature(&self) -> Result<FunctionSignature, RegistryError> {
+        let invalid = |message: String| RegistryError::InvalidCatalog { func: self.name.clone(), message };
+
+        if self.name.trim().is_empty() {
+            return Err(invalid("name is empty".to_string()));
+        }
+        if self.aggregate && self.window {
+            return Err(invalid("a function is either an aggregate or a window function".to_string()));
+        }
+        let args = self.args.iter()
+            .map(|arg| parse_type(arg).ok_or_else(|| invalid(format!("unknown argument type '{}'", arg))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let varargs = self.varargs.as_ref()
+            .map(|arg| parse_type(arg).ok_or_else(|| invalid(format!("unknown argument type '{}'", arg))))
+            .transpose()?;
+        let return_type = parse_type(&self.returns)
+            .ok_or_else(|| invalid(format!("unknown return type '{}'", self.returns)))?;
+
+        Ok(FunctionSignature {
+            name: self.name.clone(),
+            args,
+            return_type,
+            is_aggregate: self.aggregate,
+            is_window: self.window,
+            is_macro: false,
+            varargs,
+            substrait_uri: self.substrait_uri.clone(),
+            description: self.description.clone(),
+            examples: self.examples.clone(),
+        })
+    }
+}
+
+/// Parse a type name, with `ANY` as the polymorphic type
+fn parse_type(name: &str) -> Option<DataType> {
+    if name.trim().eq_ignore_ascii_case("any") {
+        return Some(DataType::Unknown);
+    }
+    match DataType::from_sql(name) {
+        DataType::Unknown => None,
+        data_type => Some(data_type),
+    }
+}
+
+impl FunctionRegistry {
+    /// Register every function in `catalog`, after checking all of them.
+    ///
+    /// Returns the number of overloads registered. An overload with the same
+    /// argument types as a registered one replaces it.
+    pub fn register_catalog(&mut self, catalog: &FunctionCatalog) -> Result<usize, RegistryError> {
+        let signatures = catalog.signatures()?;
+        let count = signatures.len();
+        for sig in signatures {
+            self.register(sig);
+        }
+        Ok(count)
+    }
+
+    /// Load and register a catalog file; see [`FunctionCatalog::load`]
+    pub fn load_catalog(&mut self, path: impl AsRef<Path>) -> Result<usize, RegistryError> {
+        self.register_catalog(&FunctionCatalog::load(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_catalog() {
+        let catalog = FunctionCatalog::from_json(r#"{"functions": [
+            {"name": "revenue_band", "args": ["DOUBLE"], "returns": "VARCHAR",
+             "description": "Bucket revenue", "examples": ["revenue_band(total)"]},
+            {"name": "p90", "args": ["DECIMAL(18, 2)"], "returns": "DOUBLE", "aggregate": true,
+             "substrait_uri": "mlql:p90:v1"},
+            {"name": "mask", "args": ["VARCHAR"], "returns": "VARCHAR", "description": "Hash an email"},
+            {"name": "first_non_null", "args": ["ANY"], "varargs": "ANY", "returns": "ANY"},
+            {"name": "label", "args": ["ANY", "ANY"], "returns": "VARCHAR"}
+        ]}"#).unwrap();
+
+        let mut registry = FunctionRegistry::default();
+        assert_eq!(registry.register_catalog(&catalog).unwrap(), 5);
+
+        let band = registry.lookup("revenue_band", &[DataType::Int32]).unwrap();
+        assert_eq!(band.return_type, DataType::String);
+        assert_eq!(band.signature.examples, vec!["revenue_band(total)"]);
+        assert!(registry.lookup("p90", &[DataType::Float64]).is_err());
+        assert!(registry.lookup("p90", &[DataType::Int64]).unwrap().signature.is_aggregate);
+        let first = registry.lookup("first_non_null", &[DataType::Null, DataType::Date]).unwrap();
+        assert_eq!(first.return_type, DataType::Date);
+        let first = registry.lookup("first_non_null", &[DataType::Int32, DataType::Int32, DataType::Int32]).unwrap();
+        assert_eq!(first.arg_types, vec![DataType::Int32; 3]);
+        assert!(registry.lookup("first_non_null", &[]).is_err());
+        // Without a polymorphic return type, each ANY parameter takes its own type
+        let label = registry.lookup("label", &[DataType::Int32, DataType::Date]).unwrap();
+        assert_eq!(label.arg_types, vec![DataType::Int32, DataType::Date]);
+
+        // Same argument types replace the built-in overload, keeping its URI only if redeclared
+        let mask = registry.lookup("mask", &[DataType::String]).unwrap();
+        assert_eq!(mask.signature.description.as_deref(), Some("Hash an email"));
+        assert_eq!(mask.signature.substrait_uri, None);
+
+        // Catalog functions with a URI get an extension document
+        assert!(registry.extension_uris().contains(&"mlql:p90:v1"));
+        assert_eq!(registry.extension("mlql:p90:v1").unwrap().aggregate_functions[0].name, "p90");
+    }
+
+    #[test]
+    fn test_invalid_catalog() {
+        let catalog = FunctionCatalog::from_json(r#"{"functions": [
+            {"name": "ok", "args": [], "returns": "INTEGER"},
+            {"name": "typo", "args": ["VARCHR"], "returns": "INTEGER"}
+        ]}"#).unwrap();
+
+        // Nothing is registered when an entry is invalid
+        let mut registry = FunctionRegistry::default();
+        let err = registry.register_catalog(&catalog).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid function catalog entry typo: unknown argument type 'VARCHR'");
+        assert!(registry.lookup("ok", &[]).is_err());
+
+        let err = FunctionCatalog::from_json(r#"{"functions": [{"name": "f", "args": []}]}"#).unwrap_err();
+        assert!(err.to_string().contains("missing field `returns`"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_catalog_file() {
+        let catalog = FunctionCatalog {
+            functions: vec![CatalogFunction {
+                name: "slugify".to_string(),
+                args: vec!["VARCHAR".to_string()],
+                varargs: None,
+                returns: "VARCHAR".to_string(),
+                aggregate: false,
+                window: false,
+                description: None,
+                examples: vec![],
+                substrait_uri: None,
+            }],
+        };
+        let dir = std::env::temp_dir();
+        let json = dir.join(format!("mlql_catalog_{}.json", std::process::id()));
+        std::fs::write(&json, serde_json::to_string(&catalog).unwrap()).unwrap();
+
+        let mut registry = FunctionRegistry::default();
+        let loaded = registry.load_catalog(&json);
+        std::fs::remove_file(&json).ok();
+        assert_eq!(loaded.unwrap(), 1);
+        assert!(registry.lookup("slugify", &[DataType::String]).is_ok());
+
+        assert!(matches!(registry.load_catalog(dir.join("missing.yaml")), Err(RegistryError::Io(_))));
+    }
+}
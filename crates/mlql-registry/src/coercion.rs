@@ -0,0 +1,97 @@
+//! Implicit type coercion for overload resolution
+//!
+//! Arguments convert along a lattice: integers widen to wider integers, then
+//! to decimal, then to floating point; `VARCHAR` and `STRING` convert to each
+//! other; `NULL` converts to anything. Each step costs 1, so the overload
+//! needing the fewest conversions wins. A polymorphic parameter accepts
+//! anything but costs more than any chain of steps, so a typed overload wins
+//! over an untyped one.
+
+use mlql_ir::DataType;
+
+/// Cost of binding an argument to a polymorphic (`Unknown`) parameter
+const POLYMORPHIC_COST: u32 = 8;
+
+/// Cost of implicitly converting an argument of type `from` to a parameter of type `to`,
+/// or `None` if there is no implicit conversion.
+///
+/// An `Unknown` argument matches any parameter for free, since nothing is
+/// known about it; an `Unknown` parameter costs [`POLYMORPHIC_COST`].
+pub fn coercion_cost(from: &DataType, to: &DataType) -> Option<u32> {
+    use DataType::*;
+
+    match (from, to) {
+        (a, b) if a == b => Some(0),
+        (Unknown, _) => Some(0),
+        (_, Unknown) => Some(POLYMORPHIC_COST),
+        (Null, _) => Some(1),
+        (a, b) if a.is_string() && b.is_string() => Some(1),
+        (Decimal { .. }, Decimal { .. }) => Some(1),
+        (a, b) if a.is_numeric() && b.is_numeric() => numeric_cost(a, b),
+        // A parameter without a dimension takes vectors of any dimension
+        (Vector(_), Vector(None)) => Some(0),
+        (Array(a), Array(b)) => coercion_cost(a, b),
+        _ => None,
+    }
+}
+
+/// Steps up the numeric lattice from `from` to `to`.
+///
+/// Unsigned integers widen to unsigned or strictly wider signed integers;
+/// signed integers never become unsigned.
+fn numeric_cost(from: &DataType, to: &DataType) -> Option<u32> {
+    use DataType::*;
+
+    fn rank(t: &DataType) -> u32 {
+        match t {
+            Int8 | UInt8 => 1,
+            Int16 | UInt16 => 2,
+            Int32 | UInt32 => 3,
+            Int64 | UInt64 => 4,
+            Decimal { .. } => 5,
+            Float32 => 6,
+            _ => 7,
+        }
+    }
+    let unsigned = |t: &DataType| matches!(t, UInt8 | UInt16 | UInt32 | UInt64);
+
+    if rank(to) <= rank(from) || (unsigned(to) && !unsigned(from)) {
+        return None;
+    }
+    Some(rank(to) - rank(from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coercion_lattice() {
+        use DataType::*;
+
+        assert_eq!(coercion_cost(&Int32, &Int32), Some(0));
+        assert_eq!(coercion_cost(&Int32, &Int64), Some(1));
+        assert_eq!(coercion_cost(&Int32, &Decimal { precision: 18, scale: 2 }), Some(2));
+        assert_eq!(coercion_cost(&Int32, &Float64), Some(4));
+        assert_eq!(coercion_cost(&UInt8, &Int16), Some(1));
+        assert_eq!(coercion_cost(&Float32, &Float64), Some(1));
+        assert_eq!(coercion_cost(&Varchar(Some(10)), &String), Some(1));
+        assert_eq!(coercion_cost(&String, &Varchar(None)), Some(1));
+        assert_eq!(coercion_cost(&Null, &Date), Some(1));
+        assert_eq!(coercion_cost(&Vector(Some(3)), &Vector(None)), Some(0));
+        assert_eq!(coercion_cost(&Unknown, &Int32), Some(0));
+
+        // Polymorphic parameters cost more than the longest chain of steps
+        assert_eq!(coercion_cost(&Int8, &Unknown), Some(POLYMORPHIC_COST));
+        assert!(coercion_cost(&Int8, &Float64).unwrap() < POLYMORPHIC_COST);
+        assert_eq!(coercion_cost(&Array(Box::new(String)), &Array(Box::new(Unknown))), Some(POLYMORPHIC_COST));
+
+        // Narrowing and sign changes are never implicit
+        assert_eq!(coercion_cost(&Int64, &Int32), None);
+        assert_eq!(coercion_cost(&Float64, &Int64), None);
+        assert_eq!(coercion_cost(&Int8, &UInt8), None);
+        assert_eq!(coercion_cost(&UInt8, &Int8), None);
+        assert_eq!(coercion_cost(&String, &Int64), None);
+        assert_eq!(coercion_cost(&Vector(Some(3)), &Vector(Some(4))), None);
+    }
+}
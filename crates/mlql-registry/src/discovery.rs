@@ -0,0 +1,134 @@
+//! Function discovery from a DuckDB connection
+//!
+//! `duckdb_functions()` lists every scalar, aggregate and window function and
+//! macro the connection can call, including those of loaded extensions such as
+//! `fts`, `vss` and `spatial` and macros created in the database. Importing
+//! them lets validation and the LLM prompt reflect the real engine.
+
+use crate::{FunctionRegistry, FunctionSignature, RegistryError};
+use duckdb::types::Value;
+use duckdb::Connection;
+use mlql_ir::DataType;
+
+/// Functions callable by name; table functions and pragmas are not expressions
+const FUNCTIONS_QUERY: &str = "
+    SELECT function_name, function_type, parameter_types, varargs, return_type, description, examples
+    FROM duckdb_functions()
+    WHERE function_type IN ('scalar', 'aggregate', 'window', 'macro')
+    ORDER BY function_name
+";
+
+impl FunctionRegistry {
+    /// Register every function the DuckDB connection `conn` can call, one
+    /// overload per `duckdb_functions()` row.
+    ///
+    /// Returns the number of overloads imported. Operators and other functions
+    /// whose names are not identifiers are skipped. `ANY`, untyped macro
+    /// parameters and types MLQL does not model (`GEOMETRY`, `UNION`, ...) are
+    /// polymorphic. An overload with the same argument types as a registered
+    /// one replaces it, so discovered signatures take precedence over the
+    /// built-ins; register a [`FunctionCatalog`](crate::FunctionCatalog)
+    /// afterwards to refine them.
+    pub fn discover_from_duckdb(&mut self, conn: &Connection) -> Result<usize, RegistryError> {
+        let mut stmt = conn.prepare(FUNCTIONS_QUERY)?;
+        let signatures = stmt
+            .query_map([], |row| {
+                let name: String = row.get(0)?;
+                let kind: String = row.get(1)?;
+                let varargs: Option<String> = row.get(3)?;
+                let return_type: Option<String> = row.get(4)?;
+                Ok(FunctionSignature {
+                    args: strings(row.get(2)?).iter().map(|arg| parse_type(arg.as_deref())).collect(),
+                    return_type: parse_type(return_type.as_deref()),
+                    is_aggregate: kind == "aggregate",
+                    is_window: kind == "window",
+                    is_macro: kind == "macro",
+                    varargs: varargs.as_deref().map(|arg| parse_type(Some(arg))),
+                    substrait_uri: None,
+                    description: row.get::<_, Option<String>>(5)?.filter(|d| !d.is_empty()),
+                    examples: strings(row.get(6)?).into_iter().flatten().collect(),
+                    name,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut count = 0;
+        for sig in signatures.into_iter().filter(|sig| is_identifier(&sig.name)) {
+            self.register(sig);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Parse a DuckDB parameter or return type; a missing type is polymorphic
+fn parse_type(name: Option<&str>) -> DataType {
+    name.map_or(DataType::Unknown, DataType::from_sql)
+}
+
+/// Items of a `VARCHAR[]` column, `None` for NULL items
+fn strings(value: Value) -> Vec<Option<String>> {
+    match value {
+        Value::List(items) => items.into_iter()
+            .map(|item| match item {
+                Value::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `name` can be called as an MLQL function
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_from_duckdb() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE MACRO revenue_band(total) AS CASE WHEN total > 100 THEN 'high' ELSE 'low' END;")
+            .unwrap();
+
+        let mut registry = FunctionRegistry::default();
+        let count = registry.discover_from_duckdb(&conn).unwrap();
+        assert!(count > 100, "{}", count);
+
+        // Macros are untyped
+        let band = registry.lookup("revenue_band", &[DataType::Float64]).unwrap();
+        assert_eq!(band.return_type, DataType::Unknown);
+        assert!(band.signature.is_macro);
+
+        // Engine signatures, including variadic ones and aggregates
+        let concat = registry.lookup("concat", &[DataType::String, DataType::Int32, DataType::Date]).unwrap();
+        assert_eq!(concat.return_type, DataType::String);
+        assert!(registry.lookup("string_agg", &[DataType::String, DataType::String]).unwrap().signature.is_aggregate);
+        assert_eq!(registry.lookup("starts_with", &[DataType::String, DataType::String]).unwrap().return_type, DataType::Bool);
+
+        // Typed overloads win over those with unmodelled types, like length(BIT)
+        assert_eq!(registry.lookup("length", &[DataType::String]).unwrap().return_type, DataType::Int64);
+        assert_eq!(registry.lookup("lower", &[DataType::String]).unwrap().return_type, DataType::String);
+        assert_eq!(registry.lookup("abs", &[DataType::Float64]).unwrap().return_type, DataType::Float64);
+        assert_eq!(registry.lookup("year", &[DataType::Date]).unwrap().return_type, DataType::Int64);
+
+        // Operators are not callable by name, and MLQL's own functions remain
+        assert!(registry.lookup("+", &[DataType::Int32, DataType::Int32]).is_err());
+        assert!(registry.lookup("mask", &[DataType::String]).is_ok());
+    }
+
+    #[test]
+    fn test_is_identifier() {
+        assert!(is_identifier("array_cosine_similarity"));
+        assert!(is_identifier("_private"));
+        assert!(!is_identifier("~~"));
+        assert!(!is_identifier("!__postfix"));
+        assert!(!is_identifier("2x"));
+        assert!(!is_identifier(""));
+    }
+}
@@ -0,0 +1,262 @@
+//! Substrait simple-extension documents for custom functions
+//!
+//! Functions registered with a `substrait_uri` (e.g. `mlql:mask:v1`) are not in
+//! the Substrait standard extension files. Plans declare them under that URI,
+//! and [`FunctionRegistry::extension`] builds the document the URI points to
+//! from the registered signatures.
+
+use crate::{FunctionRegistry, FunctionSignature, RegistryError};
+use mlql_ir::substrait::{ExtensionFunction, ExtensionResolver};
+use mlql_ir::DataType;
+use serde::{Deserialize, Serialize};
+
+/// A Substrait simple-extension document
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SimpleExtension {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scalar_functions: Vec<ExtensionFunctionDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aggregate_functions: Vec<ExtensionFunctionDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub window_functions: Vec<ExtensionFunctionDecl>,
+}
+
+/// A function declared in an extension document, with one implementation per overload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtensionFunctionDecl {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub impls: Vec<ExtensionImpl>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtensionImpl {
+    #[serde(default)]
+    pub args: Vec<ExtensionArg>,
+    /// Present when the last argument may repeat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variadic: Option<VariadicBehavior>,
+    #[serde(rename = "return")]
+    pub return_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariadicBehavior {
+    /// Fewest occurrences of the last argument
+    pub min: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtensionArg {
+    pub name: String,
+    /// Substrait type expression, e.g. `string` or `list<fp32>`
+    pub value: String,
+}
+
+impl SimpleExtension {
+    /// Render as a YAML document in the layout of the standard extension files
+    pub fn to_yaml(&self) -> Result<String, RegistryError> {
+        Ok(format!("%YAML 1.2\n---\n{}", serde_yaml::to_string(self)?))
+    }
+}
+
+impl FunctionSignature {
+    /// Substrait compound function name, e.g. `mask:str`
+    pub fn compound_name(&self) -> String {
+        if self.args.is_empty() && self.varargs.is_none() {
+            return self.name.clone();
+        }
+        let args: Vec<_> = self.args.iter().chain(&self.varargs).map(short_type_name).collect();
+        format!("{}:{}", self.name, args.join("_"))
+    }
+
+    /// Variadic arguments are declared as a last argument that repeats zero or more times
+    fn extension_impl(&self) -> ExtensionImpl {
+        ExtensionImpl {
+            args: self.args.iter().chain(&self.varargs).enumerate()
+                .map(|(i, arg)| ExtensionArg { name: format!("arg{}", i + 1), value: type_expression(arg) })
+                .collect(),
+            variadic: self.varargs.as_ref().map(|_| VariadicBehavior { min: 0 }),
+            return_type: type_expression(&self.return_type),
+        }
+    }
+}
+
+impl FunctionRegistry {
+    /// URIs of the custom extension documents, sorted
+    pub fn extension_uris(&self) -> Vec<&str> {
+        let mut uris: Vec<&str> = self.functions.values()
+            .flatten()
+            .filter_map(|sig| sig.substrait_uri.as_deref())
+            .collect();
+        uris.sort_unstable();
+        uris.dedup();
+        uris
+    }
+
+    /// Extension document declaring the functions registered under `uri`,
+    /// in name order, or `None` if no function uses `uri`
+    pub fn extension(&self, uri: &str) -> Option<SimpleExtension> {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+
+        let mut extension = SimpleExtension::default();
+        for name in names {
+            let sigs: Vec<&FunctionSignature> = self.functions[name].iter()
+                .filter(|sig| sig.substrait_uri.as_deref() == Some(uri))
+                .collect();
+            let Some(first) = sigs.first() else {
+                continue;
+            };
+            let decl = ExtensionFunctionDecl {
+                name: name.clone(),
+                description: sigs.iter().find_map(|sig| sig.description.clone()),
+                impls: sigs.iter().map(|sig| sig.extension_impl()).collect(),
+            };
+            if first.is_aggregate {
+                extension.aggregate_functions.push(decl);
+            } else if first.is_window {
+                extension.window_functions.push(decl);
+            } else {
+                extension.scalar_functions.push(decl);
+            }
+        }
+
+        (extension != SimpleExtension::default()).then_some(extension)
+    }
+}
+
+impl ExtensionResolver for FunctionRegistry {
+    fn resolve_extension(&self, name: &str, args: &[DataType]) -> Result<Option<ExtensionFunction>, String> {
+        // Built-ins lower through the Substrait standard files
+        let custom = self.functions.get(name)
+            .is_some_and(|overloads| overloads.iter().any(|sig| sig.substrait_uri.is_some()));
+        if !custom {
+            return Ok(None);
+        }
+
+        let sig = self.lookup(name, args).map_err(|e| e.to_string())?.signature;
+        Ok(sig.substrait_uri.as_ref().map(|uri| ExtensionFunction {
+            uri: uri.clone(),
+            signature: sig.compound_name(),
+        }))
+    }
+}
+
+/// Substrait type expression for `data_type`, as used in extension documents.
+///
+/// Unsigned integers widen to the next signed type, since Substrait has none.
+/// Unknown types are the polymorphic `any1`.
+fn type_expression(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Bool => "boolean".to_string(),
+        DataType::Int8 => "i8".to_string(),
+        DataType::Int16 | DataType::UInt8 => "i16".to_string(),
+        DataType::Int32 | DataType::UInt16 => "i32".to_string(),
+        DataType::Int64 | DataType::UInt32 => "i64".to_string(),
+        DataType::UInt64 => "decimal<20, 0>".to_string(),
+        DataType::Float32 => "fp32".to_string(),
+        DataType::Float64 => "fp64".to_string(),
+        DataType::Decimal { precision, scale } => format!("decimal<{}, {}>", precision, scale),
+        DataType::String | DataType::Varchar(None) => "string".to_string(),
+        DataType::Varchar(Some(length)) => format!("varchar<{}>", length),
+        DataType::Blob => "binary".to_string(),
+        DataType::Date => "date".to_string(),
+        DataType::Time => "time".to_string(),
+        DataType::Timestamp => "timestamp".to_string(),
+        DataType::TimestampTz => "timestamp_tz".to_string(),
+        DataType::Interval => "interval_day<6>".to_string(),
+        DataType::Array(element) => format!("list<{}>", type_expression(element)),
+        DataType::Vector(_) => "list<fp32>".to_string(),
+        DataType::Struct(fields) => {
+            let fields: Vec<_> = fields.iter().map(|f| type_expression(&f.data_type)).collect();
+            format!("struct<{}>", fields.join(", "))
+        }
+        DataType::Map { key, value } => format!("map<{}, {}>", type_expression(key), type_expression(value)),
+        DataType::Null | DataType::Unknown => "any1".to_string(),
+    }
+}
+
+/// Short Substrait type name for `data_type`, as used in compound function names
+fn short_type_name(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Bool => "bool",
+        DataType::Int8 => "i8",
+        DataType::Int16 | DataType::UInt8 => "i16",
+        DataType::Int32 | DataType::UInt16 => "i32",
+        DataType::Int64 | DataType::UInt32 => "i64",
+        DataType::UInt64 | DataType::Decimal { .. } => "dec",
+        DataType::Float32 => "fp32",
+        DataType::Float64 => "fp64",
+        DataType::String | DataType::Varchar(None) => "str",
+        DataType::Varchar(Some(_)) => "vchar",
+        DataType::Blob => "vbin",
+        DataType::Date => "date",
+        DataType::Time => "time",
+        DataType::Timestamp => "ts",
+        DataType::TimestampTz => "tstz",
+        DataType::Interval => "iday",
+        DataType::Array(_) | DataType::Vector(_) => "list",
+        DataType::Struct(_) => "struct",
+        DataType::Map { .. } => "map",
+        DataType::Null | DataType::Unknown => "any1",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension_documents() {
+        let registry = FunctionRegistry::default();
+        assert_eq!(registry.extension_uris(), vec![
+            "mlql:approx_percentile:v1",
+            "mlql:bm25:v1",
+            "mlql:mask:v1",
+            "mlql:vector_similarity:v1",
+        ]);
+        assert_eq!(registry.extension("https://example.com/functions.yaml"), None);
+
+        let mask = registry.extension("mlql:mask:v1").unwrap();
+        assert_eq!(mask.scalar_functions.len(), 1);
+        assert_eq!(mask.scalar_functions[0].name, "mask");
+        assert_eq!(mask.scalar_functions[0].impls, vec![ExtensionImpl {
+            args: vec![ExtensionArg { name: "arg1".to_string(), value: "string".to_string() }],
+            variadic: None,
+            return_type: "string".to_string(),
+        }]);
+
+        let approx = registry.extension("mlql:approx_percentile:v1").unwrap();
+        assert!(approx.scalar_functions.is_empty());
+        assert_eq!(approx.aggregate_functions[0].name, "approx_p");
+
+        let similarity = registry.extension("mlql:vector_similarity:v1").unwrap();
+        assert_eq!(similarity.scalar_functions[0].impls[0].args[0].value, "list<fp32>");
+
+        // The YAML document reads back as the same extension
+        let yaml = mask.to_yaml().unwrap();
+        assert!(yaml.starts_with("%YAML 1.2\n---\n"), "{}", yaml);
+        let parsed: SimpleExtension = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, mask);
+    }
+
+    #[test]
+    fn test_resolve_extension() {
+        let registry = FunctionRegistry::default();
+
+        let bm25 = registry.resolve_extension("bm25", &[DataType::String, DataType::String]).unwrap();
+        assert_eq!(bm25, Some(ExtensionFunction {
+            uri: "mlql:bm25:v1".to_string(),
+            signature: "bm25:str_str".to_string(),
+        }));
+
+        // Built-ins and unknown functions are left to the standard mapping
+        assert_eq!(registry.resolve_extension("upper", &[DataType::String]).unwrap(), None);
+        assert_eq!(registry.resolve_extension("nope", &[]).unwrap(), None);
+
+        let err = registry.resolve_extension("mask", &[DataType::Int32]).unwrap_err();
+        assert!(err.starts_with("Type mismatch for function mask"), "{}", err);
+    }
+}
@@ -0,0 +1,122 @@
+//! Graph definitions: the tables holding each graph's nodes and edges
+//!
+//! `from graph(social) g` reads the nodes of the graph registered as
+//! `social`, and `neighbors` follows its edges. In a config file:
+//!
+//! ```yaml
+//! graphs:
+//!   social:
+//!     nodes: people
+//!     edges: follows
+//!     src: follower_id
+//!     dst: followee_id
+//!     edge_type: kind
+//! ```
+//!
+//! Node keys default to `id` and edge endpoints to `src` and `dst`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Where a graph's nodes and edges are stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphDef {
+    /// Table with one row per node
+    pub nodes: String,
+    /// Node key column, which the edges' endpoints refer to
+    #[serde(default = "default_node_id")]
+    pub node_id: String,
+    /// Table with one row per edge
+    pub edges: String,
+    #[serde(default = "default_src")]
+    pub src: String,
+    #[serde(default = "default_dst")]
+    pub dst: String,
+    /// Column holding each edge's type, matched by `neighbors ... edge: <type>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge_type: Option<String>,
+    /// Follow edges from `dst` to `src` as well
+    #[serde(default)]
+    pub undirected: bool,
+}
+
+fn default_node_id() -> String {
+    "id".to_string()
+}
+
+fn default_src() -> String {
+    "src".to_string()
+}
+
+fn default_dst() -> String {
+    "dst".to_string()
+}
+
+impl GraphDef {
+    /// A directed graph keyed by `id`, with `src` and `dst` edge columns
+    pub fn new(nodes: impl Into<String>, edges: impl Into<String>) -> Self {
+        Self {
+            nodes: nodes.into(),
+            node_id: default_node_id(),
+            edges: edges.into(),
+            src: default_src(),
+            dst: default_dst(),
+            edge_type: None,
+            undirected: false,
+        }
+    }
+}
+
+/// Graphs by name; serialized as a map from name to definition
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GraphRegistry {
+    graphs: BTreeMap<String, GraphDef>,
+}
+
+impl GraphRegistry {
+    /// Register `graph` as `name`, returning the definition it replaces
+    pub fn register(&mut self, name: impl Into<String>, graph: GraphDef) -> Option<GraphDef> {
+        self.graphs.insert(name.into(), graph)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&GraphDef> {
+        self.graphs.get(name)
+    }
+
+    /// Graphs in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &GraphDef)> {
+        self.graphs.iter().map(|(name, graph)| (name.as_str(), graph))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.graphs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_registry_deserialize() {
+        let registry: GraphRegistry = serde_json::from_str(r#"{
+            "social": {"nodes": "people", "edges": "follows", "src": "follower_id", "dst": "followee_id", "edge_type": "kind"},
+            "roads": {"nodes": "cities", "edges": "roads", "undirected": true}
+        }"#).unwrap();
+
+        let social = registry.get("social").unwrap();
+        assert_eq!(social.node_id, "id");
+        assert_eq!(social.src, "follower_id");
+        assert_eq!(social.edge_type.as_deref(), Some("kind"));
+        assert!(!social.undirected);
+
+        let mut expected = GraphDef::new("cities", "roads");
+        expected.undirected = true;
+        assert_eq!(registry.get("roads"), Some(&expected));
+        assert_eq!(registry.get("nope"), None);
+
+        let names: Vec<_> = registry.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["roads", "social"]);
+    }
+}
@@ -1,8 +1,21 @@
-//! Function registry and policy definitions
+//! Function and graph registries and policy definitions
 
+mod catalog;
+mod coercion;
+mod discovery;
+mod extensions;
+mod graphs;
+
+pub use catalog::{CatalogFunction, FunctionCatalog};
+pub use coercion::coercion_cost;
+pub use extensions::{ExtensionArg, ExtensionFunctionDecl, ExtensionImpl, SimpleExtension, VariadicBehavior};
+pub use graphs::{GraphDef, GraphRegistry};
+
+use mlql_ir::validate::{FunctionResolver, FunctionType, ValidationError};
 use mlql_ir::DataType;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -10,22 +23,142 @@ pub enum RegistryError {
     #[error("Function not found: {0}")]
     FunctionNotFound(String),
 
-    #[error("Type mismatch for function {func}: expected {expected:?}, got {actual:?}")]
+    #[error("Type mismatch for function {func}: no overload accepts ({}). Candidates: {}", list(actual), list(candidates))]
     TypeMismatch {
         func: String,
-        expected: Vec<DataType>,
         actual: Vec<DataType>,
+        candidates: Vec<FunctionSignature>,
+    },
+
+    #[error("Ambiguous call to {func}({}). Equally good candidates: {}", list(actual), list(candidates))]
+    Ambiguous {
+        func: String,
+        actual: Vec<DataType>,
+        candidates: Vec<FunctionSignature>,
     },
+
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to read function catalog: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid function catalog entry {func}: {message}")]
+    InvalidCatalog { func: String, message: String },
+
+    #[error("Function discovery failed: {0}")]
+    DuckDb(#[from] duckdb::Error),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Comma-separated list, for error messages
+fn list<T: fmt::Debug>(items: &[T]) -> String {
+    items.iter().map(|item| format!("{:?}", item)).collect::<Vec<_>>().join(", ")
+}
+
+/// A function overload.
+///
+/// `Unknown` parameters are polymorphic: they take arguments of any type.
+/// With an `Unknown` return type they are like Substrait's `any1`: all of
+/// them in one call must share a common type, and the call returns it.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionSignature {
     pub name: String,
     pub args: Vec<DataType>,
     pub return_type: DataType,
     pub is_aggregate: bool,
     pub is_window: bool,
+    /// A DuckDB macro: expanded before binding, so an `Unknown` return type
+    /// stays unknown rather than taking the arguments' type
+    #[serde(default)]
+    pub is_macro: bool,
+    /// Type of any further arguments after `args`, for variadic functions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub varargs: Option<DataType>,
     pub substrait_uri: Option<String>, // For custom extensions
+    /// What the function does, for the LLM prompt and extension documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Example calls in MLQL
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+}
+
+/// Shown as `name(Int64, Float64) -> Float64`, or `concat(String...) -> String`
+/// with variadic arguments
+impl fmt::Debug for FunctionSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({}", self.name, list(&self.args))?;
+        if let Some(varargs) = &self.varargs {
+            let separator = if self.args.is_empty() { "" } else { ", " };
+            write!(f, "{}{:?}...", separator, varargs)?;
+        }
+        write!(f, ") -> {:?}", self.return_type)
+    }
+}
+
+/// A call matched to the overload needing the fewest implicit conversions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution<'a> {
+    pub signature: &'a FunctionSignature,
+    /// Argument types after implicit coercion
+    pub arg_types: Vec<DataType>,
+    /// Return type, with a polymorphic return type bound to its arguments' type
+    pub return_type: DataType,
+    /// Total coercion cost; 0 is an exact match
+    pub cost: u32,
+}
+
+impl FunctionSignature {
+    /// Match a call with arguments of type `actual`, if every argument converts
+    fn resolve(&self, actual: &[DataType]) -> Option<Resolution<'_>> {
+        let params = self.params(actual.len())?;
+
+        // With a polymorphic return type, polymorphic parameters all take
+        // the arguments' common type; otherwise each keeps its own
+        let common = match self.return_type {
+            DataType::Unknown if !self.is_macro => Some(
+                params.iter().zip(actual)
+                    .filter(|(param, _)| ***param == DataType::Unknown)
+                    .try_fold(DataType::Null, |common, (_, arg)| common.common_supertype(arg))?,
+            ),
+            _ => None,
+        };
+
+        let mut cost = 0;
+        let mut arg_types = Vec::with_capacity(actual.len());
+        for (param, arg) in params.into_iter().zip(actual) {
+            let target = match (param, &common) {
+                (DataType::Unknown, Some(common)) => common,
+                (DataType::Unknown, None) => arg,
+                _ => param,
+            };
+            cost += coercion_cost(arg, target)?;
+            arg_types.push(target.clone());
+        }
+
+        let return_type = match common {
+            Some(common) if common != DataType::Null => common,
+            _ => self.return_type.clone(),
+        };
+        Some(Resolution { signature: self, arg_types, return_type, cost })
+    }
+
+    /// Parameter types for a call with `count` arguments, repeating the
+    /// variadic type after the fixed parameters
+    fn params(&self, count: usize) -> Option<Vec<&DataType>> {
+        match &self.varargs {
+            _ if count == self.args.len() => Some(self.args.iter().collect()),
+            Some(varargs) if count > self.args.len() => Some(
+                self.args.iter()
+                    .chain((self.args.len()..count).map(|_| varargs))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
 }
 
 pub struct FunctionRegistry {
@@ -51,7 +184,11 @@ impl FunctionRegistry {
             return_type: DataType::String,
             is_aggregate: false,
             is_window: false,
+            is_macro: false,
+            varargs: None,
             substrait_uri: Some("mlql:mask:v1".to_string()),
+            description: Some("Mask personally identifiable information in a string".to_string()),
+            examples: vec!["mask(email)".to_string()],
         });
 
         // Approximate percentile
@@ -61,7 +198,11 @@ impl FunctionRegistry {
             return_type: DataType::Float64,
             is_aggregate: true,
             is_window: false,
+            is_macro: false,
+            varargs: None,
             substrait_uri: Some("mlql:approx_percentile:v1".to_string()),
+            description: Some("Approximate percentile of a column, with the percentile between 0 and 1".to_string()),
+            examples: vec!["approx_p(latency_ms, 0.95)".to_string()],
         });
 
         // BM25 full-text search
@@ -71,7 +212,11 @@ impl FunctionRegistry {
             return_type: DataType::Float64,
             is_aggregate: false,
             is_window: false,
+            is_macro: false,
+            varargs: None,
             substrait_uri: Some("mlql:bm25:v1".to_string()),
+            description: Some("BM25 full-text relevance of a document column to a query string".to_string()),
+            examples: vec!["bm25(body, \"vector database\")".to_string()],
         });
 
         // Vector similarity
@@ -81,54 +226,161 @@ impl FunctionRegistry {
             return_type: DataType::Float64,
             is_aggregate: false,
             is_window: false,
+            is_macro: false,
+            varargs: None,
             substrait_uri: Some("mlql:vector_similarity:v1".to_string()),
+            description: Some("Cosine similarity of two vectors".to_string()),
+            examples: vec!["similarity(embedding, <0.1, 0.2, 0.3>)".to_string()],
         });
 
-        // Standard aggregates (map to DuckDB/Substrait builtins)
-        for (name, ret_type) in [
-            ("count", DataType::Int64),
-            ("sum", DataType::Float64),
-            ("avg", DataType::Float64),
-            ("min", DataType::Float64),
-            ("max", DataType::Float64),
+        // Standard aggregates (map to DuckDB/Substrait builtins). Integer sums
+        // stay integers; other numbers, and averages, are doubles.
+        for (name, args, ret_type) in [
+            ("count", vec![DataType::Unknown], DataType::Int64),
+            ("sum", vec![DataType::Int64], DataType::Int64),
+            ("sum", vec![DataType::Float64], DataType::Float64),
+            ("avg", vec![DataType::Float64], DataType::Float64),
+            ("min", vec![DataType::Unknown], DataType::Unknown),
+            ("max", vec![DataType::Unknown], DataType::Unknown),
         ] {
             self.register(FunctionSignature {
                 name: name.to_string(),
-                args: vec![DataType::Unknown], // Polymorphic
+                args,
                 return_type: ret_type,
                 is_aggregate: true,
                 is_window: false,
+                is_macro: false,
+                varargs: None,
                 substrait_uri: None, // Built-in
+                description: None,
+                examples: vec![],
+            });
+        }
+
+        // count() counts rows
+        self.register(FunctionSignature {
+            name: "count".to_string(),
+            args: vec![],
+            return_type: DataType::Int64,
+            is_aggregate: true,
+            is_window: false,
+            is_macro: false,
+            varargs: None,
+            substrait_uri: None,
+            description: None,
+            examples: vec![],
+        });
+
+        // Standard window functions
+        for (name, args, ret_type) in [
+            ("row_number", vec![], DataType::Int64),
+            ("rank", vec![], DataType::Int64),
+            ("dense_rank", vec![], DataType::Int64),
+            ("percent_rank", vec![], DataType::Float64),
+            ("ntile", vec![DataType::Int64], DataType::Int64),
+            ("lag", vec![DataType::Unknown], DataType::Unknown),
+            ("lag", vec![DataType::Unknown, DataType::Int64], DataType::Unknown),
+            ("lead", vec![DataType::Unknown], DataType::Unknown),
+            ("lead", vec![DataType::Unknown, DataType::Int64], DataType::Unknown),
+            ("first_value", vec![DataType::Unknown], DataType::Unknown),
+            ("last_value", vec![DataType::Unknown], DataType::Unknown),
+        ] {
+            self.register(FunctionSignature {
+                name: name.to_string(),
+                args,
+                return_type: ret_type,
+                is_aggregate: false,
+                is_window: true,
+                is_macro: false,
+                varargs: None,
+                substrait_uri: None,
+                description: None,
+                examples: vec![],
+            });
+        }
+
+        // Standard scalar functions
+        for (name, args, ret_type) in [
+            ("lower", vec![DataType::String], DataType::String),
+            ("upper", vec![DataType::String], DataType::String),
+            ("length", vec![DataType::String], DataType::Int64),
+            ("abs", vec![DataType::Unknown], DataType::Unknown),
+            ("round", vec![DataType::Unknown], DataType::Unknown),
+            ("round", vec![DataType::Unknown, DataType::Int64], DataType::Unknown),
+            ("coalesce", vec![DataType::Unknown, DataType::Unknown], DataType::Unknown),
+        ] {
+            self.register(FunctionSignature {
+                name: name.to_string(),
+                args,
+                return_type: ret_type,
+                is_aggregate: false,
+                is_window: false,
+                is_macro: false,
+                varargs: None,
+                substrait_uri: None,
+                description: None,
+                examples: vec![],
             });
         }
     }
 
+    /// Add an overload. One with the same argument types as an existing
+    /// overload replaces it.
     pub fn register(&mut self, sig: FunctionSignature) {
-        self.functions
+        let overloads = self.functions
             .entry(sig.name.clone())
-            .or_insert_with(Vec::new)
-            .push(sig);
+            .or_insert_with(Vec::new);
+        match overloads.iter_mut().find(|existing| existing.args == sig.args && existing.varargs == sig.varargs) {
+            Some(existing) => *existing = sig,
+            None => overloads.push(sig),
+        }
     }
 
-    pub fn lookup(&self, name: &str, arg_types: &[DataType]) -> Result<&FunctionSignature, RegistryError> {
+    /// Every registered overload, by function name
+    pub fn signatures(&self) -> Vec<&FunctionSignature> {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+        names.into_iter().flat_map(|name| &self.functions[name]).collect()
+    }
+
+    /// Resolve a call to `name` to the overload needing the cheapest implicit
+    /// conversions of `arg_types`.
+    ///
+    /// Fails if no overload accepts the arguments, or if several are equally
+    /// cheap. Ties caused by unknown or NULL arguments take the first overload
+    /// registered, with an unknown return type if the tied overloads disagree.
+    pub fn lookup(&self, name: &str, arg_types: &[DataType]) -> Result<Resolution<'_>, RegistryError> {
         let overloads = self.functions
             .get(name)
             .ok_or_else(|| RegistryError::FunctionNotFound(name.to_string()))?;
 
-        // Simple overload resolution (exact match or polymorphic)
-        overloads
-            .iter()
-            .find(|sig| {
-                sig.args.len() == arg_types.len()
-                    && sig.args.iter().zip(arg_types).all(|(expected, actual)| {
-                        expected == actual || *expected == DataType::Unknown
-                    })
-            })
-            .ok_or_else(|| RegistryError::TypeMismatch {
+        let candidates: Vec<Resolution> = overloads.iter().filter_map(|sig| sig.resolve(arg_types)).collect();
+        let Some(best_cost) = candidates.iter().map(|c| c.cost).min() else {
+            return Err(RegistryError::TypeMismatch {
                 func: name.to_string(),
-                expected: overloads[0].args.clone(),
                 actual: arg_types.to_vec(),
-            })
+                candidates: overloads.clone(),
+            });
+        };
+
+        let mut best = candidates.into_iter().filter(|c| c.cost == best_cost);
+        let mut resolution = best.next().expect("a candidate has the lowest cost");
+        let tied: Vec<Resolution> = best.collect();
+        if !tied.is_empty() {
+            if !arg_types.iter().any(DataType::is_unknown_or_null) {
+                return Err(RegistryError::Ambiguous {
+                    func: name.to_string(),
+                    actual: arg_types.to_vec(),
+                    candidates: std::iter::once(&resolution).chain(&tied)
+                        .map(|c| c.signature.clone())
+                        .collect(),
+                });
+            }
+            if tied.iter().any(|c| c.return_type != resolution.return_type) {
+                resolution.return_type = DataType::Unknown;
+            }
+        }
+        Ok(resolution)
     }
 
     pub fn version(&self) -> &str {
@@ -136,6 +388,23 @@ impl FunctionRegistry {
     }
 }
 
+impl FunctionResolver for FunctionRegistry {
+    fn resolve_function(&self, name: &str, args: &[DataType]) -> Result<FunctionType, ValidationError> {
+        let sig = self.lookup(name, args).map_err(|e| match e {
+            RegistryError::FunctionNotFound(name) => ValidationError::FunctionNotFound(name),
+            e => ValidationError::InvalidArguments {
+                func: name.to_string(),
+                message: e.to_string(),
+            },
+        })?;
+        Ok(FunctionType {
+            return_type: sig.return_type,
+            is_aggregate: sig.signature.is_aggregate,
+            is_window: sig.signature.is_window,
+        })
+    }
+}
+
 impl Default for FunctionRegistry {
     fn default() -> Self {
         Self::new("0.1.0")
@@ -151,8 +420,9 @@ mod tests {
         let registry = FunctionRegistry::default();
 
         let sig = registry.lookup("mask", &[DataType::String]).unwrap();
-        assert_eq!(sig.name, "mask");
+        assert_eq!(sig.signature.name, "mask");
         assert_eq!(sig.return_type, DataType::String);
+        assert_eq!(sig.cost, 0);
     }
 
     #[test]
@@ -160,6 +430,124 @@ mod tests {
         let registry = FunctionRegistry::default();
 
         let sig = registry.lookup("sum", &[DataType::Float64]).unwrap();
-        assert!(sig.is_aggregate);
+        assert!(sig.signature.is_aggregate);
+    }
+
+    #[test]
+    fn test_overload_resolution() {
+        let registry = FunctionRegistry::default();
+
+        // Integers widen to the cheapest overload
+        let sum = registry.lookup("sum", &[DataType::Int32]).unwrap();
+        assert_eq!(sum.signature.args, vec![DataType::Int64]);
+        assert_eq!((sum.return_type, sum.cost), (DataType::Int64, 1));
+        let sum = registry.lookup("sum", &[DataType::Decimal { precision: 18, scale: 2 }]).unwrap();
+        assert_eq!(sum.return_type, DataType::Float64);
+        let avg = registry.lookup("avg", &[DataType::Int32]).unwrap();
+        assert_eq!(avg.return_type, DataType::Float64);
+
+        let approx = registry.lookup("approx_p", &[DataType::Int64, DataType::Float64]).unwrap();
+        assert_eq!(approx.arg_types, vec![DataType::Float64, DataType::Float64]);
+        assert_eq!(approx.cost, 3);
+        let mask = registry.lookup("mask", &[DataType::Varchar(Some(20))]).unwrap();
+        assert_eq!(mask.cost, 1);
+
+        // Polymorphic parameters bind to the arguments' common type
+        let coalesce = registry.lookup("coalesce", &[DataType::Int32, DataType::Int64]).unwrap();
+        assert_eq!(coalesce.arg_types, vec![DataType::Int64, DataType::Int64]);
+        assert_eq!(coalesce.return_type, DataType::Int64);
+        let max = registry.lookup("max", &[DataType::Date]).unwrap();
+        assert_eq!(max.return_type, DataType::Date);
+        let coalesce = registry.lookup("coalesce", &[DataType::Null, DataType::String]).unwrap();
+        assert_eq!(coalesce.return_type, DataType::String);
+
+        // Unknown arguments can't choose between overloads
+        let sum = registry.lookup("sum", &[DataType::Unknown]).unwrap();
+        assert_eq!(sum.return_type, DataType::Unknown);
+
+        let err = registry.lookup("sum", &[DataType::String]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch for function sum: no overload accepts (String). \
+             Candidates: sum(Int64) -> Int64, sum(Float64) -> Float64"
+        );
+        let err = registry.lookup("coalesce", &[DataType::Int32, DataType::Date]).unwrap_err();
+        assert!(matches!(err, RegistryError::TypeMismatch { .. }), "{}", err);
+    }
+
+    #[test]
+    fn test_ambiguous_overloads() {
+        let mut registry = FunctionRegistry::new("0.1.0");
+        for (args, return_type) in [
+            (vec![DataType::Int64, DataType::Float64], DataType::Float64),
+            (vec![DataType::Float64, DataType::Int64], DataType::Int64),
+        ] {
+            registry.register(FunctionSignature {
+                name: "pick".to_string(),
+                args,
+                return_type,
+                is_aggregate: false,
+                is_window: false,
+                is_macro: false,
+                varargs: None,
+                substrait_uri: None,
+                description: None,
+                examples: vec![],
+            });
+        }
+
+        // Each overload converts one argument to Float64
+        let err = registry.lookup("pick", &[DataType::Int64, DataType::Int64]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ambiguous call to pick(Int64, Int64). Equally good candidates: \
+             pick(Int64, Float64) -> Float64, pick(Float64, Int64) -> Int64"
+        );
+        assert_eq!(registry.lookup("pick", &[DataType::Int64, DataType::Float64]).unwrap().cost, 0);
+
+        // NULL can't choose either, so the first overload is used with an unknown result
+        let pick = registry.lookup("pick", &[DataType::Null, DataType::Null]).unwrap();
+        assert_eq!(pick.signature.args, vec![DataType::Int64, DataType::Float64]);
+        assert_eq!(pick.return_type, DataType::Unknown);
+    }
+
+    #[test]
+    fn test_validate_with_registry() {
+        use mlql_ir::substrait::{ColumnInfo, MockSchemaProvider, TableSchema};
+        use mlql_ir::validate::Validator;
+
+        let mut provider = MockSchemaProvider::new();
+        provider.add_table(TableSchema {
+            name: "users".to_string(),
+            columns: vec![
+                ColumnInfo { name: "name".to_string(), data_type: DataType::String, nullable: true },
+                ColumnInfo { name: "age".to_string(), data_type: DataType::Int32, nullable: true },
+            ],
+        });
+        let registry = FunctionRegistry::default();
+        let validator = Validator::new(&provider, &registry);
+
+        let program: mlql_ir::Program = serde_json::from_str(r#"{"pipeline": {
+            "source": {"type": "Table", "name": "users"},
+            "ops": [
+                {"op": "Map", "mappings": {"masked": {"type": "FuncCall", "func": "mask", "args": [{"type": "Column", "col": {"column": "name"}}]}}},
+                {"op": "GroupBy", "keys": [{"column": "masked"}], "aggs": {"n": {"func": "count"}}}
+            ]
+        }}"#).unwrap();
+        let schema = validator.validate(&program).unwrap();
+        assert_eq!(schema.fields[0].data_type, DataType::String);
+        assert_eq!(schema.fields[1].data_type, DataType::Int64);
+
+        let program: mlql_ir::Program = serde_json::from_str(r#"{"pipeline": {
+            "source": {"type": "Table", "name": "users"},
+            "ops": [
+                {"op": "Map", "mappings": {"masked": {"type": "FuncCall", "func": "mask", "args": [{"type": "Column", "col": {"column": "age"}}]}}},
+                {"op": "Map", "mappings": {"score": {"type": "FuncCall", "func": "scor", "args": []}}}
+            ]
+        }}"#).unwrap();
+        let errors = validator.validate(&program).unwrap_err();
+        assert_eq!(errors.0.len(), 2);
+        assert!(matches!(errors.0[0].root(), ValidationError::InvalidArguments { func, .. } if func == "mask"));
+        assert_eq!(errors.0[1].root(), &ValidationError::FunctionNotFound("scor".to_string()));
     }
 }
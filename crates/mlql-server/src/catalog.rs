@@ -1,6 +1,7 @@
 //! Database catalog extraction and management
 
 use duckdb::{Connection, Result as DuckResult};
+use mlql_registry::GraphRegistry;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::sync::Arc;
@@ -11,6 +12,9 @@ pub struct TableCatalog {
     pub columns: Vec<ColumnInfo>,
     pub sample_data: Vec<serde_json::Map<String, serde_json::Value>>,
     pub row_count: usize,
+    /// Columns covered by the table's full-text index, searchable with `bm25()`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fts_columns: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +29,9 @@ pub struct ColumnInfo {
 pub struct DatabaseCatalog {
     pub database_path: String,
     pub tables: Vec<TableCatalog>,
+    /// Graphs that `from graph(<name>)` can read, by name
+    #[serde(default, skip_serializing_if = "GraphRegistry::is_empty")]
+    pub graphs: GraphRegistry,
 }
 
 impl DatabaseCatalog {
@@ -51,6 +58,7 @@ impl DatabaseCatalog {
         Ok(DatabaseCatalog {
             database_path: path_str,
             tables,
+            graphs: crate::graphs::registry().clone(),
         })
     }
 
@@ -60,7 +68,7 @@ impl DatabaseCatalog {
         let query = format!(
             "SELECT column_name, data_type, is_nullable \
              FROM information_schema.columns \
-             WHERE table_name = '{}' \
+             WHERE table_name = '{}' AND table_schema = 'main' \
              ORDER BY ordinal_position",
             table_name
         );
@@ -141,6 +149,7 @@ impl DatabaseCatalog {
             columns: column_infos,
             sample_data,
             row_count: row_count as usize,
+            fts_columns: mlql_duck::fts_columns(conn, table_name)?,
         })
     }
 
@@ -156,6 +165,9 @@ impl DatabaseCatalog {
         for table in &self.tables {
             md.push_str(&format!("## Table: `{}`\n\n", table.name));
             md.push_str(&format!("**Rows:** {}\n\n", table.row_count));
+            if !table.fts_columns.is_empty() {
+                md.push_str(&format!("**Full-text index:** {}\n\n", table.fts_columns.join(", ")));
+            }
 
             md.push_str("### Columns\n\n");
             md.push_str("| Column | Type | Nullable | Sample Values |\n");
@@ -182,14 +194,28 @@ impl DatabaseCatalog {
             md.push_str("\n");
         }
 
+        for (name, graph) in self.graphs.iter() {
+            md.push_str(&format!("## Graph: `{}`\n\n", name));
+            md.push_str(&format!("**Nodes:** `{}` keyed by `{}`\n\n", graph.nodes, graph.node_id));
+            md.push_str(&format!("**Edges:** `{}` from `{}` to `{}`", graph.edges, graph.src, graph.dst));
+            if let Some(ref edge_type) = graph.edge_type {
+                md.push_str(&format!(", typed by `{}`", edge_type));
+            }
+            if graph.undirected {
+                md.push_str(" (undirected)");
+            }
+            md.push_str("\n\n");
+        }
+
         md
     }
 }
 
 /// DuckDB-backed schema provider for Substrait translation
 ///
-/// Queries the DuckDB information_schema at runtime to resolve table schemas.
-/// Holds a reference to a DuckDB connection for catalog queries.
+/// Queries the DuckDB information_schema at runtime to resolve table schemas,
+/// parsing column types (including nested LIST, STRUCT and MAP types) into
+/// `DataType`. Holds a reference to a DuckDB connection for catalog queries.
 pub struct DuckDbSchemaProvider {
     conn: Arc<Connection>,
 }
@@ -203,38 +229,6 @@ impl DuckDbSchemaProvider {
 
 impl mlql_ir::substrait::SchemaProvider for DuckDbSchemaProvider {
     fn get_table_schema(&self, table_name: &str) -> Result<mlql_ir::substrait::TableSchema, String> {
-        // Query information_schema for column information
-        let query = "
-            SELECT column_name, data_type, is_nullable
-            FROM information_schema.columns
-            WHERE table_name = ?
-            ORDER BY ordinal_position
-        ";
-
-        let mut stmt = self.conn.prepare(query)
-            .map_err(|e| format!("Failed to prepare schema query: {}", e))?;
-
-        let columns: Result<Vec<_>, _> = stmt
-            .query_map([table_name], |row| {
-                Ok(mlql_ir::substrait::ColumnInfo {
-                    name: row.get(0)?,
-                    data_type: row.get(1)?,
-                    nullable: row.get::<_, String>(2)? == "YES",
-                })
-            })
-            .map_err(|e| format!("Schema query failed: {}", e))?
-            .collect();
-
-        let columns = columns
-            .map_err(|e| format!("Failed to read schema rows: {}", e))?;
-
-        if columns.is_empty() {
-            return Err(format!("Table '{}' not found in database", table_name));
-        }
-
-        Ok(mlql_ir::substrait::TableSchema {
-            name: table_name.to_string(),
-            columns,
-        })
+        mlql_duck::table_schema(&self.conn, table_name)
     }
 }
@@ -6,6 +6,7 @@
 //!
 //! Environment variables always override config.yaml values.
 
+use mlql_registry::GraphRegistry;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use thiserror::Error;
@@ -58,6 +59,20 @@ impl Default for ExecutionConfig {
     }
 }
 
+/// Function registry configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FunctionsConfig {
+    /// YAML or JSON catalog of user-defined functions (DuckDB macros and UDFs)
+    /// to register next to the built-ins
+    #[serde(default)]
+    pub catalog_path: Option<String>,
+
+    /// DuckDB database whose functions, macros and installed extensions'
+    /// functions to import at startup (":memory:" for the engine's built-ins)
+    #[serde(default)]
+    pub discover_from: Option<String>,
+}
+
 /// Logging configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
@@ -90,6 +105,11 @@ impl Default for LoggingConfig {
 pub struct Config {
     pub server: ServerConfig,
     pub execution: ExecutionConfig,
+    #[serde(default)]
+    pub functions: FunctionsConfig,
+    /// Graphs by name: the tables holding their nodes and edges
+    #[serde(default)]
+    pub graphs: GraphRegistry,
     pub logging: LoggingConfig,
 }
 
@@ -98,6 +118,8 @@ impl Default for Config {
         Self {
             server: ServerConfig::default(),
             execution: ExecutionConfig::default(),
+            functions: FunctionsConfig::default(),
+            graphs: GraphRegistry::default(),
             logging: LoggingConfig::default(),
         }
     }
@@ -127,6 +149,14 @@ impl Config {
             config.execution.substrait_extension_path = Some(path);
         }
 
+        if let Ok(path) = std::env::var("MLQL_FUNCTION_CATALOG") {
+            config.functions.catalog_path = Some(path);
+        }
+
+        if let Ok(database) = std::env::var("MLQL_DISCOVER_FUNCTIONS") {
+            config.functions.discover_from = Some(database);
+        }
+
         if let Ok(level) = std::env::var("RUST_LOG") {
             config.logging.level = level;
         }
@@ -168,6 +198,9 @@ mod tests {
         assert_eq!(config.server.host, "127.0.0.1");
         assert_eq!(config.server.port, 8080);
         assert_eq!(config.execution.mode, "sql");
+        assert_eq!(config.functions.catalog_path, None);
+        assert_eq!(config.functions.discover_from, None);
+        assert!(config.graphs.is_empty());
         assert_eq!(config.logging.level, "info");
         assert_eq!(config.logging.format, "pretty");
         assert_eq!(config.logging.output, "stdout");
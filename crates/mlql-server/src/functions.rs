@@ -0,0 +1,82 @@
+//! Function registry shared by validation, Substrait translation and the LLM prompt
+
+use crate::config::FunctionsConfig;
+use mlql_registry::{FunctionCatalog, FunctionRegistry, RegistryError};
+use std::sync::OnceLock;
+
+static REGISTRY: OnceLock<FunctionRegistry> = OnceLock::new();
+
+/// The built-ins and the catalog, without discovered functions; the LLM
+/// prompt lists only these in full
+static DOCUMENTED: OnceLock<FunctionRegistry> = OnceLock::new();
+
+/// Build the registry from the built-ins, the functions of the DuckDB
+/// database to discover from, and the function catalog, and return the
+/// number of overloads added.
+///
+/// Call once at startup. On failure the registry keeps whatever loaded
+/// before the error.
+pub fn init(config: &FunctionsConfig) -> Result<usize, RegistryError> {
+    let mut registry = FunctionRegistry::default();
+    let mut documented = FunctionRegistry::default();
+    let loaded = load(&mut registry, &mut documented, config);
+    let _ = REGISTRY.set(registry);
+    let _ = DOCUMENTED.set(documented);
+    loaded
+}
+
+/// The server's function registry; the built-ins if [`init`] has not run
+pub fn registry() -> &'static FunctionRegistry {
+    REGISTRY.get_or_init(FunctionRegistry::default)
+}
+
+/// The built-in and catalog functions; the built-ins if [`init`] has not run
+pub fn documented() -> &'static FunctionRegistry {
+    DOCUMENTED.get_or_init(FunctionRegistry::default)
+}
+
+fn load(
+    registry: &mut FunctionRegistry,
+    documented: &mut FunctionRegistry,
+    config: &FunctionsConfig,
+) -> Result<usize, RegistryError> {
+    let mut count = 0;
+
+    // Discover first, so catalog entries refine the discovered signatures
+    if let Some(ref database) = config.discover_from {
+        let conn = duckdb::Connection::open(database)?;
+        load_installed_extensions(&conn);
+        count += registry.discover_from_duckdb(&conn)?;
+    }
+
+    if let Some(ref path) = config.catalog_path {
+        let catalog = FunctionCatalog::load(path)?;
+        count += registry.register_catalog(&catalog)?;
+        documented.register_catalog(&catalog)?;
+    }
+
+    Ok(count)
+}
+
+/// Load every installed extension, so its functions are discovered too
+fn load_installed_extensions(conn: &duckdb::Connection) {
+    let extensions = match installed_extensions(conn) {
+        Ok(extensions) => extensions,
+        Err(e) => {
+            tracing::warn!("Failed to list installed DuckDB extensions: {}", e);
+            return;
+        }
+    };
+
+    for extension in extensions {
+        if let Err(e) = conn.execute_batch(&format!("LOAD {}", extension)) {
+            tracing::warn!("Failed to load DuckDB extension {}: {}", extension, e);
+        }
+    }
+}
+
+fn installed_extensions(conn: &duckdb::Connection) -> duckdb::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT extension_name FROM duckdb_extensions() WHERE installed AND NOT loaded")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
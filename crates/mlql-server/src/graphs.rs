@@ -0,0 +1,16 @@
+//! Graphs from the config file, read by `from graph(<name>)` sources
+
+use mlql_registry::GraphRegistry;
+use std::sync::OnceLock;
+
+static GRAPHS: OnceLock<GraphRegistry> = OnceLock::new();
+
+/// Register the configured graphs. Call once at startup.
+pub fn init(graphs: GraphRegistry) {
+    let _ = GRAPHS.set(graphs);
+}
+
+/// The server's graphs; none if [`init`] has not run
+pub fn registry() -> &'static GraphRegistry {
+    GRAPHS.get_or_init(GraphRegistry::default)
+}
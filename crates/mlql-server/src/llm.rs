@@ -7,7 +7,9 @@ use async_openai::{
     },
     Client, config::OpenAIConfig,
 };
-use mlql_ir::Pipeline;
+use mlql_ir::{DataType, Pipeline, Program};
+use mlql_registry::FunctionRegistry;
+use std::collections::HashSet;
 
 /// System prompt for OpenAI - teaches it to generate MLQL IR
 const SYSTEM_PROMPT: &str = r#"You are an expert at converting natural language queries into MLQL IR (Intermediate Representation) in JSON format.
@@ -127,6 +129,16 @@ you MUST use the Aliased projection type with a name that subsequent operators c
 }
 ```
 
+Optional execution limits can be set with a top-level "pragma" next to "pipeline":
+```json
+{
+  "pragma": {"options": {"timeout": 30000, "budget": {"rows_out": 1000}}},
+  "pipeline": { ... }
+}
+```
+Recognized pragma keys: timeout (ms), budget.rows_out, memory_mb, execution_mode ("sql" or "substrait").
+Only add a pragma when the user asks for a limit.
+
 Binary Operators:
 - Arithmetic: Add, Sub, Mul, Div, Mod
 - Comparison: Eq, Ne, Lt, Gt, Le, Ge, Like
@@ -351,12 +363,61 @@ Response:
 
 Return ONLY the JSON, no other text."#;
 
+/// System prompt with the optional table catalog and the registered functions.
+///
+/// Overloads in `documented` are listed one per line; other functions in
+/// `functions`, such as the hundreds discovered from DuckDB, only by name.
+fn system_prompt(catalog_json: Option<&str>, documented: &FunctionRegistry, functions: &FunctionRegistry) -> String {
+    let mut prompt = SYSTEM_PROMPT.to_string();
+    if let Some(catalog) = catalog_json {
+        prompt.push_str(&format!("\n\n## Database Catalog\n\nThe following tables are available in the database. Use this information to construct accurate queries:\n\n{}", catalog));
+    }
+
+    // One line per overload, e.g. `- mask(String) -> String: Mask ... Example: mask(email)`
+    prompt.push_str("\n\n## Functions\n\nCall these with FuncCall expressions; aggregate functions go in GroupBy aggs. ANY accepts any type:\n");
+    for sig in documented.signatures() {
+        let args: Vec<_> = sig.args.iter().map(type_name).collect();
+        prompt.push_str(&format!("\n- {}({}) -> {}", sig.name, args.join(", "), type_name(&sig.return_type)));
+        if sig.is_aggregate {
+            prompt.push_str(" [aggregate]");
+        } else if sig.is_window {
+            prompt.push_str(" [window]");
+        }
+        if let Some(description) = &sig.description {
+            prompt.push_str(&format!(": {}", description));
+        }
+        if !sig.examples.is_empty() {
+            prompt.push_str(&format!(". Example: {}", sig.examples.join(", ")));
+        }
+    }
+
+    let listed: HashSet<&str> = documented.signatures().into_iter().map(|sig| sig.name.as_str()).collect();
+    let mut others: Vec<&str> = functions.signatures()
+        .into_iter()
+        .map(|sig| sig.name.as_str())
+        .filter(|name| !listed.contains(name))
+        .collect();
+    others.dedup();
+    if !others.is_empty() {
+        prompt.push_str(&format!("\n\nThese DuckDB functions are also available, with DuckDB's argument types: {}", others.join(", ")));
+    }
+    prompt
+}
+
+/// Name of a parameter type, `ANY` when polymorphic
+fn type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Unknown => "ANY".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
 /// Convert natural language query to MLQL IR using OpenAI with error retry loop
 #[allow(dead_code)]
 pub async fn natural_language_to_ir(
     client: &Client<OpenAIConfig>,
     query: &str,
-) -> Result<Pipeline, Box<dyn std::error::Error>> {
+) -> Result<Program, Box<dyn std::error::Error>> {
     natural_language_to_ir_with_catalog(client, query, None).await
 }
 
@@ -365,15 +426,10 @@ pub async fn natural_language_to_ir_with_catalog(
     client: &Client<OpenAIConfig>,
     query: &str,
     catalog_json: Option<&str>,
-) -> Result<Pipeline, Box<dyn std::error::Error>> {
+) -> Result<Program, Box<dyn std::error::Error>> {
     const MAX_RETRIES: usize = 3;
 
-    // Build system prompt with optional catalog
-    let system_prompt = if let Some(catalog) = catalog_json {
-        format!("{}\n\n## Database Catalog\n\nThe following tables are available in the database. Use this information to construct accurate queries:\n\n{}", SYSTEM_PROMPT, catalog)
-    } else {
-        SYSTEM_PROMPT.to_string()
-    };
+    let system_prompt = system_prompt(catalog_json, crate::functions::documented(), crate::functions::registry());
 
     let mut messages = vec![
         ChatCompletionRequestMessage::System(
@@ -408,24 +464,28 @@ pub async fn natural_language_to_ir_with_catalog(
 
         tracing::info!("LLM Response (attempt {}): {}", attempt + 1, content);
 
-        // Try to parse JSON response - first try as wrapper with "pipeline" key
-        let pipeline_result = serde_json::from_str::<serde_json::Value>(content)
+        // Try to parse JSON response - first try as Program wrapper with "pipeline" key
+        let program_result = serde_json::from_str::<serde_json::Value>(content)
             .ok()
             .and_then(|v| {
-                // Try to extract pipeline from wrapper
-                if let Some(pipeline_value) = v.get("pipeline") {
-                    match serde_json::from_value::<Pipeline>(pipeline_value.clone()) {
+                // Parse the wrapper as a Program so any pragma comes along
+                if v.get("pipeline").is_some() {
+                    match serde_json::from_value::<Program>(v.clone()) {
                         Ok(p) => Some(p),
                         Err(e) => {
-                            tracing::warn!("Failed to parse pipeline from wrapper: {}", e);
-                            tracing::warn!("Attempted to parse: {}", pipeline_value);
+                            tracing::warn!("Failed to parse program from wrapper: {}", e);
+                            tracing::warn!("Attempted to parse: {}", v);
                             None
                         }
                     }
                 } else {
                     // Try parsing whole thing as Pipeline directly
                     match serde_json::from_value::<Pipeline>(v.clone()) {
-                        Ok(p) => Some(p),
+                        Ok(pipeline) => Some(Program {
+                            pragma: None,
+                            lets: vec![],
+                            pipeline,
+                        }),
                         Err(e) => {
                             tracing::warn!("Failed to parse as direct Pipeline: {}", e);
                             tracing::warn!("Attempted to parse: {}", v);
@@ -435,10 +495,10 @@ pub async fn natural_language_to_ir_with_catalog(
                 }
             });
 
-        match pipeline_result {
-            Some(pipeline) => {
-                // Success! Return the pipeline
-                return Ok(pipeline);
+        match program_result {
+            Some(program) => {
+                // Success! Return the program
+                return Ok(program);
             }
             None => {
                 if attempt == MAX_RETRIES - 1 {
@@ -487,4 +547,25 @@ mod tests {
         assert!(SYSTEM_PROMPT.contains("GroupBy"));
         assert!(SYSTEM_PROMPT.contains("Join"));
     }
+
+    #[test]
+    fn test_system_prompt_lists_functions() {
+        let mut functions = FunctionRegistry::default();
+        functions.register_catalog(&mlql_registry::FunctionCatalog::from_json(r#"{"functions": [
+            {"name": "revenue_band", "args": ["DOUBLE"], "returns": "VARCHAR",
+             "description": "Bucket revenue", "examples": ["revenue_band(total)"]}
+        ]}"#).unwrap()).unwrap();
+
+        let prompt = system_prompt(Some("{}"), &functions, &functions);
+        assert!(prompt.contains("## Database Catalog"));
+        assert!(prompt.contains("- revenue_band(Float64) -> String: Bucket revenue. Example: revenue_band(total)"), "{}", prompt);
+        assert!(prompt.contains("- count() -> Int64 [aggregate]"), "{}", prompt);
+        assert!(!prompt.contains("also available"), "{}", prompt);
+
+        // Functions outside the documented registry are only named, once
+        let documented = FunctionRegistry::default();
+        let prompt = system_prompt(None, &documented, &functions);
+        assert!(!prompt.contains("- revenue_band("), "{}", prompt);
+        assert!(prompt.ends_with("These DuckDB functions are also available, with DuckDB's argument types: revenue_band"), "{}", prompt);
+    }
 }
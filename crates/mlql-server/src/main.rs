@@ -7,6 +7,8 @@ use rust_mcp_sdk::mcp_server::{hyper_server, HyperServerOptions};
 
 mod catalog;
 mod config;
+mod functions;
+mod graphs;
 mod llm;
 mod logging;
 mod mcp;
@@ -48,6 +50,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     logging::init();
     eprintln!("    ✅ Logging initialized");
 
+    // Register discovered and user-defined functions for validation, Substrait and the LLM prompt
+    match functions::init(&config.functions) {
+        Ok(0) => {}
+        Ok(count) => eprintln!("    ✅ Registered {} function overloads", count),
+        Err(e) => {
+            eprintln!("⚠️  Warning: Failed to load functions: {}", e);
+            eprintln!("    Continuing with the functions loaded so far");
+        }
+    }
+
+    // Graphs for `from graph(<name>)` sources
+    if !config.graphs.is_empty() {
+        let names: Vec<&str> = config.graphs.iter().map(|(name, _)| name).collect();
+        eprintln!("    ✅ Registered graphs: {}", names.join(", "));
+    }
+    graphs::init(config.graphs.clone());
+
     eprintln!("\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     eprintln!("Configuration Summary:");
     eprintln!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
@@ -57,6 +76,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if let Some(ref path) = config.execution.substrait_extension_path {
         eprintln!("  Substrait Path:     {}", path);
     }
+    if let Some(ref database) = config.functions.discover_from {
+        eprintln!("  Discover Functions: {}", database);
+    }
+    if let Some(ref path) = config.functions.catalog_path {
+        eprintln!("  Function Catalog:   {}", path);
+    }
     eprintln!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\n");
 
     // Get OpenAI API key from environment (.env file)
@@ -42,7 +42,9 @@ impl MlqlServerHandler {
             },
             instructions: Some(
                 "MLQL Server - Natural language to SQL queries. \
-                 Use the 'query' tool to execute natural language database queries."
+                 Use the 'query' tool to execute natural language database queries, \
+                 or the 'mlql' tool to run MLQL text directly. \
+                 The 'describe' tool returns the columns an MLQL query would produce without running it."
                     .to_string(),
             ),
             meta: None,
@@ -87,6 +89,73 @@ impl MlqlServerHandler {
             });
         }
 
+        // MLQL tool
+        {
+            let mut properties = HashMap::new();
+
+            let mut source_prop = Map::new();
+            source_prop.insert("type".to_string(), Value::String("string".to_string()));
+            source_prop.insert("description".to_string(), Value::String("MLQL query text (e.g., 'from users | filter age > 25 | select [name]')".to_string()));
+            properties.insert("source".to_string(), source_prop);
+
+            let mut database_prop = Map::new();
+            database_prop.insert("type".to_string(), Value::String("string".to_string()));
+            database_prop.insert("description".to_string(), Value::String("Path to DuckDB database file (defaults to data/demo.duckdb)".to_string()));
+            database_prop.insert("default".to_string(), Value::String("data/demo.duckdb".to_string()));
+            properties.insert("database".to_string(), database_prop);
+
+            tools.push(Tool {
+                name: "mlql".to_string(),
+                description: Some(
+                    "Execute an MLQL query against DuckDB. \
+                     Syntax and translation errors are returned as diagnostics with the \
+                     line, column, a caret-annotated snippet and the expected alternatives."
+                        .to_string(),
+                ),
+                input_schema: ToolInputSchema::new(
+                    vec!["source".to_string()],
+                    Some(properties),
+                ),
+                title: None,
+                annotations: None,
+                meta: None,
+                output_schema: None,
+            });
+        }
+
+        // Describe tool
+        {
+            let mut properties = HashMap::new();
+
+            let mut source_prop = Map::new();
+            source_prop.insert("type".to_string(), Value::String("string".to_string()));
+            source_prop.insert("description".to_string(), Value::String("MLQL query text (e.g., 'from users | group by city { n: count() }')".to_string()));
+            properties.insert("source".to_string(), source_prop);
+
+            let mut database_prop = Map::new();
+            database_prop.insert("type".to_string(), Value::String("string".to_string()));
+            database_prop.insert("description".to_string(), Value::String("Path to DuckDB database file (defaults to data/demo.duckdb)".to_string()));
+            database_prop.insert("default".to_string(), Value::String("data/demo.duckdb".to_string()));
+            properties.insert("database".to_string(), database_prop);
+
+            tools.push(Tool {
+                name: "describe".to_string(),
+                description: Some(
+                    "Describe the result of an MLQL query without running it. \
+                     Returns each output column's name, type and nullability."
+                        .to_string(),
+                ),
+                input_schema: ToolInputSchema::new(
+                    vec!["source".to_string()],
+                    Some(properties),
+                ),
+                title: None,
+                annotations: None,
+                meta: None,
+                output_schema: None,
+            });
+        }
+
         // Catalog tool
         {
             let mut properties = HashMap::new();
@@ -144,6 +213,8 @@ impl ServerHandler for MlqlServerHandler {
 
         match request.params.name.as_str() {
             "query" => self.handle_query_tool(request.params.arguments.map(|m| serde_json::Value::Object(m))).await,
+            "mlql" => self.handle_mlql_tool(request.params.arguments.map(|m| serde_json::Value::Object(m))).await,
+            "describe" => self.handle_describe_tool(request.params.arguments.map(|m| serde_json::Value::Object(m))).await,
             "catalog" => self.handle_catalog_tool(request.params.arguments.map(|m| serde_json::Value::Object(m))).await,
             _ => Err(CallToolError::unknown_tool(request.params.name.clone())),
         }
@@ -207,7 +278,9 @@ impl MlqlServerHandler {
             CallToolError::from_message(format!("Failed to convert query to MLQL IR: {}", e))
         })?;
 
+        let mlql = mlql_ast::print(&ir);
         info!("Generated IR: {}", serde_json::to_string_pretty(&ir).unwrap_or_default());
+        info!("Generated MLQL:\n{}", mlql);
 
         // Step 3: Execute IR against DuckDB (uses MLQL_EXECUTION_MODE env var)
         let (execution_info, results) = query::execute_ir_auto(ir.clone(), database)
@@ -223,8 +296,9 @@ impl MlqlServerHandler {
 
         // Format response as MCP content
         let response_text = format!(
-            "Query: {}\n\nGenerated IR:\n{}\n\nExecution: {}\n\nResults:\n{}",
+            "Query: {}\n\nGenerated MLQL:\n{}\n\nGenerated IR:\n{}\n\nExecution: {}\n\nResults:\n{}",
             query,
+            mlql,
             serde_json::to_string_pretty(&ir).unwrap_or_default(),
             execution_info,
             serde_json::to_string_pretty(&results).unwrap_or_default()
@@ -242,6 +316,148 @@ impl MlqlServerHandler {
         })
     }
 
+    async fn handle_mlql_tool(
+        &self,
+        arguments: Option<serde_json::Value>,
+    ) -> std::result::Result<CallToolResult, CallToolError> {
+        let args = arguments.ok_or_else(|| CallToolError::from_message("Missing arguments"))?;
+
+        let source = args
+            .get("source")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| CallToolError::from_message("Missing required argument: source"))?
+            .to_string();
+
+        let database = args
+            .get("database")
+            .and_then(|v| v.as_str())
+            .map(String::from)
+            .or_else(|| Some("data/demo.duckdb".to_string()));
+
+        info!("Executing MLQL: {}", source);
+
+        // Step 1: Parse, reporting syntax errors against the source text
+        let program = match mlql_ast::parse(&source) {
+            Ok(program) => program,
+            Err(e) => {
+                error!("Failed to parse MLQL: {}", e);
+                return Ok(diagnostic_result(e.diagnostic()));
+            }
+        };
+        let source_map = program.source_map();
+        let ir = program.to_ir();
+
+        // Step 2: Execute, mapping located translation errors back to the source text
+        let (execution_info, results) = match query::execute_ir_auto(ir, database).await {
+            Ok(output) => output,
+            Err(e) => {
+                error!("Failed to execute MLQL: {}", e);
+                return match query::error_location(e.as_ref()) {
+                    Some((location, message, expected)) => {
+                        let diagnostic = source_map
+                            .diagnostic(&source, &location, message)
+                            .with_expected(expected);
+                        Ok(diagnostic_result(&diagnostic))
+                    }
+                    None => Err(CallToolError::from_message(format!("Failed to execute query: {}", e))),
+                };
+            }
+        };
+
+        let response_text = format!(
+            "Execution: {}\n\nResults:\n{}",
+            execution_info,
+            serde_json::to_string_pretty(&results).unwrap_or_default()
+        );
+
+        Ok(CallToolResult {
+            content: vec![ContentBlock::TextContent(TextContent::new(
+                response_text,
+                None,
+                None,
+            ))],
+            is_error: None,
+            meta: None,
+            structured_content: None,
+        })
+    }
+
+    async fn handle_describe_tool(
+        &self,
+        arguments: Option<serde_json::Value>,
+    ) -> std::result::Result<CallToolResult, CallToolError> {
+        let args = arguments.ok_or_else(|| CallToolError::from_message("Missing arguments"))?;
+
+        let source = args
+            .get("source")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| CallToolError::from_message("Missing required argument: source"))?
+            .to_string();
+
+        let database = args
+            .get("database")
+            .and_then(|v| v.as_str())
+            .map(String::from)
+            .or_else(|| Some("data/demo.duckdb".to_string()));
+
+        info!("Describing MLQL: {}", source);
+
+        let program = match mlql_ast::parse(&source) {
+            Ok(program) => program,
+            Err(e) => {
+                error!("Failed to parse MLQL: {}", e);
+                return Ok(diagnostic_result(e.diagnostic()));
+            }
+        };
+
+        let source_map = program.source_map();
+
+        // Validation errors with a location are reported against the source text
+        let schema = match query::describe_ir(&program.to_ir(), database).await {
+            Ok(schema) => schema,
+            Err(e) => {
+                error!("Failed to describe query: {}", e);
+                return match query::error_location(e.as_ref()) {
+                    Some((location, message, expected)) => {
+                        let diagnostic = source_map
+                            .diagnostic(&source, &location, message)
+                            .with_expected(expected);
+                        Ok(diagnostic_result(&diagnostic))
+                    }
+                    None => Err(CallToolError::from_message(format!("Failed to describe query: {}", e))),
+                };
+            }
+        };
+
+        // One line per column, e.g. `age: Int32 (nullable)`
+        let response_text = schema.fields.iter()
+            .map(|f| format!(
+                "{}: {:?}{}",
+                f.name,
+                f.data_type,
+                if f.nullable { " (nullable)" } else { "" }
+            ))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut structured = Map::new();
+        structured.insert(
+            "schema".to_string(),
+            serde_json::to_value(&schema.fields).unwrap_or_default(),
+        );
+
+        Ok(CallToolResult {
+            content: vec![ContentBlock::TextContent(TextContent::new(
+                response_text,
+                None,
+                None,
+            ))],
+            is_error: None,
+            meta: None,
+            structured_content: Some(structured),
+        })
+    }
+
     async fn handle_catalog_tool(
         &self,
         arguments: Option<serde_json::Value>,
@@ -292,3 +508,24 @@ impl MlqlServerHandler {
         })
     }
 }
+
+/// Tool result for an error located in the MLQL source: the rendered
+/// diagnostic as text, and its structured form under `diagnostic`
+fn diagnostic_result(diagnostic: &mlql_ast::Diagnostic) -> CallToolResult {
+    let mut structured = Map::new();
+    structured.insert(
+        "diagnostic".to_string(),
+        serde_json::to_value(diagnostic).unwrap_or_default(),
+    );
+
+    CallToolResult {
+        content: vec![ContentBlock::TextContent(TextContent::new(
+            diagnostic.render(),
+            None,
+            None,
+        ))],
+        is_error: Some(true),
+        meta: None,
+        structured_content: Some(structured),
+    }
+}
@@ -1,9 +1,13 @@
 //! Query execution against DuckDB using MLQL IR
 
-use mlql_duck::{DuckExecutor, QueryResult};
-use mlql_ir::{Pipeline, Program};
+use mlql_duck::{DuckExecutor, ExecutionBudget, ExecutionError, QueryResult};
+use mlql_ir::substrait::TranslateError;
+use mlql_ir::validate::{ValidationErrors, Validator};
+use mlql_ir::{OpLocation, Program, Schema};
+use prost::Message;
 use serde_json::json;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Execution mode for MLQL queries
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -21,35 +25,46 @@ impl ExecutionMode {
     /// - "sql" → ExecutionMode::Sql (fallback mode)
     /// - anything else → ExecutionMode::Substrait (default)
     pub fn from_env() -> Self {
-        match std::env::var("MLQL_EXECUTION_MODE")
-            .unwrap_or_else(|_| "substrait".to_string())
-            .to_lowercase()
-            .as_str()
-        {
+        Self::from_name(
+            &std::env::var("MLQL_EXECUTION_MODE").unwrap_or_else(|_| "substrait".to_string()),
+        )
+    }
+
+    /// Parse a mode name: "sql" → Sql, anything else → Substrait
+    pub fn from_name(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
             "sql" => ExecutionMode::Sql,
             _ => ExecutionMode::Substrait,
         }
     }
 }
 
-/// Execute MLQL IR with automatic mode selection based on environment
+/// Execute MLQL IR with automatic mode selection
 ///
-/// Uses `MLQL_EXECUTION_MODE` environment variable to choose execution path:
+/// The program's `execution_mode` pragma takes precedence; otherwise the
+/// `MLQL_EXECUTION_MODE` environment variable chooses the execution path:
 /// - "sql" → SQL-based execution (fallback mode)
 /// - anything else → Substrait-based execution (default)
 pub async fn execute_ir_auto(
-    pipeline: Pipeline,
+    program: Program,
     database: Option<String>,
 ) -> Result<(String, serde_json::Value), Box<dyn std::error::Error>> {
-    match ExecutionMode::from_env() {
-        ExecutionMode::Substrait => execute_ir_substrait(pipeline, database).await,
-        ExecutionMode::Sql => execute_ir(pipeline, database).await,
+    let mode = program.pragma.as_ref()
+        .and_then(|p| p.execution_mode())
+        .map(ExecutionMode::from_name)
+        .unwrap_or_else(ExecutionMode::from_env);
+
+    match mode {
+        ExecutionMode::Substrait => execute_ir_substrait(program, database).await,
+        ExecutionMode::Sql => execute_ir(program, database).await,
     }
 }
 
 /// Execute MLQL IR against DuckDB and return SQL + results
+///
+/// Budget pragmas (`timeout`, `memory_mb`, `budget.rows_out`) are enforced by the executor.
 pub async fn execute_ir(
-    pipeline: Pipeline,
+    program: Program,
     database: Option<String>,
 ) -> Result<(String, serde_json::Value), Box<dyn std::error::Error>> {
     // For now, create a new executor per-request since DuckDB Connection is not Send+Sync
@@ -58,14 +73,7 @@ pub async fn execute_ir(
         DuckExecutor::open(db_path)?
     } else {
         DuckExecutor::new()?
-    };
-
-    // Convert Pipeline to Program for execution
-    let program = Program {
-        pragma: None,
-        lets: vec![],
-        pipeline: pipeline.clone(),
-    };
+    }.with_graphs(crate::graphs::registry().clone());
 
     // Execute program and capture SQL
     let result = executor.execute_ir(&program, None)?;
@@ -82,9 +90,31 @@ pub async fn execute_ir(
     Ok((sql, json_result))
 }
 
+/// Describe the result of MLQL IR without running it
+///
+/// Returns the name, type and nullability of each output column, inferred
+/// from the program, the tables in the database and the registered functions.
+/// Invalid programs fail with [`ValidationErrors`].
+pub async fn describe_ir(
+    program: &Program,
+    database: Option<String>,
+) -> Result<Schema, Box<dyn std::error::Error>> {
+    let executor = if let Some(db_path) = database {
+        DuckExecutor::open(db_path)?
+    } else {
+        DuckExecutor::new()?
+    };
+
+    let schema = Validator::new(&executor, crate::functions::registry()).validate(program)?;
+    Ok(schema)
+}
+
 /// Execute MLQL IR via Substrait translation (new execution path)
+///
+/// Budget pragmas (`timeout`, `memory_mb`, `budget.rows_out`) are applied to the
+/// connection and checked while results are fetched.
 pub async fn execute_ir_substrait(
-    pipeline: Pipeline,
+    program: Program,
     database: Option<String>,
 ) -> Result<(String, serde_json::Value), Box<dyn std::error::Error>> {
     use mlql_ir::substrait::SubstraitTranslator;
@@ -111,65 +141,82 @@ pub async fn execute_ir_substrait(
     tracing::debug!("Creating schema provider");
     let schema_provider = DuckDbSchemaProvider::new(conn.clone());
 
-    // 4. Initialize translator
+    // 4. Initialize translator, resolving custom functions such as mask()
+    // and catalog functions to their mlql-registry extension URIs
     tracing::debug!("Initializing Substrait translator");
-    let translator = SubstraitTranslator::new(&schema_provider);
-
-    // 5. Convert Pipeline to Program
-    tracing::debug!("Converting pipeline to program");
-    let program = Program {
-        pragma: None,
-        lets: vec![],
-        pipeline: pipeline.clone(),
-    };
+    let translator = SubstraitTranslator::new(&schema_provider)
+        .with_extensions(crate::functions::registry());
+    let schema = mlql_ir::infer_schema(&program, &schema_provider);
+
+    // 5. Derive execution budget from pragma
+    let budget = program.pragma.as_ref()
+        .map(ExecutionBudget::from_pragma)
+        .unwrap_or_default();
+    tracing::debug!("Execution budget: {:?}", budget);
+    // The connection is dropped after this request, so the previous memory
+    // limit needn't be restored
+    budget.apply(&conn)?;
 
     // 6. Translate to Substrait
     tracing::debug!("Translating to Substrait plan");
+    // Keep the TranslateError itself so callers can recover its location
     let plan = translator.translate(&program)
         .map_err(|e| {
             tracing::error!("Substrait translation failed: {}", e);
-            format!("Substrait translation failed: {}", e)
+            e
         })?;
     tracing::debug!("Substrait plan generated successfully");
 
-    // 7. Serialize to JSON (using prost-reflect for protobuf → JSON)
-    tracing::debug!("Serializing plan to JSON");
-    let plan_json = serde_json::to_string(&plan)
-        .map_err(|e| {
-            tracing::error!("JSON serialization failed: {}", e);
-            format!("JSON serialization failed: {}", e)
-        })?;
-    tracing::info!("Generated Substrait JSON ({} chars): {}", plan_json.len(), plan_json);
-
-    // 8. Execute via from_substrait_json() - inline JSON to avoid DuckDB 1.4.x parameter binding bug
-    tracing::debug!("Preparing from_substrait_json CALL with inlined JSON");
-
-    // Escape single quotes in JSON for SQL string literal
-    let escaped_json = plan_json.replace("'", "''");
-
-    // Inline the JSON directly in SQL (workaround for DuckDB 1.4.x hanging with parameters)
-    let query = format!("SELECT * FROM from_substrait_json('{}')", escaped_json);
-
-    tracing::debug!("Executing from_substrait_json with {} chars", plan_json.len());
-    let mut stmt = conn.prepare(&query)?;
-    let mut rows = stmt.query([])?; // No parameters - JSON is inlined
+    // 7. Serialize to protobuf bytes; the JSON form is only logged
+    tracing::debug!("Serializing plan to protobuf");
+    let plan_bytes = plan.encode_to_vec();
+    if let Ok(plan_json) = serde_json::to_string(&plan) {
+        tracing::debug!("Generated Substrait plan: {}", plan_json);
+    }
+    tracing::info!("Generated Substrait plan ({} bytes)", plan_bytes.len());
+
+    // 8. Execute via from_substrait() with the plan bound as a parameter,
+    // so no part of the plan is spliced into SQL text
+    tracing::debug!("Executing from_substrait with {} bytes", plan_bytes.len());
+    let started = Instant::now();
+    let mut stmt = conn.prepare("SELECT * FROM from_substrait(?)")?;
+    let mut rows = stmt.query([&plan_bytes])?;
     tracing::debug!("Query executed, processing results");
 
     // 9. Convert rows to JSON
-    let json_result = duckdb_rows_to_json(&mut rows)?;
+    let json_result = duckdb_rows_to_json(&mut rows, &schema, &budget, started)?;
     tracing::debug!("Results converted to JSON");
 
     // 10. Return plan info + results
-    let plan_info = format!("Substrait plan: {} chars JSON", plan_json.len());
+    let plan_info = format!("Substrait plan: {} bytes", plan_bytes.len());
     tracing::info!("Substrait execution complete: {} rows",
         json_result.get("row_count").and_then(|v| v.as_u64()).unwrap_or(0));
     Ok((plan_info, json_result))
 }
 
+/// Location, message and expected alternatives of an execution error that
+/// points at a specific source or operator of the program
+pub fn error_location(
+    error: &(dyn std::error::Error + 'static),
+) -> Option<(OpLocation, String, Vec<String>)> {
+    if let Some(e) = error.downcast_ref::<TranslateError>() {
+        return e.location().map(|location| (location, e.root().to_string(), e.expected()));
+    }
+    if let Some(e) = error.downcast_ref::<ExecutionError>() {
+        return e.location().map(|location| (location, e.root().to_string(), Vec::new()));
+    }
+    if let Some(ValidationErrors(errors)) = error.downcast_ref::<ValidationErrors>() {
+        return errors.iter().find_map(|e| {
+            e.location().map(|location| (location, e.root().to_string(), e.expected()))
+        });
+    }
+    None
+}
+
 /// Load Substrait extension into DuckDB connection
 fn load_substrait_extension(conn: &duckdb::Connection) -> Result<(), Box<dyn std::error::Error>> {
     // First, check if the extension is already loaded (e.g., statically linked in custom build)
-    let check_query = "SELECT COUNT(*) FROM duckdb_functions() WHERE function_name = 'from_substrait_json'";
+    let check_query = "SELECT COUNT(*) FROM duckdb_functions() WHERE function_name = 'from_substrait'";
     if let Ok(mut stmt) = conn.prepare(check_query) {
         if let Ok(count) = stmt.query_row([], |row| row.get::<_, i64>(0)) {
             if count > 0 {
@@ -217,8 +264,15 @@ fn load_substrait_extension(conn: &duckdb::Connection) -> Result<(), Box<dyn std
     Ok(())
 }
 
-/// Convert DuckDB rows to JSON format
-fn duckdb_rows_to_json(rows: &mut duckdb::Rows) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+/// Convert DuckDB rows to JSON format, enforcing the row and time budget
+///
+/// Column names come from the inferred `schema` when there are no rows.
+fn duckdb_rows_to_json(
+    rows: &mut duckdb::Rows,
+    schema: &Schema,
+    budget: &ExecutionBudget,
+    started: Instant,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
     let mut json_rows = Vec::new();
     let mut columns = Vec::new();
 
@@ -238,6 +292,7 @@ fn duckdb_rows_to_json(rows: &mut duckdb::Rows) -> Result<serde_json::Value, Box
         }
         json_rows.push(serde_json::Value::Object(row_obj));
     }
+    budget.check(json_rows.len(), started)?;
 
     // Process remaining rows
     while let Some(row) = rows.next()? {
@@ -247,10 +302,16 @@ fn duckdb_rows_to_json(rows: &mut duckdb::Rows) -> Result<serde_json::Value, Box
             row_obj.insert(col_name.clone(), value);
         }
         json_rows.push(serde_json::Value::Object(row_obj));
+        budget.check(json_rows.len(), started)?;
+    }
+
+    if columns.is_empty() {
+        columns = schema.fields.iter().map(|f| f.name.clone()).collect();
     }
 
     Ok(json!({
         "columns": columns,
+        "schema": schema.fields,
         "rows": json_rows,
         "row_count": json_rows.len()
     }))
@@ -298,6 +359,7 @@ fn result_to_json(result: &QueryResult) -> Result<serde_json::Value, Box<dyn std
 
     Ok(json!({
         "columns": result.columns,
+        "schema": result.schema.as_ref().map(|schema| &schema.fields),
         "rows": rows,
         "row_count": result.rows.len()
     }))
@@ -310,17 +372,21 @@ mod tests {
 
     #[tokio::test]
     async fn test_execute_simple_query() {
-        // Create simple pipeline: SELECT * FROM users
-        let pipeline = Pipeline {
-            source: Source::Table {
-                name: "users".to_string(),
-                alias: None,
+        // Create simple program: SELECT * FROM users
+        let program = Program {
+            pragma: None,
+            lets: vec![],
+            pipeline: Pipeline {
+                source: Source::Table {
+                    name: "users".to_string(),
+                    alias: None,
+                },
+                ops: vec![],
             },
-            ops: vec![],
         };
 
         // This should fail because table doesn't exist, but we're testing the flow
-        let result = execute_ir(pipeline, None).await;
+        let result = execute_ir(program, None).await;
 
         // We expect an error since the table doesn't exist
         assert!(result.is_err());
@@ -341,6 +407,8 @@ mod tests {
                 ],
             ],
             row_count: 2,
+            sql: None,
+            schema: None,
         };
 
         let json = result_to_json(&result).unwrap();
@@ -349,4 +417,31 @@ mod tests {
         assert_eq!(json["columns"].as_array().unwrap().len(), 2);
         assert_eq!(json["rows"].as_array().unwrap().len(), 2);
     }
+
+    #[test]
+    fn test_execution_mode_from_name() {
+        assert_eq!(ExecutionMode::from_name("sql"), ExecutionMode::Sql);
+        assert_eq!(ExecutionMode::from_name("SQL"), ExecutionMode::Sql);
+        assert_eq!(ExecutionMode::from_name("substrait"), ExecutionMode::Substrait);
+    }
+
+    #[test]
+    fn test_error_location() {
+        let location = OpLocation { binding: None, op: Some(1) };
+        let error: Box<dyn std::error::Error> = Box::new(TranslateError::At {
+            location,
+            error: Box::new(TranslateError::UnknownColumn {
+                column: "nmae".to_string(),
+                available: vec!["id".to_string(), "name".to_string()],
+            }),
+        });
+
+        let (found, message, expected) = error_location(error.as_ref()).unwrap();
+        assert_eq!(found, location);
+        assert!(message.starts_with("Column 'nmae' not found"));
+        assert_eq!(expected, vec!["id", "name"]);
+
+        let error: Box<dyn std::error::Error> = "no location".into();
+        assert!(error_location(error.as_ref()).is_none());
+    }
 }